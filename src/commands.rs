@@ -1,12 +1,20 @@
 //! Sub-commands of `git-branchless`.
 
+pub mod amend;
+pub mod doctor;
+pub mod events;
 pub mod gc;
 pub mod hide;
 pub mod hooks;
 pub mod init;
 pub mod r#move;
 pub mod navigation;
+pub mod reattach;
+pub mod repair;
 pub mod restack;
 pub mod smartlog;
+pub mod stack_diff;
+pub mod sync;
+pub mod test;
 pub mod undo;
 pub mod wrap;
@@ -0,0 +1,243 @@
+//! Amend a commit and restack its descendants in one step.
+//!
+//! Amending a commit with `git commit --amend` abandons its descendants, which
+//! then have to be moved onto the amended commit with `git restack`. This
+//! command does both: the staged changes (and optionally a new message) are
+//! amended into the commit in-memory, and then its descendants are rebased onto
+//! the amended version, all as part of one event-log transaction.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use fn_error_context::context;
+
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Glyphs};
+use crate::core::graph::{get_sorted_children, make_graph_from_repo, AncestryCache, MainBranchOid};
+use crate::core::mergebase::MergeBaseDb;
+use crate::core::patchid::PatchIdDb;
+use crate::core::rewrite::{
+    execute_rebase_plan, friendly_describe_commit, make_rebase_plan, move_branches,
+};
+use crate::util::{
+    get_committer_signature, get_db_conn, get_head_oid, get_main_branch_oid, get_repo,
+    resolve_commits_by_summary, run_hook, write_ambiguous_summary_message,
+    write_not_a_commit_message, GitExecutable, ResolveCommitsResult, EXIT_CODE_BAD_ARGUMENTS,
+    EXIT_CODE_COMMIT_NOT_FOUND, EXIT_CODE_CONFLICT,
+};
+
+/// Amend the staged changes into a commit, and then restack its descendants
+/// onto the amended commit.
+///
+/// If the commit to amend is not `HEAD`, then the staged changes (relative to
+/// `HEAD`) are applied on top of it. If `HEAD` is a descendant of the commit,
+/// then it's checked out at its new version once the descendants have been
+/// restacked. Otherwise, the staged changes are left as-is in the index.
+///
+/// Messages about the progress of the amend, as well as the output of any `git`
+/// commands which are run, are written to `out`.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `commit`: The commit to amend. If not provided, defaults to `HEAD`.
+/// * `message`: The new commit message. If not provided, the commit's message
+///   is kept.
+///
+/// Returns: exit code. This is one of:
+/// * 0: The commit was amended and its descendants restacked successfully.
+/// * `EXIT_CODE_CONFLICT` (1): The staged changes couldn't be applied to the
+///   commit, or restacking its descendants stopped because of a merge conflict.
+/// * `EXIT_CODE_BAD_ARGUMENTS` (2): The commit can't be amended, such as
+///   because it's an ancestor of the main branch.
+/// * `EXIT_CODE_COMMIT_NOT_FOUND` (3): The commit could not be resolved.
+#[context("Amending commit")]
+pub fn amend(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    commit: Option<String>,
+    message: Option<String>,
+) -> anyhow::Result<isize> {
+    let repo = get_repo()?;
+    let head_oid = match get_head_oid(&repo)? {
+        Some(head_oid) => head_oid,
+        None => {
+            writeln!(out, "There is no commit at HEAD to amend.")?;
+            return Ok(EXIT_CODE_BAD_ARGUMENTS);
+        }
+    };
+    let commit_to_amend = match commit {
+        None => repo.find_commit(head_oid)?,
+        Some(commit) => match resolve_commits_by_summary(&repo, vec![commit])? {
+            ResolveCommitsResult::Ok { mut commits } => commits.remove(0),
+            ResolveCommitsResult::CommitNotFound { commit } => {
+                writeln!(out, "Commit not found: {}", commit)?;
+                return Ok(EXIT_CODE_COMMIT_NOT_FOUND);
+            }
+            ResolveCommitsResult::NotACommit {
+                commit,
+                object_type,
+            } => {
+                write_not_a_commit_message(out, &commit, object_type)?;
+                return Ok(EXIT_CODE_COMMIT_NOT_FOUND);
+            }
+            ResolveCommitsResult::AmbiguousSummary {
+                commit,
+                commit_oids,
+            } => {
+                let glyphs = Glyphs::detect();
+                write_ambiguous_summary_message(out, &glyphs, &repo, &commit, &commit_oids)?;
+                return Ok(EXIT_CODE_BAD_ARGUMENTS);
+            }
+        },
+    };
+    let old_oid = commit_to_amend.id();
+
+    let glyphs = Glyphs::detect();
+    let main_branch_oid = get_main_branch_oid(&repo)?;
+    let conn = get_db_conn(&repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let patch_id_db = PatchIdDb::new(&conn)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+
+    if old_oid != main_branch_oid
+        && merge_base_db.get_merge_base_oid(&repo, main_branch_oid, old_oid)? == Some(old_oid)
+    {
+        writeln!(
+            out,
+            "Cannot amend commit {}, since it's an ancestor of the main branch.",
+            printable_styled_string(&glyphs, friendly_describe_commit(&repo, old_oid)?)?
+        )?;
+        return Ok(EXIT_CODE_BAD_ARGUMENTS);
+    }
+
+    let graph = make_graph_from_repo(
+        &repo,
+        &merge_base_db,
+        &mut AncestryCache::default(),
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        true,
+    )?;
+
+    let new_tree_oid = {
+        let index_tree_oid = repo
+            .index()?
+            .write_tree()
+            .with_context(|| "Writing index to tree")?;
+        if old_oid == head_oid {
+            index_tree_oid
+        } else {
+            let head_tree = repo.find_commit(head_oid)?.tree()?;
+            let index_tree = repo.find_tree(index_tree_oid)?;
+            let mut amended_index =
+                repo.merge_trees(&head_tree, &commit_to_amend.tree()?, &index_tree, None)?;
+            if amended_index.has_conflicts() {
+                writeln!(
+                    out,
+                    "The staged changes could not be applied to commit {} without merge conflicts.",
+                    printable_styled_string(&glyphs, friendly_describe_commit(&repo, old_oid)?)?
+                )?;
+                return Ok(EXIT_CODE_CONFLICT);
+            }
+            amended_index
+                .write_tree_to(&repo)
+                .with_context(|| "Converting index to tree")?
+        }
+    };
+    let message = match message {
+        Some(message) => git2::message_prettify(message, None)?,
+        None => match commit_to_amend.message_raw() {
+            Some(message) => message.to_string(),
+            None => anyhow::bail!("Could not decode commit message for commit: {:?}", old_oid),
+        },
+    };
+    if new_tree_oid == commit_to_amend.tree_id()
+        && Some(message.as_str()) == commit_to_amend.message_raw()
+    {
+        writeln!(
+            out,
+            "There are no staged changes to amend, and no new message was provided."
+        )?;
+        return Ok(0);
+    }
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "amend")?;
+    let new_oid = {
+        let new_tree = repo
+            .find_tree(new_tree_oid)
+            .with_context(|| "Looking up amended tree")?;
+        let parents: Vec<git2::Commit> = commit_to_amend.parents().collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(
+            None,
+            &commit_to_amend.author(),
+            &get_committer_signature(&repo, git_executable)?,
+            &message,
+            &new_tree,
+            &parents,
+        )
+        .with_context(|| "Committing amended commit")?
+    };
+    if old_oid == head_oid && repo.head_detached()? {
+        repo.set_head_detached(new_oid)?;
+    }
+    let rewritten_oids_map: HashMap<git2::Oid, git2::Oid> =
+        std::iter::once((old_oid, new_oid)).collect();
+    move_branches(&repo, event_tx_id, &rewritten_oids_map)?;
+    writeln!(
+        out,
+        "Amended commit: {}",
+        printable_styled_string(&glyphs, friendly_describe_commit(&repo, new_oid)?)?
+    )?;
+
+    let child_oids = if graph.contains_key(&old_oid) {
+        get_sorted_children(&graph, old_oid)
+    } else {
+        Vec::new()
+    };
+    let mut result = 0;
+    for child_oid in child_oids {
+        let rebase_plan = make_rebase_plan(
+            &repo,
+            &merge_base_db,
+            &patch_id_db,
+            &graph,
+            &MainBranchOid(main_branch_oid),
+            child_oid,
+            new_oid,
+        )?;
+        result = execute_rebase_plan(
+            out,
+            &glyphs,
+            git_executable,
+            &repo,
+            event_tx_id,
+            &rebase_plan,
+            child_oid,
+            new_oid,
+            false,
+            false,
+            false,
+        )?;
+        if result != 0 {
+            break;
+        }
+    }
+
+    // Record the amend only after restacking the descendants, so that we don't
+    // warn about them having been abandoned.
+    run_hook(
+        &repo,
+        "post-rewrite",
+        event_tx_id,
+        &["amend"],
+        Some(format!("{} {}\n", old_oid, new_oid)),
+    )?;
+
+    Ok(result)
+}
@@ -0,0 +1,230 @@
+//! Check that `git-branchless` is set up correctly in this repo.
+//!
+//! Problems with the setup, such as an old Git version or a missing hook,
+//! otherwise only show up as scattered errors (or silently missing events)
+//! when running other commands. This command checks for them all at once, and
+//! explains how to fix each one.
+
+use std::io::Write;
+use std::path::Path;
+
+use console::style;
+use fn_error_context::context;
+
+use crate::commands::init::{
+    determine_hook_path, get_git_version, ALL_HOOKS, MIN_UNDO_GIT_VERSION,
+};
+use crate::core::config::get_main_branch_name;
+use crate::core::eventlog::EventLogDb;
+use crate::core::formatting::Pluralize;
+use crate::util::{get_db_conn, get_main_branch_oid, get_repo, GitExecutable};
+
+/// The outcome of a single check.
+enum CheckResult {
+    /// The check passed.
+    Ok { message: String },
+
+    /// The check found a problem which only affects some functionality.
+    Warn {
+        message: String,
+        remediation: String,
+    },
+
+    /// The check found a problem which must be fixed.
+    Fail {
+        message: String,
+        remediation: String,
+    },
+}
+
+fn write_check_result(out: &mut impl Write, result: &CheckResult) -> anyhow::Result<()> {
+    match result {
+        CheckResult::Ok { message } => {
+            writeln!(out, "{} {}", style("OK  ").green().bold(), message)?;
+        }
+        CheckResult::Warn {
+            message,
+            remediation,
+        } => {
+            writeln!(out, "{} {}", style("WARN").yellow().bold(), message)?;
+            writeln!(out, "     {}", remediation)?;
+        }
+        CheckResult::Fail {
+            message,
+            remediation,
+        } => {
+            writeln!(out, "{} {}", style("FAIL").red().bold(), message)?;
+            writeln!(out, "     {}", remediation)?;
+        }
+    }
+    Ok(())
+}
+
+#[context("Checking Git version")]
+fn check_git_version(
+    repo: &git2::Repository,
+    git_executable: &GitExecutable,
+) -> anyhow::Result<CheckResult> {
+    let result = match get_git_version(repo, git_executable) {
+        Ok((version_str, version)) if version < MIN_UNDO_GIT_VERSION => CheckResult::Warn {
+            message: format!("Git version is too old for `git undo`: {}", version_str),
+            remediation: String::from(
+                "Upgrade to Git v2.29 or later, then run: git branchless init",
+            ),
+        },
+        Ok((version_str, _version)) => CheckResult::Ok {
+            message: format!("Git version: {}", version_str),
+        },
+        Err(err) => CheckResult::Fail {
+            message: format!("Could not determine Git version: {}", err),
+            remediation: String::from("Check that `git` is on your PATH"),
+        },
+    };
+    Ok(result)
+}
+
+/// Check whether the file at `path` can be executed. On non-Unix systems, Git
+/// doesn't check for execute permissions, so any file is considered
+/// executable.
+fn is_executable(path: &Path) -> anyhow::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let metadata = std::fs::metadata(path)?;
+        Ok(metadata.permissions().mode() & 0o111 != 0)
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(true)
+    }
+}
+
+#[context("Checking hook of type: {:?}", hook_type)]
+fn check_hook(repo: &git2::Repository, hook_type: &str) -> anyhow::Result<CheckResult> {
+    let hook = determine_hook_path(repo, hook_type)?;
+    let hook_path = hook.path();
+    let remediation = String::from("To reinstall the hooks, run: git branchless init");
+    let contents = match std::fs::read_to_string(hook_path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(CheckResult::Fail {
+                message: format!("Hook is not installed: {}", hook_type),
+                remediation,
+            });
+        }
+        Err(err) => {
+            return Ok(CheckResult::Fail {
+                message: format!("Hook could not be read: {} ({})", hook_type, err),
+                remediation,
+            });
+        }
+    };
+
+    let result = if !contents.contains(&format!("git branchless hook-{}", hook_type)) {
+        CheckResult::Fail {
+            message: format!("Hook does not run git-branchless: {}", hook_type),
+            remediation,
+        }
+    } else if !is_executable(hook_path)? {
+        CheckResult::Fail {
+            message: format!("Hook is not executable: {}", hook_type),
+            remediation: format!("To fix, run: chmod +x {:?}", hook_path),
+        }
+    } else {
+        CheckResult::Ok {
+            message: format!("Hook is installed: {}", hook_type),
+        }
+    };
+    Ok(result)
+}
+
+#[context("Checking main branch")]
+fn check_main_branch(repo: &git2::Repository) -> anyhow::Result<CheckResult> {
+    let main_branch_name = get_main_branch_name(repo)?;
+    let result = match get_main_branch_oid(repo) {
+        Ok(_) => CheckResult::Ok {
+            message: format!("Main branch resolves: {}", main_branch_name),
+        },
+        Err(_) => CheckResult::Fail {
+            message: format!("Main branch could not be resolved: {}", main_branch_name),
+            remediation: String::from(
+                "To fix, run: git config branchless.core.mainBranch <branch>",
+            ),
+        },
+    };
+    Ok(result)
+}
+
+fn check_database(repo: &git2::Repository) -> CheckResult {
+    let result = get_db_conn(repo).and_then(|conn| {
+        let event_log_db = EventLogDb::new(&conn)?;
+        event_log_db.get_event_log_head()?;
+        Ok(())
+    });
+    match result {
+        Ok(()) => CheckResult::Ok {
+            message: String::from("Event log database opens"),
+        },
+        Err(err) => CheckResult::Fail {
+            message: format!("Event log database could not be opened: {:#}", err),
+            remediation: String::from(
+                "Check the permissions of the .git/branchless directory, or move it aside and run: git branchless init",
+            ),
+        },
+    }
+}
+
+/// Check that `git-branchless` is set up correctly in the current repo, and
+/// print a checklist of the results, along with how to fix any problems.
+///
+/// The following are checked:
+/// * The Git version is new enough for `git undo` (only a warning).
+/// * All of the hooks installed by `git branchless init` are present and
+///   executable.
+/// * The main branch setting resolves to a commit.
+/// * The event log database can be opened.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `git_executable`: The path to the `git` executable on disk.
+///
+/// Returns: exit code. This is 0 if every check passed or only warned, and 1
+/// otherwise.
+#[context("Checking git-branchless setup")]
+pub fn doctor(out: &mut impl Write, git_executable: &GitExecutable) -> anyhow::Result<isize> {
+    let repo = get_repo()?;
+
+    let mut results = vec![check_git_version(&repo, git_executable)?];
+    for (hook_type, _hook_script) in ALL_HOOKS {
+        results.push(check_hook(&repo, hook_type)?);
+    }
+    results.push(check_main_branch(&repo)?);
+    results.push(check_database(&repo));
+
+    let mut num_failed = 0;
+    for result in results.iter() {
+        write_check_result(out, result)?;
+        if let CheckResult::Fail { .. } = result {
+            num_failed += 1;
+        }
+    }
+
+    if num_failed > 0 {
+        writeln!(
+            out,
+            "Found {}.",
+            Pluralize {
+                amount: num_failed,
+                singular: "problem",
+                plural: "problems",
+            }
+            .to_string()
+        )?;
+        Ok(1)
+    } else {
+        writeln!(out, "No problems found.")?;
+        Ok(0)
+    }
+}
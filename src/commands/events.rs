@@ -0,0 +1,97 @@
+//! Show the history of events affecting a single commit.
+//!
+//! This is mostly useful for debugging, such as to determine why a commit is
+//! visible or hidden in the smartlog.
+
+use std::io::Write;
+use std::time::SystemTime;
+
+use crate::commands::undo::{describe_clock_skew, describe_event};
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Glyphs};
+use crate::util::{
+    get_db_conn, get_repo, resolve_commits_by_summary, write_ambiguous_summary_message,
+    write_not_a_commit_message, ResolveCommitsResult, EXIT_CODE_BAD_ARGUMENTS,
+    EXIT_CODE_COMMIT_NOT_FOUND,
+};
+
+/// Print every event which refers to the provided commit, in chronological
+/// order.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `hash`: The commit to show events for. Revs will be resolved (you can
+///   provide an abbreviated commit hash or ref name). If the commit no longer
+///   exists (e.g. it has been garbage-collected), then its full hash can be
+///   provided instead.
+///
+/// Returns: exit code. This is one of:
+/// * 0: The events were shown, or there were no events for the commit.
+/// * `EXIT_CODE_BAD_ARGUMENTS` (2): `hash` matched the summaries of more than
+///   one commit.
+/// * `EXIT_CODE_COMMIT_NOT_FOUND` (3): `hash` could not be resolved to a
+///   commit.
+pub fn events(out: &mut impl Write, hash: String) -> anyhow::Result<isize> {
+    let glyphs = Glyphs::detect();
+    let repo = get_repo()?;
+    let conn = get_db_conn(&repo)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+
+    let oid = match resolve_commits_by_summary(&repo, vec![hash.clone()])? {
+        ResolveCommitsResult::Ok { commits } => commits[0].id(),
+        ResolveCommitsResult::CommitNotFound { commit } => match git2::Oid::from_str(&hash) {
+            Ok(oid) if hash.len() == oid.to_string().len() => oid,
+            _ => {
+                writeln!(out, "Commit not found: {}", commit)?;
+                return Ok(EXIT_CODE_COMMIT_NOT_FOUND);
+            }
+        },
+        ResolveCommitsResult::NotACommit {
+            commit,
+            object_type,
+        } => {
+            write_not_a_commit_message(out, &commit, object_type)?;
+            return Ok(EXIT_CODE_COMMIT_NOT_FOUND);
+        }
+        ResolveCommitsResult::AmbiguousSummary {
+            commit,
+            commit_oids,
+        } => {
+            write_ambiguous_summary_message(out, &glyphs, &repo, &commit, &commit_oids)?;
+            return Ok(EXIT_CODE_BAD_ARGUMENTS);
+        }
+    };
+
+    let events = event_replayer.get_commit_events(oid);
+    if events.is_empty() {
+        writeln!(out, "No events found for commit {}", oid)?;
+        return Ok(0);
+    }
+
+    for (event_id, event) in events {
+        let timestamp = event
+            .get_timestamp()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        let event_tx_id = event.get_event_tx_id().to_string();
+        let clock_skew = match event_replayer
+            .get_clock_skew_before_cursor(event_replayer.make_cursor(event_id + 1))
+        {
+            Some(clock_skew) => describe_clock_skew(clock_skew)?,
+            None => String::new(),
+        };
+        writeln!(
+            out,
+            "Event {} (transaction {}, timestamp {}){}:",
+            event_id, event_tx_id, timestamp, clock_skew
+        )?;
+        for line in describe_event(&repo, event)? {
+            if !line.is_empty() {
+                writeln!(out, "    {}", printable_styled_string(&glyphs, line)?)?;
+            }
+        }
+    }
+
+    Ok(0)
+}
@@ -8,16 +8,20 @@
 //! This module is responsible for adding extra references to Git, so that Git's
 //! garbage collection doesn't collect commits which branchless thinks are still
 //! visible.
+//!
+//! It's also responsible for compacting the event log when the user runs `git
+//! branchless gc` and has opted into it, so that it doesn't grow without bound.
+
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
 use fn_error_context::context;
 
-use crate::core::eventlog::{is_gc_ref, EventLogDb, EventReplayer};
-use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::config::{get_gc_event_log_horizon_days, GC_EVENT_LOG_HORIZON_DAYS_CONFIG_KEY};
+use crate::core::eventlog::{compact_events, is_gc_ref, EventLogDb, EventReplayer};
+use crate::core::graph::{make_graph_from_repo, AncestryCache, CommitGraph};
 use crate::core::mergebase::MergeBaseDb;
-use crate::util::{
-    get_branch_oid_to_names, get_db_conn, get_head_oid, get_main_branch_oid, get_repo,
-};
+use crate::util::{get_db_conn, get_repo};
 
 fn find_dangling_references<'repo>(
     repo: &'repo git2::Repository,
@@ -76,28 +80,61 @@ pub fn mark_commit_reachable(repo: &git2::Repository, commit_oid: git2::Oid) ->
     Ok(())
 }
 
+/// Compact the events in the event log which are older than the configured
+/// horizon, if any.
+#[context("Compacting event log")]
+fn compact_event_log_before_horizon(
+    repo: &git2::Repository,
+    event_log_db: &mut EventLogDb,
+    now: SystemTime,
+) -> anyhow::Result<()> {
+    let horizon_days = match get_gc_event_log_horizon_days(repo)? {
+        Some(horizon_days) => horizon_days,
+        None => return Ok(()),
+    };
+    let horizon = now - Duration::from_secs(horizon_days * 24 * 60 * 60);
+
+    let events = event_log_db.get_events()?;
+    let num_events = events.len();
+    let compacted_events = compact_events(events, horizon, |oid| repo.find_commit(oid).is_ok());
+    let num_removed_events = num_events - compacted_events.len();
+    if num_removed_events > 0 {
+        println!(
+            "branchless: compacting {} events older than {} days from the event log",
+            num_removed_events, horizon_days
+        );
+        println!(
+            "branchless: warning: `git undo` will no longer be able to return to states from before then (configure this with `{}`)",
+            GC_EVENT_LOG_HORIZON_DAYS_CONFIG_KEY
+        );
+        event_log_db.replace_events(compacted_events)?;
+    }
+    Ok(())
+}
+
 /// Run branchless's garbage collection.
 ///
 /// Frees any references to commits which are no longer visible in the smartlog.
+///
+/// If `compact_event_log` is set, also compacts the event log, provided that
+/// `branchless.gc.eventLogHorizonDays` is set. This is only done when the user
+/// runs `git branchless gc` explicitly, and not from Git's automatic garbage
+/// collection, since it discards `git undo` history.
 #[context("Running garbage-collection")]
-pub fn gc() -> anyhow::Result<()> {
+pub fn gc(compact_event_log: bool) -> anyhow::Result<()> {
+    let now = SystemTime::now();
     let repo = get_repo()?;
     let conn = get_db_conn(&repo)?;
     let merge_base_db = MergeBaseDb::new(&conn)?;
-    let event_log_db = EventLogDb::new(&conn)?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
-    let head_oid = get_head_oid(&repo)?;
-    let main_branch_oid = get_main_branch_oid(&repo)?;
-    let branch_oid_to_names = get_branch_oid_to_names(&repo)?;
 
-    let graph = make_graph(
+    let graph = make_graph_from_repo(
         &repo,
         &merge_base_db,
+        &mut AncestryCache::default(),
         &event_replayer,
         event_replayer.make_default_cursor(),
-        &HeadOid(head_oid),
-        &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().copied().collect()),
         true,
     )?;
 
@@ -108,5 +145,9 @@ pub fn gc() -> anyhow::Result<()> {
             .delete()
             .with_context(|| format!("Deleting reference {:?}", reference.name()))?;
     }
+
+    if compact_event_log {
+        compact_event_log_before_horizon(&repo, &mut event_log_db, now)?;
+    }
     Ok(())
 }
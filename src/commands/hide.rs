@@ -2,20 +2,31 @@
 //! automatically as the result of a rewrite operation).
 
 use std::collections::HashSet;
+use std::convert::TryInto;
+use std::io::{stdin, stdout, BufReader};
 use std::time::SystemTime;
 
+use fn_error_context::context;
+
 use crate::core::eventlog::{CommitVisibility, Event};
 use crate::core::eventlog::{EventLogDb, EventReplayer};
-use crate::core::formatting::{printable_styled_string, Glyphs};
-use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid, Node};
+use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize};
+use crate::core::graph::{
+    get_sorted_children, make_graph_from_repo, resolve_current_stack, AncestryCache, CommitGraph,
+    Node,
+};
 use crate::core::mergebase::MergeBaseDb;
 use crate::core::metadata::{
     render_commit_metadata, CommitMessageProvider, CommitMetadataProvider, CommitOidProvider,
 };
-use crate::util::resolve_commits;
+use crate::core::patchid::{get_patch_id, PatchIdDb};
 use crate::util::ResolveCommitsResult;
 use crate::util::{
     get_branch_oid_to_names, get_db_conn, get_head_oid, get_main_branch_oid, get_repo,
+    prompt_confirmation,
+};
+use crate::util::{
+    resolve_commits_by_summary, write_ambiguous_summary_message, write_not_a_commit_message,
 };
 
 fn recurse_on_commits_helper<
@@ -34,7 +45,7 @@ fn recurse_on_commits_helper<
         callback(node);
     };
 
-    for child_oid in node.children.iter() {
+    for child_oid in get_sorted_children(graph, commit.id()) {
         let child_commit = &graph[&child_oid].commit;
         recurse_on_commits_helper(graph, condition, child_commit, callback)
     }
@@ -47,17 +58,12 @@ fn recurse_on_commits<'repo, F: Fn(&Node) -> bool>(
     commits: Vec<git2::Commit<'repo>>,
     condition: F,
 ) -> anyhow::Result<Vec<git2::Commit<'repo>>> {
-    let head_oid = get_head_oid(repo)?;
-    let main_branch_oid = get_main_branch_oid(repo)?;
-    let branch_oid_to_names = get_branch_oid_to_names(repo)?;
-    let graph = make_graph(
+    let graph = make_graph_from_repo(
         repo,
         merge_base_db,
+        &mut AncestryCache::default(),
         event_replayer,
         event_replayer.make_default_cursor(),
-        &HeadOid(head_oid),
-        &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().copied().collect()),
         false,
     )?;
 
@@ -76,17 +82,166 @@ fn recurse_on_commits<'repo, F: Fn(&Node) -> bool>(
     Ok(result)
 }
 
+/// Get the commits in the current stack (see `resolve_current_stack`).
+#[context("Getting commits in the current stack")]
+fn get_current_stack_commits<'repo>(
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    event_replayer: &EventReplayer,
+) -> anyhow::Result<Vec<git2::Commit<'repo>>> {
+    let head_oid = get_head_oid(repo)?;
+    let graph = make_graph_from_repo(
+        repo,
+        merge_base_db,
+        &mut AncestryCache::default(),
+        event_replayer,
+        event_replayer.make_default_cursor(),
+        true,
+    )?;
+    let commits = resolve_current_stack(&graph, head_oid)
+        .into_iter()
+        .map(|oid| graph[&oid].commit.clone())
+        .collect();
+    Ok(commits)
+}
+
+/// Determine whether the given string is a commit range of the form `A..B`.
+/// (Symmetric-difference ranges, like `A...B`, aren't supported.)
+fn is_commit_range(hash: &str) -> bool {
+    hash.contains("..") && !hash.contains("...")
+}
+
+/// Resolve a commit range of the form `A..B` into the commits which are
+/// reachable from `B` but not from `A`, as with `git log A..B`.
+///
+/// Returns: The commits in the range, ordered from oldest to newest, or `None`
+/// if either end of the range couldn't be resolved to a commit.
+#[context("Resolving commit range: {:?}", range)]
+fn resolve_commit_range<'repo>(
+    repo: &'repo git2::Repository,
+    range: &str,
+) -> anyhow::Result<Option<Vec<git2::Commit<'repo>>>> {
+    let endpoints = repo.revparse(range).ok().and_then(|revspec| {
+        let from_oid = revspec.from()?.peel_to_commit().ok()?.id();
+        let to_oid = revspec.to()?.peel_to_commit().ok()?.id();
+        Some((from_oid, to_oid))
+    });
+    let (from_oid, to_oid) = match endpoints {
+        Some(endpoints) => endpoints,
+        None => return Ok(None),
+    };
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    revwalk.push(to_oid)?;
+    revwalk.hide(from_oid)?;
+    let mut commits = Vec::new();
+    for oid in revwalk {
+        commits.push(repo.find_commit(oid?)?);
+    }
+    Ok(Some(commits))
+}
+
+/// Determine whether the given commit is reachable from any branch, in which
+/// case it would stay in the smartlog even if it were hidden.
+fn is_reachable_from_branch(
+    repo: &git2::Repository,
+    branch_oids: &[git2::Oid],
+    commit_oid: git2::Oid,
+) -> anyhow::Result<bool> {
+    for branch_oid in branch_oids {
+        if *branch_oid == commit_oid || repo.graph_descendant_of(*branch_oid, commit_oid)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Resolve the hashes provided on the command-line, as with
+/// `resolve_commits_by_summary`, except that commit ranges of the form `A..B`
+/// are expanded into the commits they contain (see `resolve_commit_range`).
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `hashes`: The hashes, ref-specs, summaries, or ranges to resolve.
+/// * `skip_branch_reachable`: If `true`, leave out the commits in the ranges
+///   which are reachable from a branch. Commits which were provided directly
+///   are never left out.
+///
+/// Returns: The resolved commits, followed by the commits in the ranges, with
+/// duplicates removed; and the commits in the ranges which were left out.
+#[context("Resolving commits and commit ranges")]
+fn resolve_commits_and_ranges<'repo>(
+    repo: &'repo git2::Repository,
+    hashes: Vec<String>,
+    skip_branch_reachable: bool,
+) -> anyhow::Result<(ResolveCommitsResult<'repo>, Vec<git2::Commit<'repo>>)> {
+    let (ranges, hashes): (Vec<String>, Vec<String>) =
+        hashes.into_iter().partition(|hash| is_commit_range(hash));
+    let commits = match resolve_commits_by_summary(repo, hashes)? {
+        ResolveCommitsResult::Ok { commits } => commits,
+        result => return Ok((result, Vec::new())),
+    };
+
+    let branch_oids: Vec<git2::Oid> = if skip_branch_reachable && !ranges.is_empty() {
+        get_branch_oid_to_names(repo)?.into_keys().collect()
+    } else {
+        Vec::new()
+    };
+    let mut range_commits = Vec::new();
+    let mut skipped_commits = Vec::new();
+    for range in ranges {
+        let commits = match resolve_commit_range(repo, &range)? {
+            Some(commits) => commits,
+            None => {
+                return Ok((
+                    ResolveCommitsResult::CommitNotFound { commit: range },
+                    Vec::new(),
+                ))
+            }
+        };
+        for commit in commits {
+            if is_reachable_from_branch(repo, &branch_oids, commit.id())? {
+                skipped_commits.push(commit);
+            } else {
+                range_commits.push(commit);
+            }
+        }
+    }
+
+    let mut seen_oids: HashSet<git2::Oid> = HashSet::new();
+    let commits = commits
+        .into_iter()
+        .chain(range_commits)
+        .filter(|commit| seen_oids.insert(commit.id()))
+        .collect();
+    Ok((ResolveCommitsResult::Ok { commits }, skipped_commits))
+}
+
 /// Hide the hashes provided on the command-line.
 ///
 /// Args:
 /// * `out`: The output stream to write to.
 /// * `hashes`: A list of commit hashes to hide. Revs will be resolved (you can
-///   provide an abbreviated commit hash or ref name).
+///   provide an abbreviated commit hash or ref name), or else matched against
+///   the summaries of the visible commits (see `resolve_commits_by_summary`).
+///   Commit ranges of the form `A..B` are expanded into the commits which are
+///   reachable from `B` but not from `A`, except that commits in the range
+///   which are still reachable from a branch are left alone, with a warning.
+/// * `stack`: If `true`, also hide the commits in the current stack (see
+///   `resolve_current_stack`).
 /// * `recursive: If `true`, will recursively hide all children of the provided
 ///   commits as well.
+/// * `dry_run`: If `true`, only print the commits which would be hidden, and
+///   don't record any events.
 ///
 /// Returns: exit code (0 denotes successful exit).
-pub fn hide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
+pub fn hide(
+    hashes: Vec<String>,
+    stack: bool,
+    recursive: bool,
+    dry_run: bool,
+) -> anyhow::Result<isize> {
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
     let repo = get_repo()?;
@@ -95,13 +250,67 @@ pub fn hide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
     let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
     let merge_base_db = MergeBaseDb::new(&conn)?;
 
-    let commits = resolve_commits(&repo, hashes)?;
+    let (commits, skipped_commits) = resolve_commits_and_ranges(&repo, hashes, true)?;
+    if !skipped_commits.is_empty() {
+        println!(
+            "Not hiding {} still reachable from a branch:",
+            Pluralize {
+                amount: skipped_commits.len().try_into()?,
+                singular: "commit from the range which is",
+                plural: "commits from the range which are",
+            }
+            .to_string()
+        );
+        for commit in skipped_commits {
+            let commit_text = render_commit_metadata(
+                &commit,
+                &mut [
+                    &mut CommitOidProvider::new(&repo, true)?,
+                    &mut CommitMessageProvider::new()?,
+                ],
+            )?;
+            println!(
+                "{} {}",
+                glyphs.bullet_point,
+                printable_styled_string(&glyphs, commit_text)?
+            );
+        }
+    }
     let commits = match commits {
         ResolveCommitsResult::Ok { commits } => commits,
         ResolveCommitsResult::CommitNotFound { commit: hash } => {
             println!("Commit not found: {}", hash);
             return Ok(1);
         }
+        ResolveCommitsResult::NotACommit {
+            commit,
+            object_type,
+        } => {
+            write_not_a_commit_message(&mut stdout(), &commit, object_type)?;
+            return Ok(1);
+        }
+        ResolveCommitsResult::AmbiguousSummary {
+            commit,
+            commit_oids,
+        } => {
+            write_ambiguous_summary_message(&mut stdout(), &glyphs, &repo, &commit, &commit_oids)?;
+            return Ok(1);
+        }
+    };
+    let commits = if stack {
+        let stack_commits = get_current_stack_commits(&repo, &merge_base_db, &event_replayer)?;
+        if stack_commits.is_empty() {
+            println!("The --stack option was provided, but the current commit is not in a stack.");
+            return Ok(1);
+        }
+        let mut seen_oids: HashSet<git2::Oid> = HashSet::new();
+        commits
+            .into_iter()
+            .chain(stack_commits)
+            .filter(|commit| seen_oids.insert(commit.id()))
+            .collect()
+    } else {
+        commits
     };
     let commits = if recursive {
         recurse_on_commits(&repo, &merge_base_db, &event_replayer, commits, |node| {
@@ -111,6 +320,99 @@ pub fn hide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
         commits
     };
 
+    if dry_run {
+        print_dry_run(
+            &glyphs,
+            &repo,
+            &event_replayer,
+            &commits,
+            CommitVisibility::Hidden,
+        )?;
+        return Ok(0);
+    }
+    hide_commits(
+        &glyphs,
+        &repo,
+        &mut event_log_db,
+        &event_replayer,
+        now,
+        commits,
+    )?;
+    Ok(0)
+}
+
+/// Print the commits whose visibility would change if they were set to
+/// `visibility`, without recording any events. Commits which already have that
+/// visibility are skipped.
+#[context("Printing dry run")]
+fn print_dry_run(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    event_replayer: &EventReplayer,
+    commits: &[git2::Commit],
+    visibility: CommitVisibility,
+) -> anyhow::Result<()> {
+    let cursor = event_replayer.make_default_cursor();
+    let commits: Vec<&git2::Commit> = commits
+        .iter()
+        .filter(|commit| {
+            let current_visibility =
+                event_replayer.get_cursor_commit_visibility(cursor, commit.id());
+            !matches!(
+                (&visibility, current_visibility),
+                (CommitVisibility::Hidden, Some(CommitVisibility::Hidden))
+                    | (CommitVisibility::Visible, Some(CommitVisibility::Visible))
+            )
+        })
+        .collect();
+    let verb = match visibility {
+        CommitVisibility::Hidden => "hidden",
+        CommitVisibility::Visible => "unhidden",
+    };
+    if commits.is_empty() {
+        println!("No commits would be {}.", verb);
+        return Ok(());
+    }
+
+    println!(
+        "The following {} would be {}:",
+        Pluralize {
+            amount: commits.len().try_into()?,
+            singular: "commit",
+            plural: "commits",
+        }
+        .to_string(),
+        verb
+    );
+    for commit in commits {
+        let commit_text = render_commit_metadata(
+            commit,
+            &mut [
+                &mut CommitOidProvider::new(repo, true)?,
+                &mut CommitMessageProvider::new()?,
+            ],
+        )?;
+        println!(
+            "{} {}",
+            glyphs.bullet_point,
+            printable_styled_string(glyphs, commit_text)?
+        );
+    }
+    println!("(This was a dry run, so no commits were {}.)", verb);
+    Ok(())
+}
+
+/// Add events to hide the given commits in a single transaction, and print
+/// the commits which were hidden.
+#[context("Hiding commits")]
+fn hide_commits(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    event_log_db: &mut EventLogDb,
+    event_replayer: &EventReplayer,
+    now: SystemTime,
+    commits: Vec<git2::Commit>,
+) -> anyhow::Result<()> {
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "hide")?;
     let events = commits
@@ -129,14 +431,14 @@ pub fn hide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
             render_commit_metadata(
                 &commit,
                 &mut [
-                    &mut CommitOidProvider::new(true)? as &mut dyn CommitMetadataProvider,
+                    &mut CommitOidProvider::new(repo, true)? as &mut dyn CommitMetadataProvider,
                     &mut CommitMessageProvider::new()?,
                 ],
             )?
         };
         println!(
             "Hid commit: {}",
-            printable_styled_string(&glyphs, hidden_commit_text)?
+            printable_styled_string(glyphs, hidden_commit_text)?
         );
         if let Some(CommitVisibility::Hidden) =
             event_replayer.get_cursor_commit_visibility(cursor, commit.id())
@@ -145,13 +447,168 @@ pub fn hide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
         }
 
         let commit_target_oid =
-            render_commit_metadata(&commit, &mut [&mut CommitOidProvider::new(false)?])?;
+            render_commit_metadata(&commit, &mut [&mut CommitOidProvider::new(repo, false)?])?;
         println!(
             "To unhide this commit, run: git unhide {}",
-            printable_styled_string(&glyphs, commit_target_oid)?
+            printable_styled_string(glyphs, commit_target_oid)?
+        );
+    }
+
+    Ok(())
+}
+
+/// Find the visible commits which aren't on the main branch, but which have
+/// already been merged into it, such as by being cherry-picked or rebased onto
+/// it. A commit is considered to be merged if a commit on the main branch has
+/// the same patch ID. Commits which don't introduce any changes have no patch
+/// ID, so they're never considered to be merged.
+///
+/// Only main branch commits since the merge-base of all of the visible commits
+/// are considered.
+///
+/// Returns: The merged commits, in a deterministic order.
+#[context("Finding merged commits")]
+fn find_merged_commits<'repo>(
+    repo: &'repo git2::Repository,
+    patch_id_db: &PatchIdDb,
+    graph: &CommitGraph<'repo>,
+    main_branch_oid: git2::Oid,
+) -> anyhow::Result<Vec<git2::Commit<'repo>>> {
+    let mut candidate_commits: Vec<&git2::Commit> = graph
+        .values()
+        .filter(|node| node.is_visible && !node.is_main)
+        .map(|node| &node.commit)
+        .collect();
+    if candidate_commits.is_empty() {
+        return Ok(Vec::new());
+    }
+    candidate_commits.sort_by_key(|commit| (commit.time(), commit.id()));
+
+    let merge_base_oids: Vec<git2::Oid> = graph
+        .iter()
+        .filter(|(_oid, node)| node.is_main)
+        .map(|(oid, _node)| *oid)
+        .collect();
+    let merge_base_oid = match merge_base_oids.as_slice() {
+        [] => None,
+        [merge_base_oid] => Some(*merge_base_oid),
+        merge_base_oids => Some(repo.merge_base_many(merge_base_oids)?),
+    };
+
+    let mut main_patch_ids = HashSet::new();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(main_branch_oid)?;
+    if let Some(merge_base_oid) = merge_base_oid {
+        revwalk.hide(merge_base_oid)?;
+    }
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if let Some(patch_id) = get_patch_id(repo, patch_id_db, &commit)? {
+            main_patch_ids.insert(patch_id);
+        }
+    }
+
+    let mut result = Vec::new();
+    for commit in candidate_commits {
+        let is_merged = match get_patch_id(repo, patch_id_db, commit)? {
+            Some(patch_id) => main_patch_ids.contains(&patch_id),
+            None => false,
+        };
+        if is_merged {
+            result.push(commit.clone());
+        }
+    }
+    Ok(result)
+}
+
+/// Hide all visible commits which have already been merged into the main
+/// branch (see `find_merged_commits`). The commits are listed first, and only
+/// hidden once the user confirms.
+///
+/// Args:
+/// * `dry_run`: If `true`, only print the commits which would be hidden,
+///   without asking for confirmation or recording any events.
+/// * `assume_yes`: Hide the commits without asking for confirmation.
+///
+/// Returns: exit code (0 denotes successful exit).
+#[context("Hiding merged commits")]
+pub fn hide_merged(dry_run: bool, assume_yes: bool) -> anyhow::Result<isize> {
+    let now = SystemTime::now();
+    let mut in_ = BufReader::new(stdin());
+    let glyphs = Glyphs::detect();
+    let repo = get_repo()?;
+    let conn = get_db_conn(&repo)?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+
+    let main_branch_oid = get_main_branch_oid(&repo)?;
+    let graph = make_graph_from_repo(
+        &repo,
+        &merge_base_db,
+        &mut AncestryCache::default(),
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        true,
+    )?;
+
+    let commits = find_merged_commits(&repo, &PatchIdDb::new(&conn)?, &graph, main_branch_oid)?;
+    if commits.is_empty() {
+        println!("There are no visible commits which have been merged into the main branch.");
+        return Ok(0);
+    }
+
+    if dry_run {
+        print_dry_run(
+            &glyphs,
+            &repo,
+            &event_replayer,
+            &commits,
+            CommitVisibility::Hidden,
+        )?;
+        return Ok(0);
+    }
+    println!(
+        "The following {} been merged into the main branch:",
+        Pluralize {
+            amount: commits.len().try_into()?,
+            singular: "commit has",
+            plural: "commits have",
+        }
+        .to_string()
+    );
+    for commit in commits.iter() {
+        let commit_text = render_commit_metadata(
+            commit,
+            &mut [
+                &mut CommitOidProvider::new(&repo, true)?,
+                &mut CommitMessageProvider::new()?,
+            ],
+        )?;
+        println!(
+            "{} {}",
+            glyphs.bullet_point,
+            printable_styled_string(&glyphs, commit_text)?
         );
     }
+    if !prompt_confirmation(
+        &mut in_,
+        &mut stdout(),
+        "Hide these commits? [y/N] ",
+        assume_yes,
+    )? {
+        println!("Aborted. No commits were hidden.");
+        return Ok(1);
+    }
 
+    hide_commits(
+        &glyphs,
+        &repo,
+        &mut event_log_db,
+        &event_replayer,
+        now,
+        commits,
+    )?;
     Ok(0)
 }
 
@@ -160,12 +617,17 @@ pub fn hide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
 /// Args:
 /// * `out`: The output stream to write to.
 /// * `hashes`: A list of commit hashes to unhide. Revs will be resolved (you can
-/// provide an abbreviated commit hash or ref name).
+///   provide an abbreviated commit hash or ref name), or else matched against the
+///   summaries of the visible commits (see `resolve_commits_by_summary`).
+///   Commit ranges of the form `A..B` are expanded into the commits which are
+///   reachable from `B` but not from `A`.
 /// * `recursive: If `true`, will recursively unhide all children of the provided
 ///   commits as well.
+/// * `dry_run`: If `true`, only print the commits which would be unhidden, and
+///   don't record any events.
 ///
 /// Returns: exit code (0 denotes successful exit).
-pub fn unhide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
+pub fn unhide(hashes: Vec<String>, recursive: bool, dry_run: bool) -> anyhow::Result<isize> {
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
     let repo = get_repo()?;
@@ -174,13 +636,27 @@ pub fn unhide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
     let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
     let merge_base_db = MergeBaseDb::new(&conn)?;
 
-    let commits = resolve_commits(&repo, hashes)?;
+    let (commits, _skipped_commits) = resolve_commits_and_ranges(&repo, hashes, false)?;
     let commits = match commits {
         ResolveCommitsResult::Ok { commits } => commits,
         ResolveCommitsResult::CommitNotFound { commit: hash } => {
             println!("Commit not found: {}", hash);
             return Ok(1);
         }
+        ResolveCommitsResult::NotACommit {
+            commit,
+            object_type,
+        } => {
+            write_not_a_commit_message(&mut stdout(), &commit, object_type)?;
+            return Ok(1);
+        }
+        ResolveCommitsResult::AmbiguousSummary {
+            commit,
+            commit_oids,
+        } => {
+            write_ambiguous_summary_message(&mut stdout(), &glyphs, &repo, &commit, &commit_oids)?;
+            return Ok(1);
+        }
     };
     let commits = if recursive {
         recurse_on_commits(&repo, &merge_base_db, &event_replayer, commits, |node| {
@@ -190,6 +666,17 @@ pub fn unhide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
         commits
     };
 
+    if dry_run {
+        print_dry_run(
+            &glyphs,
+            &repo,
+            &event_replayer,
+            &commits,
+            CommitVisibility::Visible,
+        )?;
+        return Ok(0);
+    }
+
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "unhide")?;
     let events = commits
@@ -208,7 +695,7 @@ pub fn unhide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
             render_commit_metadata(
                 &commit,
                 &mut [
-                    &mut CommitOidProvider::new(true)?,
+                    &mut CommitOidProvider::new(&repo, true)?,
                     &mut CommitMessageProvider::new()?,
                 ],
             )?
@@ -224,7 +711,7 @@ pub fn unhide(hashes: Vec<String>, recursive: bool) -> anyhow::Result<isize> {
         }
 
         let commit_target_oid =
-            render_commit_metadata(&commit, &mut [&mut CommitOidProvider::new(false)?])?;
+            render_commit_metadata(&commit, &mut [&mut CommitOidProvider::new(&repo, false)?])?;
         println!(
             "To hide this commit, run: git hide {}",
             printable_styled_string(&glyphs, commit_target_oid)?
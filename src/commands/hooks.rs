@@ -7,9 +7,10 @@
 //! The hooks are installed by the `branchless init` command. This module
 //! contains the implementations for the hooks.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
-use std::io::{stdin, BufRead};
+use std::fs::OpenOptions;
+use std::io::{stdin, BufRead, Write};
 use std::time::SystemTime;
 
 use anyhow::Context;
@@ -17,17 +18,21 @@ use console::style;
 use fn_error_context::context;
 
 use crate::commands::gc::mark_commit_reachable;
-use crate::core::config::{get_restack_warn_abandoned, RESTACK_WARN_ABANDONED_CONFIG_KEY};
+use crate::core::config::{
+    get_hooks_event_sink, get_restack_warn_abandoned, RESTACK_WARN_ABANDONED_CONFIG_KEY,
+};
 use crate::core::eventlog::{
     should_ignore_ref_updates, Event, EventLogDb, EventReplayer, EventTransactionId,
 };
 use crate::core::formatting::Pluralize;
-use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::graph::{make_graph_from_repo, AncestryCache};
 use crate::core::mergebase::MergeBaseDb;
-use crate::core::rewrite::find_abandoned_children;
-use crate::util::{
-    get_branch_oid_to_names, get_db_conn, get_head_oid, get_main_branch_oid, get_repo,
+use crate::core::rewrite::{
+    delete_backup_after_rebase_on_disk, find_abandoned_children,
+    find_dropped_commits_after_rebase_on_disk, move_branches_after_rebase_on_disk,
+    preserve_dates_after_rebase_on_disk,
 };
+use crate::util::{get_branch_oid_to_names, get_db_conn, get_repo};
 
 /// Detect if an interactive rebase has started but not completed.
 ///
@@ -69,6 +74,38 @@ fn is_rebase_underway(repo: &git2::Repository) -> anyhow::Result<bool> {
     }
 }
 
+#[context("Appending events to event sink")]
+fn try_append_events_to_sink(repo: &git2::Repository, events: &[Event]) -> anyhow::Result<()> {
+    let path = match get_hooks_event_sink(repo)? {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let mut lines = String::new();
+    for event in events {
+        lines.push_str(&event.to_json().to_string());
+        lines.push('\n');
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Opening event sink: {:?}", path))?;
+    file.write_all(lines.as_bytes())
+        .with_context(|| format!("Writing to event sink: {:?}", path))?;
+    Ok(())
+}
+
+/// Append the events recorded by a hook to the file configured by
+/// `branchless.hooks.eventSink`, if any, one JSON object per line.
+///
+/// This is best-effort: the event log is the source of truth, so failing to
+/// write to the sink is logged rather than failing the hook.
+fn append_events_to_sink(repo: &git2::Repository, events: &[Event]) {
+    if let Err(err) = try_append_events_to_sink(repo, events) {
+        log::warn!("Could not append events to event sink: {:?}", err);
+    }
+}
+
 /// Handle Git's `post-rewrite` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -82,9 +119,8 @@ pub fn hook_post_rewrite(rewrite_type: &str) -> anyhow::Result<()> {
     let mut event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-rewrite")?;
 
-    let (old_commits, events) = {
-        let mut old_commits = Vec::new();
-        let mut events = Vec::new();
+    let mut rewritten_oids = {
+        let mut rewritten_oids = Vec::new();
         for line in stdin().lock().lines() {
             let line = line?;
             let line = line.trim();
@@ -99,20 +135,43 @@ pub fn hook_post_rewrite(rewrite_type: &str) -> anyhow::Result<()> {
                             format!("Could not convert {:?} to OID", new_commit_oid)
                         })?;
 
-                    old_commits.push(old_commit_oid);
-                    events.push(Event::RewriteEvent {
-                        timestamp,
-                        event_tx_id,
-                        old_commit_oid,
-                        new_commit_oid,
-                    })
+                    rewritten_oids.push((old_commit_oid, new_commit_oid));
                 }
                 _ => anyhow::bail!("Invalid rewrite line: {:?}", &line),
             }
         }
-        (old_commits, events)
+        rewritten_oids
     };
 
+    let mut recreated_oids = Vec::new();
+    if rewrite_type == "rebase" {
+        // Git sets the committer dates of the rewritten commits to the current
+        // time, so restore the original dates before recording the rewrites.
+        recreated_oids = preserve_dates_after_rebase_on_disk(&repo, event_tx_id, &rewritten_oids)?;
+        let recreated_oids_map: HashMap<git2::Oid, git2::Oid> =
+            recreated_oids.iter().copied().collect();
+        for (_old_commit_oid, new_commit_oid) in rewritten_oids.iter_mut() {
+            if let Some(recreated_oid) = recreated_oids_map.get(new_commit_oid) {
+                *new_commit_oid = *recreated_oid;
+            }
+        }
+
+        // Git doesn't report the commits which it dropped for becoming empty,
+        // so record them as rewritten as well.
+        let dropped_oids = find_dropped_commits_after_rebase_on_disk(&repo, &rewritten_oids)?;
+        rewritten_oids.extend(dropped_oids);
+    }
+
+    let mut events: Vec<Event> = rewritten_oids
+        .iter()
+        .map(|(old_commit_oid, new_commit_oid)| Event::RewriteEvent {
+            timestamp,
+            event_tx_id,
+            old_commit_oid: *old_commit_oid,
+            new_commit_oid: *new_commit_oid,
+        })
+        .collect();
+
     let is_spurious_event = rewrite_type == "amend" && is_rebase_underway(&repo)?;
     if !is_spurious_event {
         let message_rewritten_commits = Pluralize {
@@ -124,8 +183,29 @@ pub fn hook_post_rewrite(rewrite_type: &str) -> anyhow::Result<()> {
         println!("branchless: processing {}", message_rewritten_commits);
     }
 
+    // The commits created by Git were recorded by the `post-commit` hook, so
+    // hide them in favor of the recreated commits.
+    events.extend(
+        recreated_oids
+            .into_iter()
+            .map(|(old_commit_oid, new_commit_oid)| Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid,
+                new_commit_oid,
+            }),
+    );
+
+    append_events_to_sink(&repo, &events);
     event_log_db.add_events(events)?;
 
+    if rewrite_type == "rebase" {
+        // Do this before checking for abandoned commits, so that we don't warn
+        // about branches which we're about to move anyways.
+        move_branches_after_rebase_on_disk(&repo, event_tx_id, &rewritten_oids)?;
+        delete_backup_after_rebase_on_disk(&repo, event_tx_id)?;
+    }
+
     let should_check_abandoned_commits = get_restack_warn_abandoned(&repo)?;
     if is_spurious_event || !should_check_abandoned_commits {
         return Ok(());
@@ -133,24 +213,20 @@ pub fn hook_post_rewrite(rewrite_type: &str) -> anyhow::Result<()> {
 
     let merge_base_db = MergeBaseDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
-    let head_oid = get_head_oid(&repo)?;
-    let main_branch_oid = get_main_branch_oid(&repo)?;
     let branch_oid_to_names = get_branch_oid_to_names(&repo)?;
-    let graph = make_graph(
+    let graph = make_graph_from_repo(
         &repo,
         &merge_base_db,
+        &mut AncestryCache::default(),
         &event_replayer,
         event_replayer.make_default_cursor(),
-        &HeadOid(head_oid),
-        &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().copied().collect()),
         false,
     )?;
 
     let (all_abandoned_children, all_abandoned_branches) = {
         let mut all_abandoned_children: HashSet<git2::Oid> = HashSet::new();
         let mut all_abandoned_branches: HashSet<&str> = HashSet::new();
-        for old_commit_oid in old_commits {
+        for (old_commit_oid, _new_commit_oid) in rewritten_oids {
             let abandoned_result = find_abandoned_children(
                 &graph,
                 &event_replayer,
@@ -257,14 +333,16 @@ pub fn hook_post_checkout(
     let conn = get_db_conn(&repo)?;
     let mut event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-checkout")?;
-    event_log_db.add_events(vec![Event::RefUpdateEvent {
+    let events = vec![Event::RefUpdateEvent {
         timestamp: timestamp.as_secs_f64(),
         event_tx_id,
         old_ref: Some(String::from(previous_head_ref)),
         new_ref: Some(String::from(current_head_ref)),
         ref_name: String::from("HEAD"),
         message: None,
-    }])?;
+    }];
+    append_events_to_sink(&repo, &events);
+    event_log_db.add_events(events)?;
     Ok(())
 }
 
@@ -289,11 +367,59 @@ pub fn hook_post_commit() -> anyhow::Result<()> {
 
     let timestamp = commit.time().seconds() as f64;
     let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-commit")?;
-    event_log_db.add_events(vec![Event::CommitEvent {
+    let events = vec![Event::CommitEvent {
         timestamp,
         event_tx_id,
         commit_oid: commit.id(),
-    }])?;
+    }];
+    append_events_to_sink(&repo, &events);
+    event_log_db.add_events(events)?;
+
+    Ok(())
+}
+
+/// Handle Git's `post-merge` hook.
+///
+/// Git doesn't call the `post-commit` hook for merge commits which are created
+/// without conflicts, so record them here instead. (Merges with conflicts are
+/// committed by the user afterwards, which does call the `post-commit` hook.)
+///
+/// See the man-page for `githooks(5)`.
+pub fn hook_post_merge(is_squash_merge: isize) -> anyhow::Result<()> {
+    // A squash merge doesn't create a commit; the user commits the result
+    // afterwards.
+    if is_squash_merge != 0 {
+        return Ok(());
+    }
+
+    let now = SystemTime::now();
+    let repo = get_repo()?;
+    let commit = repo
+        .head()
+        .with_context(|| "Getting repo HEAD")?
+        .peel_to_commit()
+        .with_context(|| "Getting HEAD commit")?;
+
+    // Fast-forward merges don't create a merge commit.
+    if commit.parent_count() < 2 {
+        return Ok(());
+    }
+
+    println!("branchless: processing merge");
+    let conn = get_db_conn(&repo)?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    mark_commit_reachable(&repo, commit.id())
+        .with_context(|| "Marking commit as reachable for GC purposes")?;
+
+    let timestamp = commit.time().seconds() as f64;
+    let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-merge")?;
+    let events = vec![Event::CommitEvent {
+        timestamp,
+        event_tx_id,
+        commit_oid: commit.id(),
+    }];
+    append_events_to_sink(&repo, &events);
+    event_log_db.add_events(events)?;
 
     Ok(())
 }
@@ -375,6 +501,7 @@ pub fn hook_reference_transaction(transaction_state: &str) -> anyhow::Result<()>
         "branchless: processing {}",
         num_reference_updates.to_string()
     );
+    append_events_to_sink(&repo, &events);
     event_log_db.add_events(events)?;
 
     Ok(())
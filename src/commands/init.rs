@@ -1,19 +1,26 @@
 //! Install any hooks, aliases, etc. to set up `git-branchless` in this repo.
 
 use std::fmt::Display;
-use std::io::{stdin, stdout, BufRead, BufReader, Write};
-use std::path::PathBuf;
+use std::io::{stdin, stdout, BufRead, BufReader};
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use console::style;
 use fn_error_context::context;
 use log::warn;
 
-use crate::core::config::get_core_hooks_path;
-use crate::util::{get_repo, run_git_silent, wrap_git_error, GitExecutable, GitVersion};
+use crate::core::config::{
+    get_core_hooks_path, get_init_aliases, INIT_ALIASES_CONFIG_KEY, SMARTLOG_LEGEND_HINT_CONFIG_KEY,
+};
+use crate::core::formatting::Pluralize;
+use crate::util::{
+    detect_main_branch_names, get_repo, get_repo_common_dir, prompt_input, run_git_silent,
+    wrap_git_error, GitExecutable, GitVersion, EXIT_CODE_BAD_ARGUMENTS,
+};
 
+/// The location of a hook installed by `git-branchless`.
 #[derive(Debug)]
-enum Hook {
+pub(crate) enum Hook {
     /// Regular Git hook.
     RegularHook { path: PathBuf },
 
@@ -21,9 +28,22 @@ enum Hook {
     MultiHook { path: PathBuf },
 }
 
+impl Hook {
+    /// The path to the hook file on disk.
+    pub(crate) fn path(&self) -> &Path {
+        match self {
+            Hook::RegularHook { path } | Hook::MultiHook { path } => path,
+        }
+    }
+}
+
+/// Determine where the hook of the given type should be installed.
 #[context("Determining hook path")]
-fn determine_hook_path(repo: &git2::Repository, hook_type: &str) -> anyhow::Result<Hook> {
-    let multi_hooks_path = repo.path().join("hooks_multi");
+pub(crate) fn determine_hook_path(
+    repo: &git2::Repository,
+    hook_type: &str,
+) -> anyhow::Result<Hook> {
+    let multi_hooks_path = get_repo_common_dir(repo)?.join("hooks_multi");
     let hook = if multi_hooks_path.exists() {
         let path = multi_hooks_path
             .join(format!("{}.d", hook_type))
@@ -41,18 +61,31 @@ const SHEBANG: &str = "#!/bin/sh";
 const UPDATE_MARKER_START: &str = "## START BRANCHLESS CONFIG";
 const UPDATE_MARKER_END: &str = "## END BRANCHLESS CONFIG";
 
+/// The maximum number of lines of a pre-existing hook to show when warning
+/// about it.
+const MAX_FOREIGN_HOOK_LINES_TO_SHOW: usize = 5;
+
+/// Whether `line` is the given marker line. Leading whitespace and a trailing
+/// carriage return are ignored, since the hook may have been indented or
+/// checked out with CRLF line endings.
+fn is_marker_line(line: &str, marker: &str) -> bool {
+    line.trim_start().trim_end_matches('\r') == marker
+}
+
 fn update_between_lines(lines: &str, updated_lines: &str) -> String {
     let mut new_lines = String::new();
     let mut is_ignoring_lines = false;
+    let mut found_marker = false;
     for line in lines.lines() {
-        if line == UPDATE_MARKER_START {
+        if is_marker_line(line, UPDATE_MARKER_START) {
             is_ignoring_lines = true;
+            found_marker = true;
             new_lines.push_str(UPDATE_MARKER_START);
             new_lines.push('\n');
             new_lines.push_str(updated_lines);
             new_lines.push_str(UPDATE_MARKER_END);
             new_lines.push('\n');
-        } else if line == UPDATE_MARKER_END {
+        } else if is_marker_line(line, UPDATE_MARKER_END) {
             is_ignoring_lines = false;
         } else if !is_ignoring_lines {
             new_lines.push_str(line);
@@ -62,14 +95,66 @@ fn update_between_lines(lines: &str, updated_lines: &str) -> String {
     if is_ignoring_lines {
         warn!("Unterminated branchless config comment in hook");
     }
+    if !found_marker {
+        // The hook was written by someone else, so add our lines after theirs.
+        new_lines.push_str(UPDATE_MARKER_START);
+        new_lines.push('\n');
+        new_lines.push_str(updated_lines);
+        new_lines.push_str(UPDATE_MARKER_END);
+        new_lines.push('\n');
+    }
     new_lines
 }
 
+/// Get the lines of a hook which weren't written by `git-branchless`, i.e.
+/// which are outside of the branchless config markers. The shebang line and
+/// blank lines are not included.
+fn get_foreign_hook_lines(lines: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut is_ignoring_lines = false;
+    for (i, line) in lines.lines().enumerate() {
+        if is_marker_line(line, UPDATE_MARKER_START) {
+            is_ignoring_lines = true;
+        } else if is_marker_line(line, UPDATE_MARKER_END) {
+            is_ignoring_lines = false;
+        } else if is_ignoring_lines || line.trim().is_empty() {
+            continue;
+        } else if i == 0 && line.starts_with("#!") {
+            // Shebang line.
+            continue;
+        } else {
+            result.push(line);
+        }
+    }
+    result
+}
+
+fn warn_foreign_hook_lines(hook_path: &Path, foreign_lines: &[&str]) {
+    println!(
+        "{}: the existing hook at {:?} contains other commands, which will be preserved:",
+        style("Warning").yellow().bold(),
+        hook_path
+    );
+    for line in foreign_lines.iter().take(MAX_FOREIGN_HOOK_LINES_TO_SHOW) {
+        println!("    {}", line);
+    }
+    if foreign_lines.len() > MAX_FOREIGN_HOOK_LINES_TO_SHOW {
+        println!(
+            "    ({} more lines)",
+            foreign_lines.len() - MAX_FOREIGN_HOOK_LINES_TO_SHOW
+        );
+    }
+}
+
 #[context("Updating hook contents: {:?}", hook)]
 fn update_hook_contents(hook: &Hook, hook_contents: &str) -> anyhow::Result<()> {
     let (hook_path, hook_contents) = match hook {
         Hook::RegularHook { path } => match std::fs::read_to_string(path) {
             Ok(lines) => {
+                let foreign_lines = get_foreign_hook_lines(&lines);
+                if !foreign_lines.is_empty() {
+                    warn_foreign_hook_lines(path, &foreign_lines);
+                }
                 let lines = update_between_lines(&lines, hook_contents);
                 (path, lines)
             }
@@ -121,38 +206,40 @@ fn install_hook(repo: &git2::Repository, hook_type: &str, hook_script: &str) ->
     Ok(())
 }
 
-#[context("Installing all hooks")]
-fn install_hooks(repo: &git2::Repository) -> anyhow::Result<()> {
-    install_hook(
-        repo,
+/// The hooks installed by `git-branchless`, as pairs of the hook type and the
+/// script to run for it.
+pub(crate) const ALL_HOOKS: &[(&str, &str)] = &[
+    (
         "post-commit",
         r#"
 git branchless hook-post-commit "$@"
 "#,
-    )?;
-    install_hook(
-        repo,
+    ),
+    (
         "post-rewrite",
         r#"
 git branchless hook-post-rewrite "$@"
 "#,
-    )?;
-    install_hook(
-        repo,
+    ),
+    (
         "post-checkout",
         r#"
 git branchless hook-post-checkout "$@"
 "#,
-    )?;
-    install_hook(
-        repo,
+    ),
+    (
+        "post-merge",
+        r#"
+git branchless hook-post-merge "$@"
+"#,
+    ),
+    (
         "pre-auto-gc",
         r#"
 git branchless hook-pre-auto-gc "$@"
 "#,
-    )?;
-    install_hook(
-        repo,
+    ),
+    (
         "reference-transaction",
         r#"
 # Avoid canceling the reference transaction in the case that `branchless` fails
@@ -161,73 +248,231 @@ git branchless hook-reference-transaction "$@" || (
     echo 'branchless: Failed to process reference transaction!'
     echo 'branchless: Some events (e.g. branch updates) may have been lost.'
     echo 'branchless: This is a bug. Please report it.'
+    echo 'branchless: Run `git branchless repair` to recover the lost events.'
 )
 "#,
-    )?;
+    ),
+];
+
+#[context("Installing all hooks")]
+fn install_hooks(repo: &git2::Repository) -> anyhow::Result<()> {
+    for (hook_type, hook_script) in ALL_HOOKS {
+        install_hook(repo, hook_type, hook_script)?;
+    }
     Ok(())
 }
 
-#[context("Installing alias: git {:?} -> git branchless {:?}", from, to)]
-fn install_alias(config: &mut git2::Config, from: &str, to: &str) -> anyhow::Result<()> {
-    println!(
-        "Installing alias (non-global): git {} -> git branchless {}",
-        from, to
-    );
-    config
-        .set_str(
-            format!("alias.{}", from).as_str(),
-            format!("branchless {}", to).as_str(),
-        )
-        .map_err(wrap_git_error)?;
-    Ok(())
+/// The aliases installed by default, as pairs of the alias name and the
+/// `git branchless` subcommand which it runs.
+const DEFAULT_ALIASES: &[(&str, &str)] = &[
+    ("smartlog", "smartlog"),
+    ("sl", "smartlog"),
+    ("hide", "hide"),
+    ("unhide", "unhide"),
+    ("prev", "prev"),
+    ("next", "next"),
+    ("restack", "restack"),
+    ("undo", "undo"),
+    ("move", "move"),
+];
+
+/// A customization to the default aliases, as provided via
+/// `branchless.init.aliases` or `--alias`.
+#[derive(Debug, PartialEq, Eq)]
+struct AliasOverride {
+    /// The name of the alias, i.e. `git <name>`.
+    name: String,
+
+    /// The `git branchless` subcommand to run, or `None` if the alias
+    /// shouldn't be installed.
+    command: Option<String>,
+
+    /// Where the customization came from, for display to the user.
+    source: &'static str,
 }
 
-fn detect_main_branch_name(repo: &git2::Repository) -> Option<String> {
-    [
-        "master",
-        "main",
-        "mainline",
-        "devel",
-        "develop",
-        "development",
-        "trunk",
-    ]
-    .iter()
-    .find_map(|branch_name| {
-        if repo
-            .find_branch(branch_name, git2::BranchType::Local)
-            .is_ok()
-        {
-            Some(branch_name.to_string())
-        } else {
-            None
-        }
+/// Parse an alias customization of the form `name=command`. An empty command
+/// means that the alias shouldn't be installed.
+///
+/// Returns: `None` if the customization isn't of the expected form.
+fn parse_alias_override(spec: &str, source: &'static str) -> Option<AliasOverride> {
+    let (name, command) = match spec.find('=') {
+        Some(index) => (spec[..index].trim(), spec[index + 1..].trim()),
+        None => return None,
+    };
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    let command = if command.is_empty() {
+        None
+    } else {
+        Some(command.to_string())
+    };
+    Some(AliasOverride {
+        name: name.to_string(),
+        command,
+        source,
     })
 }
 
+/// An alias which `init` was configured to install or not.
+#[derive(Debug, PartialEq, Eq)]
+enum AliasInstallation {
+    /// Install `git <name>` as an alias for `git branchless <command>`.
+    Install { name: String, command: String },
+
+    /// Don't install `git <name>`, since it was disabled by `source`.
+    Disabled { name: String, source: &'static str },
+}
+
+/// Apply the given customizations, in order, to the default aliases. A
+/// customization for an alias name which isn't installed by default adds a new
+/// alias.
+fn resolve_aliases(overrides: Vec<AliasOverride>) -> Vec<AliasInstallation> {
+    let mut result: Vec<AliasInstallation> = DEFAULT_ALIASES
+        .iter()
+        .map(|(name, command)| AliasInstallation::Install {
+            name: name.to_string(),
+            command: command.to_string(),
+        })
+        .collect();
+    for AliasOverride {
+        name,
+        command,
+        source,
+    } in overrides
+    {
+        let installation = match command {
+            Some(command) => AliasInstallation::Install {
+                name: name.clone(),
+                command,
+            },
+            None => AliasInstallation::Disabled {
+                name: name.clone(),
+                source,
+            },
+        };
+        let existing = result.iter_mut().find(|installation| match installation {
+            AliasInstallation::Install {
+                name: existing_name,
+                ..
+            }
+            | AliasInstallation::Disabled {
+                name: existing_name,
+                ..
+            } => *existing_name == name,
+        });
+        match existing {
+            Some(existing) => *existing = installation,
+            None => result.push(installation),
+        }
+    }
+    result
+}
+
+fn detect_main_branch_name(repo: &git2::Repository) -> Option<String> {
+    detect_main_branch_names(repo).into_iter().next()
+}
+
+/// Determine whether the given path is inside the repository, i.e. inside its
+/// `.git` directory (or the common directory shared between its worktrees) or
+/// its working copy.
+fn is_path_in_repo(repo: &git2::Repository, path: &Path) -> anyhow::Result<bool> {
+    let canonicalize = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let path = canonicalize(path);
+    let common_dir = get_repo_common_dir(repo)?;
+    let result = std::iter::once(repo.path())
+        .chain(std::iter::once(common_dir.as_path()))
+        .chain(repo.workdir())
+        .any(|repo_path| path.starts_with(canonicalize(repo_path)));
+    Ok(result)
+}
+
+/// The earliest Git version which supports the `reference-transaction` hook,
+/// which `git undo` relies on to undo branch updates.
+pub(crate) const MIN_UNDO_GIT_VERSION: GitVersion = GitVersion(2, 29, 0);
+
+/// Get the version of the `git` executable.
+///
+/// Returns: The version string as reported by `git version`, and its parsed
+/// version.
+#[context("Determining Git version")]
+pub(crate) fn get_git_version(
+    repo: &git2::Repository,
+    git_executable: &GitExecutable,
+) -> anyhow::Result<(String, GitVersion)> {
+    let version_str = run_git_silent(repo, git_executable, None, &["version"])?;
+    let version_str = version_str.trim().to_string();
+    let version: GitVersion = version_str
+        .parse()
+        .with_context(|| format!("Parsing Git version string: {}", version_str))?;
+    Ok((version_str, version))
+}
+
 #[context("Installing all aliases")]
 fn install_aliases(
     repo: &mut git2::Repository,
     config: &mut git2::Config,
     git_executable: &GitExecutable,
+    aliases: &[AliasInstallation],
+    force: bool,
 ) -> anyhow::Result<()> {
-    install_alias(config, "smartlog", "smartlog")?;
-    install_alias(config, "sl", "smartlog")?;
-    install_alias(config, "hide", "hide")?;
-    install_alias(config, "unhide", "unhide")?;
-    install_alias(config, "prev", "prev")?;
-    install_alias(config, "next", "next")?;
-    install_alias(config, "restack", "restack")?;
-    install_alias(config, "undo", "undo")?;
-    install_alias(config, "move", "move")?;
-
-    let version_str = run_git_silent(repo, git_executable, None, &["version"])
-        .with_context(|| "Determining Git version")?;
-    let version_str = version_str.trim();
-    let version: GitVersion = version_str
-        .parse()
-        .with_context(|| format!("Parsing Git version string: {}", version_str))?;
-    if version < GitVersion(2, 29, 0) {
+    let mut num_installed = 0;
+    let mut num_skipped = 0;
+    for alias in aliases {
+        let (name, command) = match alias {
+            AliasInstallation::Install { name, command } => (name, command),
+            AliasInstallation::Disabled { name, source } => {
+                println!("Skipping alias: git {} (disabled by {})", name, source);
+                num_skipped += 1;
+                continue;
+            }
+        };
+
+        let alias_key = format!("alias.{}", name);
+        let alias_value = format!("branchless {}", command);
+        match config.get_string(&alias_key) {
+            Ok(existing_value) if !force && existing_value != alias_value => {
+                println!(
+                    "Skipping alias: git {} (already defined as: {}; use --force to overwrite)",
+                    name, existing_value
+                );
+                num_skipped += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        println!(
+            "Installing alias (non-global): git {} -> git branchless {}",
+            name, command
+        );
+        config
+            .set_str(&alias_key, &alias_value)
+            .map_err(wrap_git_error)
+            .with_context(|| format!("Installing alias: {}", alias_key))?;
+        num_installed += 1;
+    }
+    if num_skipped > 0 {
+        println!(
+            "Installed {} and skipped {}.",
+            Pluralize {
+                amount: num_installed,
+                singular: "alias",
+                plural: "aliases",
+            }
+            .to_string(),
+            Pluralize {
+                amount: num_skipped,
+                singular: "alias",
+                plural: "aliases",
+            }
+            .to_string()
+        );
+    }
+
+    let (version_str, version) = get_git_version(repo, git_executable)?;
+    if version < MIN_UNDO_GIT_VERSION {
         print!(
             "\
 {warning_str}: the branchless workflow's `git undo` command requires Git
@@ -294,11 +539,10 @@ fn set_configs(
             println!("Your main branch name could not be auto-detected.");
             println!("Examples of a main branch: master, main, trunk, etc.");
             println!("See https://github.com/arxanas/git-branchless/wiki/Concepts#main-branch");
-            print!("Enter the name of your main branch: ");
-            stdout().flush()?;
-            let mut input = String::new();
-            r#in.read_line(&mut input)?;
-            match input.trim() {
+            let input =
+                prompt_input(r#in, &mut stdout(), "Enter the name of your main branch: ")
+                    .context("Set branchless.core.mainBranch to initialize non-interactively")?;
+            match input.as_str() {
                 "" => anyhow::bail!("No main branch name provided"),
                 main_branch_name => main_branch_name.to_string(),
             }
@@ -310,28 +554,85 @@ fn set_configs(
         ConfigValue::String(main_branch_name),
     )?;
     set_config(config, "advice.detachedHead", ConfigValue::Bool(false))?;
+    // Not reported to the user, since it's not a setting they're expected to
+    // change: it just explains the smartlog the first time they see it.
+    config.set_bool(SMARTLOG_LEGEND_HINT_CONFIG_KEY, true)?;
     Ok(())
 }
 
 /// Initialize `git-branchless` in the current repo.
 ///
+/// If `core.hooksPath` points to a directory outside of the repository, it
+/// may be shared with other repositories or managed by another tool, so the
+/// repository is only initialized if `force` is set.
+///
+/// The default aliases can be renamed or disabled with
+/// `branchless.init.aliases`, and then with `aliases`. Aliases which are
+/// already defined as something else are skipped unless `force` is set.
+///
 /// Args:
-/// * `out`: The output stream to write to.
 /// * `git_executable`: The path to the `git` executable on disk.
+/// * `force`: Whether to install hooks outside of the repository, and
+///   overwrite existing aliases.
+/// * `aliases`: Customizations to the installed aliases, each of the form
+///   `name=command` (see `get_init_aliases`).
+///
+/// Returns: exit code (0 denotes successful exit).
 #[context("Initializing git-branchless for repo")]
-pub fn init(git_executable: &GitExecutable) -> anyhow::Result<()> {
+pub fn init(
+    git_executable: &GitExecutable,
+    force: bool,
+    aliases: Vec<String>,
+) -> anyhow::Result<isize> {
     let mut in_ = BufReader::new(stdin());
     let mut repo = get_repo()?;
+
+    let mut alias_overrides = Vec::new();
+    let alias_specs = get_init_aliases(&repo)?
+        .into_iter()
+        .map(|spec| (spec, INIT_ALIASES_CONFIG_KEY))
+        .chain(aliases.into_iter().map(|spec| (spec, "--alias")));
+    for (spec, source) in alias_specs {
+        match parse_alias_override(&spec, source) {
+            Some(alias_override) => alias_overrides.push(alias_override),
+            None => {
+                println!(
+                    "Invalid alias from {}: {:?} (expected `name=command`)",
+                    source, spec
+                );
+                return Ok(EXIT_CODE_BAD_ARGUMENTS);
+            }
+        }
+    }
+    let aliases = resolve_aliases(alias_overrides);
+
+    let hooks_path = get_core_hooks_path(&repo)?;
+    if !force && !is_path_in_repo(&repo, &hooks_path)? {
+        println!(
+            "\
+The hooks directory configured with `core.hooksPath` is outside of this
+repository: {hooks_path:?}
+
+It may be shared with other repositories or managed by another tool. To install
+the branchless hooks there anyway, run: git branchless init --force",
+            hooks_path = hooks_path,
+        );
+        return Ok(1);
+    }
+
     let mut config = repo.config().with_context(|| "Getting repo config")?;
     set_configs(&mut in_, &repo, &mut config)?;
     install_hooks(&repo)?;
-    install_aliases(&mut repo, &mut config, git_executable)?;
-    Ok(())
+    install_aliases(&mut repo, &mut config, git_executable, &aliases, force)?;
+    Ok(0)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{update_between_lines, UPDATE_MARKER_END, UPDATE_MARKER_START};
+    use super::{
+        get_foreign_hook_lines, parse_alias_override, resolve_aliases, update_between_lines,
+        AliasInstallation, AliasOverride, UPDATE_MARKER_END, UPDATE_MARKER_START,
+    };
 
     #[test]
     fn test_update_between_lines() {
@@ -368,4 +669,130 @@ contents 3
             expected
         )
     }
+
+    #[test]
+    fn test_update_between_lines_without_markers() {
+        assert_eq!(
+            update_between_lines("#!/bin/sh\necho hello\n", "contents\n"),
+            format!(
+                "#!/bin/sh\necho hello\n{}\ncontents\n{}\n",
+                UPDATE_MARKER_START, UPDATE_MARKER_END
+            )
+        );
+    }
+
+    #[test]
+    fn test_update_between_lines_crlf() {
+        let input = format!(
+            "#!/bin/sh\r\necho hello\r\n  {}\r\ncontents 1\r\n{}\r\r\n",
+            UPDATE_MARKER_START, UPDATE_MARKER_END
+        );
+        assert_eq!(
+            update_between_lines(&input, "contents 2\n"),
+            format!(
+                "#!/bin/sh\necho hello\n{}\ncontents 2\n{}\n",
+                UPDATE_MARKER_START, UPDATE_MARKER_END
+            )
+        );
+        assert_eq!(get_foreign_hook_lines(&input), vec!["echo hello"]);
+    }
+
+    #[test]
+    fn test_get_foreign_hook_lines() {
+        let input = format!(
+            "\
+#!/bin/sh
+echo hello
+
+{}
+git branchless hook-post-commit
+{}
+echo goodbye
+",
+            UPDATE_MARKER_START, UPDATE_MARKER_END
+        );
+        assert_eq!(
+            get_foreign_hook_lines(&input),
+            vec!["echo hello", "echo goodbye"]
+        );
+
+        let input = format!(
+            "#!/bin/sh\n{}\ngit branchless hook-post-commit\n{}\n",
+            UPDATE_MARKER_START, UPDATE_MARKER_END
+        );
+        assert!(get_foreign_hook_lines(&input).is_empty());
+    }
+
+    #[test]
+    fn test_parse_alias_override() {
+        assert_eq!(
+            parse_alias_override("bsl=smartlog", "--alias"),
+            Some(AliasOverride {
+                name: "bsl".to_string(),
+                command: Some("smartlog".to_string()),
+                source: "--alias",
+            })
+        );
+        assert_eq!(
+            parse_alias_override("sl=", "--alias"),
+            Some(AliasOverride {
+                name: "sl".to_string(),
+                command: None,
+                source: "--alias",
+            })
+        );
+        assert_eq!(parse_alias_override("sl", "--alias"), None);
+        assert_eq!(parse_alias_override("=smartlog", "--alias"), None);
+        assert_eq!(parse_alias_override("s l=smartlog", "--alias"), None);
+    }
+
+    #[test]
+    fn test_resolve_aliases() {
+        let overrides = vec![
+            AliasOverride {
+                name: "sl".to_string(),
+                command: None,
+                source: "config",
+            },
+            AliasOverride {
+                name: "move".to_string(),
+                command: Some("smartlog".to_string()),
+                source: "config",
+            },
+            AliasOverride {
+                name: "bsl".to_string(),
+                command: Some("smartlog".to_string()),
+                source: "--alias",
+            },
+            AliasOverride {
+                name: "move".to_string(),
+                command: None,
+                source: "--alias",
+            },
+        ];
+        let aliases = resolve_aliases(overrides);
+        let install = |name: &str, command: &str| AliasInstallation::Install {
+            name: name.to_string(),
+            command: command.to_string(),
+        };
+        let disabled = |name: &str, source: &'static str| AliasInstallation::Disabled {
+            name: name.to_string(),
+            source,
+        };
+        assert_eq!(
+            aliases,
+            vec![
+                install("smartlog", "smartlog"),
+                disabled("sl", "config"),
+                install("hide", "hide"),
+                install("unhide", "unhide"),
+                install("prev", "prev"),
+                install("next", "next"),
+                install("restack", "restack"),
+                install("undo", "undo"),
+                disabled("move", "--alias"),
+                install("bsl", "smartlog"),
+            ]
+        );
+    }
 }
@@ -2,118 +2,982 @@
 //!
 //! Under the hood, this makes use of Git's advanced rebase functionality, which
 //! is also used to preserve merge commits using the `--rebase-merges` option.
+//!
+//! With `--interactive`, the planned rebase can be edited in a text-based user
+//! interface before it's executed, which makes `git move` a replacement for
+//! `git rebase --interactive` which understands the commit graph.
 
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::time::SystemTime;
 
-use crate::core::eventlog::{EventLogDb, EventReplayer};
-use crate::core::formatting::Glyphs;
-use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use cursive::theme::Effect;
+use cursive::utils::markup::StyledString;
+use cursive::views::{Dialog, LinearLayout, ScrollView, TextView};
+use cursive::{Cursive, CursiveRunnable, CursiveRunner};
+
+use crate::core::error::BranchlessError;
+use crate::core::eventlog::{Event, EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, set_effect, Glyphs, StyledStringBuilder};
+use crate::core::graph::{
+    get_stack_commits, make_graph_from_repo_with_head, resolve_current_stack, AncestryCache,
+    CommitGraph, HeadOid, MainBranchOid,
+};
 use crate::core::mergebase::MergeBaseDb;
-use crate::core::rewrite::{execute_rebase_plan, make_rebase_plan};
+use crate::core::patchid::PatchIdDb;
+use crate::core::rewrite::{
+    execute_rebase_plan, find_rebase_conflicts, friendly_describe_commit, make_rebase_plan,
+    run_exec_commands, ExecOptions, PickAction, RebasePlan,
+};
+use crate::core::tui::{get_key_bindings, with_siv, KeyBindings, SingletonView};
+use crate::declare_views;
 use crate::util::get_main_branch_oid;
 use crate::util::{
-    get_branch_oid_to_names, get_db_conn, get_head_oid, get_repo, resolve_commits, GitExecutable,
-    ResolveCommitsResult,
+    get_db_conn, get_head_oid, get_repo, resolve_commits_by_summary, run_git, wrap_git_error,
+    write_ambiguous_summary_message, write_not_a_commit_message, GitExecutable,
+    ResolveCommitsResult, EXIT_CODE_BAD_ARGUMENTS, EXIT_CODE_CONFLICT,
 };
 
-fn resolve_base_commit(graph: &CommitGraph, oid: git2::Oid) -> git2::Oid {
-    let node = &graph[&oid];
-    if node.is_main {
-        oid
-    } else {
-        match node.parent {
-            Some(parent_oid) => {
-                if graph[&parent_oid].is_main {
-                    oid
-                } else {
-                    resolve_base_commit(graph, parent_oid)
-                }
+/// The value of `--dest` which refers to where the commits being moved were
+/// before they were last moved, as with `git checkout -`.
+pub const PREVIOUS_DEST: &str = "-";
+
+/// Find where the given commit was before it was last moved with `git move`.
+///
+/// When `git move` moves a subtree, it records the parent of the root of the
+/// subtree in a `MoveEvent`, keyed by the root commit as it was before the
+/// move. If the commit has been rewritten since then, such as by being moved
+/// or amended, then its rewrites are followed back to the most recent version
+/// of it which was moved.
+///
+/// Returns: The parent of the commit before it was last moved, or `None` if it
+/// hasn't been moved.
+fn find_previous_dest(
+    event_log_db: &EventLogDb,
+    commit_oid: git2::Oid,
+) -> anyhow::Result<Option<git2::Oid>> {
+    // Map each rewritten commit to the commit that it was most recently
+    // rewritten from, and each moved commit to its parent before it was most
+    // recently moved.
+    let mut rewrite_sources: HashMap<git2::Oid, git2::Oid> = HashMap::new();
+    let mut old_parent_oids: HashMap<git2::Oid, git2::Oid> = HashMap::new();
+    for event in event_log_db.get_events()? {
+        match event {
+            Event::RewriteEvent {
+                old_commit_oid,
+                new_commit_oid,
+                ..
+            } => {
+                rewrite_sources.insert(new_commit_oid, old_commit_oid);
+            }
+            Event::MoveEvent {
+                commit_oid,
+                old_parent_oid,
+                ..
+            } => {
+                old_parent_oids.insert(commit_oid, old_parent_oid);
             }
-            None => oid,
+            _ => {}
         }
     }
+
+    let mut current_oid = commit_oid;
+    let mut seen_oids = HashSet::new();
+    while seen_oids.insert(current_oid) {
+        current_oid = match rewrite_sources.get(&current_oid) {
+            Some(old_commit_oid) => *old_commit_oid,
+            None => return Ok(None),
+        };
+        if let Some(old_parent_oid) = old_parent_oids.get(&current_oid) {
+            return Ok(Some(*old_parent_oid));
+        }
+    }
+    Ok(None)
 }
 
-/// Move a subtree from one place to another.
-pub fn r#move(
-    git_executable: &GitExecutable,
-    source: Option<String>,
-    dest: Option<String>,
-    base: Option<String>,
-    force_on_disk: bool,
-) -> anyhow::Result<isize> {
-    let repo = get_repo()?;
-    let head_oid = get_head_oid(&repo)?;
-    let (source, should_resolve_base_commit) = match (source, base) {
+/// Find the commit at the base of the stack containing `oid`, i.e. the first
+/// commit in the stack after the main branch.
+///
+/// Merge commits may have parents which aren't their parent in the commit
+/// graph. A commit is only considered to be the base if none of its parents
+/// are in a stack. Otherwise, the search continues from the first of its
+/// parents (in the order recorded in the commit) which is in a stack.
+///
+/// The results for `oid` and every commit visited on the way to the base are
+/// recorded in `ancestry_cache`, so that resolving the base of another commit
+/// in the same stack doesn't walk the stack again.
+pub fn resolve_base_commit(
+    graph: &CommitGraph,
+    ancestry_cache: &mut AncestryCache,
+    oid: git2::Oid,
+) -> git2::Oid {
+    let mut visited_oids = Vec::new();
+    let mut current_oid = oid;
+    let base_oid = loop {
+        if let Some(base_oid) = ancestry_cache.get_base_oid(&current_oid) {
+            break base_oid;
+        }
+        visited_oids.push(current_oid);
+
+        let node = &graph[&current_oid];
+        if node.is_main {
+            break current_oid;
+        }
+
+        let stack_parent_oid = node
+            .commit
+            .parent_ids()
+            .chain(node.parent)
+            .find(|parent_oid| match graph.get(parent_oid) {
+                Some(parent_node) => !parent_node.is_main,
+                None => false,
+            });
+        match stack_parent_oid {
+            Some(parent_oid) => current_oid = parent_oid,
+            None => break current_oid,
+        }
+    };
+
+    for visited_oid in visited_oids {
+        ancestry_cache.insert_base_oid(visited_oid, base_oid);
+    }
+    base_oid
+}
+
+/// Find the root of the commits to move for `git move --base`, given the
+/// destination.
+///
+/// As with `git rebase <upstream>`, the commits in the stack containing `oid`
+/// which are already ancestors of `dest_oid` aren't moved. If none of them
+/// are, then this is the base of the stack (see `resolve_base_commit`).
+///
+/// Returns: The first commit on the way from the base of the stack to `oid`
+/// which isn't an ancestor of `dest_oid`.
+fn resolve_base_commit_for_dest(
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    graph: &CommitGraph,
+    ancestry_cache: &mut AncestryCache,
+    oid: git2::Oid,
+    dest_oid: git2::Oid,
+) -> anyhow::Result<git2::Oid> {
+    let base_oid = resolve_base_commit(graph, ancestry_cache, oid);
+    let merge_base_oid = match merge_base_db.get_merge_base_oid(repo, dest_oid, oid)? {
+        Some(merge_base_oid) if merge_base_oid != oid => merge_base_oid,
+        _ => return Ok(base_oid),
+    };
+
+    let mut current_oid = oid;
+    while current_oid != base_oid {
+        let parent_oid = match graph.get(&current_oid).and_then(|node| node.parent) {
+            Some(parent_oid) => parent_oid,
+            None => break,
+        };
+        if parent_oid == merge_base_oid {
+            return Ok(current_oid);
+        }
+        current_oid = parent_oid;
+    }
+    Ok(base_oid)
+}
+
+/// The options which select the commits for `git move`, `git test`, and `git
+/// stack-diff`.
+pub struct CommitSelectionOptions {
+    /// The commit to select, along with its descendants.
+    pub source: Option<String>,
+
+    /// A commit inside a stack to select, starting from the main branch.
+    pub base: Option<String>,
+
+    /// Select the commits in the current stack instead.
+    pub stack: bool,
+
+    /// If neither `source` nor `base` is provided, whether to treat `HEAD` as
+    /// the `base` rather than the `source`.
+    pub default_to_base: bool,
+
+    /// Whether the selected commit can be hidden. If so, the commit graph is
+    /// constructed with it as `HEAD`, so that it's included. Otherwise, it's
+    /// constructed with the repository's `HEAD`.
+    pub include_hidden: bool,
+
+    /// What the command does with the selected commits, such as "move", for
+    /// the error message when there's no commit at `HEAD`.
+    pub verb: &'static str,
+}
+
+/// The commits selected by `CommitSelectionOptions`.
+pub struct CommitSelection<'repo> {
+    /// The commit graph, which includes `oid`.
+    pub graph: CommitGraph<'repo>,
+
+    /// The commit passed to `source` or `base`, or else the commit at `HEAD`.
+    pub oid: git2::Oid,
+
+    /// The root of the selected commits. This is the base of the stack
+    /// containing `oid` for `base` (see `resolve_base_commit`), the first
+    /// commit in the current stack for `stack`, and `oid` otherwise.
+    pub root_oid: git2::Oid,
+
+    /// The selected commits, parents before their children. For `stack`,
+    /// these are the commits in the current stack (see
+    /// `resolve_current_stack`), and otherwise they're the visible commits
+    /// starting from `root_oid` (see `get_stack_commits`).
+    pub commit_oids: Vec<git2::Oid>,
+}
+
+/// Resolve the commits selected by the `--source`, `--base`, and `--stack`
+/// options, and construct the commit graph around them.
+///
+/// If the options are invalid, or the commit can't be resolved, a message is
+/// written to `out` and a `BranchlessError` is returned:
+///
+/// * `BranchlessError::BadArguments`: More than one of `source`, `base`, and
+///   `stack` were provided, neither `source` nor `base` were provided and
+///   there's no commit at `HEAD`, or the commit is an ambiguous summary.
+/// * `BranchlessError::CommitNotFound`: The commit couldn't be found, or isn't
+///   in the commit graph because it's hidden (see `include_hidden`).
+pub fn resolve_commit_selection<'repo>(
+    out: &mut impl Write,
+    glyphs: &Glyphs,
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    ancestry_cache: &mut AncestryCache,
+    event_replayer: &EventReplayer,
+    options: CommitSelectionOptions,
+) -> anyhow::Result<CommitSelection<'repo>> {
+    let CommitSelectionOptions {
+        source,
+        base,
+        stack,
+        default_to_base,
+        include_hidden,
+        verb,
+    } = options;
+    let head_oid = get_head_oid(repo)?;
+    if stack && (source.is_some() || base.is_some()) {
+        let message = "The --stack option cannot be provided with --source or --base.";
+        writeln!(out, "{}", message)?;
+        return Err(bad_arguments(message));
+    }
+    let (commit, is_base) = match (source, base) {
         (Some(_), Some(_)) => {
-            println!("The --source and --base options cannot both be provided.");
-            return Ok(1);
+            let message = "The --source and --base options cannot both be provided.";
+            writeln!(out, "{}", message)?;
+            return Err(bad_arguments(message));
         }
         (Some(source), None) => (source, false),
         (None, Some(base)) => (base, true),
-        (None, None) => {
-            let source_oid = head_oid
-            .expect(
-                "No --source or --base argument was provided, and no OID for HEAD is available as a default",
-            )
-            .to_string();
-            (source_oid, false)
-        }
-    };
-    let dest = match dest {
-        Some(dest) => dest,
-        None => head_oid
-            .expect(
-                "No --dest argument was provided, and no OID for HEAD is available as a default",
-            )
-            .to_string(),
+        (None, None) => match head_oid {
+            Some(head_oid) => (head_oid.to_string(), default_to_base),
+            None => {
+                let message = format!(
+                    "No commit to {}: HEAD is unborn. Pass --source or --base.",
+                    verb
+                );
+                writeln!(out, "{}", message)?;
+                return Err(bad_arguments(message));
+            }
+        },
     };
-    let (source_oid, dest_oid) = match resolve_commits(&repo, vec![source, dest])? {
-        ResolveCommitsResult::Ok { commits } => match &commits.as_slice() {
-            [source_commit, dest_commit] => (source_commit.id(), dest_commit.id()),
+    let oid = match resolve_commits_by_summary(repo, vec![commit.clone()])? {
+        ResolveCommitsResult::Ok { commits } => match commits.as_slice() {
+            [commit] => commit.id(),
             _ => anyhow::bail!("Unexpected number of returns values from resolve_commits"),
         },
         ResolveCommitsResult::CommitNotFound { commit } => {
-            println!("Commit not found: {}", commit);
-            return Ok(1);
+            writeln!(out, "Commit not found: {}", commit)?;
+            return Err(BranchlessError::CommitNotFound { commit }.into());
+        }
+        ResolveCommitsResult::NotACommit {
+            commit,
+            object_type,
+        } => {
+            write_not_a_commit_message(out, &commit, object_type)?;
+            return Err(BranchlessError::CommitNotFound { commit }.into());
+        }
+        ResolveCommitsResult::AmbiguousSummary {
+            commit,
+            commit_oids,
+        } => {
+            write_ambiguous_summary_message(out, glyphs, repo, &commit, &commit_oids)?;
+            return Err(bad_arguments(format!(
+                "The commit summary {:?} is ambiguous",
+                commit
+            )));
         }
     };
 
-    let main_branch_oid = get_main_branch_oid(&repo)?;
-    let branch_oid_to_names = get_branch_oid_to_names(&repo)?;
+    let graph = make_graph_from_repo_with_head(
+        repo,
+        merge_base_db,
+        ancestry_cache,
+        event_replayer,
+        event_replayer.make_default_cursor(),
+        &HeadOid(if include_hidden { Some(oid) } else { head_oid }),
+        true,
+    )?;
+    if !graph.contains_key(&oid) {
+        writeln!(out, "Commit not found in the commit graph: {}", commit)?;
+        return Err(BranchlessError::CommitNotFound { commit }.into());
+    }
+
+    let (root_oid, commit_oids) = if stack {
+        let commit_oids = resolve_current_stack(&graph, Some(oid));
+        let root_oid = commit_oids.first().copied().unwrap_or(oid);
+        (root_oid, commit_oids)
+    } else {
+        let root_oid = if is_base {
+            resolve_base_commit(&graph, ancestry_cache, oid)
+        } else {
+            oid
+        };
+        (root_oid, get_stack_commits(&graph, root_oid))
+    };
+    Ok(CommitSelection {
+        graph,
+        oid,
+        root_oid,
+        commit_oids,
+    })
+}
+
+struct MoveKeymap {
+    next: KeyBindings,
+    previous: KeyBindings,
+    move_down: KeyBindings,
+    move_up: KeyBindings,
+    pick: KeyBindings,
+    fixup: KeyBindings,
+    drop: KeyBindings,
+    help: KeyBindings,
+    quit: KeyBindings,
+    confirm: KeyBindings,
+}
+
+impl MoveKeymap {
+    /// Load the key bindings from the repository's configuration.
+    ///
+    /// This should be called outside of `with_siv`, since logging is disabled
+    /// there, and we want to warn the user about invalid key bindings.
+    fn from_config(repo: &git2::Repository) -> anyhow::Result<Self> {
+        Ok(MoveKeymap {
+            next: get_key_bindings(repo, "planNext", &["j", "<down>"])?,
+            previous: get_key_bindings(repo, "planPrevious", &["k", "<up>"])?,
+            move_down: get_key_bindings(repo, "planMoveDown", &["J"])?,
+            move_up: get_key_bindings(repo, "planMoveUp", &["K"])?,
+            pick: get_key_bindings(repo, "planPick", &["p"])?,
+            fixup: get_key_bindings(repo, "planFixup", &["f"])?,
+            drop: get_key_bindings(repo, "planDrop", &["d"])?,
+            help: get_key_bindings(repo, "help", &["h", "H", "?"])?,
+            quit: get_key_bindings(repo, "quit", &["q", "Q"])?,
+            confirm: get_key_bindings(repo, "confirm", &["<enter>"])?,
+        })
+    }
+}
+
+/// Let the user edit the rebase plan interactively: reorder the commits within
+/// their stacks, and mark commits to be fixed up into the previous commit or
+/// dropped.
+///
+/// Returns: The edited plan, or `None` if the user canceled the move.
+fn edit_rebase_plan(
+    mut siv: CursiveRunner<CursiveRunnable>,
+    repo: &git2::Repository,
+    rebase_plan: &RebasePlan,
+    keymap: &MoveKeymap,
+) -> anyhow::Result<Option<RebasePlan>> {
+    #[derive(Clone, Copy, Debug)]
+    enum Message {
+        Init,
+        Redraw,
+        Next,
+        Previous,
+        MoveDown,
+        MoveUp,
+        SetAction(PickAction),
+        Help,
+        Quit,
+        Confirm,
+    }
+    let (main_tx, main_rx): (Sender<Message>, Receiver<Message>) = channel();
+
+    [
+        (&keymap.next, Message::Next),
+        (&keymap.previous, Message::Previous),
+        (&keymap.move_down, Message::MoveDown),
+        (&keymap.move_up, Message::MoveUp),
+        (&keymap.pick, Message::SetAction(PickAction::Pick)),
+        (&keymap.fixup, Message::SetAction(PickAction::Fixup)),
+        (&keymap.drop, Message::SetAction(PickAction::Drop)),
+        (&keymap.help, Message::Help),
+        (&keymap.quit, Message::Quit),
+        (&keymap.confirm, Message::Confirm),
+    ]
+    .iter()
+    .flat_map(|(key_bindings, message)| {
+        key_bindings
+            .events()
+            .iter()
+            .map(move |event| (event.clone(), *message))
+    })
+    .for_each(|(event, message): (cursive::event::Event, Message)| {
+        siv.add_global_callback(event, {
+            let main_tx = main_tx.clone();
+            move |_siv| main_tx.send(message).unwrap()
+        });
+    });
+
+    siv.set_on_pre_event(cursive::event::Event::WindowResize, {
+        let main_tx = main_tx.clone();
+        move |_siv| main_tx.send(Message::Redraw).unwrap()
+    });
+
+    declare_views! {
+        PlanView => ScrollView<TextView>,
+        InfoView => TextView,
+    }
+
+    let mut rebase_plan = rebase_plan.clone();
+    let mut selected_index: usize = 0;
+    let mut error_message: Option<&str> = None;
+    main_tx.send(Message::Init)?;
+    while siv.is_running() {
+        let message = main_rx.try_recv();
+        if message.is_err() {
+            // For tests: only pump the Cursive event loop if we have no events
+            // of our own to process. See `undo::select_past_event`.
+            siv.step();
+        }
+
+        let redraw = |siv: &mut Cursive,
+                      rebase_plan: &RebasePlan,
+                      selected_index: usize,
+                      error_message: Option<&str>|
+         -> anyhow::Result<()> {
+            let lines = rebase_plan
+                .get_picks()
+                .into_iter()
+                .enumerate()
+                .map(|(pick_index, (commit_oid, action))| {
+                    let cursor = if pick_index == selected_index {
+                        ">"
+                    } else {
+                        " "
+                    };
+                    let line = StyledStringBuilder::new()
+                        .append_plain(format!("{} {:<5} ", cursor, action))
+                        .append(friendly_describe_commit(repo, commit_oid)?)
+                        .build();
+                    if pick_index == selected_index {
+                        Ok(set_effect(line, Effect::Reverse))
+                    } else {
+                        Ok(line)
+                    }
+                })
+                .collect::<anyhow::Result<Vec<StyledString>>>()?;
+            PlanView::find(siv)
+                .get_inner_mut()
+                .set_content(StyledStringBuilder::from_lines(lines));
+
+            let info = match error_message {
+                Some(error_message) => error_message.to_string(),
+                None => format!(
+                    "Edit the commits to move. Press '{}' for help, '{}' to move the commits, or '{}' to cancel.",
+                    keymap.help.describe_first(),
+                    keymap.confirm.describe_first(),
+                    keymap.quit.describe_first()
+                ),
+            };
+            InfoView::find(siv).set_content(info);
+            Ok(())
+        };
+
+        let num_picks = rebase_plan.get_picks().len();
+        match message {
+            Err(TryRecvError::Disconnected) => break,
+
+            Err(TryRecvError::Empty) => {
+                // If we haven't received a message yet, defer to `siv.step`
+                // to process the next user input.
+                continue;
+            }
+
+            Ok(Message::Init) => {
+                let plan_view: PlanView = ScrollView::new(TextView::new("")).into();
+                let info_view: InfoView = TextView::new("").into();
+                siv.add_layer(LinearLayout::vertical().child(plan_view).child(info_view));
+            }
+
+            Ok(Message::Redraw) => {}
+
+            Ok(Message::Next) => {
+                error_message = None;
+                if selected_index + 1 < num_picks {
+                    selected_index += 1;
+                }
+            }
+
+            Ok(Message::Previous) => {
+                error_message = None;
+                selected_index = selected_index.saturating_sub(1);
+            }
+
+            Ok(Message::MoveDown) => {
+                error_message = if rebase_plan.swap_with_next_pick(selected_index) {
+                    selected_index += 1;
+                    None
+                } else {
+                    Some(
+                        "That commit can't be moved down, since it's the last commit in its stack.",
+                    )
+                };
+            }
+
+            Ok(Message::MoveUp) => {
+                error_message = if selected_index > 0
+                    && rebase_plan.swap_with_next_pick(selected_index - 1)
+                {
+                    selected_index -= 1;
+                    None
+                } else {
+                    Some("That commit can't be moved up, since it's the first commit in its stack.")
+                };
+            }
+
+            Ok(Message::SetAction(action)) => {
+                error_message = if rebase_plan.set_pick_action(selected_index, action) {
+                    None
+                } else {
+                    Some("A commit can only be fixed up into the commit before it in its stack.")
+                };
+            }
+
+            Ok(Message::Help) => {
+                siv.add_layer(
+                    Dialog::new()
+                        .title("How to use")
+                        .content(TextView::new(format!(
+"Edit how the commits will be moved. Commits are applied from top to bottom.
+
+{help}: Show this help.
+{previous} or {next}: Select the previous/next commit.
+{move_up} or {move_down}: Move the selected commit up/down within its stack.
+{pick}: Apply the selected commit as-is.
+{fixup}: Squash the selected commit into the commit before it.
+{drop}: Don't apply the selected commit. Dropped commits are hidden.
+{confirm}: Move the commits as shown.
+{quit}: Cancel, without moving any commits.
+
+Key bindings can be changed in the `branchless.tui.keymap` config section.
+",
+                                help = keymap.help.describe(),
+                                previous = keymap.previous.describe(),
+                                next = keymap.next.describe(),
+                                move_up = keymap.move_up.describe(),
+                                move_down = keymap.move_down.describe(),
+                                pick = keymap.pick.describe(),
+                                fixup = keymap.fixup.describe(),
+                                drop = keymap.drop.describe(),
+                                confirm = keymap.confirm.describe(),
+                                quit = keymap.quit.describe(),
+                            )))
+                        .dismiss_button("Close"),
+                );
+            }
+
+            Ok(Message::Quit) => {
+                siv.quit();
+                return Ok(None);
+            }
+
+            Ok(Message::Confirm) => {
+                siv.quit();
+                return Ok(Some(rebase_plan));
+            }
+        };
+
+        if message.is_ok() {
+            redraw(&mut siv, &rebase_plan, selected_index, error_message)?;
+            siv.refresh();
+        }
+    }
+
+    Ok(None)
+}
+
+/// Options for `move`.
+#[derive(Clone, Debug, Default)]
+pub struct MoveOptions {
+    /// The commit to move, along with its descendants.
+    pub source: Option<String>,
+
+    /// The commit to move the commits onto, as with `git rebase <upstream>`.
+    /// It becomes the new parent of the root of the moved subtree. The root is
+    /// `source`, or the commit which `base` or `stack` resolves to, except
+    /// that if `base` is provided, commits in its stack which are already
+    /// ancestors of `dest` aren't moved (see `resolve_base_commit_for_dest`).
+    /// If the root is on the main branch, then the main branch commits
+    /// descending from it are moved as well. If `PREVIOUS_DEST`, then the
+    /// commits are moved back to where they were before they were last moved
+    /// (see `find_previous_dest`).
+    pub dest: Option<String>,
+
+    /// The commit to make the new parent of the root of the moved subtree, as
+    /// with `git rebase --onto`. Unlike `dest`, this never affects which
+    /// commits are moved: the root is always `source`, or the commit which
+    /// `base` or `stack` resolves to, starting from the main branch. Can't be
+    /// provided together with `dest`.
+    pub onto: Option<String>,
+
+    /// A commit inside a subtree to move, starting from the main branch.
+    pub base: Option<String>,
+
+    /// Move the current stack, i.e. the subtree containing the current
+    /// commit, as if `base` were the current commit (see
+    /// `resolve_current_stack`).
+    pub stack: bool,
+
+    /// Skip the in-memory rebase and rebase on disk directly.
+    pub force_on_disk: bool,
+
+    /// Don't start the move if any of the commits would cause merge conflicts.
+    pub abort_on_conflict: bool,
+
+    /// If `dest` names a branch, check out that branch once the move has
+    /// completed, rather than leaving `HEAD` detached.
+    pub attach: bool,
+
+    /// Let the user reorder, fix up, or drop the commits to move before moving
+    /// them. Dropped commits are hidden afterward.
+    pub interactive: bool,
+
+    /// Allow moving commits which share no history with the destination, such
+    /// as the root of an imported repository. The root commit is grafted onto
+    /// the destination, as with `git merge --allow-unrelated-histories`.
+    pub allow_unrelated: bool,
+
+    /// Set the committer date of each moved commit to its author date, as with
+    /// `git rebase --committer-date-is-author-date`.
+    pub committer_date_is_author_date: bool,
+
+    /// Commands to run once the commits have been moved. See
+    /// `run_exec_commands`.
+    pub exec_options: ExecOptions,
+}
+
+/// Move a subtree from one place to another.
+///
+/// Messages about the progress of the move, as well as the output of any `git`
+/// commands which are run, are written to `out` rather than directly to
+/// `stdout`, so that callers embedding this command can capture them. (The
+/// error output of `git` and any hooks it runs is still written to `stderr`.)
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `options`: What to move, where to move it, and how.
+///
+/// Returns: `Ok` if the commits were moved successfully, the user canceled the
+/// move, or there was nothing to do because the destination is already the
+/// parent of the commit to move. Otherwise, a message about the failure is
+/// written to `out`, and the error is one of:
+/// * `BranchlessError::Conflict`: The move stopped or was aborted because of a
+///   merge conflict, which requires manual resolution.
+/// * `BranchlessError::BadArguments`: The provided arguments were invalid,
+///   such as when more than one of `source`, `base`, and `stack` are provided,
+///   when both `dest` and `onto` are provided, when `HEAD` is unborn and
+///   either the commits to move or the destination aren't provided,
+///   when `stack` is provided but the current commit isn't in a stack, when
+///   the commits to move are unrelated to the destination and
+///   `allow_unrelated` isn't set, or when `dest` is `PREVIOUS_DEST` but the
+///   source commit hasn't been moved before, or when the destination is the
+///   commit to move or one of its descendants.
+/// * `BranchlessError::CommitNotFound`: One of the provided commits could not
+///   be resolved.
+/// * `BranchlessError::Failed`: A `git` command or exec command failed, with
+///   the given exit code.
+///
+/// The exit code for the `git-branchless` binary is given by
+/// `BranchlessError::exit_code`.
+pub fn r#move(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    options: &MoveOptions,
+) -> Result<(), BranchlessError> {
+    move_commits(out, git_executable, options)?;
+    Ok(())
+}
+
+fn move_commits(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    options: &MoveOptions,
+) -> anyhow::Result<()> {
+    let MoveOptions {
+        source,
+        dest,
+        onto,
+        base,
+        stack,
+        force_on_disk,
+        abort_on_conflict,
+        attach,
+        interactive,
+        allow_unrelated,
+        committer_date_is_author_date,
+        exec_options,
+    } = options.clone();
+    let repo = get_repo()?;
+    let glyphs = Glyphs::detect();
     let conn = get_db_conn(&repo)?;
     let merge_base_db = MergeBaseDb::new(&conn)?;
-    let event_log_db = EventLogDb::new(&conn)?;
+    let patch_id_db = PatchIdDb::new(&conn)?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
-    let event_cursor = event_replayer.make_default_cursor();
-    let graph = make_graph(
+    let mut ancestry_cache = AncestryCache::default();
+    let is_base = base.is_some();
+    let CommitSelection {
+        graph,
+        oid: source_oid,
+        root_oid,
+        commit_oids,
+    } = resolve_commit_selection(
+        out,
+        &glyphs,
         &repo,
         &merge_base_db,
+        &mut ancestry_cache,
         &event_replayer,
-        event_cursor,
-        &HeadOid(Some(source_oid)),
-        &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().copied().collect()),
-        true,
+        CommitSelectionOptions {
+            source,
+            base,
+            stack,
+            default_to_base: false,
+            include_hidden: true,
+            verb: "move",
+        },
     )?;
+    if stack && commit_oids.is_empty() {
+        let message = "The --stack option was provided, but the current commit is not in a stack.";
+        writeln!(out, "{}", message)?;
+        return Err(bad_arguments(message));
+    }
 
-    let source_oid = if should_resolve_base_commit {
-        resolve_base_commit(&graph, source_oid)
+    let (dest, is_onto) = match (dest, onto) {
+        (Some(_), Some(_)) => {
+            let message = "The --dest and --onto options cannot both be provided.";
+            writeln!(out, "{}", message)?;
+            return Err(bad_arguments(message));
+        }
+        (Some(dest), None) => (dest, false),
+        (None, Some(onto)) => (onto, true),
+        (None, None) => match get_head_oid(&repo)? {
+            Some(head_oid) => (head_oid.to_string(), false),
+            None => {
+                let message = "No commit to move onto: HEAD is unborn. Pass --dest or --onto.";
+                writeln!(out, "{}", message)?;
+                return Err(bad_arguments(message));
+            }
+        },
+    };
+    let dest_branch = if attach {
+        match repo.find_branch(&dest, git2::BranchType::Local) {
+            Ok(_) => Some(dest.clone()),
+            Err(_) => {
+                let message = format!(
+                    "The --attach option was provided, but the destination is not a branch: {}",
+                    dest
+                );
+                writeln!(out, "{}", message)?;
+                return Err(bad_arguments(message));
+            }
+        }
     } else {
-        source_oid
+        None
+    };
+    // The previous location can only be found once the root of the commits to
+    // move has been resolved, so it's looked up below.
+    let is_previous_dest = dest == PREVIOUS_DEST;
+    let dest_oid = if is_previous_dest {
+        None
+    } else {
+        match resolve_commits_by_summary(&repo, vec![dest])? {
+            ResolveCommitsResult::Ok { commits } => match commits.as_slice() {
+                [dest_commit] => Some(dest_commit.id()),
+                _ => anyhow::bail!("Unexpected number of returns values from resolve_commits"),
+            },
+            ResolveCommitsResult::CommitNotFound { commit } => {
+                writeln!(out, "Commit not found: {}", commit)?;
+                return Err(BranchlessError::CommitNotFound { commit }.into());
+            }
+            ResolveCommitsResult::NotACommit {
+                commit,
+                object_type,
+            } => {
+                write_not_a_commit_message(out, &commit, object_type)?;
+                return Err(BranchlessError::CommitNotFound { commit }.into());
+            }
+            ResolveCommitsResult::AmbiguousSummary {
+                commit,
+                commit_oids,
+            } => {
+                write_ambiguous_summary_message(out, &glyphs, &repo, &commit, &commit_oids)?;
+                return Err(bad_arguments(format!(
+                    "The commit summary {:?} is ambiguous",
+                    commit
+                )));
+            }
+        }
     };
 
-    let glyphs = Glyphs::detect();
-    let now = SystemTime::now();
-    let event_tx_id = event_log_db.make_transaction_id(now, "move")?;
+    let main_branch_oid = get_main_branch_oid(&repo)?;
+    let source_oid = match dest_oid {
+        Some(dest_oid) if is_base && !is_onto => resolve_base_commit_for_dest(
+            &repo,
+            &merge_base_db,
+            &graph,
+            &mut ancestry_cache,
+            source_oid,
+            dest_oid,
+        )?,
+        _ => root_oid,
+    };
+    let dest_oid = match dest_oid {
+        Some(dest_oid) => dest_oid,
+        None => match find_previous_dest(&event_log_db, source_oid)? {
+            Some(previous_dest_oid) => previous_dest_oid,
+            None => {
+                writeln!(
+                    out,
+                    "The commit to move hasn't been moved before, so it has no previous location: {}",
+                    printable_styled_string(&glyphs, friendly_describe_commit(&repo, source_oid)?)?
+                )?;
+                return Err(bad_arguments(format!(
+                    "The commit to move hasn't been moved before: {}",
+                    source_oid
+                )));
+            }
+        },
+    };
+    if !allow_unrelated
+        && merge_base_db
+            .get_merge_base_oid(&repo, dest_oid, source_oid)?
+            .is_none()
+    {
+        writeln!(
+            out,
+            "The commit to move has no history in common with the destination: {}",
+            printable_styled_string(&glyphs, friendly_describe_commit(&repo, source_oid)?)?
+        )?;
+        writeln!(
+            out,
+            "(Pass --allow-unrelated to graft its history onto the destination anyway)"
+        )?;
+        return Err(bad_arguments(format!(
+            "The commit to move has no history in common with the destination: {}",
+            source_oid
+        )));
+    }
+
+    if dest_oid == source_oid {
+        writeln!(
+            out,
+            "Cannot move a commit onto itself: {}",
+            printable_styled_string(&glyphs, friendly_describe_commit(&repo, source_oid)?)?
+        )?;
+        return Err(bad_arguments(format!(
+            "Cannot move commit {} onto itself",
+            source_oid
+        )));
+    }
+
+    if merge_base_db.get_merge_base_oid(&repo, dest_oid, source_oid)? == Some(source_oid) {
+        writeln!(out, "Cannot move a commit onto its own descendant:")?;
+        writeln!(
+            out,
+            "- Commit: {}",
+            printable_styled_string(&glyphs, friendly_describe_commit(&repo, source_oid)?)?
+        )?;
+        writeln!(
+            out,
+            "- Destination: {}",
+            printable_styled_string(&glyphs, friendly_describe_commit(&repo, dest_oid)?)?
+        )?;
+        return Err(bad_arguments(format!(
+            "Cannot move commit {} onto its own descendant {}",
+            source_oid, dest_oid
+        )));
+    }
+
+    // An interactive move can still reorder or drop the commits, and
+    // `--attach` still checks out the destination branch, so those are carried
+    // out even if the commits wouldn't otherwise move.
+    if !interactive
+        && dest_branch.is_none()
+        && repo.find_commit(source_oid)?.parent_ids().next() == Some(dest_oid)
+    {
+        writeln!(
+            out,
+            "Nothing to do: the destination is already the parent of the commit to move: {}",
+            printable_styled_string(&glyphs, friendly_describe_commit(&repo, dest_oid)?)?
+        )?;
+        // No commits are rewritten, so this only runs the `--exec` command.
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "move")?;
+        let result = run_exec_commands(
+            out,
+            &glyphs,
+            &repo,
+            &event_log_db,
+            event_tx_id,
+            &exec_options,
+        )?;
+        return check_result(result);
+    }
+
     let rebase_plan = make_rebase_plan(
         &repo,
         &merge_base_db,
+        &patch_id_db,
         &graph,
         &MainBranchOid(main_branch_oid),
         source_oid,
+        dest_oid,
     )?;
+    let rebase_plan = if interactive {
+        let keymap = MoveKeymap::from_config(&repo)?;
+        match with_siv(&repo, |siv| {
+            edit_rebase_plan(siv, &repo, &rebase_plan, &keymap)
+        })? {
+            Some(rebase_plan) => rebase_plan,
+            None => {
+                writeln!(out, "Move canceled.")?;
+                return Ok(());
+            }
+        }
+    } else {
+        rebase_plan
+    };
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "move")?;
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    // Record where the commits are being moved from before starting, so that
+    // it's also available if the move stops because of a merge conflict and
+    // is then continued by hand.
+    if let Some(old_parent_oid) = repo.find_commit(source_oid)?.parent_ids().next() {
+        event_log_db.add_events(vec![Event::MoveEvent {
+            timestamp,
+            event_tx_id,
+            commit_oid: source_oid,
+            old_parent_oid,
+        }])?;
+    }
     let result = execute_rebase_plan(
+        out,
         &glyphs,
         git_executable,
         &repo,
@@ -122,6 +986,254 @@ pub fn r#move(
         source_oid,
         dest_oid,
         force_on_disk,
+        abort_on_conflict,
+        committer_date_is_author_date,
+    )?;
+    match result {
+        0 => {}
+        EXIT_CODE_CONFLICT => {
+            return Err(find_conflict(&repo, &rebase_plan, dest_oid)?.into());
+        }
+        EXIT_CODE_BAD_ARGUMENTS => {
+            return Err(bad_arguments(
+                "The commits to move include a merge commit, which can't be rebased",
+            ));
+        }
+        result => return check_result(result),
+    }
+
+    event_log_db.add_events(
+        rebase_plan
+            .get_dropped_oids()
+            .into_iter()
+            .map(|commit_oid| Event::HideEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            })
+            .collect(),
+    )?;
+
+    if let Some(dest_branch) = dest_branch {
+        let result = run_git(
+            out,
+            git_executable,
+            Some(event_tx_id),
+            &["checkout", &dest_branch],
+        )?;
+        check_result(result)?;
+    }
+
+    let result = run_exec_commands(
+        out,
+        &glyphs,
+        &repo,
+        &event_log_db,
+        event_tx_id,
+        &exec_options,
     )?;
-    Ok(result)
+    check_result(result)
+}
+
+/// Make the error for invalid arguments to `git move`.
+fn bad_arguments(message: impl Into<String>) -> anyhow::Error {
+    BranchlessError::BadArguments {
+        message: message.into(),
+    }
+    .into()
+}
+
+/// Convert the exit code of a `git` or exec command run as part of the move
+/// into a result.
+fn check_result(exit_code: isize) -> anyhow::Result<()> {
+    if exit_code == 0 {
+        Ok(())
+    } else {
+        Err(BranchlessError::Failed { exit_code }.into())
+    }
+}
+
+/// Describe the merge conflict which stopped a move, or prevented it from
+/// starting.
+fn find_conflict(
+    repo: &git2::Repository,
+    rebase_plan: &RebasePlan,
+    dest_oid: git2::Oid,
+) -> anyhow::Result<BranchlessError> {
+    // If an on-disk rebase stopped because of the conflict, then `REBASE_HEAD`
+    // points to the commit being applied, and the index holds the conflicts.
+    // Otherwise, the rebase was never started.
+    let rebase_head_oid = match repo.find_reference("REBASE_HEAD") {
+        Ok(reference) => reference.target(),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+        Err(err) => return Err(wrap_git_error(err)),
+    };
+    let error = match rebase_head_oid {
+        Some(rebase_head_oid) => {
+            let index = repo.index().map_err(wrap_git_error)?;
+            let mut paths = Vec::new();
+            for conflict in index.conflicts().map_err(wrap_git_error)? {
+                let conflict = conflict.map_err(wrap_git_error)?;
+                let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+                if let Some(entry) = entry {
+                    paths.push(PathBuf::from(String::from_utf8_lossy(&entry.path).as_ref()));
+                }
+            }
+            BranchlessError::Conflict {
+                commit_oids: vec![rebase_head_oid],
+                paths,
+            }
+        }
+        None => BranchlessError::Conflict {
+            commit_oids: find_rebase_conflicts(repo, rebase_plan, dest_oid)?,
+            paths: Vec::new(),
+        },
+    };
+    Ok(error)
+}
+
+#[allow(missing_docs)]
+pub mod testing {
+    use cursive::{CursiveRunnable, CursiveRunner};
+
+    use crate::core::rewrite::RebasePlan;
+
+    pub fn edit_rebase_plan(
+        siv: CursiveRunner<CursiveRunnable>,
+        repo: &git2::Repository,
+        rebase_plan: &RebasePlan,
+    ) -> anyhow::Result<Option<RebasePlan>> {
+        let keymap = super::MoveKeymap::from_config(repo)?;
+        super::edit_rebase_plan(siv, repo, rebase_plan, &keymap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::graph::{
+        make_graph, make_smartlog_graph, AdditionalMainBranchOids, BranchOids, TagOids,
+        UnhideableRefOids,
+    };
+    use crate::testing::with_git;
+
+    #[test]
+    fn test_resolve_base_commit_merge() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            git.commit_file("test1", 1)?;
+            git.detach_head()?;
+            let test2_oid = git.commit_file("test2", 2)?;
+            let test3_oid = git.commit_file("test3", 3)?;
+
+            // A merge commit whose first parent is on the main branch, so its
+            // parent in the commit graph is on the main branch as well.
+            git.run(&["checkout", "master"])?;
+            git.detach_head()?;
+            git.run(&[
+                "merge",
+                "--no-ff",
+                "-m",
+                "merge test3",
+                &test3_oid.to_string(),
+            ])?;
+            let merge1_oid = git.get_repo()?.head()?.peel_to_commit()?.id();
+
+            // A merge commit whose first parent is in the stack.
+            git.run(&["checkout", &test3_oid.to_string()])?;
+            git.commit_file("test4", 4)?;
+            git.run(&["checkout", "master"])?;
+            git.detach_head()?;
+            let test5_oid = git.commit_file("test5", 5)?;
+            git.run(&["checkout", &test3_oid.to_string()])?;
+            git.run(&[
+                "merge",
+                "--no-ff",
+                "-m",
+                "merge test5",
+                &test5_oid.to_string(),
+            ])?;
+            let merge2_oid = git.get_repo()?.head()?.peel_to_commit()?.id();
+            git.run(&["checkout", "master"])?;
+
+            let repo = git.get_repo()?;
+            let graph = make_smartlog_graph(&repo)?;
+            let mut ancestry_cache = AncestryCache::default();
+            assert_eq!(
+                resolve_base_commit(&graph, &mut ancestry_cache, test3_oid),
+                test2_oid
+            );
+            assert_eq!(
+                resolve_base_commit(&graph, &mut ancestry_cache, merge1_oid),
+                test2_oid
+            );
+            assert_eq!(
+                resolve_base_commit(&graph, &mut ancestry_cache, merge2_oid),
+                test2_oid
+            );
+            assert_eq!(
+                resolve_base_commit(&graph, &mut ancestry_cache, test5_oid),
+                test5_oid
+            );
+
+            // The results should be the same without any cached results.
+            assert_eq!(
+                resolve_base_commit(&graph, &mut AncestryCache::default(), merge2_oid),
+                test2_oid
+            );
+            assert_eq!(ancestry_cache.get_base_oid(&test3_oid), Some(test2_oid));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_ancestry_cache_shared_across_calls() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            git.detach_head()?;
+            let test1_oid = git.commit_file("test1", 1)?;
+            let test2_oid = git.commit_file("test2", 2)?;
+            let test3_oid = git.commit_file("test3", 3)?;
+
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let merge_base_db = MergeBaseDb::new(&conn)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+            let mut ancestry_cache = AncestryCache::default();
+            let graph = make_graph(
+                &repo,
+                &merge_base_db,
+                &mut ancestry_cache,
+                &event_replayer,
+                event_replayer.make_default_cursor(),
+                &HeadOid(Some(test3_oid)),
+                &MainBranchOid(get_main_branch_oid(&repo)?),
+                &AdditionalMainBranchOids(Vec::new()),
+                &BranchOids(Default::default()),
+                &TagOids(Default::default()),
+                &UnhideableRefOids(Default::default()),
+                true,
+            )?;
+            // Whether each commit should be hidden depends on whether its
+            // children should be, so at least one of those results is reused.
+            let num_graph_hits = ancestry_cache.num_hits();
+            assert!(num_graph_hits > 0);
+
+            assert_eq!(
+                resolve_base_commit(&graph, &mut ancestry_cache, test3_oid),
+                test1_oid
+            );
+            assert_eq!(ancestry_cache.num_hits(), num_graph_hits);
+            assert_eq!(
+                resolve_base_commit(&graph, &mut ancestry_cache, test2_oid),
+                test1_oid
+            );
+            assert_eq!(ancestry_cache.num_hits(), num_graph_hits + 1);
+
+            Ok(())
+        })
+    }
 }
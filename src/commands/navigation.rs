@@ -1,27 +1,35 @@
 //! Convenience commands to help the user move through a stack of commits.
 
 use std::collections::HashMap;
+use std::io::stdout;
+use std::time::SystemTime;
 
 use log::warn;
 
-use crate::commands::smartlog::smartlog;
+use crate::commands::smartlog::{smartlog, SmartlogOptions};
 use crate::core::eventlog::{EventLogDb, EventReplayer};
 use crate::core::formatting::{printable_styled_string, Glyphs};
 use crate::core::graph::{
-    find_path_to_merge_base, make_graph, BranchOids, HeadOid, MainBranchOid, Node,
+    find_path_to_merge_base, get_sorted_children, make_graph_from_repo, AncestryCache,
+    MainBranchOid, Node,
 };
 use crate::core::mergebase::MergeBaseDb;
 use crate::core::metadata::{render_commit_metadata, CommitMessageProvider, CommitOidProvider};
 use crate::util::{
-    get_branch_oid_to_names, get_db_conn, get_head_oid, get_main_branch_oid, get_repo, run_git,
-    GitExecutable,
+    get_db_conn, get_head_oid, get_main_branch_oid, get_repo, resolve_commits_by_summary, run_git,
+    write_ambiguous_summary_message, write_not_a_commit_message, GitExecutable,
+    ResolveCommitsResult, EXIT_CODE_BAD_ARGUMENTS, EXIT_CODE_COMMIT_NOT_FOUND,
 };
 
 /// Go back a certain number of commits.
+///
+/// This follows the parents of the current commit, so, unlike `next`, it can
+/// already step onto hidden commits.
 pub fn prev(git_executable: &GitExecutable, num_commits: Option<isize>) -> anyhow::Result<isize> {
     let exit_code = match num_commits {
-        None => run_git(git_executable, None, &["checkout", "HEAD^"])?,
+        None => run_git(&mut stdout(), git_executable, None, &["checkout", "HEAD^"])?,
         Some(num_commits) => run_git(
+            &mut stdout(),
             git_executable,
             None,
             &["checkout", &format!("HEAD~{}", num_commits)],
@@ -30,7 +38,7 @@ pub fn prev(git_executable: &GitExecutable, num_commits: Option<isize>) -> anyho
     if exit_code != 0 {
         return Ok(exit_code);
     }
-    smartlog()?;
+    smartlog(&SmartlogOptions::default())?;
     Ok(0)
 }
 
@@ -84,8 +92,7 @@ fn advance_towards_own_commit(
 ) -> anyhow::Result<Option<git2::Oid>> {
     let mut current_oid = current_oid;
     for i in 0..num_commits {
-        let mut children: Vec<git2::Oid> = graph[&current_oid].children.iter().copied().collect();
-        children.sort_by_key(|child_oid| graph[child_oid].commit.time());
+        let children = get_sorted_children(graph, current_oid);
         current_oid = match (towards, &children.as_slice()) {
             (_, []) => {
                 // It would also make sense to issue an error here, rather than
@@ -113,7 +120,7 @@ fn advance_towards_own_commit(
                     let commit_text = render_commit_metadata(
                         &repo.find_commit(*child_oid)?,
                         &mut [
-                            &mut CommitOidProvider::new(true)?,
+                            &mut CommitOidProvider::new(repo, true)?,
                             &mut CommitMessageProvider::new()?,
                         ],
                     )?;
@@ -133,10 +140,15 @@ fn advance_towards_own_commit(
 }
 
 /// Go forward a certain number of commits.
+///
+/// If `include_hidden` is set, then hidden commits (such as old versions of
+/// rewritten commits) are traversed as well. Checking out a hidden commit
+/// doesn't unhide it.
 pub fn next(
     git_executable: &GitExecutable,
     num_commits: Option<isize>,
     towards: Option<Towards>,
+    include_hidden: bool,
 ) -> anyhow::Result<isize> {
     let glyphs = Glyphs::detect();
     let repo = get_repo()?;
@@ -150,16 +162,13 @@ pub fn next(
         None => anyhow::bail!("No HEAD present; cannot calculate next commit"),
     };
     let main_branch_oid = get_main_branch_oid(&repo)?;
-    let branch_oid_to_names = get_branch_oid_to_names(&repo)?;
-    let graph = make_graph(
+    let graph = make_graph_from_repo(
         &repo,
         &merge_base_db,
+        &mut AncestryCache::default(),
         &event_replayer,
         event_replayer.make_default_cursor(),
-        &HeadOid(Some(head_oid)),
-        &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().copied().collect()),
-        true,
+        !include_hidden,
     )?;
 
     let num_commits = num_commits.unwrap_or(1);
@@ -179,6 +188,7 @@ pub fn next(
     };
 
     let result = run_git(
+        &mut stdout(),
         git_executable,
         None,
         &["checkout", &current_oid.to_string()],
@@ -187,6 +197,78 @@ pub fn next(
         return Ok(result);
     }
 
-    smartlog()?;
+    smartlog(&SmartlogOptions::default())?;
+    Ok(0)
+}
+
+/// Check out the given commit and display the resulting smartlog.
+///
+/// The target is resolved in the following order:
+///
+/// - If it's the name of a local branch, that branch is checked out.
+/// - If it's a commit hash or any other revision understood by Git, that
+///   commit is checked out.
+/// - Otherwise, the commits in the smartlog whose summary lines contain the
+///   target (case-insensitively) are searched. If exactly one commit matches,
+///   it's checked out.
+///
+/// Args:
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `target`: The branch, commit, or summary line substring to check out.
+///
+/// Returns: exit code (0 denotes successful exit).
+pub fn checkout(git_executable: &GitExecutable, target: String) -> anyhow::Result<isize> {
+    let glyphs = Glyphs::detect();
+    let repo = get_repo()?;
+    let conn = get_db_conn(&repo)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let checkout_target = if repo.find_branch(&target, git2::BranchType::Local).is_ok() {
+        target
+    } else {
+        match resolve_commits_by_summary(&repo, vec![target])? {
+            ResolveCommitsResult::Ok { commits } => match commits.as_slice() {
+                [commit] => commit.id().to_string(),
+                _ => anyhow::bail!("Unexpected number of returns values from resolve_commits"),
+            },
+            ResolveCommitsResult::CommitNotFound { commit } => {
+                println!("Commit not found: {}", commit);
+                return Ok(EXIT_CODE_COMMIT_NOT_FOUND);
+            }
+            ResolveCommitsResult::NotACommit {
+                commit,
+                object_type,
+            } => {
+                write_not_a_commit_message(&mut stdout(), &commit, object_type)?;
+                return Ok(EXIT_CODE_COMMIT_NOT_FOUND);
+            }
+            ResolveCommitsResult::AmbiguousSummary {
+                commit,
+                commit_oids,
+            } => {
+                write_ambiguous_summary_message(
+                    &mut stdout(),
+                    &glyphs,
+                    &repo,
+                    &commit,
+                    &commit_oids,
+                )?;
+                return Ok(EXIT_CODE_BAD_ARGUMENTS);
+            }
+        }
+    };
+
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "checkout")?;
+    let result = run_git(
+        &mut stdout(),
+        git_executable,
+        Some(event_tx_id),
+        &["checkout", &checkout_target],
+    )?;
+    if result != 0 {
+        return Ok(result);
+    }
+
+    smartlog(&SmartlogOptions::default())?;
     Ok(0)
 }
@@ -0,0 +1,75 @@
+//! Point a branch at the current commit and check it out.
+//!
+//! Navigating with `git prev` and `git next` leaves `HEAD` detached. Once the
+//! user has arrived at the commit they want, this attaches `HEAD` to a branch
+//! there, creating the branch or moving it from wherever it pointed before.
+
+use std::io::{stdin, BufReader, Write};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use fn_error_context::context;
+
+use crate::core::eventlog::EventLogDb;
+use crate::util::{
+    get_db_conn, get_head_oid, get_repo, prompt_input, run_git, GitExecutable,
+    EXIT_CODE_BAD_ARGUMENTS,
+};
+
+/// Point a branch at `HEAD` and check it out, creating the branch if it
+/// doesn't exist, or moving it to `HEAD` if it does.
+///
+/// The branch is updated in its own event-log transaction, so that it can be
+/// moved back with `git undo`.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `branch`: The name of the branch to point at `HEAD`. If not provided, the
+///   user is prompted for it.
+///
+/// Returns: exit code. This is one of:
+/// * 0: The branch was checked out at `HEAD`.
+/// * `EXIT_CODE_BAD_ARGUMENTS` (2): There is no `HEAD` commit to point the
+///   branch at, or no branch name was entered.
+///
+/// Other nonzero exit codes may be passed through from `git` itself, such as
+/// when the branch name isn't valid.
+#[context("Reattaching HEAD to a branch")]
+pub fn reattach(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    branch: Option<String>,
+) -> anyhow::Result<isize> {
+    let mut in_ = BufReader::new(stdin());
+    let repo = get_repo()?;
+    if get_head_oid(&repo)?.is_none() {
+        writeln!(
+            out,
+            "There is no commit checked out to point the branch at."
+        )?;
+        return Ok(EXIT_CODE_BAD_ARGUMENTS);
+    }
+
+    let branch = match branch {
+        Some(branch) => branch,
+        None => prompt_input(&mut in_, out, "Enter a name for the branch: ")
+            .context("Pass the branch name as an argument to reattach non-interactively")?,
+    };
+    if branch.is_empty() {
+        writeln!(out, "No branch name provided.")?;
+        return Ok(EXIT_CODE_BAD_ARGUMENTS);
+    }
+
+    let conn = get_db_conn(&repo)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "reattach")?;
+    // `checkout -B` both points the branch at `HEAD` and checks it out, so the
+    // branch update and the checkout are recorded together.
+    run_git(
+        out,
+        git_executable,
+        Some(event_tx_id),
+        &["checkout", "-B", &branch],
+    )
+}
@@ -0,0 +1,287 @@
+//! Reconcile the event log with the actual state of the repository.
+//!
+//! The event log is populated by Git hooks. If a hook fails to run (such as
+//! because `git-branchless` wasn't on the `PATH` at the time, or because the
+//! `reference-transaction` hook failed), then the corresponding events are
+//! lost, and the smartlog no longer matches what's actually in the repository.
+//! This command records synthetic events to fill in those gaps.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::io::Write;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use fn_error_context::context;
+
+use crate::core::config::get_core_abbrev;
+use crate::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId};
+use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize};
+use crate::core::rewrite::friendly_describe_commit;
+use crate::util::{abbreviate_oid, get_db_conn, get_head_oid, get_repo};
+
+/// The prefixes of the references whose updates are compared against the
+/// event log.
+const REPAIRED_REF_PREFIXES: &[&str] = &["refs/heads/", "refs/tags/"];
+
+/// Find commits which were made or amended according to the `HEAD` reflog, but
+/// which have no corresponding events in the event log.
+///
+/// Reflog entries from before the oldest entry whose commit appears in the
+/// event log are ignored, since they were presumably made before
+/// `git-branchless` was installed, and the user wouldn't expect those commits
+/// to start showing up in the smartlog.
+///
+/// Returns: The synthetic events to record for the missing commits.
+#[context("Finding commits missing from the event log")]
+fn find_missing_commit_events(
+    repo: &git2::Repository,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    timestamp: f64,
+    event_tx_id: EventTransactionId,
+) -> anyhow::Result<Vec<Event>> {
+    let reflog = match repo.reflog("HEAD") {
+        Ok(reflog) => reflog,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).context("Reading reflog for HEAD"),
+    };
+    let is_known_commit = |oid: git2::Oid| -> bool {
+        event_replayer
+            .get_cursor_commit_latest_event(event_cursor, oid)
+            .is_some()
+    };
+
+    let mut result = Vec::new();
+    let mut seen_oids: HashSet<git2::Oid> = HashSet::new();
+    let mut is_after_install = false;
+    // The reflog is iterated from newest to oldest entry.
+    let entries: Vec<git2::ReflogEntry> = reflog.iter().collect();
+    for entry in entries.iter().rev() {
+        let new_oid = entry.id_new();
+        if is_known_commit(new_oid) {
+            is_after_install = true;
+            continue;
+        }
+        if !is_after_install || seen_oids.contains(&new_oid) {
+            continue;
+        }
+        // The commit may have been garbage-collected since.
+        if repo.find_commit(new_oid).is_err() {
+            continue;
+        }
+
+        let message = entry.message().unwrap_or_default();
+        if message.starts_with("commit (amend):") {
+            seen_oids.insert(new_oid);
+            result.push(Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid: entry.id_old(),
+                new_commit_oid: new_oid,
+            });
+        } else if message.starts_with("commit:") || message.starts_with("commit (") {
+            seen_oids.insert(new_oid);
+            result.push(Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: new_oid,
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// Find references (including `HEAD`) which point somewhere other than where
+/// the event log says they do.
+///
+/// Returns: The synthetic events to record to move the references in the event
+/// log to their actual locations.
+#[context("Finding references missing from the event log")]
+fn find_missing_ref_update_events(
+    repo: &git2::Repository,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    timestamp: f64,
+    event_tx_id: EventTransactionId,
+) -> anyhow::Result<Vec<Event>> {
+    let make_event = |ref_name: &str, old_oid: Option<git2::Oid>, new_oid: Option<git2::Oid>| {
+        Event::RefUpdateEvent {
+            timestamp,
+            event_tx_id,
+            ref_name: ref_name.to_string(),
+            old_ref: old_oid.map(|oid| oid.to_string()),
+            new_ref: new_oid.map(|oid| oid.to_string()),
+            message: None,
+        }
+    };
+
+    let mut result = Vec::new();
+    let recorded_head_oid = event_replayer.get_cursor_head_oid(event_cursor);
+    let actual_head_oid = get_head_oid(repo)?;
+    if actual_head_oid.is_some() && actual_head_oid != recorded_head_oid {
+        result.push(make_event("HEAD", recorded_head_oid, actual_head_oid));
+    }
+
+    let mut recorded_ref_name_to_oid = event_replayer.get_cursor_ref_name_to_oid(event_cursor);
+    recorded_ref_name_to_oid.retain(|ref_name, _| {
+        REPAIRED_REF_PREFIXES
+            .iter()
+            .any(|prefix| ref_name.starts_with(prefix))
+    });
+    let mut actual_ref_names: Vec<(String, git2::Oid)> = Vec::new();
+    for reference in repo.references().context("Getting repo references")? {
+        let reference = reference.context("Reading reference info")?;
+        let (ref_name, oid) = match (reference.name(), reference.target()) {
+            (Some(ref_name), Some(oid)) => (ref_name, oid),
+            _ => continue,
+        };
+        if REPAIRED_REF_PREFIXES
+            .iter()
+            .any(|prefix| ref_name.starts_with(prefix))
+        {
+            actual_ref_names.push((ref_name.to_string(), oid));
+        }
+    }
+    actual_ref_names.sort();
+
+    for (ref_name, actual_oid) in actual_ref_names.iter() {
+        let recorded_oid = recorded_ref_name_to_oid.remove(ref_name);
+        if recorded_oid != Some(*actual_oid) {
+            result.push(make_event(ref_name, recorded_oid, Some(*actual_oid)));
+        }
+    }
+
+    // Any references left over were deleted without the event log noticing.
+    let mut deleted_refs: Vec<(String, git2::Oid)> = recorded_ref_name_to_oid.into_iter().collect();
+    deleted_refs.sort();
+    for (ref_name, recorded_oid) in deleted_refs {
+        result.push(make_event(&ref_name, Some(recorded_oid), None));
+    }
+
+    Ok(result)
+}
+
+/// Describe a synthetic event recorded by `repair` for the user.
+fn describe_repair_event(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    event: &Event,
+) -> anyhow::Result<String> {
+    let describe_commit = |oid: git2::Oid| -> anyhow::Result<String> {
+        printable_styled_string(glyphs, friendly_describe_commit(repo, oid)?)
+    };
+    let abbrev_len = get_core_abbrev(repo)?;
+    let describe_ref = |ref_value: &Option<String>| -> anyhow::Result<String> {
+        match ref_value {
+            Some(ref_value) => match git2::Oid::from_str(ref_value) {
+                Ok(oid) => abbreviate_oid(repo, oid, abbrev_len),
+                Err(_) => Ok(ref_value.clone()),
+            },
+            None => Ok("(none)".to_string()),
+        }
+    };
+    let description = match event {
+        Event::CommitEvent { commit_oid, .. } => {
+            format!("Recorded commit: {}", describe_commit(*commit_oid)?)
+        }
+        Event::RewriteEvent {
+            old_commit_oid,
+            new_commit_oid,
+            ..
+        } => format!(
+            "Recorded amended commit: {} (was {})",
+            describe_commit(*new_commit_oid)?,
+            abbreviate_oid(repo, *old_commit_oid, abbrev_len)?
+        ),
+        Event::RefUpdateEvent {
+            ref_name,
+            old_ref,
+            new_ref,
+            ..
+        } => format!(
+            "Recorded update to {}: {} -> {}",
+            ref_name,
+            describe_ref(old_ref)?,
+            describe_ref(new_ref)?
+        ),
+        Event::HideEvent { .. } | Event::UnhideEvent { .. } | Event::MoveEvent { .. } => {
+            anyhow::bail!("BUG: unexpected event recorded by repair: {:?}", event)
+        }
+    };
+    Ok(description)
+}
+
+/// Compare the event log against the current references and the `HEAD`
+/// reflog, and record synthetic events for anything that's missing, so that
+/// the smartlog matches the repository again.
+///
+/// This reconciles:
+/// * Commits made or amended according to the `HEAD` reflog, but which the
+///   `post-commit` or `post-rewrite` hooks failed to record.
+/// * Branches and tags, as well as `HEAD`, which were moved, created, or
+///   deleted without the `reference-transaction` hook recording it.
+///
+/// Running the command again immediately afterward has no effect, since there
+/// will be no more gaps to fill in.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+///
+/// Returns: exit code (0 denotes successful exit).
+#[context("Repairing the event log")]
+pub fn repair(out: &mut impl Write) -> anyhow::Result<isize> {
+    let glyphs = Glyphs::detect();
+    let repo = get_repo()?;
+    let conn = get_db_conn(&repo)?;
+    let mut event_log_db = EventLogDb::new(&conn)?;
+    let mut event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+
+    let now = SystemTime::now();
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let event_tx_id = event_log_db.make_transaction_id(now, "repair")?;
+    let mut events = find_missing_commit_events(
+        &repo,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        timestamp,
+        event_tx_id,
+    )?;
+    // Commit events also move `HEAD` as far as the event replayer is concerned,
+    // so account for them before comparing the references.
+    for event in events.iter() {
+        event_replayer.process_event(event);
+    }
+    events.extend(find_missing_ref_update_events(
+        &repo,
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        timestamp,
+        event_tx_id,
+    )?);
+
+    if events.is_empty() {
+        writeln!(
+            out,
+            "The event log already matches the repository; there is nothing to repair."
+        )?;
+        return Ok(0);
+    }
+
+    for event in events.iter() {
+        writeln!(out, "{}", describe_repair_event(&glyphs, &repo, event)?)?;
+    }
+    let num_events = events.len();
+    event_log_db.add_events(events)?;
+    writeln!(
+        out,
+        "Repaired the event log by recording {}.",
+        Pluralize {
+            amount: num_events.try_into()?,
+            singular: "event",
+            plural: "events",
+        }
+        .to_string()
+    )?;
+    Ok(0)
+}
@@ -55,21 +55,24 @@
 //! o def003 Commit 3
 //! ```
 
+use std::io::stdout;
 use std::time::SystemTime;
 
 use anyhow::Context;
 use fn_error_context::context;
 use log::info;
 
-use crate::commands::smartlog::smartlog;
-use crate::core::config::get_restack_preserve_timestamps;
+use crate::commands::smartlog::{smartlog, SmartlogOptions};
+use crate::core::config::{get_restack_preserve_timestamps, get_rewrite_sign};
 use crate::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
-use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::core::formatting::Glyphs;
+use crate::core::graph::{make_graph_from_repo, AncestryCache};
 use crate::core::mergebase::MergeBaseDb;
-use crate::core::rewrite::{find_abandoned_children, find_rewrite_target};
+use crate::core::rewrite::{
+    find_abandoned_children, find_rewrite_target, run_exec_commands, ExecOptions,
+};
 use crate::util::{
-    get_branch_oid_to_names, get_db_conn, get_head_oid, get_main_branch_oid, get_repo, run_git,
-    GitExecutable,
+    get_db_conn, get_head_oid, get_repo, run_git, GitExecutable, EXIT_CODE_CONFLICT,
 };
 
 #[context("Restacking commits")]
@@ -79,22 +82,20 @@ fn restack_commits(
     merge_base_db: &MergeBaseDb,
     event_log_db: &EventLogDb,
     event_tx_id: EventTransactionId,
+    committer_date_is_author_date: bool,
 ) -> anyhow::Result<isize> {
     let event_replayer = EventReplayer::from_event_log_db(event_log_db)?;
-    let head_oid = get_head_oid(repo)?;
-    let main_branch_oid = get_main_branch_oid(repo)?;
-    let branch_oid_to_names = get_branch_oid_to_names(repo)?;
-    let graph = make_graph(
+    let graph = make_graph_from_repo(
         repo,
         merge_base_db,
+        &mut AncestryCache::default(),
         &event_replayer,
         event_replayer.make_default_cursor(),
-        &HeadOid(head_oid),
-        &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().copied().collect()),
         true,
     )?;
-    let preserve_timestamps = get_restack_preserve_timestamps(&repo)?;
+    let preserve_timestamps =
+        committer_date_is_author_date || get_restack_preserve_timestamps(&repo)?;
+    let sign = get_rewrite_sign(repo)?;
 
     for original_oid in graph.keys() {
         let (rewritten_oid, abandoned_child_oids) = match find_abandoned_children(
@@ -129,12 +130,15 @@ fn restack_commits(
             if preserve_timestamps {
                 args.push("--committer-date-is-author-date");
             }
+            if sign {
+                args.push("--gpg-sign");
+            }
             args
         };
-        let result = run_git(git_executable, Some(event_tx_id), &args)?;
+        let result = run_git(&mut stdout(), git_executable, Some(event_tx_id), &args)?;
         if result != 0 {
             println!("branchless: resolve rebase, then run 'git restack' again");
-            return Ok(result);
+            return Ok(EXIT_CODE_CONFLICT);
         }
 
         // Repeat until we reach a fixed point.
@@ -144,6 +148,7 @@ fn restack_commits(
             merge_base_db,
             event_log_db,
             event_tx_id,
+            committer_date_is_author_date,
         );
     }
 
@@ -160,17 +165,12 @@ fn restack_branches(
     event_tx_id: EventTransactionId,
 ) -> anyhow::Result<isize> {
     let event_replayer = EventReplayer::from_event_log_db(event_log_db)?;
-    let head_oid = get_head_oid(repo)?;
-    let main_branch_oid = get_main_branch_oid(repo)?;
-    let branch_oid_to_names = get_branch_oid_to_names(repo)?;
-    let graph = make_graph(
+    let graph = make_graph_from_repo(
         repo,
         merge_base_db,
+        &mut AncestryCache::default(),
         &event_replayer,
         event_replayer.make_default_cursor(),
-        &HeadOid(head_oid),
-        &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().copied().collect()),
         true,
     )?;
 
@@ -210,7 +210,7 @@ fn restack_branches(
             None => anyhow::bail!("Invalid UTF-8 branch name: {:?}", branch.name_bytes()?),
         };
         let args = ["branch", "-f", branch_name, &new_oid];
-        let result = run_git(git_executable, Some(event_tx_id), &args)?;
+        let result = run_git(&mut stdout(), git_executable, Some(event_tx_id), &args)?;
         if result != 0 {
             return Ok(result);
         } else {
@@ -234,10 +234,22 @@ fn restack_branches(
 /// * `out`: The output stream to write to.
 /// * `err`: The error stream to write to.
 /// * `git_executable`: The path to the `git` executable on disk.
+/// * `committer_date_is_author_date`: Set the committer date of each restacked
+///   commit to its author date, as with
+///   `git rebase --committer-date-is-author-date`. This is always done if
+///   `branchless.restack.preserveTimestamps` is set.
+/// * `exec_options`: Commands to run once the commits and branches have been
+///   restacked. See `run_exec_commands`.
 ///
-/// Returns: Exit code (0 denotes successful exit).
+/// Returns: Exit code (0 denotes successful exit). If a commit couldn't be
+/// restacked because of a merge conflict, this is `EXIT_CODE_CONFLICT`. If an
+/// exec command failed, this is its exit code.
 #[context("Restacking commits and branches")]
-pub fn restack(git_executable: &GitExecutable) -> anyhow::Result<isize> {
+pub fn restack(
+    git_executable: &GitExecutable,
+    committer_date_is_author_date: bool,
+    exec_options: &ExecOptions,
+) -> anyhow::Result<isize> {
     let repo = get_repo()?;
     let conn = get_db_conn(&repo)?;
     let merge_base_db = MergeBaseDb::new(&conn)?;
@@ -251,6 +263,7 @@ pub fn restack(git_executable: &GitExecutable) -> anyhow::Result<isize> {
         &merge_base_db,
         &event_log_db,
         event_tx_id,
+        committer_date_is_author_date,
     )?;
     if result != 0 {
         return Ok(result);
@@ -269,13 +282,26 @@ pub fn restack(git_executable: &GitExecutable) -> anyhow::Result<isize> {
 
     let result = match head_oid {
         Some(head_oid) => run_git(
+            &mut stdout(),
             &git_executable,
             Some(event_tx_id),
             &["checkout", &head_oid.to_string()],
         )?,
         None => result,
     };
+    if result != 0 {
+        return Ok(result);
+    }
+
+    let result = run_exec_commands(
+        &mut stdout(),
+        &Glyphs::detect(),
+        &repo,
+        &event_log_db,
+        event_tx_id,
+        exec_options,
+    )?;
 
-    smartlog()?;
+    smartlog(&SmartlogOptions::default())?;
     Ok(result)
 }
@@ -4,24 +4,47 @@
 //! log; see the `eventlog` module.
 
 use std::cmp::Ordering;
-use std::time::SystemTime;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryInto;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
-use cursive::theme::Effect;
+use anyhow::Context;
+use cursive::theme::{BaseColor, Color, Effect};
 use cursive::utils::markup::StyledString;
 use fn_error_context::context;
+use rusqlite::OptionalExtension;
 
-use crate::core::eventlog::{EventLogDb, EventReplayer};
-use crate::core::formatting::set_effect;
+use crate::core::config::{
+    get_commit_metadata_relative_time, get_core_abbrev, get_main_branch_name,
+    get_smartlog_body_wrap_width, get_smartlog_cache, get_smartlog_context_commits,
+    get_smartlog_head_glyph, get_smartlog_legend_hint, get_smartlog_page_size,
+    get_smartlog_show_stashes, get_smartlog_summary_column, get_smartlog_wip_prefixes,
+    set_smartlog_legend_hint,
+};
+use crate::core::diffstat::{get_diff_stats, DiffStatDb};
+use crate::core::eventlog::{CommitVisibility, EventLogDb, EventReplayer};
 use crate::core::formatting::{printable_styled_string, Glyphs, StyledStringBuilder};
-use crate::core::graph::{make_graph, BranchOids, CommitGraph, HeadOid, MainBranchOid};
+use crate::core::formatting::{set_effect, truncate_styled_string};
+use crate::core::graph::{
+    filter_graph, get_graph_components, get_sorted_children, make_graph_from_commit_oids,
+    make_graph_in_time_range, AdditionalMainBranchOids, AncestryCache, BranchOids, CommitGraph,
+    CommitOids, CommitTimeRange, HeadOid, MainBranchOid, Node, TagOids, UnhideableRefOids,
+};
 use crate::core::mergebase::MergeBaseDb;
 use crate::core::metadata::{
-    render_commit_metadata, BranchesProvider, CommitMessageProvider, CommitMetadataProvider,
-    CommitOidProvider, DifferentialRevisionProvider, HiddenExplanationProvider,
-    RelativeTimeProvider,
+    get_operation_state, render_commit_metadata, AheadBehindProvider, BranchesProvider,
+    CommitMessageProvider, CommitMetadataProvider, CommitOidProvider, CommitStatusProvider,
+    DiffStatProvider, DifferentialRevisionProvider, HiddenExplanationProvider, OperationState,
+    OperationStateProvider, RelativeTimeProvider, StashesProvider, TagsProvider,
+    WorkingCopyStatusProvider,
 };
+use crate::core::timing::start_timer;
 use crate::util::{
-    get_branch_oid_to_names, get_db_conn, get_head_oid, get_main_branch_oid, get_repo,
+    abbreviate_oid, get_additional_main_branch_oids, get_branch_oid_to_names, get_db_conn,
+    get_head_oid, get_main_branch_oid, get_pager, get_repo, get_stash_oid_to_names,
+    get_tag_oid_to_names, get_unhideable_ref_oids, glob_matches, run_pager,
 };
 
 /// Split fully-independent subgraphs into multiple graphs.
@@ -74,9 +97,87 @@ fn split_commit_graph_by_roots(
     };
 
     root_commit_oids.sort_by(compare);
+
+    // Lines of work which aren't connected to the main branch are rendered
+    // separately at the end.
+    root_commit_oids.sort_by_key(|oid| is_detached_root(graph, *oid));
     root_commit_oids
 }
 
+/// Whether the given root commit has parents which were cut off from the
+/// graph and isn't on the main branch, so that it's not connected to any other
+/// root. For example, it may be at the boundary of a shallow clone, or its
+/// main branch parent may have been removed by `prune_main_branch_tail`.
+fn is_detached_root(graph: &CommitGraph, oid: git2::Oid) -> bool {
+    let node = &graph[&oid];
+    !node.is_main && (node.is_shallow_boundary || node.commit.parent_count() > 0)
+}
+
+/// The narrowest that we'll wrap commit bodies to, no matter how deeply nested
+/// the commit is in the graph.
+const MIN_BODY_WRAP_WIDTH: usize = 20;
+
+/// Get the body of the commit message, i.e. everything after the summary
+/// paragraph.
+///
+/// Returns: The body of the commit message, or `None` if it's empty.
+fn get_commit_body(commit: &git2::Commit) -> Option<String> {
+    let message = commit.message()?;
+    let (_summary, body) = message.trim_start().split_once("\n\n")?;
+    let body = body.trim();
+    if body.is_empty() {
+        None
+    } else {
+        Some(body.to_owned())
+    }
+}
+
+/// Render the body of the given commit, wrapped to the given width and
+/// indented under its summary line. The `connector` glyph is drawn in the graph
+/// column to the left of the body, so that the graph lines continue unbroken
+/// past it.
+fn render_commit_body(body: &str, connector: &str, width: usize) -> Vec<StyledString> {
+    let indent = format!("{}   ", connector);
+    let wrap_width = width
+        .saturating_sub(console::measure_text_width(&indent))
+        .max(MIN_BODY_WRAP_WIDTH);
+    body.lines()
+        .flat_map(|line| {
+            if line.trim().is_empty() {
+                vec![StyledString::plain(connector.trim_end())]
+            } else {
+                textwrap::wrap(line, wrap_width)
+                    .into_iter()
+                    .map(|wrapped_line| StyledString::plain(format!("{}{}", indent, wrapped_line)))
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+/// Get the glyph to draw in the graph for the given node.
+fn get_cursor_glyph(
+    glyphs: &Glyphs,
+    node: &Node,
+    is_head: bool,
+    is_fork_point: bool,
+) -> &'static str {
+    if node.is_stash && !is_head {
+        return glyphs.commit_stash;
+    }
+    match (node.is_main, node.is_visible, is_head) {
+        (false, false, false) => glyphs.commit_hidden,
+        (false, false, true) => glyphs.commit_hidden_head,
+        (false, true, false) => glyphs.commit_visible,
+        (false, true, true) => glyphs.commit_visible_head,
+        (true, false, false) => glyphs.commit_main_hidden,
+        (true, false, true) => glyphs.commit_main_hidden_head,
+        (true, true, false) if is_fork_point => glyphs.commit_main_fork_point,
+        (true, true, false) => glyphs.commit_main,
+        (true, true, true) => glyphs.commit_main_head,
+    }
+}
+
 #[context("Getting child smartlog output for OID {:?}", &current_oid)]
 fn get_child_output(
     glyphs: &Glyphs,
@@ -84,8 +185,12 @@ fn get_child_output(
     root_oids: &[git2::Oid],
     commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
     head_oid: &HeadOid,
+    fork_point_oid: Option<git2::Oid>,
     current_oid: git2::Oid,
     last_child_line_char: Option<&str>,
+    body_width: Option<usize>,
+    summary_column: Option<usize>,
+    main_branch_color: Option<Color>,
 ) -> anyhow::Result<Vec<StyledString>> {
     let current_node = &graph[&current_oid];
     let is_head = {
@@ -93,38 +198,84 @@ fn get_child_output(
         Some(current_node.commit.id()) == *head_oid
     };
 
-    let text = render_commit_metadata(&current_node.commit, commit_metadata_providers)?;
-    let cursor = match (current_node.is_main, current_node.is_visible, is_head) {
-        (false, false, false) => glyphs.commit_hidden,
-        (false, false, true) => glyphs.commit_hidden_head,
-        (false, true, false) => glyphs.commit_visible,
-        (false, true, true) => glyphs.commit_visible_head,
-        (true, false, false) => glyphs.commit_main_hidden,
-        (true, false, true) => glyphs.commit_main_hidden_head,
-        (true, true, false) => glyphs.commit_main,
-        (true, true, true) => glyphs.commit_main_head,
+    let (text, summary) = match summary_column {
+        Some(_) => {
+            let mut descriptions = Vec::new();
+            let mut summary_descriptions = Vec::new();
+            for provider in commit_metadata_providers.iter_mut() {
+                let description =
+                    render_commit_metadata(&current_node.commit, std::slice::from_mut(provider))?;
+                if description.is_empty() {
+                    continue;
+                }
+                if provider.is_summary() {
+                    summary_descriptions.push(description);
+                } else {
+                    descriptions.push(description);
+                }
+            }
+            (
+                StyledStringBuilder::join(" ", descriptions),
+                StyledStringBuilder::join(" ", summary_descriptions),
+            )
+        }
+        None => (
+            render_commit_metadata(&current_node.commit, commit_metadata_providers)?,
+            StyledString::new(),
+        ),
     };
+    let cursor = get_cursor_glyph(
+        glyphs,
+        current_node,
+        is_head,
+        fork_point_oid == Some(current_oid),
+    );
 
     let first_line = {
         let mut first_line = StyledString::new();
-        first_line.append_plain(cursor);
+        match main_branch_color {
+            Some(main_branch_color) if current_node.is_main => {
+                first_line.append_styled(cursor, main_branch_color)
+            }
+            _ => first_line.append_plain(cursor),
+        }
         first_line.append_plain(" ");
         first_line.append(text);
+        if let (Some(summary_column), false) = (summary_column, summary.is_empty()) {
+            // If the line is already past the summary column, just separate
+            // the summary with a single space.
+            let padding = summary_column.saturating_sub(first_line.width()).max(1);
+            first_line.append_plain(" ".repeat(padding));
+            first_line.append(summary);
+        }
         if is_head {
-            set_effect(first_line, Effect::Bold)
+            // Highlight the whole line, so that it stands out in a dense
+            // graph. The effects aren't rendered if colors are disabled.
+            set_effect(set_effect(first_line, Effect::Bold), Effect::Reverse)
         } else {
             first_line
         }
     };
 
     let mut lines = vec![first_line];
-    let mut children: Vec<_> = current_node
-        .children
-        .iter()
-        .filter(|child_oid| graph.contains_key(child_oid))
-        .copied()
-        .collect();
-    children.sort_by_key(|child_oid| (graph[child_oid].commit.time(), child_oid.to_string()));
+    let children = get_sorted_children(graph, current_oid);
+
+    if let Some(body_width) = body_width {
+        if current_node.is_visible {
+            if let Some(body) = get_commit_body(&current_node.commit) {
+                let has_rendered_children = children
+                    .iter()
+                    .any(|child_oid| !root_oids.contains(child_oid));
+                let connector = if has_rendered_children {
+                    glyphs.line
+                } else {
+                    last_child_line_char.unwrap_or(" ")
+                };
+                lines.extend(render_commit_body(&body, connector, body_width));
+            }
+        }
+    }
+
     for (child_idx, child_oid) in children.iter().enumerate() {
         if root_oids.contains(child_oid) {
             // Will be rendered by the parent.
@@ -147,14 +298,27 @@ fn get_child_output(
             )))
         }
 
+        let is_child_indented = child_idx != children.len() - 1 || last_child_line_char.is_some();
+        let (child_body_width, child_summary_column) = if is_child_indented {
+            (
+                body_width.map(|body_width| body_width.saturating_sub(2)),
+                summary_column.map(|summary_column| summary_column.saturating_sub(2)),
+            )
+        } else {
+            (body_width, summary_column)
+        };
         let child_output = get_child_output(
             glyphs,
             graph,
             root_oids,
             commit_metadata_providers,
             head_oid,
+            fork_point_oid,
             *child_oid,
             None,
+            child_body_width,
+            child_summary_column,
+            main_branch_color,
         )?;
         for child_line in child_output {
             let line = if child_idx == children.len() - 1 {
@@ -188,7 +352,11 @@ fn get_output(
     graph: &CommitGraph,
     commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
     head_oid: &HeadOid,
+    fork_point_oid: Option<git2::Oid>,
     root_oids: &[git2::Oid],
+    body_width: Option<usize>,
+    summary_column: Option<usize>,
+    main_branch_color: Option<Color>,
 ) -> anyhow::Result<Vec<StyledString>> {
     let mut lines = Vec::new();
 
@@ -206,14 +374,28 @@ fn get_output(
 
     for (root_idx, root_oid) in root_oids.iter().enumerate() {
         let root_node = &graph[root_oid];
-        if root_node.commit.parent_count() > 0 {
-            let line = if root_idx > 0 && has_real_parent(*root_oid, root_oids[root_idx - 1]) {
+        let is_detached = is_detached_root(graph, *root_oid);
+        if root_idx > 0 && is_detached {
+            // Multiple topologically-unrelated roots. Separate them with a
+            // newline.
+            lines.push(StyledString::new());
+        }
+        if root_node.is_shallow_boundary {
+            lines.push(StyledString::plain(format!(
+                "{} (shallow boundary)",
+                glyphs.vertical_ellipsis
+            )));
+        } else if root_node.commit.parent_count() > 0 {
+            let line = if root_idx > 0
+                && !is_detached
+                && has_real_parent(*root_oid, root_oids[root_idx - 1])
+            {
                 StyledString::plain(glyphs.line.to_owned())
             } else {
                 StyledString::plain(glyphs.vertical_ellipsis.to_owned())
             };
             lines.push(line);
-        } else if root_idx > 0 {
+        } else if root_idx > 0 && !is_detached {
             // Pathological case: multiple topologically-unrelated roots.
             // Separate them with a newline.
             lines.push(StyledString::new());
@@ -224,7 +406,9 @@ fn get_output(
                 None
             } else {
                 let next_root_oid = root_oids[root_idx + 1];
-                if has_real_parent(next_root_oid, *root_oid) {
+                if is_detached_root(graph, next_root_oid) {
+                    None
+                } else if has_real_parent(next_root_oid, *root_oid) {
                     Some(glyphs.line)
                 } else {
                     Some(glyphs.vertical_ellipsis)
@@ -238,8 +422,12 @@ fn get_output(
             root_oids,
             commit_metadata_providers,
             head_oid,
+            fork_point_oid,
             *root_oid,
             last_child_line_char,
+            body_width,
+            summary_column,
+            main_branch_color,
         )?;
         lines.extend(child_output.into_iter());
     }
@@ -247,69 +435,1770 @@ fn get_output(
     Ok(lines)
 }
 
-/// Render the smartlog graph and write it to the provided stream.
+/// Find the main branch commit which `HEAD` forked off from, if it's in the
+/// graph. There's no fork point if `HEAD` is on the main branch.
+fn find_fork_point_oid(
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    graph: &CommitGraph,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+) -> anyhow::Result<Option<git2::Oid>> {
+    let (head_oid, MainBranchOid(main_branch_oid)) = match head_oid {
+        HeadOid(Some(head_oid)) => (*head_oid, main_branch_oid),
+        HeadOid(None) => return Ok(None),
+    };
+    let fork_point_oid = merge_base_db.get_merge_base_oid(repo, head_oid, *main_branch_oid)?;
+    Ok(fork_point_oid
+        .filter(|fork_point_oid| *fork_point_oid != head_oid && graph.contains_key(fork_point_oid)))
+}
+
+/// Options for rendering the smartlog graph with `render_graph`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderOptions {
+    /// If provided, then the body of each visible commit's message is rendered
+    /// under its summary line, wrapped to fit in that many columns.
+    pub body_width: Option<usize>,
+
+    /// If provided, then the description from the summary provider (see
+    /// `CommitMetadataProvider::is_summary`) is padded so that it starts at
+    /// that column, and the summaries of all commits line up. If the rest of
+    /// the line is already wider than that, then the summary is separated by a
+    /// single space instead.
+    pub summary_column: Option<usize>,
+
+    /// If provided, then the glyphs for commits on the main branch are
+    /// rendered in that color.
+    pub main_branch_color: Option<Color>,
+
+    /// If provided, then each line is truncated to fit in that many columns,
+    /// with `Glyphs::ellipsis` appended to lines which were cut off.
+    pub max_width: Option<usize>,
+}
+
+/// Render the smartlog graph into lines, without writing them anywhere. This
+/// doesn't depend on the terminal, so the lines can be embedded elsewhere.
+///
+/// The main branch commit which `HEAD` forked off from (the merge-base of
+/// `HEAD` and `main_branch_oid`) is rendered with a distinct glyph, unless
+/// `HEAD` is itself on the main branch.
 pub fn render_graph(
     glyphs: &Glyphs,
     repo: &git2::Repository,
     merge_base_db: &MergeBaseDb,
     graph: &CommitGraph,
     head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
     commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
+    options: &RenderOptions,
 ) -> anyhow::Result<Vec<StyledString>> {
+    let RenderOptions {
+        body_width,
+        summary_column,
+        main_branch_color,
+        max_width,
+    } = *options;
     let root_oids = split_commit_graph_by_roots(repo, merge_base_db, graph);
+    let fork_point_oid =
+        find_fork_point_oid(repo, merge_base_db, graph, head_oid, main_branch_oid)?;
     let lines = get_output(
         glyphs,
         graph,
         commit_metadata_providers,
         head_oid,
+        fork_point_oid,
         &root_oids,
+        body_width,
+        summary_column,
+        main_branch_color,
+    )?;
+    let lines = match max_width {
+        Some(max_width) => lines
+            .into_iter()
+            .map(|line| truncate_styled_string(glyphs, line, max_width))
+            .collect(),
+        None => lines,
+    };
+    Ok(lines)
+}
+
+/// Render the smartlog for the given commit graph as plain lines, with the
+/// default commit metadata, such as would be shown by `git smartlog`. Unlike
+/// the `smartlog` command, this doesn't touch the terminal, the pager, or the
+/// smartlog cache, so it's suitable for embedding the smartlog elsewhere.
+///
+/// Args:
+/// * `glyphs`: The glyphs to render with. ANSI escape codes are only included
+///   in the output if `glyphs` is configured to write them.
+/// * `repo`: The Git repository.
+/// * `graph`: The commit graph to render, such as from `make_smartlog_graph`.
+/// * `max_width`: If provided, each line is truncated to fit in that many
+///   columns.
+///
+/// Returns: The rendered lines, without trailing newlines.
+#[context("Rendering smartlog graph")]
+pub fn render_smartlog_graph(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    graph: &CommitGraph,
+    max_width: Option<usize>,
+) -> anyhow::Result<Vec<String>> {
+    let conn = get_db_conn(repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let head_oid = get_head_oid(repo)?;
+    let main_branch_oid = get_main_branch_oid(repo)?;
+    let branch_oid_to_names = get_branch_oid_to_names(repo)?;
+    let tag_oid_to_names = get_tag_oid_to_names(repo)?;
+    let lines = render_graph(
+        glyphs,
+        repo,
+        &merge_base_db,
+        graph,
+        &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
+        &mut [
+            &mut CommitOidProvider::new(repo, true)?,
+            &mut RelativeTimeProvider::new(repo, SystemTime::now())?,
+            &mut BranchesProvider::new(repo, &branch_oid_to_names)?,
+            &mut TagsProvider::new(repo, &tag_oid_to_names)?,
+            &mut CommitMessageProvider::new()?.with_wip_prefixes(get_smartlog_wip_prefixes(repo)?),
+        ],
+        &RenderOptions {
+            summary_column: get_smartlog_summary_column(repo)?,
+            max_width,
+            ..Default::default()
+        },
     )?;
+    lines
+        .into_iter()
+        .map(|line| printable_styled_string(glyphs, line))
+        .collect()
+}
+
+/// A set of commits in the smartlog which are rendered together under a
+/// header line, when grouping the smartlog by branch.
+#[derive(Debug)]
+struct BranchGroup {
+    /// The header to render above the commits.
+    header: String,
+
+    /// The OIDs of the commits in the group.
+    oids: HashSet<git2::Oid>,
+
+    /// The names of the other branches which some commits in this group are
+    /// also reachable from.
+    shared_with: Vec<String>,
+
+    /// The headers of the other groups which contain the parents of some
+    /// commits in this group.
+    based_on: Vec<String>,
+}
+
+/// Partition the nodes of the graph by the branch they belong to.
+///
+/// Each non-main commit is assigned to the nearest branch whose tip it's
+/// reachable from (ties are broken by branch name). Non-main commits which
+/// aren't reachable from any branch are put in a group with the header
+/// `(no branch)`, and the main branch commits are put in a group by
+/// themselves, which comes first.
+///
+/// Returns: The groups, in the order that they should be rendered.
+fn group_commits_by_branch(
+    graph: &CommitGraph,
+    main_branch_name: &str,
+    branch_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+) -> Vec<BranchGroup> {
+    // For each commit, the distance to and name of each branch it's reachable
+    // from.
+    let mut reachable_from: HashMap<git2::Oid, Vec<(usize, String)>> = HashMap::new();
+    for (branch_oid, branch_names) in branch_oid_to_names {
+        let mut branch_names: Vec<&str> = branch_names.iter().map(String::as_str).collect();
+        branch_names.sort_unstable();
+        let branch_name = branch_names.join(", ");
+
+        let mut current_oid = Some(*branch_oid);
+        let mut distance = 0;
+        while let Some(oid) = current_oid {
+            let node = match graph.get(&oid) {
+                Some(node) if !node.is_main => node,
+                _ => break,
+            };
+            reachable_from
+                .entry(oid)
+                .or_default()
+                .push((distance, branch_name.clone()));
+            current_oid = node.parent;
+            distance += 1;
+        }
+    }
+
+    let mut main_group = BranchGroup {
+        header: main_branch_name.to_string(),
+        oids: HashSet::new(),
+        shared_with: Vec::new(),
+        based_on: Vec::new(),
+    };
+    let mut branch_groups: HashMap<String, BranchGroup> = HashMap::new();
+    let mut unbranched_group = BranchGroup {
+        header: "(no branch)".to_string(),
+        oids: HashSet::new(),
+        shared_with: Vec::new(),
+        based_on: Vec::new(),
+    };
+    for (oid, node) in graph {
+        if node.is_main {
+            main_group.oids.insert(*oid);
+            continue;
+        }
+
+        let mut branches = match reachable_from.remove(oid) {
+            Some(branches) => branches,
+            None => {
+                unbranched_group.oids.insert(*oid);
+                continue;
+            }
+        };
+        branches.sort();
+        let mut branches = branches.into_iter().map(|(_distance, name)| name);
+        let nearest_branch_name = branches.next().unwrap();
+        let group = branch_groups
+            .entry(nearest_branch_name.clone())
+            .or_insert_with(|| BranchGroup {
+                header: nearest_branch_name,
+                oids: HashSet::new(),
+                shared_with: Vec::new(),
+                based_on: Vec::new(),
+            });
+        group.oids.insert(*oid);
+        for branch_name in branches {
+            if !group.shared_with.contains(&branch_name) {
+                group.shared_with.push(branch_name);
+            }
+        }
+    }
+
+    let mut branch_groups: Vec<BranchGroup> = branch_groups.into_values().collect();
+    branch_groups.sort_by(|lhs, rhs| lhs.header.cmp(&rhs.header));
+    for group in branch_groups.iter_mut() {
+        group.shared_with.sort();
+    }
+
+    let mut result = vec![main_group];
+    result.extend(branch_groups);
+    if !unbranched_group.oids.is_empty() {
+        result.push(unbranched_group);
+    }
+
+    let oid_to_header: HashMap<git2::Oid, String> = result
+        .iter()
+        .flat_map(|group| {
+            group
+                .oids
+                .iter()
+                .map(move |oid| (*oid, group.header.clone()))
+        })
+        .collect();
+    for group in result.iter_mut() {
+        let mut based_on: Vec<String> = group
+            .oids
+            .iter()
+            .filter_map(|oid| graph[oid].parent)
+            .filter(|parent_oid| !graph[parent_oid].is_main && !group.oids.contains(parent_oid))
+            .map(|parent_oid| oid_to_header[&parent_oid].clone())
+            .collect();
+        based_on.sort();
+        based_on.dedup();
+        group.based_on = based_on;
+    }
+    result
+}
+
+/// Make a copy of the graph containing only the commits in the given group,
+/// along with the main branch commits that the group is based on.
+fn make_branch_group_graph<'repo>(
+    graph: &CommitGraph<'repo>,
+    group: &BranchGroup,
+) -> CommitGraph<'repo> {
+    let mut oids = group.oids.clone();
+    for oid in group.oids.iter() {
+        if let Some(parent_oid) = graph[oid].parent {
+            if graph[&parent_oid].is_main {
+                oids.insert(parent_oid);
+            }
+        }
+    }
+
+    oids.iter()
+        .map(|oid| {
+            let node = &graph[oid];
+            let node = Node {
+                commit: node.commit.clone(),
+                parent: node.parent.filter(|parent_oid| oids.contains(parent_oid)),
+                children: node
+                    .children
+                    .iter()
+                    .filter(|child_oid| oids.contains(child_oid))
+                    .copied()
+                    .collect(),
+                is_main: node.is_main,
+                is_visible: node.is_visible,
+                is_shallow_boundary: node.is_shallow_boundary,
+                is_stash: node.is_stash,
+                event: node.event.clone(),
+            };
+            (*oid, node)
+        })
+        .collect()
+}
+
+/// Render the smartlog graph with the commits of each branch grouped together
+/// under a header line with the branch name (see `group_commits_by_branch`).
+/// Each group is rendered along with the main branch commits that it's based
+/// on.
+///
+/// Args are as for `render_graph`, with the addition of:
+/// * `main_branch_name`: The name of the main branch, used as the header for
+///   the main branch commits.
+/// * `branch_oid_to_names`: The branches pointing to each commit.
+#[context("Rendering smartlog grouped by branch")]
+pub fn render_graph_grouped_by_branch(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    graph: &CommitGraph,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+    main_branch_name: &str,
+    branch_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+    commit_metadata_providers: &mut [&mut dyn CommitMetadataProvider],
+    options: &RenderOptions,
+) -> anyhow::Result<Vec<StyledString>> {
+    let mut lines = Vec::new();
+    for group in group_commits_by_branch(graph, main_branch_name, branch_oid_to_names) {
+        if !lines.is_empty() {
+            lines.push(StyledString::new());
+        }
+
+        let mut header = StyledStringBuilder::new()
+            .append_styled(group.header.clone(), Effect::Bold)
+            .build();
+        if !group.based_on.is_empty() {
+            header.append_plain(format!(" (based on: {})", group.based_on.join(", ")));
+        }
+        if !group.shared_with.is_empty() {
+            header.append_plain(format!(
+                " (some commits are also on: {})",
+                group.shared_with.join(", ")
+            ));
+        }
+        lines.push(header);
+
+        let group_graph = make_branch_group_graph(graph, &group);
+        lines.extend(render_graph(
+            glyphs,
+            repo,
+            merge_base_db,
+            &group_graph,
+            head_oid,
+            main_branch_oid,
+            commit_metadata_providers,
+            options,
+        )?);
+    }
+    Ok(lines)
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '"' => '\'',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '?',
+        })
+        .collect()
+}
+
+struct MermaidRenderer<'a> {
+    repo: &'a git2::Repository,
+    abbrev_len: usize,
+    graph: &'a CommitGraph<'a>,
+    head_oid: Option<git2::Oid>,
+    branch_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
+    lines: Vec<String>,
+}
+
+impl<'a> MermaidRenderer<'a> {
+    fn abbreviate_oid(&self, oid: git2::Oid) -> anyhow::Result<String> {
+        abbreviate_oid(self.repo, oid, self.abbrev_len)
+    }
+
+    fn render_commit(&mut self, oid: git2::Oid) -> anyhow::Result<()> {
+        let node = &self.graph[&oid];
+        let summary = node.commit.summary().unwrap_or_default();
+        let mut line = format!(
+            "    commit id: \"{} {}\"",
+            self.abbreviate_oid(oid)?,
+            mermaid_escape(summary)
+        );
+        if Some(oid) == self.head_oid {
+            line.push_str(" type: HIGHLIGHT");
+        } else if !node.is_visible {
+            line.push_str(" type: REVERSE");
+        }
+        if let Some(branch_names) = self.branch_oid_to_names.get(&oid) {
+            let mut branch_names: Vec<&str> = branch_names.iter().map(String::as_str).collect();
+            branch_names.sort_unstable();
+            line.push_str(&format!(
+                " tag: \"{}\"",
+                mermaid_escape(&branch_names.join(", "))
+            ));
+        }
+        self.lines.push(line);
+        Ok(())
+    }
+
+    /// Render the given commit and its descendants onto the given branch.
+    /// Every child but the first is rendered onto its own branch, which is
+    /// created before continuing with the first child, so that it forks from
+    /// the right commit.
+    fn render_stack(&mut self, branch_name: &str, oid: git2::Oid) -> anyhow::Result<()> {
+        self.render_commit(oid)?;
+
+        let children = get_sorted_children(self.graph, oid);
+
+        if let Some((first_child_oid, other_child_oids)) = children.split_first() {
+            for child_oid in other_child_oids {
+                let child_branch_name = format!("stack-{}", self.abbreviate_oid(*child_oid)?);
+                self.lines.push(format!("    branch {}", child_branch_name));
+                self.render_stack(&child_branch_name, *child_oid)?;
+                self.lines.push(format!("    checkout {}", branch_name));
+            }
+            self.render_stack(branch_name, *first_child_oid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Render the smartlog graph as a Mermaid `gitGraph` diagram.
+///
+/// Main branch commits are rendered on the trunk, and each stack of commits
+/// is rendered as a branch off of it. The current commit is highlighted, and
+/// hidden commits are rendered as reversed. The output contains only ASCII
+/// characters.
+///
+/// Returns: The lines of the diagram.
+pub fn render_graph_mermaid(
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    graph: &CommitGraph,
+    head_oid: &HeadOid,
+    branch_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+) -> anyhow::Result<Vec<String>> {
+    let main_branch_name = get_main_branch_name(repo)?;
+    let main_branch_name = mermaid_escape(&main_branch_name);
+    let root_oids = split_commit_graph_by_roots(repo, merge_base_db, graph);
+
+    let HeadOid(head_oid) = head_oid;
+    let mut renderer = MermaidRenderer {
+        repo,
+        abbrev_len: get_core_abbrev(repo)?,
+        graph,
+        head_oid: *head_oid,
+        branch_oid_to_names,
+        lines: vec![
+            format!(
+                "%%{{init: {{'gitGraph': {{'mainBranchName': '{}'}}}}}}%%",
+                main_branch_name
+            ),
+            "gitGraph".to_string(),
+        ],
+    };
+    for root_oid in root_oids {
+        let root_node = &graph[&root_oid];
+        if root_node.is_main {
+            renderer.render_commit(root_oid)?;
+            let children = get_sorted_children(graph, root_oid);
+            for child_oid in children {
+                let branch_name = format!("stack-{}", renderer.abbreviate_oid(child_oid)?);
+                renderer.lines.push(format!("    branch {}", branch_name));
+                renderer.render_stack(&branch_name, child_oid)?;
+                renderer
+                    .lines
+                    .push(format!("    checkout {}", main_branch_name));
+            }
+        } else {
+            // Pathological case: a root which isn't on the main branch, such
+            // as a rewritten initial commit.
+            let branch_name = format!("stack-{}", renderer.abbreviate_oid(root_oid)?);
+            renderer.lines.push(format!("    branch {}", branch_name));
+            renderer.render_stack(&branch_name, root_oid)?;
+            renderer
+                .lines
+                .push(format!("    checkout {}", main_branch_name));
+        }
+    }
+
+    Ok(renderer.lines)
+}
+
+/// Render each commit in the smartlog graph as a single line, according to
+/// `template`. The commits are listed in the same order as in the rendered
+/// graph, but no graph lines are drawn, so that the output is easy to parse.
+///
+/// Returns: The rendered lines, without any styling.
+#[context("Rendering smartlog graph with format template")]
+pub fn render_graph_template(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    graph: &CommitGraph,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+    template: &FormatTemplate,
+    now: SystemTime,
+) -> anyhow::Result<Vec<String>> {
+    let root_oids = split_commit_graph_by_roots(repo, merge_base_db, graph);
+    let fork_point_oid =
+        find_fork_point_oid(repo, merge_base_db, graph, head_oid, main_branch_oid)?;
+    let HeadOid(head_oid) = head_oid;
+    let abbrev_len = get_core_abbrev(repo)?;
+
+    // Visit the nodes in the same order as `get_output`: each node is
+    // followed by its children's subgraphs.
+    let mut oids = Vec::new();
+    for root_oid in root_oids.iter() {
+        let mut stack = vec![*root_oid];
+        while let Some(oid) = stack.pop() {
+            oids.push(oid);
+            let children = get_sorted_children(graph, oid);
+            stack.extend(
+                children
+                    .into_iter()
+                    .rev()
+                    .filter(|child_oid| !root_oids.contains(child_oid)),
+            );
+        }
+    }
+
+    let FormatTemplate(parts) = template;
+    let mut lines = Vec::new();
+    for oid in oids {
+        let node = &graph[&oid];
+        let commit = &node.commit;
+        let mut line = String::new();
+        for part in parts {
+            match part {
+                TemplatePart::Literal(text) => line.push_str(text),
+                TemplatePart::Oid => line.push_str(&oid.to_string()),
+                TemplatePart::ShortOid => line.push_str(&abbreviate_oid(repo, oid, abbrev_len)?),
+                TemplatePart::Summary => line.push_str(commit.summary().unwrap_or_default()),
+                TemplatePart::AuthorName => {
+                    line.push_str(commit.author().name().unwrap_or_default())
+                }
+                TemplatePart::AuthorEmail => {
+                    line.push_str(commit.author().email().unwrap_or_default())
+                }
+                TemplatePart::RelativeDate => {
+                    let commit_time = SystemTime::UNIX_EPOCH
+                        + Duration::from_secs(commit.time().seconds().try_into()?);
+                    line.push_str(&RelativeTimeProvider::describe_time_delta(
+                        now,
+                        commit_time,
+                    )?);
+                }
+                TemplatePart::Graph => line.push_str(get_cursor_glyph(
+                    glyphs,
+                    node,
+                    Some(oid) == *head_oid,
+                    Some(oid) == fork_point_oid,
+                )),
+            }
+        }
+        lines.push(line);
+    }
     Ok(lines)
 }
 
+/// Render a legend explaining what each glyph in the smartlog means. The
+/// legend is derived from `glyphs`, so it matches the glyphs which the graph
+/// was actually rendered with.
+pub fn render_legend(glyphs: &Glyphs) -> Vec<StyledString> {
+    let legend = glyphs.legend();
+    let glyph_width = legend
+        .iter()
+        .map(|(glyph, _description)| console::measure_text_width(glyph))
+        .max()
+        .unwrap_or_default();
+
+    let mut lines = vec![StyledStringBuilder::new()
+        .append_styled("Legend:", Effect::Bold)
+        .build()];
+    for (glyph, description) in legend {
+        let padding = " ".repeat(glyph_width - console::measure_text_width(&glyph));
+        lines.push(StyledString::plain(format!(
+            "  {}{}  {}",
+            glyph, padding, description
+        )));
+    }
+    lines
+}
+
+/// A piece of a `FormatTemplate`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TemplatePart {
+    /// Text which is copied to the output as-is.
+    Literal(String),
+
+    /// `%H`: The full OID of the commit.
+    Oid,
+
+    /// `%h`: The abbreviated OID of the commit.
+    ShortOid,
+
+    /// `%s`: The summary line of the commit message.
+    Summary,
+
+    /// `%an`: The name of the commit's author.
+    AuthorName,
+
+    /// `%ae`: The email address of the commit's author.
+    AuthorEmail,
+
+    /// `%cr`: The commit time, relative to now, such as `3d`.
+    RelativeDate,
+
+    /// `%g`: The glyph which the graph would show for the commit, such as
+    /// `@` for `HEAD`.
+    Graph,
+}
+
+/// A template for rendering each commit in the smartlog as a single line, as
+/// with `git log --format`. See `render_graph_template`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatTemplate(pub Vec<TemplatePart>);
+
+impl FromStr for FormatTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let placeholders = [
+            ("%%", None),
+            ("%H", Some(TemplatePart::Oid)),
+            ("%h", Some(TemplatePart::ShortOid)),
+            ("%s", Some(TemplatePart::Summary)),
+            ("%an", Some(TemplatePart::AuthorName)),
+            ("%ae", Some(TemplatePart::AuthorEmail)),
+            ("%cr", Some(TemplatePart::RelativeDate)),
+            ("%g", Some(TemplatePart::Graph)),
+        ];
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = s;
+        while let Some(index) = rest.find('%') {
+            literal.push_str(&rest[..index]);
+            rest = &rest[index..];
+            let (placeholder, part) = placeholders
+                .iter()
+                .find(|(placeholder, _part)| rest.starts_with(placeholder))
+                .ok_or_else(|| {
+                    let placeholder: String = rest.chars().take(2).collect();
+                    format!(
+                        "Unknown placeholder in format template: {} (expected one of: %h, %H, %s, %an, %ae, %cr, %g, %%)",
+                        placeholder
+                    )
+                })?;
+            match part {
+                None => literal.push('%'),
+                Some(part) => {
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part.clone());
+                }
+            }
+            rest = &rest[placeholder.len()..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(FormatTemplate(parts))
+    }
+}
+
+/// The output format for `smartlog`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SmartlogFormat {
+    /// Render the graph as text, for display in the terminal.
+    #[default]
+    Text,
+
+    /// Render the graph as a Mermaid `gitGraph` diagram. See
+    /// `render_graph_mermaid`.
+    Mermaid,
+
+    /// Render each commit as a line according to the given template. This is
+    /// selected by any format containing a `%`. See `render_graph_template`.
+    Template(FormatTemplate),
+}
+
+impl FromStr for SmartlogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(SmartlogFormat::Text),
+            "mermaid" => Ok(SmartlogFormat::Mermaid),
+            template if template.contains('%') => {
+                Ok(SmartlogFormat::Template(FormatTemplate::from_str(template)?))
+            }
+            other => Err(format!(
+                "Unknown format {:?} (expected one of: text, mermaid, or a template containing placeholders such as %h)",
+                other
+            )),
+        }
+    }
+}
+
+/// A previously-rendered smartlog, as stored in the `SmartlogCacheDb`.
+#[derive(Debug)]
+pub struct CachedSmartlog {
+    /// The time at which the smartlog was rendered.
+    pub rendered_at: SystemTime,
+
+    /// The commit times of the commits in the rendered graph, in seconds since
+    /// the Unix epoch. Only populated if relative commit times were shown,
+    /// since the rendered output then also depends on the current time.
+    pub commit_times: Vec<i64>,
+
+    /// The rendered output, with glyphs and ANSI escape codes already applied.
+    pub output: String,
+}
+
+impl CachedSmartlog {
+    /// Determine whether the rendered relative commit times would still be the
+    /// same if the smartlog were rendered at time `now`.
+    pub fn is_fresh(&self, now: SystemTime) -> anyhow::Result<bool> {
+        for commit_time in self.commit_times.iter().copied() {
+            let commit_time = SystemTime::UNIX_EPOCH + Duration::from_secs(commit_time.try_into()?);
+            if RelativeTimeProvider::describe_time_delta(self.rendered_at, commit_time)?
+                != RelativeTimeProvider::describe_time_delta(now, commit_time)?
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// On-disk cache for the most recently rendered smartlog, so that repeated
+/// calls (such as from a shell prompt) don't need to replay the event log and
+/// rebuild the commit graph if nothing has changed. Only one rendering is
+/// stored at a time.
+pub struct SmartlogCacheDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+#[context("Initializing tables for `SmartlogCacheDb`")]
+fn init_tables(conn: &rusqlite::Connection) -> anyhow::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS smartlog_cache (
+    key TEXT NOT NULL,
+    rendered_at INTEGER NOT NULL,
+    commit_times TEXT NOT NULL,
+    output TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .context("Creating tables")?;
+    Ok(())
+}
+
+impl<'conn> SmartlogCacheDb<'conn> {
+    /// Constructor.
+    #[context("Constructing `SmartlogCacheDb`")]
+    pub fn new(conn: &'conn rusqlite::Connection) -> anyhow::Result<Self> {
+        init_tables(conn).context("Initializing tables")?;
+        Ok(SmartlogCacheDb { conn })
+    }
+
+    /// Get the cached smartlog, if it was rendered with the given cache key
+    /// (see `get_smartlog_cache_key`).
+    ///
+    /// Returns: `None` if there is no cached smartlog for the key.
+    #[context("Querying smartlog cache for key {:?}", key)]
+    pub fn get(&self, key: &str) -> anyhow::Result<Option<CachedSmartlog>> {
+        let row: Option<(i64, String, String)> = self
+            .conn
+            .query_row_named(
+                "
+SELECT rendered_at, commit_times, output
+FROM smartlog_cache
+WHERE key = :key
+",
+                rusqlite::named_params! {
+                    ":key": key,
+                },
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .context("Querying smartlog cache")?;
+        let (rendered_at, commit_times, output) = match row {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+        let rendered_at = SystemTime::UNIX_EPOCH + Duration::from_nanos(rendered_at.try_into()?);
+        let commit_times = commit_times
+            .split_whitespace()
+            .map(|commit_time| commit_time.parse::<i64>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("Parsing cached commit times")?;
+        Ok(Some(CachedSmartlog {
+            rendered_at,
+            commit_times,
+            output,
+        }))
+    }
+
+    /// Replace the cached smartlog with the given one.
+    #[context("Caching smartlog for key {:?}", key)]
+    pub fn set(&self, key: &str, cached_smartlog: &CachedSmartlog) -> anyhow::Result<()> {
+        let rendered_at: i64 = cached_smartlog
+            .rendered_at
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_nanos()
+            .try_into()?;
+        let commit_times = cached_smartlog
+            .commit_times
+            .iter()
+            .map(|commit_time| commit_time.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM smartlog_cache", rusqlite::params![])
+            .context("Clearing smartlog cache")?;
+        tx.execute_named(
+            "
+INSERT INTO smartlog_cache
+VALUES (:key, :rendered_at, :commit_times, :output)
+",
+            rusqlite::named_params! {
+                ":key": key,
+                ":rendered_at": rendered_at,
+                ":commit_times": commit_times,
+                ":output": cached_smartlog.output,
+            },
+        )
+        .context("Caching smartlog")?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Compute a key which changes whenever anything that the rendered smartlog
+/// depends on changes: any reference (including `HEAD` and notes refs), the
+/// event log, the configuration, whether the working copy is dirty, any
+/// in-progress operation, and the rendering options.
+///
+/// The current time isn't included in the key, since the relative commit
+/// times only change occasionally; see `CachedSmartlog::is_fresh`.
+#[context("Computing smartlog cache key")]
+fn get_smartlog_cache_key(
+    repo: &git2::Repository,
+    event_log_db: &EventLogDb,
+    glyphs: &Glyphs,
+    options: &SmartlogOptions,
+    body_width: Option<usize>,
+    is_dirty: bool,
+    operation_state: &Option<OperationState>,
+) -> anyhow::Result<String> {
+    let mut key = String::new();
+    let describe_reference = |reference: &git2::Reference| match reference.symbolic_target() {
+        Some(symbolic_target) => format!(
+            "{} -> {}\n",
+            reference.name().unwrap_or_default(),
+            symbolic_target
+        ),
+        None => format!(
+            "{} {:?}\n",
+            reference.name().unwrap_or_default(),
+            reference.target()
+        ),
+    };
+    key.push_str(&describe_reference(&repo.find_reference("HEAD")?));
+    for reference in repo.references()? {
+        key.push_str(&describe_reference(&reference?));
+    }
+
+    let (num_events, last_event_id) = event_log_db.get_event_log_head()?;
+    key.push_str(&format!("events {} {:?}\n", num_events, last_event_id));
+
+    for entry in &repo.config()?.entries(None)? {
+        let entry = entry?;
+        key.push_str(&format!("config {:?}={:?}\n", entry.name(), entry.value()));
+    }
+
+    key.push_str(&format!(
+        "options {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?} {:?}\n",
+        options.verbose,
+        options.commit_time_range,
+        options.group_by_branch,
+        options.hide_main_branch_tail,
+        options.author,
+        options.show_stat,
+        options.page,
+        options.show_stashes,
+        options.fast,
+        body_width
+    ));
+    // Dropping an older stash entry only changes the reflog, not any
+    // reference.
+    if let Ok(reflog) = repo.reflog("refs/stash") {
+        for entry in reflog.iter() {
+            key.push_str(&format!("stash {:?}\n", entry.id_new()));
+        }
+    }
+    key.push_str(&format!(
+        "glyphs {:?} {:?}\n",
+        glyphs.should_write_ansi_escape_codes, glyphs.commit_visible
+    ));
+    key.push_str(&format!(
+        "working copy {:?} {:?}\n",
+        is_dirty,
+        operation_state
+            .as_ref()
+            .map(|operation_state| &operation_state.description)
+    ));
+
+    let key = git2::Oid::hash_object(git2::ObjectType::Blob, key.as_bytes())?;
+    Ok(key.to_string())
+}
+
+/// Options for `smartlog`.
+#[derive(Debug, Default)]
+pub struct SmartlogOptions {
+    /// If `true`, and the output is too tall to fit in the terminal, display
+    /// it using the user's pager (as configured with `$GIT_PAGER`,
+    /// `core.pager`, or `$PAGER`).
+    pub use_pager: bool,
+
+    /// If `true`, show the full commit message body under each visible
+    /// commit's summary line.
+    pub verbose: bool,
+
+    /// The format to render the graph in.
+    pub format: SmartlogFormat,
+
+    /// Only start from visible commits and branches whose commit times fall
+    /// into this range. See `make_graph_in_time_range`.
+    pub commit_time_range: CommitTimeRange,
+
+    /// If `true`, render the commits of each branch under a header with the
+    /// branch name. See `render_graph_grouped_by_branch`. Only applies to the
+    /// text format.
+    pub group_by_branch: bool,
+
+    /// If `true`, and `branchless.smartlog.cache` is set, reuse the previously
+    /// rendered smartlog if nothing has changed since it was rendered. See
+    /// `SmartlogCacheDb`. Only applies to the text format.
+    pub use_cache: bool,
+
+    /// If `true`, show a legend explaining the glyphs after the graph. See
+    /// `render_legend`. Only applies to the text format.
+    pub show_legend: bool,
+
+    /// If `true`, don't show main branch commits which are ancestors of the
+    /// commit that `HEAD` is based on. See `prune_main_branch_tail`.
+    pub hide_main_branch_tail: bool,
+
+    /// Only show commits whose author matches this pattern, along with the
+    /// commits needed to connect them to the main branch. See
+    /// `node_matches_author`.
+    pub author: Option<String>,
+
+    /// If `true`, show a summary of how many files and lines each visible
+    /// commit changed relative to its first parent. See `DiffStatProvider`.
+    pub show_stat: bool,
+
+    /// If set, only show this page of stacks (starting from 1), with
+    /// `branchless.smartlog.pageSize` stacks per page. See `paginate_graph`.
+    pub page: Option<usize>,
+
+    /// If `true`, show stash entries as nodes attached to the commits they
+    /// were based on. Also enabled by `branchless.smartlog.showStashes`. See
+    /// `StashesProvider`.
+    pub show_stashes: bool,
+
+    /// If `true`, only show the component of the graph containing `HEAD`. See
+    /// `get_graph_components`.
+    pub fast: bool,
+
+    /// If `true`, print the component of the graph containing `HEAD` as soon
+    /// as it's constructed, and then print the remaining components one at a
+    /// time. See `get_graph_components`. The pager and the smartlog cache
+    /// aren't used. Only applies to the text format, and not with
+    /// `group_by_branch` or `page`.
+    pub incremental: bool,
+}
+
+/// Determine whether the author of the node's commit matches `pattern`.
+/// Matching is case-insensitive. If the pattern contains a `*` or `?`, then it
+/// must match the author's name, email, or `Name <email>` in its entirety, as a
+/// glob. Otherwise, it only needs to be a substring of `Name <email>`.
+///
+/// Main branch commits never match, since they're not part of anyone's stack.
+fn node_matches_author(node: &Node, pattern: &str) -> bool {
+    if node.is_main {
+        return false;
+    }
+    let pattern = pattern.to_lowercase();
+    let author = node.commit.author();
+    let name = author.name().unwrap_or_default().to_lowercase();
+    let email = author.email().unwrap_or_default().to_lowercase();
+    let name_and_email = format!("{} <{}>", name, email);
+    if pattern.contains(['*', '?']) {
+        [name, email, name_and_email]
+            .iter()
+            .any(|text| glob_matches(&pattern, text))
+    } else {
+        name_and_email.contains(&pattern)
+    }
+}
+
+/// Add up to `context_commits` main branch commits before and after each main
+/// branch commit already in the graph, following first parents, so that more
+/// of the main branch is shown for orientation. The main branch commits which
+/// are still missing from the graph are elided with a `:` line when rendered.
+#[context("Adding {} main branch context commits", context_commits)]
+fn add_main_branch_context<'repo>(
+    repo: &'repo git2::Repository,
+    event_replayer: &EventReplayer,
+    graph: &mut CommitGraph<'repo>,
+    main_branch_oid: &MainBranchOid,
+    context_commits: usize,
+) -> anyhow::Result<()> {
+    let MainBranchOid(main_branch_oid) = main_branch_oid;
+    let anchor_oids: Vec<git2::Oid> = graph
+        .iter()
+        .filter(|(_oid, node)| node.is_main)
+        .map(|(oid, _node)| *oid)
+        .collect();
+    if context_commits == 0 || anchor_oids.is_empty() {
+        return Ok(());
+    }
+
+    let mut context_oids = HashSet::new();
+    for anchor_oid in anchor_oids.iter() {
+        let mut commit = repo.find_commit(*anchor_oid)?;
+        for _ in 0..context_commits {
+            commit = match commit.parents().next() {
+                Some(parent) => parent,
+                None => break,
+            };
+            context_oids.insert(commit.id());
+        }
+    }
+
+    // Walk the main branch from its head, keeping track of the most recent
+    // commits, which are the descendants of the next anchor that's reached.
+    let mut revwalk = repo.revwalk()?;
+    revwalk.simplify_first_parent()?;
+    revwalk.push(*main_branch_oid)?;
+    let oldest_anchor_oid = match anchor_oids.as_slice() {
+        [anchor_oid] => *anchor_oid,
+        anchor_oids => repo.merge_base_many(anchor_oids)?,
+    };
+    revwalk.hide(oldest_anchor_oid)?;
+    let mut recent_oids = VecDeque::new();
+    for oid in revwalk {
+        let oid = oid?;
+        if graph.contains_key(&oid) {
+            context_oids.extend(recent_oids.drain(..));
+        } else {
+            recent_oids.push_back(oid);
+            if recent_oids.len() > context_commits {
+                recent_oids.pop_front();
+            }
+        }
+    }
+    if graph.contains_key(&oldest_anchor_oid) {
+        context_oids.extend(recent_oids.drain(..));
+    }
+
+    let event_cursor = event_replayer.make_default_cursor();
+    for oid in context_oids {
+        if graph.contains_key(&oid) {
+            continue;
+        }
+        let is_visible = !matches!(
+            event_replayer.get_cursor_commit_visibility(event_cursor, oid),
+            Some(CommitVisibility::Hidden)
+        );
+        graph.insert(
+            oid,
+            Node {
+                commit: repo.find_commit(oid)?,
+                parent: None,
+                children: HashSet::new(),
+                is_main: true,
+                is_visible,
+                is_shallow_boundary: false,
+                is_stash: false,
+                event: event_replayer
+                    .get_cursor_commit_latest_event(event_cursor, oid)
+                    .cloned(),
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Remove the main branch commits which are ancestors of the main branch
+/// commit that `HEAD` is based on (its "anchor"), so that the smartlog only
+/// shows the user's own work.
+///
+/// The anchor itself is kept, as are `HEAD` and any commits which branches
+/// point to, for orientation. Lines of work based on a removed commit are
+/// shown without their main branch parent.
+///
+/// Returns: The pruned graph. If `HEAD` isn't based on the main branch, the
+/// graph is returned unchanged.
+#[context("Pruning main branch tail")]
+fn prune_main_branch_tail<'repo>(
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    mut graph: CommitGraph<'repo>,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+    branch_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+) -> anyhow::Result<CommitGraph<'repo>> {
+    let HeadOid(head_oid) = head_oid;
+    let MainBranchOid(main_branch_oid) = main_branch_oid;
+    let head_oid = match head_oid {
+        Some(head_oid) => *head_oid,
+        None => return Ok(graph),
+    };
+    let anchor_oid = match merge_base_db.get_merge_base_oid(repo, head_oid, *main_branch_oid)? {
+        Some(anchor_oid) => anchor_oid,
+        None => return Ok(graph),
+    };
+
+    let mut pruned_oids = Vec::new();
+    for (oid, node) in graph.iter() {
+        if !node.is_main
+            || *oid == anchor_oid
+            || *oid == head_oid
+            || branch_oid_to_names.contains_key(oid)
+        {
+            continue;
+        }
+        if merge_base_db.get_merge_base_oid(repo, *oid, anchor_oid)? == Some(*oid) {
+            pruned_oids.push(*oid);
+        }
+    }
+
+    for oid in pruned_oids {
+        if let Some(node) = graph.remove(&oid) {
+            for child_oid in node.children {
+                if let Some(child_node) = graph.get_mut(&child_oid) {
+                    child_node.parent = None;
+                }
+            }
+        }
+    }
+    Ok(graph)
+}
+
+/// Get the stacks in the graph, most recently updated first. A stack is a
+/// subtree of non-main commits rooted at either a child of a main branch
+/// commit, or a commit with no parent in the graph. Its recency is the latest
+/// commit time of any commit in it.
+///
+/// Returns: The commits in each stack.
+fn get_stacks(graph: &CommitGraph) -> Vec<HashSet<git2::Oid>> {
+    let mut stacks: Vec<(git2::Time, git2::Oid, HashSet<git2::Oid>)> = graph
+        .iter()
+        .filter(|(_oid, node)| {
+            !node.is_main
+                && match node.parent {
+                    Some(parent_oid) => graph[&parent_oid].is_main,
+                    None => true,
+                }
+        })
+        .map(|(root_oid, _node)| {
+            let mut stack_oids = HashSet::new();
+            let mut latest_time = graph[root_oid].commit.time();
+            let mut oids_to_visit = vec![*root_oid];
+            while let Some(oid) = oids_to_visit.pop() {
+                if !stack_oids.insert(oid) {
+                    continue;
+                }
+                latest_time = latest_time.max(graph[&oid].commit.time());
+                oids_to_visit.extend(get_sorted_children(graph, oid));
+            }
+            (latest_time, *root_oid, stack_oids)
+        })
+        .collect();
+    stacks.sort_by(|(lhs_time, lhs_oid, _), (rhs_time, rhs_oid, _)| {
+        rhs_time.cmp(lhs_time).then(lhs_oid.cmp(rhs_oid))
+    });
+    stacks
+        .into_iter()
+        .map(|(_time, _root_oid, stack_oids)| stack_oids)
+        .collect()
+}
+
+/// Which stacks were selected by `paginate_graph`.
+#[derive(Debug, PartialEq, Eq)]
+struct SmartlogPage {
+    /// The page which was requested, starting from 1.
+    page: usize,
+
+    /// The number of stacks on each page.
+    page_size: usize,
+
+    /// The number of stacks in the entire graph.
+    num_stacks: usize,
+}
+
+impl SmartlogPage {
+    /// Render a line describing which stacks are shown, and how to see the
+    /// others.
+    fn describe(&self) -> String {
+        if self.num_stacks == 0 {
+            return "There are no stacks to show.".to_string();
+        }
+        let num_pages = self.num_stacks.div_ceil(self.page_size);
+        if self.page == 0 || self.page > num_pages {
+            return format!(
+                "Page {} is out of range: there are {} stacks, in {} pages of up to {} stacks.",
+                self.page, self.num_stacks, num_pages, self.page_size
+            );
+        }
+        let first_stack = (self.page - 1) * self.page_size + 1;
+        let last_stack = (self.page * self.page_size).min(self.num_stacks);
+        let mut result = if first_stack == last_stack {
+            format!("Stack {} of {}", first_stack, self.num_stacks)
+        } else {
+            format!(
+                "Stacks {}-{} of {}",
+                first_stack, last_stack, self.num_stacks
+            )
+        };
+        if self.page < num_pages {
+            result.push_str(&format!(", use --page {} for more", self.page + 1));
+        }
+        result
+    }
+}
+
+/// Remove the stacks (see `get_stacks`) which aren't on the given page from
+/// the graph. The main branch commits needed to render the remaining stacks
+/// are kept, as is the main branch head.
+///
+/// Args:
+/// * `graph`: The commit graph to paginate.
+/// * `main_branch_oid`: The OID of the main branch head.
+/// * `page`: The page of stacks to keep, starting from 1.
+/// * `page_size`: The number of stacks on each page.
+///
+/// Returns: A description of the selected page.
+fn paginate_graph(
+    graph: &mut CommitGraph,
+    main_branch_oid: &MainBranchOid,
+    page: usize,
+    page_size: usize,
+) -> SmartlogPage {
+    let MainBranchOid(main_branch_oid) = main_branch_oid;
+    let stacks = get_stacks(graph);
+    let num_stacks = stacks.len();
+    let oids_to_keep: HashSet<git2::Oid> = stacks
+        .into_iter()
+        .skip(page.saturating_sub(1) * page_size)
+        .take(if page == 0 { 0 } else { page_size })
+        .flatten()
+        .collect();
+    filter_graph(graph, |node| {
+        node.commit.id() == *main_branch_oid || oids_to_keep.contains(&node.commit.id())
+    });
+    SmartlogPage {
+        page,
+        page_size,
+        num_stacks,
+    }
+}
+
 /// Display a nice graph of commits you've recently worked on.
-pub fn smartlog() -> anyhow::Result<()> {
-    let glyphs = Glyphs::detect();
+pub fn smartlog(options: &SmartlogOptions) -> anyhow::Result<()> {
     let repo = get_repo()?;
+    let glyphs = match get_smartlog_head_glyph(&repo)? {
+        Some(head_glyph) => Glyphs::detect().with_head_glyph(head_glyph),
+        None => Glyphs::detect(),
+    };
     let conn = get_db_conn(&repo)?;
     let merge_base_db = MergeBaseDb::new(&conn)?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
     let head_oid = get_head_oid(&repo)?;
+
+    let body_width = if options.verbose {
+        match get_smartlog_body_wrap_width(&repo)? {
+            Some(body_wrap_width) => Some(body_wrap_width),
+            None => {
+                let (_terminal_height, terminal_width) = console::Term::stdout().size();
+                Some(usize::from(terminal_width))
+            }
+        }
+    } else {
+        None
+    };
+    let now = SystemTime::now();
+    let operation_state = get_operation_state(&repo);
+    let mut working_copy_status_provider = WorkingCopyStatusProvider::new(&repo, head_oid)?;
+    let cache = if options.use_cache
+        && options.format == SmartlogFormat::Text
+        && !is_incremental(options)
+        && get_smartlog_cache(&repo)?
+    {
+        let smartlog_cache_db = SmartlogCacheDb::new(&conn)?;
+        let key = get_smartlog_cache_key(
+            &repo,
+            &event_log_db,
+            &glyphs,
+            options,
+            body_width,
+            working_copy_status_provider.is_dirty(),
+            &operation_state,
+        )?;
+        if let Some(cached_smartlog) = smartlog_cache_db.get(&key)? {
+            if cached_smartlog.is_fresh(now)? {
+                let lines: Vec<String> =
+                    cached_smartlog.output.lines().map(str::to_string).collect();
+                return print_smartlog(&glyphs, &repo, options, lines);
+            }
+        }
+        Some((smartlog_cache_db, key))
+    } else {
+        None
+    };
+
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
     let main_branch_oid = get_main_branch_oid(&repo)?;
     let branch_oid_to_names = get_branch_oid_to_names(&repo)?;
-    let graph = make_graph(
+    let tag_oid_to_names = get_tag_oid_to_names(&repo)?;
+    let stash_oid_to_names = if options.show_stashes || get_smartlog_show_stashes(&repo)? {
+        get_stash_oid_to_names(&repo)?
+    } else {
+        HashMap::new()
+    };
+    // Stashes are added to the graph like branches, so that they (and the
+    // commits they're based on) aren't removed as hidden.
+    let mut branch_oids: HashSet<git2::Oid> = branch_oid_to_names.keys().cloned().collect();
+    branch_oids.extend(stash_oid_to_names.keys().cloned());
+    let additional_main_branch_oids =
+        AdditionalMainBranchOids(get_additional_main_branch_oids(&repo)?);
+    let branch_oids = BranchOids(branch_oids);
+    let tag_oids = TagOids(tag_oid_to_names.keys().cloned().collect());
+    let unhideable_ref_oids = UnhideableRefOids(get_unhideable_ref_oids(&repo)?);
+    let make_component_graph = |commit_oids: &CommitOids| {
+        make_graph_from_commit_oids(
+            &repo,
+            &merge_base_db,
+            &mut AncestryCache::default(),
+            &event_replayer,
+            event_replayer.make_default_cursor(),
+            &HeadOid(head_oid),
+            &MainBranchOid(main_branch_oid),
+            &additional_main_branch_oids,
+            &branch_oids,
+            &tag_oids,
+            &unhideable_ref_oids,
+            true,
+            commit_oids,
+        )
+    };
+    let components = match head_oid {
+        Some(head_oid) if options.fast || is_incremental(options) => Some(get_graph_components(
+            &repo,
+            &merge_base_db,
+            &event_replayer,
+            event_replayer.make_default_cursor(),
+            head_oid,
+            &MainBranchOid(main_branch_oid),
+            &additional_main_branch_oids,
+            &branch_oids,
+            &options.commit_time_range,
+        )?),
+        _ => None,
+    };
+
+    if let (Some(components), false) = (&components, options.fast) {
+        // Render and print each component as soon as it's constructed, so
+        // that the component containing `HEAD` is shown without waiting for
+        // the rest of the graph.
+        if let Some(operation_state) = &operation_state {
+            let line = StyledString::styled(
+                operation_state.description.clone(),
+                BaseColor::Yellow.light(),
+            );
+            println!("{}", printable_styled_string(&glyphs, line)?);
+        }
+        for (i, commit_oids) in components.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            let graph = prepare_smartlog_graph(
+                &repo,
+                &merge_base_db,
+                &event_replayer,
+                make_component_graph(commit_oids)?,
+                options,
+                &HeadOid(head_oid),
+                &MainBranchOid(main_branch_oid),
+                &branch_oid_to_names,
+                &stash_oid_to_names,
+            )?;
+            let lines = render_smartlog_text(
+                &glyphs,
+                &repo,
+                &conn,
+                &merge_base_db,
+                &event_replayer,
+                &graph,
+                options,
+                &HeadOid(head_oid),
+                &MainBranchOid(main_branch_oid),
+                &branch_oid_to_names,
+                &tag_oid_to_names,
+                &stash_oid_to_names,
+                &mut working_copy_status_provider,
+                &operation_state,
+                body_width,
+                now,
+            )?;
+            for line in lines {
+                println!("{}", printable_styled_string(&glyphs, line)?);
+            }
+            std::io::stdout()
+                .flush()
+                .context("Flushing the smartlog to stdout")?;
+        }
+        return print_smartlog(&glyphs, &repo, options, Vec::new());
+    }
+
+    let graph = match &components {
+        Some(components) => make_component_graph(&components[0])?,
+        None => make_graph_in_time_range(
+            &repo,
+            &merge_base_db,
+            &mut AncestryCache::default(),
+            &event_replayer,
+            event_replayer.make_default_cursor(),
+            &HeadOid(head_oid),
+            &MainBranchOid(main_branch_oid),
+            &additional_main_branch_oids,
+            &branch_oids,
+            &tag_oids,
+            &unhideable_ref_oids,
+            true,
+            &options.commit_time_range,
+        )?,
+    };
+    let mut graph = prepare_smartlog_graph(
         &repo,
         &merge_base_db,
         &event_replayer,
-        event_replayer.make_default_cursor(),
+        graph,
+        options,
         &HeadOid(head_oid),
         &MainBranchOid(main_branch_oid),
-        &BranchOids(branch_oid_to_names.keys().cloned().collect()),
-        true,
+        &branch_oid_to_names,
+        &stash_oid_to_names,
     )?;
+    let smartlog_page = match options.page {
+        Some(page) => Some(paginate_graph(
+            &mut graph,
+            &MainBranchOid(main_branch_oid),
+            page,
+            get_smartlog_page_size(&repo)?,
+        )),
+        None => None,
+    };
 
-    let lines = render_graph(
+    if let SmartlogFormat::Template(template) = &options.format {
+        let lines = render_graph_template(
+            &glyphs,
+            &repo,
+            &merge_base_db,
+            &graph,
+            &HeadOid(head_oid),
+            &MainBranchOid(main_branch_oid),
+            template,
+            now,
+        )?;
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    if options.format == SmartlogFormat::Mermaid {
+        let lines = render_graph_mermaid(
+            &repo,
+            &merge_base_db,
+            &graph,
+            &HeadOid(head_oid),
+            &branch_oid_to_names,
+        )?;
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let render_timer = start_timer("render smartlog");
+    let graph_lines = render_smartlog_text(
         &glyphs,
         &repo,
+        &conn,
         &merge_base_db,
+        &event_replayer,
         &graph,
+        options,
         &HeadOid(head_oid),
-        &mut [
-            &mut CommitOidProvider::new(true)?,
-            &mut RelativeTimeProvider::new(&repo, SystemTime::now())?,
-            &mut HiddenExplanationProvider::new(
-                &graph,
-                &event_replayer,
-                event_replayer.make_default_cursor(),
-            )?,
-            &mut BranchesProvider::new(&repo, &branch_oid_to_names)?,
-            &mut DifferentialRevisionProvider::new(&repo)?,
-            &mut CommitMessageProvider::new()?,
-        ],
+        &MainBranchOid(main_branch_oid),
+        &branch_oid_to_names,
+        &tag_oid_to_names,
+        &stash_oid_to_names,
+        &mut working_copy_status_provider,
+        &operation_state,
+        body_width,
+        now,
     )?;
-    for line in lines {
-        println!("{}", printable_styled_string(&glyphs, line)?);
+    let lines = {
+        let mut lines = Vec::new();
+        if let Some(operation_state) = &operation_state {
+            lines.push(StyledString::styled(
+                operation_state.description.clone(),
+                BaseColor::Yellow.light(),
+            ));
+        }
+        lines.extend(graph_lines);
+        if let Some(smartlog_page) = &smartlog_page {
+            lines.push(StyledString::plain(smartlog_page.describe()));
+        }
+        lines
+            .into_iter()
+            .map(|line| printable_styled_string(&glyphs, line))
+            .collect::<anyhow::Result<Vec<String>>>()?
+    };
+    drop(render_timer);
+
+    if let Some((smartlog_cache_db, key)) = cache {
+        let commit_times = if get_commit_metadata_relative_time(&repo)? {
+            graph
+                .values()
+                .map(|node| node.commit.time().seconds())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        smartlog_cache_db.set(
+            &key,
+            &CachedSmartlog {
+                rendered_at: now,
+                commit_times,
+                output: lines.join("\n"),
+            },
+        )?;
+    }
+
+    print_smartlog(&glyphs, &repo, options, lines)
+}
+
+/// Whether the smartlog should be printed one component at a time, as with
+/// `SmartlogOptions::incremental`.
+fn is_incremental(options: &SmartlogOptions) -> bool {
+    options.incremental
+        && options.format == SmartlogFormat::Text
+        && !options.group_by_branch
+        && options.page.is_none()
+}
+
+/// Apply the display options to a newly-constructed smartlog graph: mark the
+/// stash entries, add the main branch context commits, and filter out the
+/// commits which shouldn't be shown.
+fn prepare_smartlog_graph<'repo>(
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    event_replayer: &EventReplayer,
+    mut graph: CommitGraph<'repo>,
+    options: &SmartlogOptions,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+    branch_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+    stash_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+) -> anyhow::Result<CommitGraph<'repo>> {
+    for stash_oid in stash_oid_to_names.keys() {
+        if let Some(node) = graph.get_mut(stash_oid) {
+            node.is_stash = true;
+        }
+    }
+    add_main_branch_context(
+        repo,
+        event_replayer,
+        &mut graph,
+        main_branch_oid,
+        get_smartlog_context_commits(repo)?,
+    )?;
+    let mut graph = if options.hide_main_branch_tail {
+        prune_main_branch_tail(
+            repo,
+            merge_base_db,
+            graph,
+            head_oid,
+            main_branch_oid,
+            branch_oid_to_names,
+        )?
+    } else {
+        graph
+    };
+    if let Some(author) = &options.author {
+        // Keep `HEAD`, as with `commit_time_range`, so that the user can
+        // still orient themselves.
+        filter_graph(&mut graph, |node| {
+            Some(node.commit.id()) == head_oid.0 || node_matches_author(node, author)
+        });
+    }
+    Ok(graph)
+}
+
+/// Render the smartlog graph as text, with the commit metadata shown by `git
+/// smartlog`.
+fn render_smartlog_text(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    conn: &rusqlite::Connection,
+    merge_base_db: &MergeBaseDb,
+    event_replayer: &EventReplayer,
+    graph: &CommitGraph,
+    options: &SmartlogOptions,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+    branch_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+    tag_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+    stash_oid_to_names: &HashMap<git2::Oid, HashSet<String>>,
+    working_copy_status_provider: &mut WorkingCopyStatusProvider,
+    operation_state: &Option<OperationState>,
+    body_width: Option<usize>,
+    now: SystemTime,
+) -> anyhow::Result<Vec<StyledString>> {
+    let render_options = RenderOptions {
+        body_width,
+        summary_column: get_smartlog_summary_column(repo)?,
+        ..Default::default()
+    };
+    let diff_stats = if options.show_stat {
+        let commit_oids: Vec<git2::Oid> = graph.keys().copied().collect();
+        get_diff_stats(repo, &DiffStatDb::new(conn)?, &commit_oids)?
+    } else {
+        HashMap::new()
+    };
+    let mut commit_metadata_providers: [&mut dyn CommitMetadataProvider; 13] = [
+        &mut CommitOidProvider::new(repo, true)?,
+        &mut CommitStatusProvider::new(glyphs, repo)?,
+        &mut RelativeTimeProvider::new(repo, now)?,
+        working_copy_status_provider,
+        &mut OperationStateProvider::new(operation_state)?,
+        &mut HiddenExplanationProvider::new(
+            repo,
+            graph,
+            event_replayer,
+            event_replayer.make_default_cursor(),
+        )?,
+        &mut BranchesProvider::new(repo, branch_oid_to_names)?,
+        &mut AheadBehindProvider::new(repo, main_branch_oid, branch_oid_to_names)?,
+        &mut TagsProvider::new(repo, tag_oid_to_names)?,
+        &mut StashesProvider::new(stash_oid_to_names)?,
+        &mut DifferentialRevisionProvider::new(repo)?,
+        &mut DiffStatProvider::new(diff_stats)?,
+        &mut CommitMessageProvider::new()?.with_wip_prefixes(get_smartlog_wip_prefixes(repo)?),
+    ];
+    if options.group_by_branch {
+        render_graph_grouped_by_branch(
+            glyphs,
+            repo,
+            merge_base_db,
+            graph,
+            head_oid,
+            main_branch_oid,
+            &get_main_branch_name(repo)?,
+            branch_oid_to_names,
+            &mut commit_metadata_providers,
+            &render_options,
+        )
+    } else {
+        render_graph(
+            glyphs,
+            repo,
+            merge_base_db,
+            graph,
+            head_oid,
+            main_branch_oid,
+            &mut commit_metadata_providers,
+            &render_options,
+        )
+    }
+}
+
+/// Print the rendered smartlog lines, followed by the legend if it was
+/// requested or hasn't been shown since `git branchless init`. The pager is
+/// used if requested and the output is too tall to fit in the terminal.
+fn print_smartlog(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    options: &SmartlogOptions,
+    mut lines: Vec<String>,
+) -> anyhow::Result<()> {
+    let show_legend_hint = get_smartlog_legend_hint(repo)?;
+    if options.show_legend || show_legend_hint {
+        lines.push(String::new());
+        for line in render_legend(glyphs) {
+            lines.push(printable_styled_string(glyphs, line)?);
+        }
+    }
+    if show_legend_hint {
+        lines.push(
+            "hint: this legend is only shown once; run `git smartlog --legend` to see it again"
+                .to_string(),
+        );
+        // This is the only time that the smartlog writes to the config.
+        set_smartlog_legend_hint(repo, false)?;
+    }
+
+    let pager = if options.use_pager && console::user_attended() {
+        let (terminal_height, _terminal_width) = console::Term::stdout().size();
+        if lines.len() >= usize::from(terminal_height) {
+            get_pager(repo)?
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    match pager {
+        Some(pager) => {
+            let output: String = lines.into_iter().map(|line| line + "\n").collect();
+            run_pager(&pager, &output)?;
+        }
+        None => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,251 @@
+//! Show the diffs of each commit in a stack, for reviewing the stack as a
+//! whole.
+//!
+//! The commits are visited in the same order as they're rendered in the
+//! smartlog, and each is diffed against its parent. Alternatively, the
+//! cumulative diff from the base of the stack to its tip can be shown. The
+//! diffs can also be opened in the user's configured `git difftool` instead of
+//! being printed.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::io::{stderr, Write};
+use std::process::Command;
+
+use anyhow::Context;
+use fn_error_context::context;
+
+use crate::commands::r#move::{resolve_commit_selection, CommitSelection, CommitSelectionOptions};
+use crate::core::config::get_core_abbrev;
+use crate::core::diffstat::EMPTY_TREE_OID;
+use crate::core::error::BranchlessError;
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Glyphs};
+use crate::core::graph::{AncestryCache, CommitGraph};
+use crate::core::mergebase::MergeBaseDb;
+use crate::core::rewrite::friendly_describe_commit;
+use crate::util::{abbreviate_oid, get_db_conn, get_repo, GitExecutable};
+
+/// A pair of commits to diff.
+struct DiffPair<'repo> {
+    /// The commit to diff from, or `None` to diff from the empty tree.
+    old_commit: Option<git2::Commit<'repo>>,
+
+    /// The commit to diff to.
+    new_commit: git2::Commit<'repo>,
+}
+
+/// Get the pairs of commits to diff for each commit in `commit_oids`: each
+/// commit and its first parent.
+fn get_commit_diff_pairs<'repo>(
+    repo: &'repo git2::Repository,
+    commit_oids: &[git2::Oid],
+) -> anyhow::Result<Vec<DiffPair<'repo>>> {
+    commit_oids
+        .iter()
+        .map(|oid| {
+            let new_commit = repo.find_commit(*oid)?;
+            Ok(DiffPair {
+                old_commit: new_commit.parents().next(),
+                new_commit,
+            })
+        })
+        .collect()
+}
+
+/// Get the pairs of commits to diff for the cumulative diff of each tip in
+/// `commit_oids`: the parent of the base of the stack containing the tip, and
+/// the tip itself.
+fn get_combined_diff_pairs<'repo>(
+    repo: &'repo git2::Repository,
+    graph: &CommitGraph,
+    commit_oids: &[git2::Oid],
+) -> anyhow::Result<Vec<DiffPair<'repo>>> {
+    let stack_oids: HashSet<git2::Oid> = commit_oids.iter().copied().collect();
+    let mut result = Vec::new();
+    for oid in commit_oids {
+        let is_tip = !graph[oid]
+            .children
+            .iter()
+            .any(|child_oid| stack_oids.contains(child_oid));
+        if !is_tip {
+            continue;
+        }
+
+        let new_commit = repo.find_commit(*oid)?;
+        let mut base_commit = new_commit.clone();
+        while let Some(parent_commit) = base_commit.parents().next() {
+            if !stack_oids.contains(&parent_commit.id()) {
+                break;
+            }
+            base_commit = parent_commit;
+        }
+        result.push(DiffPair {
+            old_commit: base_commit.parents().next(),
+            new_commit,
+        });
+    }
+    Ok(result)
+}
+
+/// Write the diff between the given commits, in patch format.
+#[context("Writing diff for commit {:?}", diff_pair.new_commit.id())]
+fn write_diff(
+    out: &mut impl Write,
+    repo: &git2::Repository,
+    diff_pair: &DiffPair,
+) -> anyhow::Result<()> {
+    let old_tree = match &diff_pair.old_commit {
+        Some(old_commit) => Some(old_commit.tree()?),
+        None => None,
+    };
+    let new_tree = diff_pair.new_commit.tree()?;
+    let diff = repo
+        .diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), None)
+        .context("Computing diff")?;
+
+    let mut patch: Vec<u8> = Vec::new();
+    diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+        if let origin @ '+' | origin @ '-' | origin @ ' ' = line.origin() {
+            patch.push(origin as u8);
+        }
+        patch.extend(line.content());
+        true
+    })
+    .context("Formatting diff")?;
+    out.write_all(&patch)?;
+    Ok(())
+}
+
+/// Open the diff between the given commits in the user's configured
+/// `git difftool`.
+///
+/// Returns: The exit code of `git difftool`.
+#[context("Running difftool for commit {:?}", diff_pair.new_commit.id())]
+fn run_difftool(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    diff_pair: &DiffPair,
+) -> anyhow::Result<isize> {
+    let GitExecutable(git_executable) = git_executable;
+    let old_oid = match &diff_pair.old_commit {
+        Some(old_commit) => old_commit.id().to_string(),
+        None => EMPTY_TREE_OID.to_string(),
+    };
+    let new_oid = diff_pair.new_commit.id().to_string();
+    out.flush()?;
+    stderr().flush()?;
+
+    // Unlike `run_git`, the terminal is passed through, since the difftool may
+    // be interactive.
+    let status = Command::new(git_executable)
+        .arg("difftool")
+        .arg(&old_oid)
+        .arg(&new_oid)
+        .status()
+        .context("Running git difftool")?;
+    let exit_code = status.code().unwrap_or(1).try_into()?;
+    Ok(exit_code)
+}
+
+/// Show the diff of each commit in a stack against its parent, in the order
+/// that the commits are rendered in the smartlog.
+///
+/// The commits to diff are resolved in the same way as for `git move`: the
+/// `source` commit and its descendants, or the entire stack containing the
+/// `base` commit. If neither is provided, the entire stack containing the
+/// current commit is diffed.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `source`: The commit to diff, along with its descendants.
+/// * `base`: A commit inside a stack to diff, starting from the main branch.
+/// * `stack`: Diff the commits in the current stack instead (see
+///   `resolve_current_stack`).
+/// * `combined`: Instead of diffing each commit, show the cumulative diff from
+///   the parent of the base of the stack to each tip of the stack.
+/// * `use_difftool`: Open each diff with `git difftool` rather than printing
+///   it.
+///
+/// Returns: exit code (0 denotes successful exit). If `git difftool` fails,
+/// then no further diffs are opened, and its exit code is returned.
+#[context("Showing diffs for stack")]
+pub fn stack_diff(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    source: Option<String>,
+    base: Option<String>,
+    stack: bool,
+    combined: bool,
+    use_difftool: bool,
+) -> anyhow::Result<isize> {
+    let repo = get_repo()?;
+    let glyphs = Glyphs::detect();
+    let conn = get_db_conn(&repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+    let CommitSelection {
+        graph, commit_oids, ..
+    } = match resolve_commit_selection(
+        out,
+        &glyphs,
+        &repo,
+        &merge_base_db,
+        &mut AncestryCache::default(),
+        &event_replayer,
+        CommitSelectionOptions {
+            source,
+            base,
+            stack,
+            default_to_base: true,
+            include_hidden: false,
+            verb: "diff",
+        },
+    ) {
+        Ok(selection) => selection,
+        // The reason for the failure has already been written to `out`.
+        Err(err) => match err
+            .downcast_ref::<BranchlessError>()
+            .and_then(|err| err.exit_code())
+        {
+            Some(exit_code) => return Ok(exit_code),
+            None => return Err(err),
+        },
+    };
+    if commit_oids.is_empty() {
+        writeln!(out, "There are no visible commits to diff.")?;
+        return Ok(0);
+    }
+
+    let diff_pairs = if combined {
+        get_combined_diff_pairs(&repo, &graph, &commit_oids)?
+    } else {
+        get_commit_diff_pairs(&repo, &commit_oids)?
+    };
+    for diff_pair in diff_pairs {
+        let description = friendly_describe_commit(&repo, diff_pair.new_commit.id())?;
+        let description = printable_styled_string(&glyphs, description)?;
+        match &diff_pair.old_commit {
+            Some(old_commit) if combined => writeln!(
+                out,
+                "Combined diff from {} to {}",
+                abbreviate_oid(&repo, old_commit.id(), get_core_abbrev(&repo)?)?,
+                description
+            )?,
+            None if combined => writeln!(out, "Combined diff to {}", description)?,
+            _ => writeln!(out, "{}", description)?,
+        }
+
+        if use_difftool {
+            let exit_code = run_difftool(out, git_executable, &diff_pair)?;
+            if exit_code != 0 {
+                return Ok(exit_code);
+            }
+        } else {
+            write_diff(out, &repo, &diff_pair)?;
+        }
+    }
+    Ok(0)
+}
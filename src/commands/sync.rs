@@ -0,0 +1,174 @@
+//! Move all stacks of commits onto the main branch.
+//!
+//! This is the counterpart to `restack` for when the main branch has moved,
+//! such as after running `git pull`, rather than when commits in a stack have
+//! been rewritten.
+
+use std::convert::TryInto;
+use std::io::stdout;
+use std::time::SystemTime;
+
+use fn_error_context::context;
+
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize};
+use crate::core::graph::{make_graph_from_repo, AncestryCache, CommitGraph, MainBranchOid};
+use crate::core::mergebase::MergeBaseDb;
+use crate::core::patchid::PatchIdDb;
+use crate::core::rewrite::{
+    execute_rebase_plan, find_rebase_conflicts, friendly_describe_commit, make_rebase_plan,
+};
+use crate::util::{get_db_conn, get_main_branch_oid, get_repo, GitExecutable, EXIT_CODE_CONFLICT};
+
+/// Find the roots of the stacks which are based on an older version of the
+/// main branch, i.e. the non-main children of main branch commits other than
+/// `main_branch_oid`. Stacks based on other main branches (see
+/// `get_main_branch_names`) are not included.
+#[context("Finding stacks to sync")]
+fn find_stale_stack_roots(
+    repo: &git2::Repository,
+    graph: &CommitGraph,
+    main_branch_oid: git2::Oid,
+) -> anyhow::Result<Vec<git2::Oid>> {
+    let mut root_oids = Vec::new();
+    for (oid, node) in graph.iter() {
+        if !node.is_main
+            || *oid == main_branch_oid
+            || !repo.graph_descendant_of(main_branch_oid, *oid)?
+        {
+            continue;
+        }
+        root_oids.extend(
+            node.children
+                .iter()
+                .filter(|child_oid| match graph.get(child_oid) {
+                    Some(child_node) => !child_node.is_main,
+                    None => false,
+                })
+                .copied(),
+        );
+    }
+
+    // Sort for determinism.
+    root_oids.sort_by_key(|root_oid| (graph[root_oid].commit.time(), root_oid.to_string()));
+    Ok(root_oids)
+}
+
+/// Move every stack of commits which isn't based on the current main branch
+/// commit onto it. All stacks are moved in the same transaction. Stacks which
+/// would cause merge conflicts are skipped and reported.
+///
+/// Args:
+/// * `git_executable`: The path to the `git` executable on disk.
+///
+/// Returns: exit code. This is one of:
+/// * 0: All stacks are based on the main branch.
+/// * `EXIT_CODE_CONFLICT` (1): Some stacks were skipped because of merge
+///   conflicts.
+///
+/// Other nonzero exit codes are passed through from executing the rebase, in
+/// which case any remaining stacks aren't synced.
+#[context("Syncing stacks with the main branch")]
+pub fn sync(git_executable: &GitExecutable) -> anyhow::Result<isize> {
+    let glyphs = Glyphs::detect();
+    let repo = get_repo()?;
+    let conn = get_db_conn(&repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let patch_id_db = PatchIdDb::new(&conn)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+    let main_branch_oid = get_main_branch_oid(&repo)?;
+    let graph = make_graph_from_repo(
+        &repo,
+        &merge_base_db,
+        &mut AncestryCache::default(),
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        true,
+    )?;
+
+    let root_oids = find_stale_stack_roots(&repo, &graph, main_branch_oid)?;
+    if root_oids.is_empty() {
+        println!("All stacks are already based on the main branch.");
+        return Ok(0);
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "sync")?;
+    let mut num_synced_stacks = 0;
+    let mut skipped_root_oids = Vec::new();
+    for root_oid in root_oids {
+        let rebase_plan = make_rebase_plan(
+            &repo,
+            &merge_base_db,
+            &patch_id_db,
+            &graph,
+            &MainBranchOid(main_branch_oid),
+            root_oid,
+            main_branch_oid,
+        )?;
+
+        // Check for conflicts ahead of time, so that we never fall back to an
+        // on-disk rebase partway through syncing.
+        if !find_rebase_conflicts(&repo, &rebase_plan, main_branch_oid)?.is_empty() {
+            skipped_root_oids.push(root_oid);
+            continue;
+        }
+
+        let result = execute_rebase_plan(
+            &mut stdout(),
+            &glyphs,
+            git_executable,
+            &repo,
+            event_tx_id,
+            &rebase_plan,
+            root_oid,
+            main_branch_oid,
+            false,
+            false,
+            false,
+        )?;
+        match result {
+            0 => num_synced_stacks += 1,
+            EXIT_CODE_CONFLICT => skipped_root_oids.push(root_oid),
+            result => {
+                // Something other than a merge conflict went wrong, such as a
+                // failing hook, so don't try to sync the remaining stacks.
+                println!(
+                    "Failed to sync stack: {}",
+                    printable_styled_string(&glyphs, friendly_describe_commit(&repo, root_oid)?)?
+                );
+                return Ok(result);
+            }
+        }
+    }
+
+    println!(
+        "Synced {}.",
+        Pluralize {
+            amount: num_synced_stacks,
+            singular: "stack",
+            plural: "stacks",
+        }
+        .to_string()
+    );
+    if skipped_root_oids.is_empty() {
+        return Ok(0);
+    }
+
+    println!(
+        "Skipped {}, which could not be moved without conflicts:",
+        Pluralize {
+            amount: skipped_root_oids.len().try_into()?,
+            singular: "stack",
+            plural: "stacks",
+        }
+        .to_string()
+    );
+    for root_oid in skipped_root_oids {
+        println!(
+            "- {}",
+            printable_styled_string(&glyphs, friendly_describe_commit(&repo, root_oid)?)?
+        );
+    }
+    Ok(EXIT_CODE_CONFLICT)
+}
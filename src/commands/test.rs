@@ -0,0 +1,305 @@
+//! Run a command on each commit in a stack, and record whether it passed.
+//!
+//! Each commit is checked out into its own temporary directory, so the working
+//! copy isn't touched. The results are cached by tree OID, so commits whose
+//! contents haven't changed (such as after a rebase which only touched their
+//! ancestors' messages) aren't tested again. If a notes ref is configured with
+//! `branchless.smartlog.statusNotesRef`, the results are also recorded as notes
+//! under it, so that they're shown in the smartlog.
+
+use std::convert::TryInto;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context;
+use fn_error_context::context;
+use rusqlite::OptionalExtension;
+
+use crate::commands::r#move::{resolve_commit_selection, CommitSelection, CommitSelectionOptions};
+use crate::core::config::get_smartlog_status_notes_ref;
+use crate::core::error::BranchlessError;
+use crate::core::eventlog::{EventLogDb, EventReplayer};
+use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize, StyledStringBuilder};
+use crate::core::graph::AncestryCache;
+use crate::core::mergebase::MergeBaseDb;
+use crate::core::rewrite::friendly_describe_commit;
+use crate::util::{
+    checkout_commit_into_dir, get_committer_signature, get_db_conn, get_repo, get_sh, GitExecutable,
+};
+
+/// The environment variable which is set to the OID of the commit being
+/// tested when running the test command.
+pub const BRANCHLESS_TEST_COMMIT_ENV_VAR: &str = "BRANCHLESS_TEST_COMMIT";
+
+/// On-disk cache for the results of running test commands, keyed by the tree
+/// OID of the tested commit and the command which was run.
+pub struct TestResultDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+#[context("Initializing tables for `TestResultDb`")]
+fn init_tables(conn: &rusqlite::Connection) -> anyhow::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS test_results (
+    tree_oid TEXT NOT NULL,
+    command TEXT NOT NULL,
+    exit_code INTEGER NOT NULL,
+    UNIQUE (tree_oid, command)
+)
+",
+        rusqlite::params![],
+    )
+    .context("Creating tables")?;
+    Ok(())
+}
+
+impl<'conn> TestResultDb<'conn> {
+    /// Constructor.
+    #[context("Constructing `TestResultDb`")]
+    pub fn new(conn: &'conn rusqlite::Connection) -> anyhow::Result<Self> {
+        init_tables(conn).context("Initializing tables")?;
+        Ok(TestResultDb { conn })
+    }
+
+    /// Get the cached exit code of running `command` on a commit with the
+    /// given tree.
+    ///
+    /// Returns: `None` if the command hasn't been run on the tree before.
+    #[context("Querying cached test result for tree {:?}", tree_oid)]
+    pub fn get_cached_exit_code(
+        &self,
+        tree_oid: git2::Oid,
+        command: &str,
+    ) -> anyhow::Result<Option<isize>> {
+        let exit_code: Option<i64> = self
+            .conn
+            .query_row_named(
+                "
+SELECT exit_code
+FROM test_results
+WHERE tree_oid = :tree_oid
+  AND command = :command
+",
+                rusqlite::named_params! {
+                    ":tree_oid": tree_oid.to_string(),
+                    ":command": command,
+                },
+                |row| row.get("exit_code"),
+            )
+            .optional()
+            .context("Querying test result DB")?;
+        match exit_code {
+            Some(exit_code) => Ok(Some(exit_code.try_into()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the exit code of running `command` on a commit with the given
+    /// tree in the cache.
+    #[context("Caching test result for tree {:?}", tree_oid)]
+    pub fn cache_exit_code(
+        &self,
+        tree_oid: git2::Oid,
+        command: &str,
+        exit_code: isize,
+    ) -> anyhow::Result<()> {
+        let exit_code: i64 = exit_code.try_into()?;
+        self.conn
+            .execute_named(
+                "
+INSERT OR REPLACE INTO test_results
+VALUES (:tree_oid, :command, :exit_code)
+",
+                rusqlite::named_params! {
+                    ":tree_oid": tree_oid.to_string(),
+                    ":command": command,
+                    ":exit_code": exit_code,
+                },
+            )
+            .context("Caching test result")?;
+        Ok(())
+    }
+}
+
+/// Check out the given commit into `dir` and run `command` there.
+///
+/// Returns: The exit code of the command, and its output (standard output
+/// followed by standard error).
+#[context("Running test command on commit {:?}", commit.id())]
+fn run_test_command(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    command: &str,
+    dir: &Path,
+) -> anyhow::Result<(isize, Vec<u8>)> {
+    checkout_commit_into_dir(repo, commit, dir)?;
+
+    let result = Command::new(get_sh().context("shell needed to run test command")?)
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .env(BRANCHLESS_TEST_COMMIT_ENV_VAR, commit.id().to_string())
+        .output()
+        .with_context(|| format!("Running test command: {:?}", command))?;
+    // If the command was terminated by a signal, treat it as a failure.
+    let exit_code = result.status.code().unwrap_or(1).try_into()?;
+    let mut output = result.stdout;
+    output.extend(result.stderr);
+    Ok((exit_code, output))
+}
+
+/// Run a command on each commit in a stack, and report which commits it
+/// passed or failed on.
+///
+/// The commits to test are resolved in the same way as for `git move`: the
+/// `source` commit and its descendants, or the entire stack containing the
+/// `base` commit. If neither is provided, the current commit and its
+/// descendants are tested.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `command`: The shell command to run on each commit. It's considered to
+///   pass if it exits with code 0.
+/// * `source`: The commit to test, along with its descendants.
+/// * `base`: A commit inside a stack to test, starting from the main branch.
+/// * `stack`: Test the commits in the current stack instead (see
+///   `resolve_current_stack`).
+/// * `notes_ref`: The notes ref to record the results under. If not provided,
+///   defaults to `branchless.smartlog.statusNotesRef`. If that isn't set
+///   either, the results aren't recorded as notes.
+///
+/// Returns: exit code. This is 0 if the command passed on every commit, 1 if it
+/// failed on any commit, or the exit code for the error from
+/// `resolve_commit_selection` if the commits couldn't be selected, such as
+/// `EXIT_CODE_COMMIT_NOT_FOUND` if the commit couldn't be resolved.
+#[context("Testing commits")]
+pub fn test(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    command: &str,
+    source: Option<String>,
+    base: Option<String>,
+    stack: bool,
+    notes_ref: Option<String>,
+) -> anyhow::Result<isize> {
+    let repo = get_repo()?;
+    let glyphs = Glyphs::detect();
+    let conn = get_db_conn(&repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let test_result_db = TestResultDb::new(&conn)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+    let CommitSelection { commit_oids, .. } = match resolve_commit_selection(
+        out,
+        &glyphs,
+        &repo,
+        &merge_base_db,
+        &mut AncestryCache::default(),
+        &event_replayer,
+        CommitSelectionOptions {
+            source,
+            base,
+            stack,
+            default_to_base: false,
+            include_hidden: false,
+            verb: "test",
+        },
+    ) {
+        Ok(selection) => selection,
+        // The reason for the failure has already been written to `out`.
+        Err(err) => match err
+            .downcast_ref::<BranchlessError>()
+            .and_then(|err| err.exit_code())
+        {
+            Some(exit_code) => return Ok(exit_code),
+            None => return Err(err),
+        },
+    };
+    if commit_oids.is_empty() {
+        writeln!(out, "There are no visible commits to test.")?;
+        return Ok(0);
+    }
+
+    let notes_ref = match notes_ref {
+        Some(notes_ref) => Some(notes_ref),
+        None => get_smartlog_status_notes_ref(&repo)?,
+    };
+    let signature = get_committer_signature(&repo, git_executable)?;
+    let mut num_passed = 0;
+    let mut num_failed = 0;
+    for commit_oid in commit_oids.iter().copied() {
+        let commit = repo.find_commit(commit_oid)?;
+        let (exit_code, is_cached) =
+            match test_result_db.get_cached_exit_code(commit.tree_id(), command)? {
+                Some(exit_code) => (exit_code, true),
+                None => {
+                    let temp_dir = tempfile::tempdir().context("Creating temporary directory")?;
+                    let (exit_code, output) =
+                        run_test_command(&repo, &commit, command, temp_dir.path())?;
+                    test_result_db.cache_exit_code(commit.tree_id(), command, exit_code)?;
+                    if exit_code != 0 {
+                        out.write_all(&output)?;
+                    }
+                    (exit_code, false)
+                }
+            };
+
+        let passed = exit_code == 0;
+        if passed {
+            num_passed += 1;
+        } else {
+            num_failed += 1;
+        }
+        if let Some(notes_ref) = &notes_ref {
+            let status = if passed { "passed" } else { "failed" };
+            repo.note(
+                &signature,
+                &signature,
+                Some(notes_ref),
+                commit_oid,
+                &format!("{} `{}`\n", status, command),
+                true,
+            )
+            .with_context(|| format!("Recording test result in notes ref: {}", notes_ref))?;
+        }
+
+        let status = if passed {
+            glyphs.commit_status_success
+        } else {
+            glyphs.commit_status_failure
+        };
+        let mut line = StyledStringBuilder::new()
+            .append_plain(format!("{} ", status))
+            .append(friendly_describe_commit(&repo, commit_oid)?)
+            .build();
+        if !passed {
+            line.append_plain(format!(" (exit code {})", exit_code));
+        }
+        if is_cached {
+            line.append_plain(" (cached)");
+        }
+        writeln!(out, "{}", printable_styled_string(&glyphs, line)?)?;
+    }
+
+    writeln!(
+        out,
+        "Ran `{}` on {}: {} passed, {} failed.",
+        command,
+        Pluralize {
+            amount: commit_oids.len().try_into()?,
+            singular: "commit",
+            plural: "commits",
+        }
+        .to_string(),
+        num_passed,
+        num_failed
+    )?;
+    if num_failed > 0 {
+        Ok(1)
+    } else {
+        Ok(0)
+    }
+}
@@ -3,68 +3,293 @@
 //! This is accomplished by finding the events that have happened since a certain
 //! time and inverting them.
 
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::convert::TryInto;
-use std::io::{stdin, stdout, BufRead, BufReader, Read, Write};
+use std::io::{stdin, stdout, BufReader, Read, Write};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::time::SystemTime;
 
 use anyhow::Context;
-use cursive::event::Key;
+use cursive::theme::{BaseColor, Color};
 use cursive::utils::markup::StyledString;
-use cursive::views::{Dialog, EditView, LinearLayout, OnEventView, ScrollView, TextView};
+use cursive::views::{
+    Dialog, EditView, HideableView, LinearLayout, OnEventView, ScrollView, TextView,
+};
 use cursive::{Cursive, CursiveRunnable, CursiveRunner};
 
-use crate::commands::smartlog::render_graph;
-use crate::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId};
-use crate::core::formatting::{printable_styled_string, Glyphs, Pluralize, StyledStringBuilder};
-use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+use crate::commands::smartlog::{render_graph, RenderOptions};
+use crate::core::config::get_core_abbrev;
+use crate::core::eventlog::{
+    is_backup_ref, ClockSkew, Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId,
+};
+use crate::core::formatting::{
+    printable_styled_string, replace_color, truncate_styled_string, Glyphs, Pluralize,
+    StyledStringBuilder,
+};
+use crate::core::graph::{
+    filter_graph, make_graph, AdditionalMainBranchOids, AncestryCache, BranchOids, CommitGraph,
+    HeadOid, MainBranchOid, Node, TagOids, UnhideableRefOids,
+};
 use crate::core::mergebase::MergeBaseDb;
 use crate::core::metadata::{
-    render_commit_metadata, BranchesProvider, CommitMessageProvider, CommitOidProvider,
-    DifferentialRevisionProvider, HiddenExplanationProvider, RelativeTimeProvider,
+    render_commit_metadata, AheadBehindProvider, BranchesProvider, CommitMessageProvider,
+    CommitOidProvider, DifferentialRevisionProvider, HiddenExplanationProvider,
+    RelativeTimeProvider, TagsProvider, HIDDEN_EXPLANATION_COLOR,
+};
+use crate::core::rewrite::{friendly_describe_commit, restore_backup};
+use crate::core::tui::{
+    get_key_bindings, with_siv, KeyBindings, SingletonView, HIDDEN_PALETTE_KEY,
+    MAIN_BRANCH_PALETTE_KEY,
 };
-use crate::core::tui::{with_siv, SingletonView};
 use crate::declare_views;
-use crate::util::{get_db_conn, get_repo, run_git, GitExecutable};
+use crate::util::{
+    abbreviate_oid, get_db_conn, get_repo, get_unhideable_ref_oids, prompt_confirmation, run_git,
+    GitExecutable,
+};
 
-fn render_cursor_smartlog(
-    glyphs: &Glyphs,
-    repo: &git2::Repository,
+/// Determine whether the node matches the filter typed by the user, by
+/// commit hash prefix or by a substring of the commit summary. Matching is
+/// case-insensitive.
+fn node_matches_filter(node: &Node, filter: &str) -> bool {
+    let filter = filter.to_lowercase();
+    let commit = &node.commit;
+    commit.id().to_string().starts_with(&filter)
+        || commit
+            .summary()
+            .map(|summary| summary.to_lowercase().contains(&filter))
+            .unwrap_or(false)
+}
+
+/// Construct the commit graph as of the given event cursor.
+fn make_cursor_graph<'repo>(
+    repo: &'repo git2::Repository,
     merge_base_db: &MergeBaseDb,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
-) -> anyhow::Result<Vec<StyledString>> {
+) -> anyhow::Result<CommitGraph<'repo>> {
     let head_oid = event_replayer.get_cursor_head_oid(event_cursor);
     let main_branch_oid = event_replayer.get_cursor_main_branch_oid(event_cursor, repo)?;
     let branch_oid_to_names = event_replayer.get_cursor_branch_oid_to_names(event_cursor, repo)?;
-    let graph = make_graph(
+    let tag_oid_to_names = event_replayer.get_cursor_tag_oid_to_names(event_cursor);
+    let additional_main_branch_oids =
+        event_replayer.get_cursor_additional_main_branch_oids(event_cursor, repo)?;
+    make_graph(
         repo,
         merge_base_db,
+        &mut AncestryCache::default(),
         event_replayer,
         event_cursor,
         &HeadOid(head_oid),
         &MainBranchOid(main_branch_oid),
+        &AdditionalMainBranchOids(additional_main_branch_oids),
         &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        &TagOids(tag_oid_to_names.keys().copied().collect()),
+        &UnhideableRefOids(get_unhideable_ref_oids(repo)?),
         true,
-    )?;
+    )
+}
+
+fn render_cursor_smartlog(
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    filter: &str,
+    main_branch_color: Option<Color>,
+    hidden_color: Option<Color>,
+) -> anyhow::Result<Vec<StyledString>> {
+    let head_oid = event_replayer.get_cursor_head_oid(event_cursor);
+    let main_branch_oid = event_replayer.get_cursor_main_branch_oid(event_cursor, repo)?;
+    let branch_oid_to_names = event_replayer.get_cursor_branch_oid_to_names(event_cursor, repo)?;
+    let tag_oid_to_names = event_replayer.get_cursor_tag_oid_to_names(event_cursor);
+    let graph = make_cursor_graph(repo, merge_base_db, event_replayer, event_cursor)?;
+    let graph = {
+        let mut graph = graph;
+        if !filter.is_empty() {
+            filter_graph(&mut graph, |node| node_matches_filter(node, filter));
+        }
+        graph
+    };
     let result = render_graph(
         glyphs,
         repo,
         merge_base_db,
         &graph,
         &HeadOid(head_oid),
+        &MainBranchOid(main_branch_oid),
         &mut [
-            &mut CommitOidProvider::new(true)?,
+            &mut CommitOidProvider::new(repo, true)?,
             &mut RelativeTimeProvider::new(&repo, SystemTime::now())?,
-            &mut HiddenExplanationProvider::new(&graph, &event_replayer, event_cursor)?,
+            &mut HiddenExplanationProvider::new(repo, &graph, &event_replayer, event_cursor)?,
             &mut BranchesProvider::new(&repo, &branch_oid_to_names)?,
+            &mut AheadBehindProvider::new(
+                repo,
+                &MainBranchOid(main_branch_oid),
+                &branch_oid_to_names,
+            )?,
+            &mut TagsProvider::new(repo, &tag_oid_to_names)?,
             &mut DifferentialRevisionProvider::new(&repo)?,
             &mut CommitMessageProvider::new()?,
         ],
+        &RenderOptions {
+            main_branch_color,
+            ..Default::default()
+        },
     )?;
+    let result = match hidden_color {
+        Some(hidden_color) => result
+            .into_iter()
+            .map(|line| replace_color(line, HIDDEN_EXPLANATION_COLOR, hidden_color))
+            .collect(),
+        None => result,
+    };
     Ok(result)
 }
 
+/// The differences between the smartlogs at two event cursors, which are
+/// shown to the user before they confirm an undo.
+#[derive(Debug)]
+struct SmartlogDiff {
+    /// The visible commits which are only shown in the new smartlog, in order
+    /// of commit time.
+    appeared_commit_oids: Vec<git2::Oid>,
+
+    /// The visible commits which are only shown in the old smartlog, in order
+    /// of commit time.
+    disappeared_commit_oids: Vec<git2::Oid>,
+
+    /// The branches which point to different commits in the two smartlogs, as
+    /// the branch name, the old target, and the new target. A missing target
+    /// means that the branch doesn't exist at that cursor.
+    moved_branches: Vec<(String, Option<git2::Oid>, Option<git2::Oid>)>,
+}
+
+/// Compare the smartlog at `old_cursor` with the smartlog at `new_cursor`.
+fn diff_cursor_smartlogs(
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    event_replayer: &EventReplayer,
+    old_cursor: EventCursor,
+    new_cursor: EventCursor,
+) -> anyhow::Result<SmartlogDiff> {
+    let get_visible_oids = |event_cursor: EventCursor| -> anyhow::Result<HashSet<git2::Oid>> {
+        let graph = make_cursor_graph(repo, merge_base_db, event_replayer, event_cursor)?;
+        Ok(graph
+            .iter()
+            .filter(|(_oid, node)| node.is_visible && !node.is_main)
+            .map(|(oid, _node)| *oid)
+            .collect())
+    };
+    let sort_by_commit_time = |oids: Vec<git2::Oid>| -> anyhow::Result<Vec<git2::Oid>> {
+        let mut oids_with_times = oids
+            .into_iter()
+            .map(|oid| Ok((repo.find_commit(oid)?.time().seconds(), oid)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        oids_with_times.sort();
+        Ok(oids_with_times
+            .into_iter()
+            .map(|(_time, oid)| oid)
+            .collect())
+    };
+    let old_visible_oids = get_visible_oids(old_cursor)?;
+    let new_visible_oids = get_visible_oids(new_cursor)?;
+    let appeared_commit_oids = sort_by_commit_time(
+        new_visible_oids
+            .difference(&old_visible_oids)
+            .copied()
+            .collect(),
+    )?;
+    let disappeared_commit_oids = sort_by_commit_time(
+        old_visible_oids
+            .difference(&new_visible_oids)
+            .copied()
+            .collect(),
+    )?;
+
+    let get_branch_name_to_oid =
+        |event_cursor: EventCursor| -> anyhow::Result<BTreeMap<String, git2::Oid>> {
+            let branch_oid_to_names =
+                event_replayer.get_cursor_branch_oid_to_names(event_cursor, repo)?;
+            Ok(branch_oid_to_names
+                .into_iter()
+                .flat_map(|(oid, names)| names.into_iter().map(move |name| (name, oid)))
+                .collect())
+        };
+    let old_branch_name_to_oid = get_branch_name_to_oid(old_cursor)?;
+    let new_branch_name_to_oid = get_branch_name_to_oid(new_cursor)?;
+    let branch_names: BTreeSet<&String> = old_branch_name_to_oid
+        .keys()
+        .chain(new_branch_name_to_oid.keys())
+        .collect();
+    let moved_branches = branch_names
+        .into_iter()
+        .filter_map(|branch_name| {
+            let old_oid = old_branch_name_to_oid.get(branch_name).copied();
+            let new_oid = new_branch_name_to_oid.get(branch_name).copied();
+            if old_oid == new_oid {
+                None
+            } else {
+                Some((branch_name.clone(), old_oid, new_oid))
+            }
+        })
+        .collect();
+
+    Ok(SmartlogDiff {
+        appeared_commit_oids,
+        disappeared_commit_oids,
+        moved_branches,
+    })
+}
+
+/// Describe the differences in `smartlog_diff` to the user, one per line.
+/// Commits which will appear are marked with `+`, commits which will disappear
+/// are marked with `-`, and branches which will move are marked with `~`.
+fn describe_smartlog_diff(
+    repo: &git2::Repository,
+    smartlog_diff: &SmartlogDiff,
+) -> anyhow::Result<Vec<StyledString>> {
+    let abbrev_len = get_core_abbrev(repo)?;
+    let describe_branch_target = |oid: Option<git2::Oid>| -> anyhow::Result<String> {
+        match oid {
+            Some(oid) => abbreviate_oid(repo, oid, abbrev_len),
+            None => Ok("(none)".to_string()),
+        }
+    };
+
+    let mut lines = Vec::new();
+    for commit_oid in smartlog_diff.appeared_commit_oids.iter() {
+        lines.push(
+            StyledStringBuilder::new()
+                .append_styled("+ ", BaseColor::Green.light())
+                .append(friendly_describe_commit(repo, *commit_oid)?)
+                .build(),
+        );
+    }
+    for commit_oid in smartlog_diff.disappeared_commit_oids.iter() {
+        lines.push(
+            StyledStringBuilder::new()
+                .append_styled("- ", BaseColor::Red.light())
+                .append(friendly_describe_commit(repo, *commit_oid)?)
+                .build(),
+        );
+    }
+    for (branch_name, old_oid, new_oid) in smartlog_diff.moved_branches.iter() {
+        lines.push(
+            StyledStringBuilder::new()
+                .append_styled("~ ", BaseColor::Yellow.light())
+                .append_plain(format!(
+                    "branch {}: {} -> {}",
+                    branch_name,
+                    describe_branch_target(*old_oid)?,
+                    describe_branch_target(*new_oid)?
+                ))
+                .build(),
+        );
+    }
+    Ok(lines)
+}
+
 fn render_ref_name(ref_name: &str) -> String {
     match ref_name.strip_prefix("refs/heads/") {
         Some(branch_name) => format!("branch {}", branch_name),
@@ -72,13 +297,17 @@ fn render_ref_name(ref_name: &str) -> String {
     }
 }
 
-fn describe_event(repo: &git2::Repository, event: &Event) -> anyhow::Result<Vec<StyledString>> {
+/// Describe the given event to the user, as one or more lines of text.
+pub(crate) fn describe_event(
+    repo: &git2::Repository,
+    event: &Event,
+) -> anyhow::Result<Vec<StyledString>> {
     let render_commit = |oid: git2::Oid| -> anyhow::Result<StyledString> {
         match repo.find_commit(oid) {
             Ok(commit) => render_commit_metadata(
                 &commit,
                 &mut [
-                    &mut CommitOidProvider::new(true)?,
+                    &mut CommitOidProvider::new(repo, true)?,
                     &mut CommitMessageProvider::new()?,
                 ],
             ),
@@ -270,10 +499,42 @@ fn describe_event(repo: &git2::Repository, event: &Event) -> anyhow::Result<Vec<
                     .build(),
             ]
         }
+
+        Event::MoveEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+            old_parent_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Move commit ")
+                    .append(render_commit(*commit_oid)?)
+                    .build(),
+                StyledStringBuilder::new()
+                    .append_plain("       from ")
+                    .append(render_commit(*old_parent_oid)?)
+                    .build(),
+            ]
+        }
     };
     Ok(result)
 }
 
+/// Describe the clock skew of an event, for display next to its timestamp.
+/// This explains why the undo timeline may not match the recorded timestamps.
+pub fn describe_clock_skew(clock_skew: &ClockSkew) -> anyhow::Result<String> {
+    let ClockSkew {
+        cursor: _,
+        timestamp,
+        previous_timestamp,
+    } = clock_skew;
+    Ok(format!(
+        " (clock skew: recorded {} before an earlier event)",
+        RelativeTimeProvider::describe_time_delta(*previous_timestamp, *timestamp)?
+    ))
+}
+
 fn describe_events_numbered(
     repo: &git2::Repository,
     events: &[Event],
@@ -298,16 +559,56 @@ fn describe_events_numbered(
     Ok(lines)
 }
 
+/// The key bindings for the `git undo` interface. These can be configured in
+/// the `branchless.tui.keymap` config section.
+#[derive(Clone, Debug)]
+struct UndoKeymap {
+    next: KeyBindings,
+    previous: KeyBindings,
+    help: KeyBindings,
+    go_to_event: KeyBindings,
+    quit: KeyBindings,
+    confirm: KeyBindings,
+    cancel: KeyBindings,
+    filter: KeyBindings,
+}
+
+impl UndoKeymap {
+    /// Load the key bindings from the repository's configuration.
+    ///
+    /// This should be called outside of `with_siv`, since logging is disabled
+    /// there, and we want to warn the user about invalid key bindings.
+    fn from_config(repo: &git2::Repository) -> anyhow::Result<Self> {
+        Ok(UndoKeymap {
+            next: get_key_bindings(repo, "next", &["n", "N", "<right>"])?,
+            previous: get_key_bindings(repo, "previous", &["p", "P", "<left>"])?,
+            help: get_key_bindings(repo, "help", &["h", "H", "?"])?,
+            go_to_event: get_key_bindings(repo, "goToEvent", &["g", "G"])?,
+            quit: get_key_bindings(repo, "quit", &["q", "Q"])?,
+            confirm: get_key_bindings(repo, "confirm", &["<enter>"])?,
+            cancel: get_key_bindings(repo, "cancel", &["<esc>"])?,
+            filter: get_key_bindings(repo, "filter", &["/"])?,
+        })
+    }
+}
+
+/// The number of columns around a layer added with `Cursive::add_layer` which
+/// aren't available to its contents: one for the left padding and one for the
+/// shadow.
+const LAYER_HORIZONTAL_PADDING: usize = 2;
+
 fn select_past_event(
     mut siv: CursiveRunner<CursiveRunnable>,
     glyphs: &Glyphs,
     repo: &git2::Repository,
     merge_base_db: &MergeBaseDb,
     event_replayer: &mut EventReplayer,
+    keymap: &UndoKeymap,
 ) -> anyhow::Result<Option<EventCursor>> {
     #[derive(Clone, Copy, Debug)]
     enum Message {
         Init,
+        Redraw,
         Next,
         Previous,
         GoToEvent,
@@ -315,30 +616,30 @@ fn select_past_event(
         Help,
         Quit,
         SelectEventIdAndQuit,
+        StartFilter,
+        UpdateFilter,
+        FinishFilter,
+        ClearFilter,
     }
     let (main_tx, main_rx): (Sender<Message>, Receiver<Message>) = channel();
 
     [
-        ('n'.into(), Message::Next),
-        ('N'.into(), Message::Next),
-        (Key::Right.into(), Message::Next),
-        ('p'.into(), Message::Previous),
-        ('P'.into(), Message::Previous),
-        (Key::Left.into(), Message::Previous),
-        ('h'.into(), Message::Help),
-        ('H'.into(), Message::Help),
-        ('?'.into(), Message::Help),
-        ('g'.into(), Message::GoToEvent),
-        ('G'.into(), Message::GoToEvent),
-        ('q'.into(), Message::Quit),
-        ('Q'.into(), Message::Quit),
-        (
-            cursive::event::Key::Enter.into(),
-            Message::SelectEventIdAndQuit,
-        ),
+        (&keymap.next, Message::Next),
+        (&keymap.previous, Message::Previous),
+        (&keymap.help, Message::Help),
+        (&keymap.go_to_event, Message::GoToEvent),
+        (&keymap.quit, Message::Quit),
+        (&keymap.confirm, Message::SelectEventIdAndQuit),
+        (&keymap.filter, Message::StartFilter),
+        (&keymap.cancel, Message::ClearFilter),
     ]
     .iter()
-    .cloned()
+    .flat_map(|(key_bindings, message)| {
+        key_bindings
+            .events()
+            .iter()
+            .map(move |event| (event.clone(), *message))
+    })
     .for_each(|(event, message): (cursive::event::Event, Message)| {
         siv.add_global_callback(event, {
             let main_tx = main_tx.clone();
@@ -346,6 +647,11 @@ fn select_past_event(
         });
     });
 
+    siv.set_on_pre_event(cursive::event::Event::WindowResize, {
+        let main_tx = main_tx.clone();
+        move |_siv| main_tx.send(Message::Redraw).unwrap()
+    });
+
     let mut cursor = event_replayer.make_default_cursor();
     let now = SystemTime::now();
     main_tx.send(Message::Init)?;
@@ -362,19 +668,39 @@ fn select_past_event(
         declare_views! {
             SmartlogView => ScrollView<TextView>,
             InfoView => TextView,
+            FilterView => HideableView<EditView>,
         }
 
         let redraw = |siv: &mut Cursive,
                       event_replayer: &mut EventReplayer,
                       event_cursor: EventCursor|
          -> anyhow::Result<()> {
+            let filter = FilterView::find(siv).get_inner().get_content();
+            let (main_branch_color, hidden_color) = {
+                let palette = &siv.current_theme().palette;
+                (
+                    palette.custom(MAIN_BRANCH_PALETTE_KEY).copied(),
+                    palette.custom(HIDDEN_PALETTE_KEY).copied(),
+                )
+            };
             let smartlog = render_cursor_smartlog(
                 &glyphs,
                 &repo,
                 &merge_base_db,
                 &event_replayer,
                 event_cursor,
+                &filter,
+                main_branch_color,
+                hidden_color,
             )?;
+            // Truncate long lines rather than wrapping them, so that each
+            // commit takes up only one line. This is recomputed on every
+            // redraw, including when the terminal is resized.
+            let max_width = siv.screen_size().x.saturating_sub(LAYER_HORIZONTAL_PADDING);
+            let smartlog = smartlog
+                .into_iter()
+                .map(|line| truncate_styled_string(glyphs, line, max_width))
+                .collect();
             SmartlogView::find(siv)
                 .get_inner_mut()
                 .set_content(StyledStringBuilder::from_lines(smartlog));
@@ -391,16 +717,25 @@ fn select_past_event(
                     };
                     let relative_time_provider = RelativeTimeProvider::new(repo, now)?;
                     let relative_time = if relative_time_provider.is_enabled() {
+                        let timestamp = event_replayer
+                            .get_cursor_logical_timestamp(event_replayer.make_cursor(event_id))
+                            .unwrap_or_else(|| events[0].get_timestamp());
                         format!(
                             " ({} ago)",
-                            RelativeTimeProvider::describe_time_delta(
-                                now,
-                                events[0].get_timestamp()
-                            )?
+                            RelativeTimeProvider::describe_time_delta(now, timestamp)?
                         )
                     } else {
                         String::new()
                     };
+                    let clock_skew =
+                        (event_id..=event_id + events.len() as isize - 1).find_map(|event_id| {
+                            event_replayer
+                                .get_clock_skew_before_cursor(event_replayer.make_cursor(event_id))
+                        });
+                    let clock_skew = match clock_skew {
+                        Some(clock_skew) => describe_clock_skew(clock_skew)?,
+                        None => String::new(),
+                    };
                     vec![
                         StyledStringBuilder::new()
                             .append_plain("Repo after transaction ")
@@ -409,7 +744,12 @@ fn select_past_event(
                             .append_plain(event_id.to_string())
                             .append_plain(")")
                             .append_plain(relative_time)
-                            .append_plain(". Press 'h' for help, 'q' to quit.")
+                            .append_plain(clock_skew)
+                            .append_plain(format!(
+                                ". Press '{}' for help, '{}' to quit.",
+                                keymap.help.describe_first(),
+                                keymap.quit.describe_first()
+                            ))
                             .build(),
                         event_description,
                     ]
@@ -431,11 +771,32 @@ fn select_past_event(
             Ok(Message::Init) => {
                 let smartlog_view: SmartlogView = ScrollView::new(TextView::new("")).into();
                 let info_view: InfoView = TextView::new("").into();
+                let filter_view: FilterView = HideableView::new(
+                    EditView::new()
+                        .on_edit({
+                            let main_tx = main_tx.clone();
+                            move |_siv, _text, _cursor| main_tx.send(Message::UpdateFilter).unwrap()
+                        })
+                        .on_submit({
+                            let main_tx = main_tx.clone();
+                            move |_siv, _text| main_tx.send(Message::FinishFilter).unwrap()
+                        }),
+                )
+                .hidden()
+                .into();
                 siv.add_layer(
                     LinearLayout::vertical()
                         .child(smartlog_view)
+                        .child(filter_view)
                         .child(info_view),
                 );
+                // Lay out the views so that the screen size is known when
+                // rendering the smartlog.
+                siv.refresh();
+                redraw(&mut siv, event_replayer, cursor)?;
+            }
+
+            Ok(Message::Redraw) => {
                 redraw(&mut siv, event_replayer, cursor)?;
             }
 
@@ -456,52 +817,95 @@ fn select_past_event(
 
             Ok(Message::GoToEvent) => {
                 let main_tx = main_tx.clone();
-                siv.add_layer(
-                    OnEventView::new(
-                        Dialog::new()
-                            .title("Go to event")
-                            .content(EditView::new().on_submit(move |siv, text| {
-                                match text.parse::<isize>() {
-                                    Ok(event_id) => {
-                                        main_tx
-                                            .send(Message::SetEventReplayerCursor { event_id })
-                                            .unwrap();
-                                        siv.pop_layer();
-                                    }
-                                    Err(_) => {
-                                        siv.add_layer(Dialog::info(format!(
-                                            "Invalid event ID: {}",
-                                            text
-                                        )));
-                                    }
+                let mut dialog = OnEventView::new(
+                    Dialog::new()
+                        .title("Go to event")
+                        .content(EditView::new().on_submit(move |siv, text| {
+                            match text.parse::<isize>() {
+                                Ok(event_id) => {
+                                    main_tx
+                                        .send(Message::SetEventReplayerCursor { event_id })
+                                        .unwrap();
+                                    siv.pop_layer();
                                 }
-                            }))
-                            .dismiss_button("Cancel"),
-                    )
-                    .on_event(Key::Esc, |siv| {
-                        siv.pop_layer();
-                    }),
+                                Err(_) => {
+                                    siv.add_layer(Dialog::info(format!(
+                                        "Invalid event ID: {}",
+                                        text
+                                    )));
+                                }
+                            }
+                        }))
+                        .dismiss_button("Cancel"),
                 );
+                for event in keymap.cancel.events() {
+                    dialog.set_on_event(event.clone(), |siv| {
+                        siv.pop_layer();
+                    });
+                }
+                siv.add_layer(dialog);
             }
 
             Ok(Message::Help) => {
                 siv.add_layer(
-                        Dialog::new()
-                            .title("How to use")
-                            .content(TextView::new(
+                    Dialog::new()
+                        .title("How to use")
+                        .content(TextView::new(format!(
 "Use `git undo` to view and revert to previous states of the repository.
 
-h/?: Show this help.
-q: Quit.
-p/n or <left>/<right>: View next/previous state.
-g: Go to a provided event ID.
-<enter>: Revert the repository to the given state (requires confirmation).
+{help}: Show this help.
+{quit}: Quit.
+{previous} or {next}: View previous/next state.
+{go_to_event}: Go to a provided event ID.
+{filter}: Filter commits by hash prefix or message ({cancel} to clear).
+{confirm}: Revert the repository to the given state (requires confirmation).
 
 You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it.
+Key bindings can be changed in the `branchless.tui.keymap` config section.
 ",
-                            ))
-                            .dismiss_button("Close"),
-                    );
+                                help = keymap.help.describe(),
+                                quit = keymap.quit.describe(),
+                                previous = keymap.previous.describe(),
+                                next = keymap.next.describe(),
+                                go_to_event = keymap.go_to_event.describe(),
+                                filter = keymap.filter.describe(),
+                                cancel = keymap.cancel.describe(),
+                                confirm = keymap.confirm.describe(),
+                            )))
+                        .dismiss_button("Close"),
+                );
+            }
+
+            Ok(Message::StartFilter) => {
+                {
+                    let mut filter_view = FilterView::find(&mut siv);
+                    filter_view.unhide();
+                    filter_view.get_inner_mut().enable();
+                }
+                // Typing is then handled by the filter view, rather than by
+                // the global key bindings.
+                let _ignored = siv.focus_name("FilterView");
+            }
+
+            Ok(Message::UpdateFilter) => {
+                redraw(&mut siv, event_replayer, cursor)?;
+            }
+
+            Ok(Message::FinishFilter) => {
+                // Keep the filter applied, but hand control back to the
+                // global key bindings.
+                FilterView::find(&mut siv).get_inner_mut().disable();
+            }
+
+            Ok(Message::ClearFilter) => {
+                {
+                    let mut filter_view = FilterView::find(&mut siv);
+                    filter_view.hide();
+                    let filter_edit_view = filter_view.get_inner_mut();
+                    filter_edit_view.set_content("");
+                    filter_edit_view.disable();
+                }
+                redraw(&mut siv, event_replayer, cursor)?;
             }
 
             Ok(Message::Quit) => siv.quit(),
@@ -564,6 +968,10 @@ fn inverse_event(
             new_commit_oid: old_commit_oid,
         },
 
+        Event::MoveEvent { .. } => {
+            anyhow::bail!("BUG: a move event has no inverse: {:?}", event)
+        }
+
         Event::RefUpdateEvent {
             timestamp: _,
             event_tx_id: _,
@@ -609,9 +1017,11 @@ fn undo_events(
     glyphs: &Glyphs,
     repo: &git2::Repository,
     git_executable: &GitExecutable,
+    merge_base_db: &MergeBaseDb,
     event_log_db: &mut EventLogDb,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
+    assume_yes: bool,
 ) -> anyhow::Result<isize> {
     let now = SystemTime::now();
     let event_tx_id = event_log_db.make_transaction_id(now, "undo")?;
@@ -632,6 +1042,9 @@ fn undo_events(
                 } if ref_name == "HEAD"
             )
         })
+        // A move event only records where the moved commits came from. The
+        // move itself is undone by inverting its rewrite events.
+        .filter(|event| !matches!(event, Event::MoveEvent { .. }))
         .map(|event| inverse_event(event.clone(), now, event_tx_id))
         .collect::<anyhow::Result<Vec<Event>>>()?;
     let mut inverse_events = optimize_inverse_events(inverse_events);
@@ -656,24 +1069,83 @@ fn undo_events(
         writeln!(out, "{}", printable_styled_string(&glyphs, line)?)?;
     }
 
+    let current_cursor = event_replayer.make_default_cursor();
+    for (header, cursor) in &[
+        ("Smartlog before undo:", current_cursor),
+        ("Smartlog after undo:", event_cursor),
+    ] {
+        writeln!(out, "{}", header)?;
+        let lines = render_cursor_smartlog(
+            glyphs,
+            repo,
+            merge_base_db,
+            event_replayer,
+            *cursor,
+            "",
+            None,
+            None,
+        )?;
+        for line in lines {
+            writeln!(out, "{}", printable_styled_string(glyphs, line)?)?;
+        }
+    }
+
+    let smartlog_diff = diff_cursor_smartlogs(
+        repo,
+        merge_base_db,
+        event_replayer,
+        current_cursor,
+        event_cursor,
+    )?;
+    let diff_lines = describe_smartlog_diff(repo, &smartlog_diff)?;
+    if !diff_lines.is_empty() {
+        writeln!(out, "Changes:")?;
+        for line in diff_lines {
+            writeln!(out, "{}", printable_styled_string(glyphs, line)?)?;
+        }
+    }
+
     let confirmed = {
-        write!(out, "Confirm? [yN] ")?;
-        out.flush()?;
-        let mut user_input = String::new();
-        let mut reader = BufReader::new(in_);
-        match reader.read_line(&mut user_input) {
-            Ok(_size) => {
-                let user_input = user_input.trim();
-                user_input == "y" || user_input == "Y"
+        let prompt = format!(
+            "{} affected, {} moved. Confirm? [yN] ",
+            Pluralize {
+                amount: (smartlog_diff.appeared_commit_oids.len()
+                    + smartlog_diff.disappeared_commit_oids.len())
+                .try_into()?,
+                singular: "commit",
+                plural: "commits",
             }
-            Err(_) => false,
-        }
+            .to_string(),
+            Pluralize {
+                amount: smartlog_diff.moved_branches.len().try_into()?,
+                singular: "branch",
+                plural: "branches",
+            }
+            .to_string()
+        );
+        let mut reader = BufReader::new(in_);
+        prompt_confirmation(&mut reader, out, &prompt, assume_yes)?
     };
     if !confirmed {
         writeln!(out, "Aborted.")?;
         return Ok(1);
     }
 
+    // If the on-disk rebase which made a backup of `HEAD` and the index is
+    // being undone, then restore them from the earliest such backup once the
+    // references have been restored.
+    let backup_oid = inverse_events.iter().rev().find_map(|event| match event {
+        Event::RefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name,
+            old_ref: Some(old_ref),
+            new_ref: None,
+            message: _,
+        } if is_backup_ref(ref_name) => Some(old_ref.clone()),
+        _ => None,
+    });
+
     let num_inverse_events = Pluralize {
         amount: inverse_events.len().try_into().unwrap(),
         singular: "inverse event",
@@ -696,6 +1168,7 @@ fn undo_events(
                 // dirty working copy). The `Git` command will update the event
                 // log appropriately, as it will invoke our hooks.
                 run_git(
+                    &mut stdout(),
                     git_executable,
                     Some(event_tx_id),
                     &["checkout", "--detach", &new_ref],
@@ -756,28 +1229,53 @@ fn undo_events(
             Event::CommitEvent { .. }
             | Event::HideEvent { .. }
             | Event::UnhideEvent { .. }
-            | Event::RewriteEvent { .. } => {
+            | Event::RewriteEvent { .. }
+            | Event::MoveEvent { .. } => {
                 event_log_db.add_events(vec![event])?;
             }
         }
     }
 
+    if let Some(backup_oid) = backup_oid {
+        let backup_oid = backup_oid.parse()?;
+        restore_backup(&mut stdout(), git_executable, repo, event_tx_id, backup_oid)?;
+        writeln!(
+            out,
+            "Restored HEAD and the index from backup {}.",
+            backup_oid
+        )?;
+    }
+
     writeln!(out, "Applied {}.", num_inverse_events)?;
     Ok(0)
 }
 
 /// Restore the repository to a previous state interactively.
-pub fn undo(git_executable: &GitExecutable) -> anyhow::Result<isize> {
+///
+/// Args:
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `assume_yes`: Apply the selected undo without asking for confirmation.
+///
+/// Returns: exit code (0 denotes successful exit).
+pub fn undo(git_executable: &GitExecutable, assume_yes: bool) -> anyhow::Result<isize> {
     let glyphs = Glyphs::detect();
     let repo = get_repo()?;
     let conn = get_db_conn(&repo)?;
     let merge_base_db = MergeBaseDb::new(&conn)?;
     let mut event_log_db = EventLogDb::new(&conn)?;
     let mut event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+    let keymap = UndoKeymap::from_config(&repo)?;
 
     let event_cursor = {
-        let result = with_siv(|siv| {
-            select_past_event(siv, &glyphs, &repo, &merge_base_db, &mut event_replayer)
+        let result = with_siv(&repo, |siv| {
+            select_past_event(
+                siv,
+                &glyphs,
+                &repo,
+                &merge_base_db,
+                &mut event_replayer,
+                &keymap,
+            )
         })?;
         match result {
             Some(event_cursor) => event_cursor,
@@ -791,9 +1289,11 @@ pub fn undo(git_executable: &GitExecutable) -> anyhow::Result<isize> {
         &glyphs,
         &repo,
         &git_executable,
+        &merge_base_db,
         &mut event_log_db,
         &event_replayer,
         event_cursor,
+        assume_yes,
     )?;
     Ok(result)
 }
@@ -816,7 +1316,8 @@ pub mod testing {
         merge_base_db: &MergeBaseDb,
         event_replayer: &mut EventReplayer,
     ) -> anyhow::Result<Option<EventCursor>> {
-        super::select_past_event(siv, glyphs, repo, merge_base_db, event_replayer)
+        let keymap = super::UndoKeymap::from_config(repo)?;
+        super::select_past_event(siv, glyphs, repo, merge_base_db, event_replayer, &keymap)
     }
 
     pub fn undo_events(
@@ -825,9 +1326,11 @@ pub mod testing {
         glyphs: &Glyphs,
         repo: &git2::Repository,
         git_executable: &GitExecutable,
+        merge_base_db: &MergeBaseDb,
         event_log_db: &mut EventLogDb,
         event_replayer: &EventReplayer,
         event_cursor: EventCursor,
+        assume_yes: bool,
     ) -> anyhow::Result<isize> {
         super::undo_events(
             in_,
@@ -835,9 +1338,11 @@ pub mod testing {
             glyphs,
             repo,
             git_executable,
+            merge_base_db,
             event_log_db,
             event_replayer,
             event_cursor,
+            assume_yes,
         )
     }
 }
@@ -1,10 +1,16 @@
 //! Core algorithms and data structures.
 
+pub mod commitgraph;
 pub mod config;
+pub mod diffstat;
+pub mod error;
 pub mod eventlog;
 pub mod formatting;
 pub mod graph;
 pub mod mergebase;
 pub mod metadata;
+pub mod patchid;
 pub mod rewrite;
+pub mod sign;
+pub mod timing;
 pub mod tui;
@@ -0,0 +1,247 @@
+//! Read generation numbers from Git's `commit-graph` file.
+//!
+//! Git can maintain a `commit-graph` file (see `git commit-graph write`, or
+//! the `fetch.writeCommitGraph` and `gc.writeCommitGraph` options), which
+//! records a "generation number" for each commit: one more than the maximum
+//! generation number of its parents, with root commits having generation 1.
+//! A commit can therefore only be an ancestor of another commit if its
+//! generation number is strictly smaller, which lets traversals of the commit
+//! history skip over large parts of it without loading any commits.
+//!
+//! libgit2 doesn't expose the commit-graph, so the file is parsed here. Only a
+//! single `objects/info/commit-graph` file is supported. If the repository uses
+//! split commit-graph chains instead, or the file can't be parsed, then no
+//! generation numbers are available and traversals fall back to visiting every
+//! commit, as before.
+
+use std::convert::TryInto;
+
+use anyhow::Context;
+use fn_error_context::context;
+use log::warn;
+
+use crate::core::config::get_core_commit_graph;
+use crate::util::get_repo_common_dir;
+
+const SIGNATURE: &[u8] = b"CGPH";
+const HEADER_LEN: usize = 8;
+const CHUNK_LOOKUP_ENTRY_LEN: usize = 12;
+const CHUNK_ID_OID_FANOUT: &[u8] = b"OIDF";
+const CHUNK_ID_OID_LOOKUP: &[u8] = b"OIDL";
+const CHUNK_ID_COMMIT_DATA: &[u8] = b"CDAT";
+const OID_LEN: usize = 20;
+
+/// The length of each commit's entry in the commit data chunk: the tree OID,
+/// the two parent positions, and the generation number and commit time.
+const COMMIT_DATA_ENTRY_LEN: usize = OID_LEN + 16;
+
+/// The generation numbers of the commits in a repository's `commit-graph` file.
+///
+/// The file may be out of date, so commits which were created since it was
+/// written have no generation number.
+pub struct CommitGenerations {
+    contents: Vec<u8>,
+    num_commits: usize,
+    oid_fanout_offset: usize,
+    oid_lookup_offset: usize,
+    commit_data_offset: usize,
+}
+
+impl CommitGenerations {
+    /// Parse the contents of a `commit-graph` file.
+    ///
+    /// Returns: The parsed generation numbers, or `None` if the file uses a
+    /// format which isn't supported, such as a hash algorithm other than SHA-1
+    /// or a split commit-graph.
+    #[context("Parsing commit-graph file")]
+    pub fn from_bytes(contents: Vec<u8>) -> anyhow::Result<Option<Self>> {
+        if contents.len() < HEADER_LEN || &contents[0..4] != SIGNATURE {
+            anyhow::bail!("Invalid commit-graph signature");
+        }
+        let (version, hash_version, num_chunks, num_base_graphs) =
+            (contents[4], contents[5], contents[6], contents[7]);
+        if version != 1 || hash_version != 1 || num_base_graphs != 0 {
+            return Ok(None);
+        }
+
+        let mut oid_fanout_offset = None;
+        let mut oid_lookup_offset = None;
+        let mut commit_data_offset = None;
+        for i in 0..usize::from(num_chunks) {
+            let entry_offset = HEADER_LEN + i * CHUNK_LOOKUP_ENTRY_LEN;
+            let entry = contents
+                .get(entry_offset..entry_offset + CHUNK_LOOKUP_ENTRY_LEN)
+                .context("Truncated chunk lookup table")?;
+            let chunk_offset: usize = u64::from_be_bytes(entry[4..12].try_into()?).try_into()?;
+            match &entry[0..4] {
+                CHUNK_ID_OID_FANOUT => oid_fanout_offset = Some(chunk_offset),
+                CHUNK_ID_OID_LOOKUP => oid_lookup_offset = Some(chunk_offset),
+                CHUNK_ID_COMMIT_DATA => commit_data_offset = Some(chunk_offset),
+                _ => {}
+            }
+        }
+        let (oid_fanout_offset, oid_lookup_offset, commit_data_offset) =
+            match (oid_fanout_offset, oid_lookup_offset, commit_data_offset) {
+                (Some(fanout), Some(lookup), Some(data)) => (fanout, lookup, data),
+                _ => anyhow::bail!("Missing required commit-graph chunk"),
+            };
+
+        let mut result = CommitGenerations {
+            contents,
+            num_commits: 0,
+            oid_fanout_offset,
+            oid_lookup_offset,
+            commit_data_offset,
+        };
+        result.num_commits = result.read_fanout(255)?;
+        let is_in_bounds = |offset: usize, entry_len: usize| {
+            offset + result.num_commits * entry_len <= result.contents.len()
+        };
+        if !is_in_bounds(oid_lookup_offset, OID_LEN)
+            || !is_in_bounds(commit_data_offset, COMMIT_DATA_ENTRY_LEN)
+        {
+            anyhow::bail!("Truncated commit-graph chunk");
+        }
+        Ok(Some(result))
+    }
+
+    /// Read the number of commits whose OIDs start with a byte less than or
+    /// equal to `byte`.
+    fn read_fanout(&self, byte: u8) -> anyhow::Result<usize> {
+        let offset = self.oid_fanout_offset + 4 * usize::from(byte);
+        let value = self
+            .contents
+            .get(offset..offset + 4)
+            .context("Truncated OID fanout chunk")?;
+        Ok(u32::from_be_bytes(value.try_into()?).try_into()?)
+    }
+
+    fn get_position(&self, oid: git2::Oid) -> Option<usize> {
+        let oid = oid.as_bytes();
+        let mut lo = match oid[0] {
+            0 => 0,
+            byte => self.read_fanout(byte - 1).ok()?,
+        };
+        let mut hi = self.read_fanout(oid[0]).ok()?.min(self.num_commits);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let offset = self.oid_lookup_offset + mid * OID_LEN;
+            match self.contents[offset..offset + OID_LEN].cmp(oid) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// Get the generation number of the given commit.
+    ///
+    /// Returns: The generation number, or `None` if the commit isn't in the
+    /// commit-graph, or its generation number wasn't computed when the file
+    /// was written.
+    pub fn get_generation(&self, oid: git2::Oid) -> Option<u32> {
+        let position = self.get_position(oid)?;
+        let offset = self.commit_data_offset + position * COMMIT_DATA_ENTRY_LEN + OID_LEN + 8;
+        let value = u32::from_be_bytes(self.contents[offset..offset + 4].try_into().ok()?);
+        // The lower two bits are the high bits of the commit time.
+        match value >> 2 {
+            0 => None,
+            generation => Some(generation),
+        }
+    }
+
+    /// Determine whether `ancestor_oid` can't possibly be an ancestor of
+    /// `descendant_oid`, according to their generation numbers. A commit is
+    /// not considered to be its own ancestor here.
+    ///
+    /// Returns: `true` if `ancestor_oid` is definitely not an ancestor of
+    /// `descendant_oid`, or `false` if it may be, including if either commit
+    /// has no generation number.
+    pub fn cannot_be_ancestor(&self, ancestor_oid: git2::Oid, descendant_oid: git2::Oid) -> bool {
+        match (
+            self.get_generation(ancestor_oid),
+            self.get_generation(descendant_oid),
+        ) {
+            (Some(ancestor_generation), Some(descendant_generation)) => {
+                ancestor_generation >= descendant_generation
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Load the generation numbers from the repository's `commit-graph` file.
+///
+/// Returns: The generation numbers, or `None` if there's no usable
+/// commit-graph file, or it's been disabled with `branchless.core.commitGraph`.
+/// Generation numbers aren't used in shallow clones, since the commit-graph
+/// doesn't account for the missing history.
+#[context("Loading commit-graph")]
+pub fn get_commit_generations(
+    repo: &git2::Repository,
+) -> anyhow::Result<Option<CommitGenerations>> {
+    if !get_core_commit_graph(repo)? || repo.is_shallow() {
+        return Ok(None);
+    }
+
+    let path = get_repo_common_dir(repo)?
+        .join("objects")
+        .join("info")
+        .join("commit-graph");
+    let contents = match std::fs::read(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Reading commit-graph file: {:?}", path))
+        }
+    };
+    match CommitGenerations::from_bytes(contents) {
+        Ok(commit_generations) => Ok(commit_generations),
+        Err(err) => {
+            warn!("Ignoring commit-graph file {:?}: {:#}", path, err);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::with_git;
+
+    #[test]
+    fn test_get_commit_generations() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            let test1_oid = git.commit_file("test1", 1)?;
+            git.run(&["checkout", "HEAD^"])?;
+            let test2_oid = git.commit_file("test2", 2)?;
+            let test3_oid = git.commit_file("test3", 3)?;
+
+            let repo = git.get_repo()?;
+            assert!(get_commit_generations(&repo)?.is_none());
+
+            git.run(&["commit-graph", "write", "--reachable"])?;
+            let test4_oid = git.commit_file("test4", 4)?;
+            let initial_oid = repo.find_commit(test1_oid)?.parent_id(0)?;
+            let commit_generations = get_commit_generations(&repo)?.unwrap();
+            assert_eq!(commit_generations.get_generation(initial_oid), Some(1));
+            assert_eq!(commit_generations.get_generation(test1_oid), Some(2));
+            assert_eq!(commit_generations.get_generation(test2_oid), Some(2));
+            assert_eq!(commit_generations.get_generation(test3_oid), Some(3));
+            assert_eq!(commit_generations.get_generation(test4_oid), None);
+
+            assert!(commit_generations.cannot_be_ancestor(test3_oid, test1_oid));
+            assert!(commit_generations.cannot_be_ancestor(test2_oid, test1_oid));
+            assert!(!commit_generations.cannot_be_ancestor(initial_oid, test1_oid));
+            assert!(!commit_generations.cannot_be_ancestor(test1_oid, test4_oid));
+
+            git.run(&["config", "branchless.core.commitGraph", "false"])?;
+            assert!(get_commit_generations(&repo)?.is_none());
+
+            Ok(())
+        })
+    }
+}
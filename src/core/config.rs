@@ -1,9 +1,13 @@
 //! Accesses repo-specific configuration.
 
+use std::convert::TryFrom;
 use std::path::PathBuf;
 
 use fn_error_context::context;
 
+use crate::core::formatting::ColorSetting;
+use crate::util::get_repo_common_dir;
+
 #[context("Getting repo configuration")]
 fn get_config(repo: &git2::Repository) -> anyhow::Result<git2::Config> {
     let result = repo.config()?;
@@ -11,22 +15,93 @@ fn get_config(repo: &git2::Repository) -> anyhow::Result<git2::Config> {
 }
 
 /// Get the path where Git hooks are stored on disk.
+///
+/// If `core.hooksPath` is a relative path, it's resolved relative to the
+/// working copy, which is where Git runs hooks from (or relative to the `.git`
+/// directory for bare repositories). Otherwise, the hooks are shared between
+/// all of the repository's worktrees (see `get_repo_common_dir`).
 pub fn get_core_hooks_path(repo: &git2::Repository) -> anyhow::Result<PathBuf> {
+    let result = match get_config(repo)?.get_path("core.hooksPath") {
+        Ok(hooks_path) if hooks_path.is_relative() => repo
+            .workdir()
+            .unwrap_or_else(|| repo.path())
+            .join(hooks_path),
+        Ok(hooks_path) => hooks_path,
+        Err(_) => get_repo_common_dir(repo)?.join("hooks"),
+    };
+    Ok(result)
+}
+
+/// Get the pager configured with `core.pager`, if any.
+pub fn get_core_pager(repo: &git2::Repository) -> anyhow::Result<Option<String>> {
+    let result = get_config(repo)?.get_string("core.pager").ok();
+    Ok(result)
+}
+
+/// Config key for `get_core_color`.
+pub const CORE_COLOR_CONFIG_KEY: &str = "branchless.core.color";
+
+/// Get whether to write colors to the terminal, if the user has configured
+/// it. This is overridden by the `--color` option.
+pub fn get_core_color(repo: &git2::Repository) -> anyhow::Result<Option<ColorSetting>> {
     let result = get_config(repo)?
-        .get_path("core.hooksPath")
-        .unwrap_or_else(|_err| repo.path().join("hooks"));
+        .get_string(CORE_COLOR_CONFIG_KEY)
+        .ok()
+        .and_then(|color| color.parse().ok());
     Ok(result)
 }
 
-/// Get the name of the main branch for the repository.
+/// Config section for `get_tui_keymap`.
+pub const TUI_KEYMAP_CONFIG_SECTION: &str = "branchless.tui.keymap";
+
+/// Get the keys bound to the given action in interactive interfaces, as a
+/// whitespace-separated list, if the user has configured them.
+pub fn get_tui_keymap(repo: &git2::Repository, action: &str) -> anyhow::Result<Option<String>> {
+    let result = get_config(repo)?
+        .get_string(&format!("{}.{}", TUI_KEYMAP_CONFIG_SECTION, action))
+        .ok();
+    Ok(result)
+}
+
+/// Config section for `get_tui_theme_preset` and `get_tui_theme_color`.
+pub const TUI_THEME_CONFIG_SECTION: &str = "branchless.tui.theme";
+
+/// Get the name of the built-in color theme to use in interactive interfaces,
+/// if the user has configured one.
+pub fn get_tui_theme_preset(repo: &git2::Repository) -> anyhow::Result<Option<String>> {
+    let result = get_config(repo)?
+        .get_string(&format!("{}.preset", TUI_THEME_CONFIG_SECTION))
+        .ok();
+    Ok(result)
+}
+
+/// Get the color to use for the given element of interactive interfaces, if
+/// the user has configured one.
+pub fn get_tui_theme_color(
+    repo: &git2::Repository,
+    element: &str,
+) -> anyhow::Result<Option<String>> {
+    let result = get_config(repo)?
+        .get_string(&format!("{}.{}", TUI_THEME_CONFIG_SECTION, element))
+        .ok();
+    Ok(result)
+}
+
+/// Get the names of the main branches for the repository.
+///
+/// Usually there's only one main branch, but `branchless.core.mainBranch` may
+/// be set to a comma-separated list of branches, such as `master,release/1.x`,
+/// in which case all of them are treated as immutable lines of development.
 ///
 /// Args:
 /// * `repo`: The Git repository.
 ///
-/// Returns: The name of the main branch for the repository.
-pub fn get_main_branch_name(repo: &git2::Repository) -> anyhow::Result<String> {
+/// Returns: The names of the main branches for the repository. The first name
+/// is the primary main branch (see `get_main_branch_name`). There is always at
+/// least one name.
+pub fn get_main_branch_names(repo: &git2::Repository) -> anyhow::Result<Vec<String>> {
     let config = get_config(repo)?;
-    let main_branch_name =
+    let main_branch_names =
         config
             .get_string("branchless.core.mainBranch")
             .or_else(|_| -> anyhow::Result<String> {
@@ -35,7 +110,128 @@ pub fn get_main_branch_name(repo: &git2::Repository) -> anyhow::Result<String> {
                     .get_string("branchless.mainBranch")
                     .or_else(|_| Ok(String::from("master")))
             })?;
-    Ok(main_branch_name)
+    let mut main_branch_names: Vec<String> = main_branch_names
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect();
+    if main_branch_names.is_empty() {
+        main_branch_names.push(String::from("master"));
+    }
+    Ok(main_branch_names)
+}
+
+/// Get the name of the main branch for the repository.
+///
+/// If multiple main branches are configured, this is the first of them. It's
+/// used whenever a single main branch is needed, such as to decide where to
+/// restack commits onto.
+///
+/// Args:
+/// * `repo`: The Git repository.
+///
+/// Returns: The name of the main branch for the repository.
+pub fn get_main_branch_name(repo: &git2::Repository) -> anyhow::Result<String> {
+    let mut main_branch_names = get_main_branch_names(repo)?;
+    Ok(main_branch_names.remove(0))
+}
+
+/// Config key for `get_core_unhideable_refs`.
+pub const CORE_UNHIDEABLE_REFS_CONFIG_KEY: &str = "branchless.core.unhideableRefs";
+
+/// Get the glob patterns for references whose commits should never be hidden
+/// in the smartlog, such as `refs/pinned/*`. The config key may be given
+/// multiple times, and each value may contain multiple whitespace-separated
+/// patterns.
+pub fn get_core_unhideable_refs(repo: &git2::Repository) -> anyhow::Result<Vec<String>> {
+    let config = get_config(repo)?;
+    let entries = match config.multivar(CORE_UNHIDEABLE_REFS_CONFIG_KEY, None) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut result = Vec::new();
+    for entry in &entries {
+        let entry = entry?;
+        if let Some(value) = entry.value() {
+            result.extend(value.split_whitespace().map(String::from));
+        }
+    }
+    Ok(result)
+}
+
+/// Config key for `get_init_aliases`.
+pub const INIT_ALIASES_CONFIG_KEY: &str = "branchless.init.aliases";
+
+/// Get the customizations to the aliases installed by `git branchless init`,
+/// each of the form `name=command`, such as `bsl=smartlog`. An empty command,
+/// as in `sl=`, means that the alias shouldn't be installed. The config key
+/// may be given multiple times.
+pub fn get_init_aliases(repo: &git2::Repository) -> anyhow::Result<Vec<String>> {
+    let config = get_config(repo)?;
+    let entries = match config.multivar(INIT_ALIASES_CONFIG_KEY, None) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut result = Vec::new();
+    for entry in &entries {
+        let entry = entry?;
+        if let Some(value) = entry.value() {
+            result.push(value.to_string());
+        }
+    }
+    Ok(result)
+}
+
+/// The minimum number of hexadecimal digits to abbreviate OIDs to when
+/// displaying them, as set by `core.abbrev`. Defaults to 8. Values which
+/// aren't numbers (such as `auto`) also use the default, and numbers are
+/// clamped to between 4 and 40, as in Git.
+///
+/// OIDs are abbreviated to more digits than this if necessary to make them
+/// unambiguous (see `abbreviate_oid`).
+pub fn get_core_abbrev(repo: &git2::Repository) -> anyhow::Result<usize> {
+    let abbrev = get_config(repo)?
+        .get_i64("core.abbrev")
+        .ok()
+        .and_then(|abbrev| usize::try_from(abbrev).ok())
+        .map(|abbrev| abbrev.clamp(4, 40))
+        .unwrap_or(8);
+    Ok(abbrev)
+}
+
+/// Config key for `get_core_commit_graph`.
+pub const CORE_COMMIT_GRAPH_CONFIG_KEY: &str = "branchless.core.commitGraph";
+
+/// Whether to use the generation numbers in Git's `commit-graph` file, if
+/// present, to speed up traversals of the commit history (see
+/// `get_commit_generations`). Defaults to `true`; it can be disabled for
+/// debugging.
+pub fn get_core_commit_graph(repo: &git2::Repository) -> anyhow::Result<bool> {
+    let commit_graph = get_config(repo)?
+        .get_bool(CORE_COMMIT_GRAPH_CONFIG_KEY)
+        .unwrap_or(true);
+    Ok(commit_graph)
+}
+
+/// Config key for `get_core_max_path_commits`.
+pub const CORE_MAX_PATH_COMMITS_CONFIG_KEY: &str = "branchless.core.maxPathCommits";
+
+/// The maximum number of commits to traverse when finding a path between two
+/// commits, such as from a commit to its merge-base with the main branch (see
+/// `find_path_to_merge_base`). If the limit is exceeded, an error is returned,
+/// rather than traversing an arbitrarily large amount of history. Defaults to
+/// 100,000.
+pub fn get_core_max_path_commits(repo: &git2::Repository) -> anyhow::Result<usize> {
+    let max_commits = get_config(repo)?
+        .get_i64(CORE_MAX_PATH_COMMITS_CONFIG_KEY)
+        .ok()
+        .and_then(|max_commits| usize::try_from(max_commits).ok())
+        .filter(|max_commits| *max_commits > 0)
+        .unwrap_or(100_000);
+    Ok(max_commits)
 }
 
 /// If `true`, when restacking a commit, do not update its timestamp to the
@@ -46,6 +242,69 @@ pub fn get_restack_preserve_timestamps(repo: &git2::Repository) -> anyhow::Resul
         .or(Ok(false))
 }
 
+/// Config key for `get_rewrite_preserve_dates`.
+pub const REWRITE_PRESERVE_DATES_CONFIG_KEY: &str = "branchless.rewrite.preserveDates";
+
+/// If `true` (the default), commits rewritten by executing a rebase plan, such
+/// as with `git move`, keep their original committer dates, rather than having
+/// them set to the current time. Their author dates are always kept.
+pub fn get_rewrite_preserve_dates(repo: &git2::Repository) -> anyhow::Result<bool> {
+    get_config(repo)?
+        .get_bool(REWRITE_PRESERVE_DATES_CONFIG_KEY)
+        .or(Ok(true))
+}
+
+/// What to do with commits which become empty when they're rewritten, such as
+/// because their changes are already present upstream. See
+/// `get_rewrite_empty_commits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyCommitPolicy {
+    /// Keep the commit, even though it no longer makes any changes.
+    Keep,
+
+    /// Don't apply the commit, and record it as having been rewritten into the
+    /// commit it would have been applied onto.
+    Drop,
+}
+
+/// Config key for `get_rewrite_empty_commits`.
+pub const REWRITE_EMPTY_COMMITS_CONFIG_KEY: &str = "branchless.rewrite.emptyCommits";
+
+/// How to handle commits which become empty when rewritten by executing a
+/// rebase plan, such as with `git move`: either `keep` or `drop` them. The
+/// default is `drop`, which matches `git rebase`. Commits which were already
+/// empty before being rewritten are always kept.
+pub fn get_rewrite_empty_commits(repo: &git2::Repository) -> anyhow::Result<EmptyCommitPolicy> {
+    match get_config(repo)?.get_string(REWRITE_EMPTY_COMMITS_CONFIG_KEY) {
+        Err(_) => Ok(EmptyCommitPolicy::Drop),
+        Ok(value) => match value.to_lowercase().as_str() {
+            "keep" => Ok(EmptyCommitPolicy::Keep),
+            "drop" => Ok(EmptyCommitPolicy::Drop),
+            _ => anyhow::bail!(
+                "Invalid value for {}: {:?} (expected `keep` or `drop`)",
+                REWRITE_EMPTY_COMMITS_CONFIG_KEY,
+                value
+            ),
+        },
+    }
+}
+
+/// Config key for `get_rewrite_sign`.
+pub const REWRITE_SIGN_CONFIG_KEY: &str = "branchless.rewrite.sign";
+
+/// If `true`, commits rewritten by executing a rebase plan or by restacking are
+/// signed with the user's configured signing program (see
+/// `crate::core::sign::CommitSigner`). Defaults to the value of
+/// `commit.gpgSign`, so that rewritten commits are signed if newly-made ones
+/// would be.
+pub fn get_rewrite_sign(repo: &git2::Repository) -> anyhow::Result<bool> {
+    let config = get_config(repo)?;
+    match config.get_bool(REWRITE_SIGN_CONFIG_KEY) {
+        Ok(sign) => Ok(sign),
+        Err(_) => Ok(config.get_bool("commit.gpgSign").unwrap_or(false)),
+    }
+}
+
 /// Config key for `get_restack_warn_abandoned`.
 pub const RESTACK_WARN_ABANDONED_CONFIG_KEY: &str = "branchless.restack.warnAbandoned";
 
@@ -57,6 +316,202 @@ pub fn get_restack_warn_abandoned(repo: &git2::Repository) -> anyhow::Result<boo
         .or(Ok(true))
 }
 
+/// The width to wrap commit message bodies to in `git smartlog --verbose`. If
+/// not set, the width of the terminal is used instead.
+pub fn get_smartlog_body_wrap_width(repo: &git2::Repository) -> anyhow::Result<Option<usize>> {
+    let width = get_config(repo)?
+        .get_i64("branchless.smartlog.bodyWrapWidth")
+        .ok()
+        .and_then(|width| usize::try_from(width).ok())
+        .filter(|width| *width > 0);
+    Ok(width)
+}
+
+/// Config key for `get_smartlog_head_glyph`.
+pub const SMARTLOG_HEAD_GLYPH_CONFIG_KEY: &str = "branchless.smartlog.headGlyph";
+
+/// The glyph to use in the smartlog for the visible commit which is currently
+/// checked out, instead of the default glyph (such as `@`).
+pub fn get_smartlog_head_glyph(repo: &git2::Repository) -> anyhow::Result<Option<String>> {
+    let head_glyph = get_config(repo)?
+        .get_string(SMARTLOG_HEAD_GLYPH_CONFIG_KEY)
+        .ok()
+        .filter(|head_glyph| !head_glyph.is_empty());
+    Ok(head_glyph)
+}
+
+/// Config key for `get_smartlog_cache`.
+pub const SMARTLOG_CACHE_CONFIG_KEY: &str = "branchless.smartlog.cache";
+
+/// If `true`, cache the rendered smartlog on disk, and reuse it if nothing
+/// which affects it has changed since it was rendered, such as when calling
+/// `git smartlog` from a shell prompt.
+pub fn get_smartlog_cache(repo: &git2::Repository) -> anyhow::Result<bool> {
+    get_config(repo)?
+        .get_bool(SMARTLOG_CACHE_CONFIG_KEY)
+        .or(Ok(false))
+}
+
+/// Config key for `get_smartlog_legend_hint`.
+pub const SMARTLOG_LEGEND_HINT_CONFIG_KEY: &str = "branchless.hint.smartlogLegend";
+
+/// If `true`, show the smartlog legend the next time that the smartlog is
+/// rendered, to explain its glyphs to new users. This is set by `git branchless
+/// init`, and unset once the legend has been shown.
+pub fn get_smartlog_legend_hint(repo: &git2::Repository) -> anyhow::Result<bool> {
+    get_config(repo)?
+        .get_bool(SMARTLOG_LEGEND_HINT_CONFIG_KEY)
+        .or(Ok(false))
+}
+
+/// Set whether the smartlog legend should be shown the next time that the
+/// smartlog is rendered. See `get_smartlog_legend_hint`.
+#[context("Setting smartlog legend hint to {:?}", value)]
+pub fn set_smartlog_legend_hint(repo: &git2::Repository, value: bool) -> anyhow::Result<()> {
+    get_config(repo)?.set_bool(SMARTLOG_LEGEND_HINT_CONFIG_KEY, value)?;
+    Ok(())
+}
+
+/// Config key for `get_smartlog_summary_column`.
+pub const SMARTLOG_SUMMARY_COLUMN_CONFIG_KEY: &str = "branchless.smartlog.summaryColumn";
+
+/// The column at which to start each commit's summary in the smartlog, so that
+/// the summaries line up regardless of the branches and other annotations
+/// before them. If not set, each summary directly follows its annotations.
+pub fn get_smartlog_summary_column(repo: &git2::Repository) -> anyhow::Result<Option<usize>> {
+    let column = get_config(repo)?
+        .get_i64(SMARTLOG_SUMMARY_COLUMN_CONFIG_KEY)
+        .ok()
+        .and_then(|column| usize::try_from(column).ok())
+        .filter(|column| *column > 0);
+    Ok(column)
+}
+
+/// Config key for `get_smartlog_context_commits`.
+pub const SMARTLOG_CONTEXT_COMMITS_CONFIG_KEY: &str = "branchless.smartlog.contextCommits";
+
+/// The number of main branch commits to show before and after each main branch
+/// commit in the smartlog, for orientation. Main branch commits beyond these
+/// are elided with a `:` line. Defaults to 0, so that only the main branch
+/// commits which are needed to connect the user's commits are shown.
+pub fn get_smartlog_context_commits(repo: &git2::Repository) -> anyhow::Result<usize> {
+    let context_commits = get_config(repo)?
+        .get_i64(SMARTLOG_CONTEXT_COMMITS_CONFIG_KEY)
+        .ok()
+        .and_then(|context_commits| usize::try_from(context_commits).ok())
+        .unwrap_or(0);
+    Ok(context_commits)
+}
+
+/// Config key for `get_smartlog_show_stashes`.
+pub const SMARTLOG_SHOW_STASHES_CONFIG_KEY: &str = "branchless.smartlog.showStashes";
+
+/// If `true`, show stash entries in the smartlog, as if `--stashes` were
+/// passed. Defaults to `false`.
+pub fn get_smartlog_show_stashes(repo: &git2::Repository) -> anyhow::Result<bool> {
+    let show_stashes = get_config(repo)?
+        .get_bool(SMARTLOG_SHOW_STASHES_CONFIG_KEY)
+        .unwrap_or(false);
+    Ok(show_stashes)
+}
+
+/// Config key for `get_smartlog_wip_prefixes`.
+pub const SMARTLOG_WIP_PREFIXES_CONFIG_KEY: &str = "branchless.smartlog.wipPrefixes";
+
+/// The default value for `get_smartlog_wip_prefixes`.
+pub const DEFAULT_SMARTLOG_WIP_PREFIXES: &[&str] = &["fixup!", "squash!", "WIP"];
+
+/// Get the prefixes of commit summaries which mark a commit as a work in
+/// progress, such as `fixup!` commits made with `git commit --fixup`. These
+/// commits are highlighted in the smartlog, as a reminder to clean them up
+/// before pushing. The config key may be given multiple times, and each value
+/// may contain multiple whitespace-separated prefixes. Defaults to
+/// `DEFAULT_SMARTLOG_WIP_PREFIXES`. Set it to an empty string to not highlight
+/// any commits.
+pub fn get_smartlog_wip_prefixes(repo: &git2::Repository) -> anyhow::Result<Vec<String>> {
+    let config = get_config(repo)?;
+    let entries = match config.multivar(SMARTLOG_WIP_PREFIXES_CONFIG_KEY, None) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(default_wip_prefixes()),
+    };
+
+    let mut result = None;
+    for entry in &entries {
+        let entry = entry?;
+        if let Some(value) = entry.value() {
+            result
+                .get_or_insert_with(Vec::new)
+                .extend(value.split_whitespace().map(String::from));
+        }
+    }
+    Ok(result.unwrap_or_else(default_wip_prefixes))
+}
+
+fn default_wip_prefixes() -> Vec<String> {
+    DEFAULT_SMARTLOG_WIP_PREFIXES
+        .iter()
+        .map(|prefix| prefix.to_string())
+        .collect()
+}
+
+/// Config key for `get_smartlog_page_size`.
+pub const SMARTLOG_PAGE_SIZE_CONFIG_KEY: &str = "branchless.smartlog.pageSize";
+
+/// The default value for `get_smartlog_page_size`.
+pub const DEFAULT_SMARTLOG_PAGE_SIZE: usize = 5;
+
+/// The number of stacks to show on each page of the smartlog, when using
+/// `git smartlog --page`.
+pub fn get_smartlog_page_size(repo: &git2::Repository) -> anyhow::Result<usize> {
+    let page_size = get_config(repo)?
+        .get_i64(SMARTLOG_PAGE_SIZE_CONFIG_KEY)
+        .ok()
+        .and_then(|page_size| usize::try_from(page_size).ok())
+        .filter(|page_size| *page_size > 0)
+        .unwrap_or(DEFAULT_SMARTLOG_PAGE_SIZE);
+    Ok(page_size)
+}
+
+/// Config key for `get_smartlog_status_notes_ref`.
+pub const SMARTLOG_STATUS_NOTES_REF_CONFIG_KEY: &str = "branchless.smartlog.statusNotesRef";
+
+/// The notes reference, such as `refs/notes/ci`, which holds the test or CI
+/// status of commits, to be displayed in the smartlog. `git branchless test`
+/// also records its results there. If not set, no statuses are displayed or
+/// recorded.
+pub fn get_smartlog_status_notes_ref(repo: &git2::Repository) -> anyhow::Result<Option<String>> {
+    let result = get_config(repo)?
+        .get_string(SMARTLOG_STATUS_NOTES_REF_CONFIG_KEY)
+        .ok()
+        .filter(|notes_ref| !notes_ref.is_empty());
+    Ok(result)
+}
+
+/// Config key for `get_hooks_event_sink`.
+pub const HOOKS_EVENT_SINK_CONFIG_KEY: &str = "branchless.hooks.eventSink";
+
+/// The path of a file to which the hooks append each event that they record,
+/// as one JSON object per line (see `Event::to_json`), for consumption by
+/// external tools. If not set, events are only recorded in the event log.
+pub fn get_hooks_event_sink(repo: &git2::Repository) -> anyhow::Result<Option<PathBuf>> {
+    let result = get_config(repo)?.get_path(HOOKS_EVENT_SINK_CONFIG_KEY).ok();
+    Ok(result)
+}
+
+/// Config key for `get_gc_event_log_horizon_days`.
+pub const GC_EVENT_LOG_HORIZON_DAYS_CONFIG_KEY: &str = "branchless.gc.eventLogHorizonDays";
+
+/// The number of days of history to keep in the event log when running `git
+/// branchless gc`. Older events are compacted (see
+/// `eventlog::compact_events`). If not set, the event log is never compacted.
+pub fn get_gc_event_log_horizon_days(repo: &git2::Repository) -> anyhow::Result<Option<u64>> {
+    let days = get_config(repo)?
+        .get_i64(GC_EVENT_LOG_HORIZON_DAYS_CONFIG_KEY)
+        .ok()
+        .and_then(|days| u64::try_from(days).ok());
+    Ok(days)
+}
+
 /// If `true`, show branches pointing to each commit in the smartlog.
 pub fn get_commit_metadata_branches(repo: &git2::Repository) -> anyhow::Result<bool> {
     get_config(repo)?
@@ -64,6 +519,35 @@ pub fn get_commit_metadata_branches(repo: &git2::Repository) -> anyhow::Result<b
         .or(Ok(true))
 }
 
+/// The maximum number of branch names to show for each commit in the
+/// smartlog. Any further branches are summarized as `+N more`. If not set (or
+/// set to 0), all branch names are shown.
+pub fn get_commit_metadata_max_branches(repo: &git2::Repository) -> anyhow::Result<Option<usize>> {
+    let max_branches = get_config(repo)?
+        .get_i64("branchless.commitMetadata.maxBranches")
+        .ok()
+        .and_then(|max_branches| usize::try_from(max_branches).ok())
+        .filter(|max_branches| *max_branches > 0);
+    Ok(max_branches)
+}
+
+/// If `true`, when a rewritten commit is shown in the smartlog and it has
+/// descendants which need to be restacked, suggest running `git restack`.
+/// Descendants on the main branch don't count, since `git restack` doesn't
+/// move them. Defaults to `false`.
+pub fn get_hidden_explanation_restack_hint(repo: &git2::Repository) -> anyhow::Result<bool> {
+    get_config(repo)?
+        .get_bool("branchless.commitMetadata.restackHint")
+        .or(Ok(false))
+}
+
+/// If `true`, show tags pointing to each commit in the smartlog.
+pub fn get_commit_metadata_tags(repo: &git2::Repository) -> anyhow::Result<bool> {
+    get_config(repo)?
+        .get_bool("branchless.commitMetadata.tags")
+        .or(Ok(true))
+}
+
 /// If `true`, show associated Phabricator commits in the smartlog.
 pub fn get_commit_metadata_differential_revision(repo: &git2::Repository) -> anyhow::Result<bool> {
     get_config(repo)?
@@ -71,6 +555,22 @@ pub fn get_commit_metadata_differential_revision(repo: &git2::Repository) -> any
         .or(Ok(true))
 }
 
+/// If `true`, mark the current commit in the smartlog if the working copy has
+/// uncommitted changes.
+pub fn get_commit_metadata_working_copy_status(repo: &git2::Repository) -> anyhow::Result<bool> {
+    get_config(repo)?
+        .get_bool("branchless.commitMetadata.workingCopyStatus")
+        .or(Ok(true))
+}
+
+/// If `true`, show how far each branch is ahead of and behind the main branch
+/// in the smartlog. Defaults to `false`.
+pub fn get_commit_metadata_ahead_behind(repo: &git2::Repository) -> anyhow::Result<bool> {
+    get_config(repo)?
+        .get_bool("branchless.commitMetadata.aheadBehind")
+        .or(Ok(false))
+}
+
 /// If `true`, show the age of each commit in the smartlog.
 pub fn get_commit_metadata_relative_time(repo: &git2::Repository) -> anyhow::Result<bool> {
     get_config(repo)?
@@ -0,0 +1,231 @@
+//! Persistent storage to cache diffstats.
+//!
+//! A diffstat summarizes how many files and lines a commit changed relative to
+//! its parent. Computing one requires diffing the two trees, which can be
+//! expensive for large commits, so they're computed in parallel and cached by
+//! the OIDs of the trees being compared.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use anyhow::Context;
+use fn_error_context::context;
+use rayon::prelude::*;
+use rusqlite::OptionalExtension;
+
+use crate::core::timing::start_timer;
+
+/// The OID of the empty tree, which is used as the "parent tree" of root
+/// commits, such as when invoking `git difftool`.
+pub const EMPTY_TREE_OID: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// A summary of the changes between two trees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiffStat {
+    /// The number of files which were added, deleted, or modified.
+    pub files_changed: usize,
+
+    /// The number of lines which were added.
+    pub insertions: usize,
+
+    /// The number of lines which were deleted.
+    pub deletions: usize,
+}
+
+/// On-disk cache for diffstats, keyed by the OIDs of the trees being compared.
+pub struct DiffStatDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+#[context("Initializing tables for `DiffStatDb`")]
+fn init_tables(conn: &rusqlite::Connection) -> anyhow::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS diff_stats (
+    old_tree_oid TEXT NOT NULL,
+    new_tree_oid TEXT NOT NULL,
+    files_changed INTEGER NOT NULL,
+    insertions INTEGER NOT NULL,
+    deletions INTEGER NOT NULL,
+    UNIQUE (old_tree_oid, new_tree_oid)
+)
+",
+        rusqlite::params![],
+    )
+    .context("Creating tables")?;
+    Ok(())
+}
+
+impl<'conn> DiffStatDb<'conn> {
+    /// Constructor.
+    #[context("Constructing `DiffStatDb`")]
+    pub fn new(conn: &'conn rusqlite::Connection) -> anyhow::Result<Self> {
+        init_tables(conn).context("Initializing tables")?;
+        Ok(DiffStatDb { conn })
+    }
+
+    /// Get the cached diffstat between the two given trees.
+    ///
+    /// Returns: `None` if the diffstat hasn't been computed before.
+    #[context(
+        "Querying cached diffstat for trees {:?} and {:?}",
+        old_tree_oid,
+        new_tree_oid
+    )]
+    pub fn get_cached_diff_stat(
+        &self,
+        old_tree_oid: &str,
+        new_tree_oid: &str,
+    ) -> anyhow::Result<Option<DiffStat>> {
+        let result: Option<(i64, i64, i64)> = self
+            .conn
+            .query_row_named(
+                "
+SELECT files_changed, insertions, deletions
+FROM diff_stats
+WHERE old_tree_oid = :old_tree_oid
+  AND new_tree_oid = :new_tree_oid
+",
+                rusqlite::named_params! {
+                    ":old_tree_oid": old_tree_oid,
+                    ":new_tree_oid": new_tree_oid,
+                },
+                |row| {
+                    Ok((
+                        row.get("files_changed")?,
+                        row.get("insertions")?,
+                        row.get("deletions")?,
+                    ))
+                },
+            )
+            .optional()
+            .context("Querying diffstat DB")?;
+        match result {
+            Some((files_changed, insertions, deletions)) => Ok(Some(DiffStat {
+                files_changed: files_changed.try_into()?,
+                insertions: insertions.try_into()?,
+                deletions: deletions.try_into()?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the diffstat between the two given trees in the cache.
+    #[context("Caching diffstat for trees {:?} and {:?}", old_tree_oid, new_tree_oid)]
+    pub fn cache_diff_stat(
+        &self,
+        old_tree_oid: &str,
+        new_tree_oid: &str,
+        diff_stat: DiffStat,
+    ) -> anyhow::Result<()> {
+        let files_changed: i64 = diff_stat.files_changed.try_into()?;
+        let insertions: i64 = diff_stat.insertions.try_into()?;
+        let deletions: i64 = diff_stat.deletions.try_into()?;
+        self.conn
+            .execute_named(
+                "
+INSERT OR REPLACE INTO diff_stats
+VALUES (:old_tree_oid, :new_tree_oid, :files_changed, :insertions, :deletions)
+",
+                rusqlite::named_params! {
+                    ":old_tree_oid": old_tree_oid,
+                    ":new_tree_oid": new_tree_oid,
+                    ":files_changed": files_changed,
+                    ":insertions": insertions,
+                    ":deletions": deletions,
+                },
+            )
+            .context("Caching diffstat")?;
+        Ok(())
+    }
+}
+
+/// Get the OIDs of the trees to compare for the given commit: its first
+/// parent's tree (or the empty tree, for root commits), and its own tree.
+fn get_tree_pair(commit: &git2::Commit) -> (String, String) {
+    let old_tree_oid = match commit.parents().next() {
+        Some(parent) => parent.tree_id().to_string(),
+        None => EMPTY_TREE_OID.to_string(),
+    };
+    (old_tree_oid, commit.tree_id().to_string())
+}
+
+/// Compute the diffstat between the two given trees.
+fn compute_diff_stat(
+    repo: &git2::Repository,
+    old_tree_oid: &str,
+    new_tree_oid: &str,
+) -> anyhow::Result<DiffStat> {
+    let old_tree = if old_tree_oid == EMPTY_TREE_OID {
+        None
+    } else {
+        Some(repo.find_tree(git2::Oid::from_str(old_tree_oid)?)?)
+    };
+    let new_tree = repo.find_tree(git2::Oid::from_str(new_tree_oid)?)?;
+    let stats = repo
+        .diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), None)
+        .context("Computing diff")?
+        .stats()
+        .context("Computing diff stats")?;
+    Ok(DiffStat {
+        files_changed: stats.files_changed(),
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+    })
+}
+
+/// Get the diffstat of each of the given commits against its first parent.
+/// Merge commits are thus summarized against their first parent, and root
+/// commits against the empty tree.
+///
+/// Diffstats which aren't already in `diff_stat_db` are computed in parallel,
+/// and then cached there.
+///
+/// Returns: A mapping from commit OID to its diffstat.
+#[context("Getting diffstats for {} commits", commit_oids.len())]
+pub fn get_diff_stats(
+    repo: &git2::Repository,
+    diff_stat_db: &DiffStatDb,
+    commit_oids: &[git2::Oid],
+) -> anyhow::Result<HashMap<git2::Oid, DiffStat>> {
+    let _timer = start_timer("get diff stats");
+
+    let mut result = HashMap::new();
+    let mut uncached_tree_pairs: Vec<(git2::Oid, String, String)> = Vec::new();
+    for commit_oid in commit_oids {
+        let commit = repo.find_commit(*commit_oid)?;
+        let (old_tree_oid, new_tree_oid) = get_tree_pair(&commit);
+        match diff_stat_db.get_cached_diff_stat(&old_tree_oid, &new_tree_oid)? {
+            Some(diff_stat) => {
+                result.insert(*commit_oid, diff_stat);
+            }
+            None => uncached_tree_pairs.push((*commit_oid, old_tree_oid, new_tree_oid)),
+        }
+    }
+
+    // The `git2::Repository` can't be shared between threads, so each thread
+    // opens its own copy.
+    let repo_path = repo.path();
+    let computed_diff_stats: Vec<(git2::Oid, String, String, DiffStat)> = uncached_tree_pairs
+        .into_par_iter()
+        .map_init(
+            || git2::Repository::open(repo_path),
+            |thread_repo, (commit_oid, old_tree_oid, new_tree_oid)| {
+                let thread_repo = match thread_repo {
+                    Ok(thread_repo) => thread_repo,
+                    Err(err) => {
+                        anyhow::bail!("Could not open repository at {:?}: {}", repo_path, err)
+                    }
+                };
+                let diff_stat = compute_diff_stat(thread_repo, &old_tree_oid, &new_tree_oid)?;
+                Ok((commit_oid, old_tree_oid, new_tree_oid, diff_stat))
+            },
+        )
+        .collect::<anyhow::Result<_>>()?;
+
+    for (commit_oid, old_tree_oid, new_tree_oid, diff_stat) in computed_diff_stats {
+        diff_stat_db.cache_diff_stat(&old_tree_oid, &new_tree_oid, diff_stat)?;
+        result.insert(commit_oid, diff_stat);
+    }
+    Ok(result)
+}
@@ -0,0 +1,214 @@
+//! Structured errors for the library API.
+//!
+//! Internally, `git-branchless` uses `anyhow::Error` throughout, which is
+//! convenient for attaching context but can't be matched on. The public entry
+//! points which are intended to be called from other programs, such as
+//! `make_smartlog_graph` and `commands::move::move`, instead return a
+//! `BranchlessError`, which is converted from the internal error at the
+//! boundary.
+//!
+//! Errors of a specific kind are raised as a `BranchlessError` wrapped in an
+//! `anyhow::Error`, so that context can still be attached as they propagate.
+//! At the boundary, the original `BranchlessError` is recovered, and any other
+//! error becomes `BranchlessError::Other`.
+
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use crate::util::{EXIT_CODE_BAD_ARGUMENTS, EXIT_CODE_COMMIT_NOT_FOUND, EXIT_CODE_CONFLICT};
+
+/// An error returned by the library API.
+#[derive(Debug)]
+pub enum BranchlessError {
+    /// A commit passed to the command could not be resolved.
+    CommitNotFound {
+        /// The commit hash or reference, as passed to the command.
+        commit: String,
+    },
+
+    /// The command stopped, or didn't start, because of a merge conflict,
+    /// which must be resolved manually.
+    Conflict {
+        /// The commits which conflicted. If the command stopped partway
+        /// through, this is the commit which it stopped at.
+        commit_oids: Vec<git2::Oid>,
+
+        /// The paths with conflicts in the working copy, relative to the root
+        /// of the repository. This is empty if the command didn't start.
+        paths: Vec<PathBuf>,
+    },
+
+    /// The arguments passed to the command were invalid, such as a combination
+    /// of mutually-exclusive options.
+    BadArguments {
+        /// A description of the problem.
+        message: String,
+    },
+
+    /// The main branch (see `get_main_branch_name`) could not be resolved to a
+    /// commit.
+    MainBranchUnresolved {
+        /// The configured name of the main branch.
+        main_branch_name: String,
+
+        /// A description of the problem, including how to fix it.
+        message: String,
+    },
+
+    /// The event log contains an entry which couldn't be read.
+    EventLogCorrupt {
+        /// A description of the problem.
+        message: String,
+    },
+
+    /// The command failed with an exit code other than those above, such as
+    /// one passed through from `git` itself.
+    Failed {
+        /// The nonzero exit code.
+        exit_code: isize,
+    },
+
+    /// Any other error.
+    Other(anyhow::Error),
+}
+
+impl BranchlessError {
+    /// The exit code which the `git-branchless` binary exits with when a
+    /// command fails with this error. The command has already written a message
+    /// about the failure to its output stream in that case.
+    ///
+    /// Returns: The exit code, or `None` if the error should be reported to the
+    /// user instead.
+    pub fn exit_code(&self) -> Option<isize> {
+        match self {
+            BranchlessError::CommitNotFound { .. } => Some(EXIT_CODE_COMMIT_NOT_FOUND),
+            BranchlessError::Conflict { .. } => Some(EXIT_CODE_CONFLICT),
+            BranchlessError::BadArguments { .. } => Some(EXIT_CODE_BAD_ARGUMENTS),
+            BranchlessError::Failed { exit_code } => Some(*exit_code),
+            BranchlessError::MainBranchUnresolved { .. }
+            | BranchlessError::EventLogCorrupt { .. }
+            | BranchlessError::Other(_) => None,
+        }
+    }
+}
+
+impl Display for BranchlessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BranchlessError::CommitNotFound { commit } => {
+                write!(f, "Commit not found: {}", commit)
+            }
+            BranchlessError::Conflict { commit_oids, paths } => {
+                write!(f, "Merge conflict")?;
+                if !commit_oids.is_empty() {
+                    let commit_oids: Vec<String> =
+                        commit_oids.iter().map(|oid| oid.to_string()).collect();
+                    write!(f, " in {}", commit_oids.join(", "))?;
+                }
+                if !paths.is_empty() {
+                    let paths: Vec<String> = paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect();
+                    write!(f, " (conflicting paths: {})", paths.join(", "))?;
+                }
+                Ok(())
+            }
+            BranchlessError::BadArguments { message } => {
+                write!(f, "Invalid arguments: {}", message)
+            }
+            BranchlessError::MainBranchUnresolved {
+                main_branch_name: _,
+                message,
+            } => write!(f, "{}", message),
+            BranchlessError::EventLogCorrupt { message } => {
+                write!(f, "Corrupt event log: {}", message)
+            }
+            BranchlessError::Failed { exit_code } => {
+                write!(f, "Failed with exit code {}", exit_code)
+            }
+            BranchlessError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for BranchlessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BranchlessError::Other(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for BranchlessError {
+    fn from(err: anyhow::Error) -> Self {
+        // `downcast` also finds a `BranchlessError` underneath any context
+        // which was attached to it.
+        match err.downcast::<BranchlessError>() {
+            Ok(err) => err,
+            Err(err) => BranchlessError::Other(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use anyhow::Context;
+
+    #[test]
+    fn test_from_anyhow_error() {
+        let err: anyhow::Result<()> = Err(BranchlessError::EventLogCorrupt {
+            message: String::from("bad row"),
+        }
+        .into());
+        let err = err.context("Reading events").unwrap_err();
+        assert!(matches!(
+            BranchlessError::from(err),
+            BranchlessError::EventLogCorrupt { message } if message == "bad row"
+        ));
+
+        let err = anyhow::anyhow!("something else").context("Doing something");
+        let err = BranchlessError::from(err);
+        assert!(matches!(err, BranchlessError::Other(_)));
+        assert_eq!(err.to_string(), "Doing something");
+
+        // Each cause is reported once when walking the chain of sources.
+        let mut causes = Vec::new();
+        let mut source = std::error::Error::source(&err);
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        assert_eq!(causes, vec!["something else"]);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(
+            BranchlessError::Conflict {
+                commit_oids: Vec::new(),
+                paths: vec![PathBuf::from("foo.txt")],
+            }
+            .exit_code(),
+            Some(EXIT_CODE_CONFLICT)
+        );
+        assert_eq!(
+            BranchlessError::CommitNotFound {
+                commit: String::from("foo"),
+            }
+            .exit_code(),
+            Some(EXIT_CODE_COMMIT_NOT_FOUND)
+        );
+        assert_eq!(
+            BranchlessError::Failed { exit_code: 128 }.exit_code(),
+            Some(128)
+        );
+        assert_eq!(
+            BranchlessError::Other(anyhow::anyhow!("something else")).exit_code(),
+            None
+        );
+    }
+}
@@ -6,7 +6,7 @@
 //! they're still working on.
 
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
@@ -15,8 +15,13 @@ use anyhow::Context;
 use fn_error_context::context;
 use log::warn;
 
-use crate::core::config::get_main_branch_name;
-use crate::util::{get_main_branch_oid, wrap_git_error};
+use crate::core::config::{get_main_branch_name, get_main_branch_names};
+use crate::core::error::BranchlessError;
+use crate::core::timing::start_timer;
+use crate::util::{
+    find_additional_main_branch_oid, get_main_branch_commit_oid, get_main_branch_oid,
+    wrap_git_error,
+};
 
 /// When this environment variable is set, we reuse the ID for the transaction
 /// which the caller has already started.
@@ -169,6 +174,26 @@ pub enum Event {
         /// The OID of the commit that was unhidden.
         commit_oid: git2::Oid,
     },
+
+    /// Indicates that a commit, along with its descendants, was moved by `git
+    /// move`.
+    ///
+    /// The moved commits themselves are recorded with `RewriteEvent`s in the
+    /// same transaction. This event records where the root of the moved
+    /// subtree was before the move, so that it can be moved back.
+    MoveEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// The OID of the moved commit, before it was moved.
+        commit_oid: git2::Oid,
+
+        /// The OID of the parent of the moved commit, before it was moved.
+        old_parent_oid: git2::Oid,
+    },
 }
 
 impl Event {
@@ -180,6 +205,7 @@ impl Event {
             Event::CommitEvent { timestamp, .. } => timestamp,
             Event::HideEvent { timestamp, .. } => timestamp,
             Event::UnhideEvent { timestamp, .. } => timestamp,
+            Event::MoveEvent { timestamp, .. } => timestamp,
         };
         SystemTime::UNIX_EPOCH + Duration::from_secs_f64(*timestamp)
     }
@@ -192,6 +218,91 @@ impl Event {
             Event::CommitEvent { event_tx_id, .. } => *event_tx_id,
             Event::HideEvent { event_tx_id, .. } => *event_tx_id,
             Event::UnhideEvent { event_tx_id, .. } => *event_tx_id,
+            Event::MoveEvent { event_tx_id, .. } => *event_tx_id,
+        }
+    }
+
+    /// Convert this event into a JSON object for consumption by external
+    /// tools. The `type` field holds the same name as the event's type in the
+    /// event log database (such as `commit` or `ref-move`), and the remaining
+    /// fields are named after the fields of the corresponding `Event` variant.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Event::RewriteEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                old_commit_oid,
+                new_commit_oid,
+            } => serde_json::json!({
+                "type": "rewrite",
+                "timestamp": timestamp,
+                "event_tx_id": event_tx_id,
+                "old_commit_oid": old_commit_oid.to_string(),
+                "new_commit_oid": new_commit_oid.to_string(),
+            }),
+
+            Event::RefUpdateEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                ref_name,
+                old_ref,
+                new_ref,
+                message,
+            } => serde_json::json!({
+                "type": "ref-move",
+                "timestamp": timestamp,
+                "event_tx_id": event_tx_id,
+                "ref_name": ref_name,
+                "old_ref": old_ref,
+                "new_ref": new_ref,
+                "message": message,
+            }),
+
+            Event::CommitEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid,
+            } => serde_json::json!({
+                "type": "commit",
+                "timestamp": timestamp,
+                "event_tx_id": event_tx_id,
+                "commit_oid": commit_oid.to_string(),
+            }),
+
+            Event::HideEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid,
+            } => serde_json::json!({
+                "type": "hide",
+                "timestamp": timestamp,
+                "event_tx_id": event_tx_id,
+                "commit_oid": commit_oid.to_string(),
+            }),
+
+            Event::UnhideEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid,
+            } => serde_json::json!({
+                "type": "unhide",
+                "timestamp": timestamp,
+                "event_tx_id": event_tx_id,
+                "commit_oid": commit_oid.to_string(),
+            }),
+
+            Event::MoveEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid,
+                old_parent_oid,
+            } => serde_json::json!({
+                "type": "move",
+                "timestamp": timestamp,
+                "event_tx_id": event_tx_id,
+                "commit_oid": commit_oid.to_string(),
+                "old_parent_oid": old_parent_oid.to_string(),
+            }),
         }
     }
 }
@@ -272,6 +383,21 @@ impl From<Event> for Row {
                 ref_name: None,
                 message: None,
             },
+
+            Event::MoveEvent {
+                timestamp,
+                event_tx_id: EventTransactionId(event_tx_id),
+                commit_oid,
+                old_parent_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from("move"),
+                ref1: Some(commit_oid.to_string()),
+                ref2: Some(old_parent_oid.to_string()),
+                ref_name: None,
+                message: None,
+            },
         }
     }
 }
@@ -358,12 +484,83 @@ impl TryFrom<Row> for Event {
                 }
             }
 
+            "move" => {
+                let commit_oid = get_oid(&ref1, "commit OID")?;
+                let old_parent_oid = get_oid(&ref2, "old parent OID")?;
+                Event::MoveEvent {
+                    timestamp,
+                    event_tx_id,
+                    commit_oid,
+                    old_parent_oid,
+                }
+            }
+
             other => anyhow::bail!("Unknown event type {}", other),
         };
         Ok(event)
     }
 }
 
+/// A transaction recorded in the event log, along with the events which belong
+/// to it. Returned by `EventLogDb::iter_transactions`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventTransaction {
+    /// The ID of the transaction.
+    pub id: EventTransactionId,
+
+    /// The time at which the transaction was started.
+    pub timestamp: SystemTime,
+
+    /// A description of the operation which started the transaction, such as
+    /// `hide` or the name of a Git hook.
+    pub message: Option<String>,
+
+    /// The events in the transaction, ordered from oldest to newest. This may
+    /// be empty, such as if the operation didn't end up changing anything.
+    pub events: Vec<Event>,
+}
+
+/// A store of `Event`s, such as `EventLogDb`, which stores them in SQLite, or
+/// `InMemoryEventLog`, which doesn't touch the disk at all. Events are kept in
+/// the order in which they were appended.
+pub trait EventLog {
+    /// Add events in the given order to the end of the log.
+    ///
+    /// Args:
+    /// * events: The events to add.
+    fn append(&mut self, events: Vec<Event>) -> anyhow::Result<()>;
+
+    /// Iterate over all the events in the log.
+    ///
+    /// Returns: An iterator over the events, ordered from oldest to newest.
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = Event> + '_>>;
+}
+
+/// Stores `Event`s in memory. This is useful for tests, or for constructing an
+/// `EventReplayer` without reading the on-disk event log.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryEventLog {
+    events: Vec<Event>,
+}
+
+impl InMemoryEventLog {
+    /// Constructor.
+    pub fn new(events: Vec<Event>) -> Self {
+        InMemoryEventLog { events }
+    }
+}
+
+impl EventLog for InMemoryEventLog {
+    fn append(&mut self, events: Vec<Event>) -> anyhow::Result<()> {
+        self.events.extend(events);
+        Ok(())
+    }
+
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = Event> + '_>> {
+        Ok(Box::new(self.events.iter().cloned()))
+    }
+}
+
 /// Stores `Event`s on disk.
 pub struct EventLogDb<'conn> {
     conn: &'conn rusqlite::Connection,
@@ -408,6 +605,43 @@ CREATE TABLE IF NOT EXISTS event_transactions (
     Ok(())
 }
 
+fn insert_events(tx: &rusqlite::Transaction, events: Vec<Event>) -> anyhow::Result<()> {
+    for event in events {
+        let Row {
+            timestamp,
+            type_,
+            event_tx_id,
+            ref1,
+            ref2,
+            ref_name,
+            message,
+        } = Row::from(event);
+        tx.execute_named(
+            "
+INSERT INTO event_log VALUES (
+    :timestamp,
+    :type,
+    :event_tx_id,
+    :old_ref,
+    :new_ref,
+    :ref_name,
+    :message
+)
+            ",
+            rusqlite::named_params! {
+                ":timestamp": timestamp,
+                ":type": &type_,
+                ":event_tx_id": event_tx_id,
+                ":old_ref": &ref1,
+                ":new_ref": &ref2,
+                ":ref_name": &ref_name,
+                ":message": &message,
+            },
+        )?;
+    }
+    Ok(())
+}
+
 impl<'conn> EventLogDb<'conn> {
     /// Constructor.
     #[context("Constructing `EventLogDb`")]
@@ -423,39 +657,22 @@ impl<'conn> EventLogDb<'conn> {
     #[context("Adding events to event-log")]
     pub fn add_events(&mut self, events: Vec<Event>) -> anyhow::Result<()> {
         let tx = self.conn.unchecked_transaction()?;
-        for event in events {
-            let Row {
-                timestamp,
-                type_,
-                event_tx_id,
-                ref1,
-                ref2,
-                ref_name,
-                message,
-            } = Row::from(event);
-            tx.execute_named(
-                "
-INSERT INTO event_log VALUES (
-    :timestamp,
-    :type,
-    :event_tx_id,
-    :old_ref,
-    :new_ref,
-    :ref_name,
-    :message
-)
-            ",
-                rusqlite::named_params! {
-                    ":timestamp": timestamp,
-                    ":type": &type_,
-                    ":event_tx_id": event_tx_id,
-                    ":old_ref": &ref1,
-                    ":new_ref": &ref2,
-                    ":ref_name": &ref_name,
-                    ":message": &message,
-                },
-            )?;
-        }
+        insert_events(&tx, events)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Replace all the events in the database with the given events, in a
+    /// transaction. This is used to compact the event log (see
+    /// `compact_events`).
+    ///
+    /// Args:
+    /// * events: The events to store, ordered from oldest to newest.
+    #[context("Replacing events in event-log")]
+    pub fn replace_events(&mut self, events: Vec<Event>) -> anyhow::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM event_log", rusqlite::params![])?;
+        insert_events(&tx, events)?;
         tx.commit()?;
         Ok(())
     }
@@ -499,7 +716,90 @@ ORDER BY rowid ASC
             })?
             .collect();
         let rows = rows?;
-        rows.into_iter().map(Event::try_from).collect()
+        rows.into_iter()
+            .map(|row| {
+                Event::try_from(row).map_err(|err| {
+                    anyhow::Error::from(BranchlessError::EventLogCorrupt {
+                        message: format!("{:#}", err),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Get every transaction in the database, along with its events, without
+    /// replaying them (see `EventReplayer` for that).
+    ///
+    /// Events which refer to a transaction which wasn't recorded, such as
+    /// because the transaction ID was provided by a parent process, are
+    /// grouped into a transaction with no message, whose timestamp is that of
+    /// its first event.
+    ///
+    /// Returns: The transactions, ordered by transaction ID (and therefore from
+    /// oldest to newest).
+    pub fn iter_transactions(&self) -> anyhow::Result<impl Iterator<Item = EventTransaction>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "
+SELECT event_tx_id, timestamp, message
+FROM event_transactions
+ORDER BY event_tx_id ASC
+",
+            )
+            .context("Querying transactions from `EventLogDb`")?;
+        let mut transactions: BTreeMap<isize, EventTransaction> = BTreeMap::new();
+        for row in stmt.query_map(rusqlite::params![], |row| {
+            let event_tx_id: isize = row.get("event_tx_id")?;
+            let timestamp: f64 = row.get("timestamp")?;
+            let message: Option<String> = row.get("message")?;
+            Ok((event_tx_id, timestamp, message))
+        })? {
+            let (event_tx_id, timestamp, message) = row.context("Reading transaction")?;
+            transactions.insert(
+                event_tx_id,
+                EventTransaction {
+                    id: EventTransactionId(event_tx_id),
+                    timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs_f64(timestamp),
+                    message,
+                    events: Vec::new(),
+                },
+            );
+        }
+
+        for event in self.get_events()? {
+            let EventTransactionId(event_tx_id) = event.get_event_tx_id();
+            transactions
+                .entry(event_tx_id)
+                .or_insert_with(|| EventTransaction {
+                    id: event.get_event_tx_id(),
+                    timestamp: event.get_timestamp(),
+                    message: None,
+                    events: Vec::new(),
+                })
+                .events
+                .push(event);
+        }
+        Ok(transactions.into_values())
+    }
+
+    /// Get a summary of the current state of the event log, which changes
+    /// whenever events are added to or replaced in the database. This is much
+    /// cheaper than reading all of the events with `get_events`.
+    ///
+    /// Returns: The number of events in the database, and the row ID of the
+    /// newest event, if any.
+    #[context("Querying event log head from `EventLogDb`")]
+    pub fn get_event_log_head(&self) -> anyhow::Result<(isize, Option<isize>)> {
+        let result = self.conn.query_row(
+            "
+SELECT COUNT(*), MAX(rowid)
+FROM event_log
+",
+            rusqlite::params![],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(result)
     }
 
     /// Create a new event transaction ID to be used to insert subsequent
@@ -552,6 +852,16 @@ ORDER BY rowid ASC
     }
 }
 
+impl EventLog for EventLogDb<'_> {
+    fn append(&mut self, events: Vec<Event>) -> anyhow::Result<()> {
+        self.add_events(events)
+    }
+
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = Event> + '_>> {
+        Ok(Box::new(self.get_events()?.into_iter()))
+    }
+}
+
 /// Determine whether a given reference is used to keep a commit alive.
 ///
 /// Args:
@@ -561,7 +871,23 @@ ORDER BY rowid ASC
 /// commit alive, so that it's not collected by Git's garbage collection
 /// mechanism.
 pub fn is_gc_ref(ref_name: &str) -> bool {
-    ref_name.starts_with("refs/branchless/")
+    ref_name.starts_with("refs/branchless/") && !is_backup_ref(ref_name)
+}
+
+/// The prefix of references which hold backups of `HEAD` and the index, made
+/// before starting an on-disk rebase (see `rewrite::execute_rebase_plan`).
+pub const BACKUP_REF_PREFIX: &str = "refs/branchless/backup/";
+
+/// Determine whether a given reference holds a backup made before an on-disk
+/// rebase. Unlike the references used to keep commits alive, updates to these
+/// references are recorded in the event log, so that they can be undone.
+///
+/// Args:
+/// * `ref_name`: The name of the reference.
+///
+/// Returns: Whether or not the given reference is a backup reference.
+pub fn is_backup_ref(ref_name: &str) -> bool {
+    ref_name.starts_with(BACKUP_REF_PREFIX)
 }
 
 /// Determines whether or not updates to the given reference should be ignored.
@@ -581,6 +907,79 @@ pub fn should_ignore_ref_updates(ref_name: &str) -> bool {
     )
 }
 
+/// Compact the event log by collapsing all events which happened before
+/// `horizon` into a baseline snapshot of the repository's state at that time.
+///
+/// For each commit which still exists (according to `commit_exists`), only
+/// the latest event affecting it before the horizon is kept, so that its
+/// visibility (and the explanation for why it's hidden, if it is) is
+/// preserved. Likewise, only the latest update to each reference before the
+/// horizon is kept. Events before the horizon which only affect commits that no
+/// longer exist (e.g. they were garbage-collected) are dropped entirely. All
+/// events after the horizon are kept.
+///
+/// Note that this means that it's no longer possible to undo to a point in
+/// time before the horizon.
+///
+/// Args:
+/// * `events`: The events to compact, ordered from oldest to newest.
+/// * `horizon`: Events before this time are compacted.
+/// * `commit_exists`: Whether or not the commit with the given OID still
+///   exists in the repository.
+///
+/// Returns: The compacted events, ordered from oldest to newest.
+pub fn compact_events(
+    events: Vec<Event>,
+    horizon: SystemTime,
+    commit_exists: impl Fn(git2::Oid) -> bool,
+) -> Vec<Event> {
+    let (old_events, new_events): (Vec<Event>, Vec<Event>) = events
+        .into_iter()
+        .partition(|event| event.get_timestamp() < horizon);
+
+    // Find the index of the latest old event for each commit and reference.
+    let mut latest_commit_events: HashMap<git2::Oid, usize> = HashMap::new();
+    let mut latest_ref_events: HashMap<&str, usize> = HashMap::new();
+    for (i, event) in old_events.iter().enumerate() {
+        match event {
+            Event::RewriteEvent {
+                old_commit_oid,
+                new_commit_oid,
+                ..
+            } => {
+                latest_commit_events.insert(*old_commit_oid, i);
+                latest_commit_events.insert(*new_commit_oid, i);
+            }
+            Event::RefUpdateEvent { ref_name, .. } => {
+                latest_ref_events.insert(ref_name, i);
+            }
+            Event::CommitEvent { commit_oid, .. }
+            | Event::HideEvent { commit_oid, .. }
+            | Event::UnhideEvent { commit_oid, .. } => {
+                latest_commit_events.insert(*commit_oid, i);
+            }
+            // This only records where a commit was moved from, which is part
+            // of the history being compacted away.
+            Event::MoveEvent { .. } => {}
+        }
+    }
+
+    let kept_indices: HashSet<usize> = latest_commit_events
+        .into_iter()
+        .filter(|(oid, _i)| commit_exists(*oid))
+        .map(|(_oid, i)| i)
+        .chain(latest_ref_events.values().copied())
+        .collect();
+    let mut result: Vec<Event> = old_events
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _event)| kept_indices.contains(i))
+        .map(|(_i, event)| event)
+        .collect();
+    result.extend(new_events);
+    result
+}
+
 #[derive(Debug)]
 enum EventClassification {
     Show,
@@ -622,6 +1021,28 @@ pub struct EventCursor {
     event_id: isize,
 }
 
+/// Timestamps which go backward by less than this amount aren't reported as
+/// clock skew, since they can happen legitimately when multiple processes
+/// record events at around the same time.
+const CLOCK_SKEW_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// An event whose recorded timestamp is earlier than that of an event which
+/// was recorded before it, such as when the repository is shared between
+/// machines whose clocks disagree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockSkew {
+    /// The cursor immediately after the event with the skewed timestamp.
+    pub cursor: EventCursor,
+
+    /// The timestamp recorded for the event.
+    pub timestamp: SystemTime,
+
+    /// The greatest timestamp recorded for any earlier event. The event's
+    /// logical timestamp is at least this (see
+    /// `EventReplayer::get_cursor_logical_timestamp`).
+    pub previous_timestamp: SystemTime,
+}
+
 /// Processes events in order and determine the repo's visible commits.
 #[derive(Debug)]
 pub struct EventReplayer {
@@ -631,6 +1052,20 @@ pub struct EventReplayer {
     /// The list of observed events.
     events: Vec<Event>,
 
+    /// The logical timestamp of each event in `events`. This is the greatest
+    /// timestamp of the event and all events before it, so that the logical
+    /// timestamps never decrease, even if the clocks of the machines which
+    /// recorded the events disagreed.
+    logical_timestamps: Vec<SystemTime>,
+
+    /// The greatest timestamp of any event which was recorded with the time
+    /// that it was observed (see `record_logical_timestamp`).
+    latest_observed_timestamp: Option<SystemTime>,
+
+    /// The events whose timestamps were earlier than those of the events
+    /// before them, in order.
+    clock_skews: Vec<ClockSkew>,
+
     /// The events that have affected each commit.
     commit_history: HashMap<git2::Oid, Vec<EventInfo>>,
 
@@ -647,20 +1082,25 @@ impl EventReplayer {
         EventReplayer {
             id_counter: 0,
             events: vec![],
+            logical_timestamps: vec![],
+            latest_observed_timestamp: None,
+            clock_skews: vec![],
             commit_history: HashMap::new(),
             ref_locations: HashMap::new(),
         }
     }
 
-    /// Construct the replayer from all the events in the database.
+    /// Construct the replayer from all the events in the event log.
     ///
     /// Args:
-    /// * `event_log_db`: The database to query events from.
+    /// * `event_log_db`: The event log to read events from, usually an
+    ///   `EventLogDb`.
     ///
     /// Returns: The constructed replayer.
-    pub fn from_event_log_db(event_log_db: &EventLogDb) -> anyhow::Result<Self> {
+    pub fn from_event_log_db(event_log_db: &impl EventLog) -> anyhow::Result<Self> {
+        let _timer = start_timer("EventReplayer::from_event_log_db");
         let mut result = EventReplayer::new();
-        for event in event_log_db.get_events()? {
+        for event in event_log_db.iter()? {
             result.process_event(&event);
         }
         Ok(result)
@@ -687,6 +1127,7 @@ impl EventReplayer {
                 return;
             }
             Some(event) => {
+                self.record_logical_timestamp(&event);
                 self.events.push(event);
                 self.events.last().unwrap()
             }
@@ -775,6 +1216,10 @@ impl EventReplayer {
                     event: event.clone(),
                     event_classification: EventClassification::Show,
                 }),
+
+            // The moved commits are recorded separately with `RewriteEvent`s,
+            // so this doesn't change the visibility of any commit.
+            Event::MoveEvent { .. } => {}
         };
     }
 
@@ -885,6 +1330,38 @@ impl EventReplayer {
         Some(&event_info.event)
     }
 
+    /// Get every event which refers to the given commit, along with its event
+    /// ID, in chronological order.
+    ///
+    /// Unlike the commit history used to determine visibility, this includes
+    /// reference updates which moved a reference to or from the commit.
+    pub fn get_commit_events(&self, oid: git2::Oid) -> Vec<(isize, &Event)> {
+        let oid_str = oid.to_string();
+        let refers_to_oid = |event: &Event| -> bool {
+            match event {
+                Event::RewriteEvent {
+                    old_commit_oid,
+                    new_commit_oid,
+                    ..
+                } => *old_commit_oid == oid || *new_commit_oid == oid,
+                Event::RefUpdateEvent {
+                    old_ref, new_ref, ..
+                } => {
+                    old_ref.as_deref() == Some(oid_str.as_str())
+                        || new_ref.as_deref() == Some(oid_str.as_str())
+                }
+                Event::CommitEvent { commit_oid, .. }
+                | Event::HideEvent { commit_oid, .. }
+                | Event::UnhideEvent { commit_oid, .. }
+                | Event::MoveEvent { commit_oid, .. } => *commit_oid == oid,
+            }
+        };
+        (0..)
+            .zip(self.events.iter())
+            .filter(|(_event_id, event)| refers_to_oid(event))
+            .collect()
+    }
+
     /// Get the OIDs which have activity according to the repository history.
     ///
     /// Returns: The set of OIDs referring to commits which are thought to be
@@ -907,6 +1384,118 @@ impl EventReplayer {
         self.make_cursor(self.events.len().try_into().unwrap())
     }
 
+    /// Create an event cursor pointing to the state of the repository as of
+    /// the provided time.
+    ///
+    /// The cursor is placed immediately after the last event whose logical
+    /// timestamp doesn't exceed `time` (see `get_cursor_logical_timestamp`).
+    /// Since logical timestamps never decrease, this is always consistent
+    /// with the order that the events were added to the event log, even if
+    /// some of their recorded timestamps are skewed. If there are no such
+    /// events, the cursor points to before all events.
+    pub fn make_cursor_at_time(&self, time: SystemTime) -> EventCursor {
+        let event_id = self
+            .logical_timestamps
+            .partition_point(|logical_timestamp| *logical_timestamp <= time);
+        self.make_cursor(event_id.try_into().unwrap())
+    }
+
+    /// Record the logical timestamp of the next event to be added to
+    /// `events`, and note whether its recorded timestamp was skewed.
+    ///
+    /// Commit events are recorded with the timestamp of the commit itself,
+    /// rather than the time that the commit was observed, so they may
+    /// legitimately be out of order (such as when fast-forwarding to an old
+    /// commit, or committing with a date in the future). They therefore don't
+    /// advance the logical clock for the events after them: a commit event's
+    /// logical timestamp is lowered to that of the next other event, if that's
+    /// earlier. They're also not used to detect clock skew.
+    fn record_logical_timestamp(&mut self, event: &Event) {
+        let timestamp = event.get_timestamp();
+        let num_trailing_commit_events = self
+            .events
+            .iter()
+            .rev()
+            .take_while(|event| matches!(event, Event::CommitEvent { .. }))
+            .count();
+        let num_events = self.logical_timestamps.len();
+        let previous_timestamp = if let Event::CommitEvent { .. } = event {
+            self.logical_timestamps.last().copied()
+        } else {
+            (num_events - num_trailing_commit_events)
+                .checked_sub(1)
+                .map(|i| self.logical_timestamps[i])
+        };
+        let logical_timestamp = match previous_timestamp {
+            Some(previous_timestamp) if previous_timestamp > timestamp => previous_timestamp,
+            _ => timestamp,
+        };
+        if let Event::CommitEvent { .. } = event {
+            self.logical_timestamps.push(logical_timestamp);
+            return;
+        }
+
+        for commit_logical_timestamp in
+            &mut self.logical_timestamps[num_events - num_trailing_commit_events..]
+        {
+            if *commit_logical_timestamp > logical_timestamp {
+                *commit_logical_timestamp = logical_timestamp;
+            }
+        }
+        self.logical_timestamps.push(logical_timestamp);
+        match self.latest_observed_timestamp {
+            Some(previous_timestamp) if previous_timestamp > timestamp => {
+                let is_skewed = previous_timestamp
+                    .duration_since(timestamp)
+                    .map(|skew| skew > CLOCK_SKEW_TOLERANCE)
+                    .unwrap_or(false);
+                if is_skewed {
+                    let num_events: isize = self.logical_timestamps.len().try_into().unwrap();
+                    self.clock_skews.push(ClockSkew {
+                        cursor: EventCursor {
+                            event_id: num_events,
+                        },
+                        timestamp,
+                        previous_timestamp,
+                    });
+                }
+            }
+            _ => self.latest_observed_timestamp = Some(timestamp),
+        }
+    }
+
+    /// Get the logical timestamp of the event immediately before the cursor.
+    /// Unlike the timestamp recorded for the event, this is never earlier than
+    /// the logical timestamp of any previous event, so it should be used to
+    /// order events in time or to describe how long ago they happened.
+    ///
+    /// Returns: The logical timestamp, or `None` if there is no event before
+    /// the cursor.
+    pub fn get_cursor_logical_timestamp(&self, cursor: EventCursor) -> Option<SystemTime> {
+        if cursor.event_id == 0 {
+            None
+        } else {
+            let previous_cursor_event_id: usize = (cursor.event_id - 1).try_into().unwrap();
+            self.logical_timestamps
+                .get(previous_cursor_event_id)
+                .copied()
+        }
+    }
+
+    /// Get all of the clock skew detected in the event log, in order. See
+    /// `ClockSkew`.
+    pub fn get_clock_skews(&self) -> &[ClockSkew] {
+        &self.clock_skews
+    }
+
+    /// Get the clock skew of the event immediately before the cursor, if its
+    /// timestamp was skewed.
+    pub fn get_clock_skew_before_cursor(&self, cursor: EventCursor) -> Option<&ClockSkew> {
+        self.clock_skews
+            .iter()
+            .find(|clock_skew| clock_skew.cursor == cursor)
+    }
+
     /// Create an event cursor pointing to immediately after the provided event ID.
     ///
     /// If the event ID is too low or too high, it will be clamped to the valid
@@ -1045,7 +1634,8 @@ impl EventReplayer {
 
                     Event::RewriteEvent { .. }
                     | Event::HideEvent { .. }
-                    | Event::UnhideEvent { .. } => None,
+                    | Event::UnhideEvent { .. }
+                    | Event::MoveEvent { .. } => None,
                 }
             })
     }
@@ -1106,6 +1696,28 @@ impl EventReplayer {
         }
     }
 
+    /// Get the OIDs of the additional main branches at the cursor's point in
+    /// time.
+    ///
+    /// Same as `get_additional_main_branch_oids`, but for a previous point in
+    /// time. As in `get_cursor_main_branch_oid`, a main branch which hasn't
+    /// been observed moving yet is assumed to be at its current location.
+    pub fn get_cursor_additional_main_branch_oids(
+        &self,
+        cursor: EventCursor,
+        repo: &git2::Repository,
+    ) -> anyhow::Result<Vec<git2::Oid>> {
+        let mut result = Vec::new();
+        for main_branch_name in get_main_branch_names(repo)?.into_iter().skip(1) {
+            let main_branch_oid = match self.get_cursor_branch_oid(cursor, &main_branch_name)? {
+                Some(main_branch_oid) => Some(main_branch_oid),
+                None => find_additional_main_branch_oid(repo, &main_branch_name),
+            };
+            result.extend(main_branch_oid);
+        }
+        Ok(result)
+    }
+
     /// Get the mapping of branch OIDs to names at the cursor's point in
     /// time.
     ///
@@ -1121,7 +1733,37 @@ impl EventReplayer {
         cursor: EventCursor,
         repo: &git2::Repository,
     ) -> anyhow::Result<HashMap<git2::Oid, HashSet<String>>> {
-        let mut ref_name_to_oid: HashMap<&String, git2::Oid> = HashMap::new();
+        let mut result = self.get_cursor_ref_oid_to_names(cursor, "refs/heads/");
+        let main_branch_name = get_main_branch_name(&repo)?;
+        if get_main_branch_commit_oid(repo, &main_branch_name)?.is_none() {
+            let main_branch_oid = self.get_cursor_main_branch_oid(cursor, repo)?;
+            result
+                .entry(main_branch_oid)
+                .or_insert_with(HashSet::new)
+                .insert(main_branch_name);
+        }
+        Ok(result)
+    }
+
+    /// Get the mapping of tag OIDs to names at the cursor's point in time.
+    ///
+    /// Same as `get_tag_oid_to_names`, but for a previous point in time. Only
+    /// tags which point directly to a commit are included, since the event log
+    /// doesn't record the commit that an annotated tag points to.
+    pub fn get_cursor_tag_oid_to_names(
+        &self,
+        cursor: EventCursor,
+    ) -> HashMap<git2::Oid, HashSet<String>> {
+        self.get_cursor_ref_oid_to_names(cursor, "refs/tags/")
+    }
+
+    /// Get the OID that each reference pointed to at the cursor's point in
+    /// time, keyed by the full reference name (such as `refs/heads/master`).
+    ///
+    /// References which were deleted are not included. Updates which made a
+    /// reference point to another reference rather than to an OID are ignored.
+    pub fn get_cursor_ref_name_to_oid(&self, cursor: EventCursor) -> HashMap<String, git2::Oid> {
+        let mut ref_name_to_oid: HashMap<String, git2::Oid> = HashMap::new();
         let cursor_event_id: usize = cursor.event_id.try_into().unwrap();
         for event in self.events[..cursor_event_id].iter() {
             match event {
@@ -1131,7 +1773,7 @@ impl EventReplayer {
                     ..
                 } => {
                     if let Ok(oid) = git2::Oid::from_str(new_ref) {
-                        ref_name_to_oid.insert(ref_name, oid);
+                        ref_name_to_oid.insert(ref_name.clone(), oid);
                     }
                 }
                 Event::RefUpdateEvent {
@@ -1144,27 +1786,28 @@ impl EventReplayer {
                 _ => {}
             }
         }
+        ref_name_to_oid
+    }
 
+    fn get_cursor_ref_oid_to_names(
+        &self,
+        cursor: EventCursor,
+        ref_prefix: &str,
+    ) -> HashMap<git2::Oid, HashSet<String>> {
+        let ref_name_to_oid = self.get_cursor_ref_name_to_oid(cursor);
         let mut result: HashMap<git2::Oid, HashSet<String>> = HashMap::new();
         for (ref_name, ref_oid) in ref_name_to_oid.iter() {
-            match ref_name.strip_prefix("refs/heads/") {
+            match ref_name.strip_prefix(ref_prefix) {
                 None => {}
-                Some(branch_name) => {
+                Some(name) => {
                     result
                         .entry(*ref_oid)
                         .or_insert_with(HashSet::new)
-                        .insert(String::from(branch_name));
+                        .insert(String::from(name));
                 }
             }
         }
-
-        let main_branch_name = get_main_branch_name(&repo)?;
-        let main_branch_oid = self.get_cursor_main_branch_oid(cursor, repo)?;
         result
-            .entry(main_branch_oid)
-            .or_insert_with(HashSet::new)
-            .insert(main_branch_name);
-        Ok(result)
     }
 
     /// Get the event immediately before the cursor.
@@ -1239,6 +1882,9 @@ pub mod testing {
             Event::UnhideEvent {
                 ref mut timestamp, ..
             } => *timestamp = 0.0,
+            Event::MoveEvent {
+                ref mut timestamp, ..
+            } => *timestamp = 0.0,
         }
         event
     }
@@ -1257,6 +1903,15 @@ mod tests {
     use crate::util::get_db_conn;
     use testing::make_dummy_transaction_id;
 
+    #[test]
+    fn test_is_gc_ref() {
+        assert!(is_gc_ref("refs/branchless/abc123"));
+        assert!(!is_gc_ref("refs/branchless/backup/1"));
+        assert!(is_backup_ref("refs/branchless/backup/1"));
+        assert!(!should_ignore_ref_updates("refs/branchless/backup/1"));
+        assert!(!is_gc_ref("refs/heads/master"));
+    }
+
     #[test]
     fn test_drop_non_meaningful_events() -> anyhow::Result<()> {
         let event_tx_id = make_dummy_transaction_id(123);
@@ -1292,6 +1947,38 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_in_memory_event_log() -> anyhow::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(123);
+        let commit_event = Event::CommitEvent {
+            timestamp: 0.0,
+            event_tx_id,
+            commit_oid: git2::Oid::from_str("abc")?,
+        };
+        let hide_event = Event::HideEvent {
+            timestamp: 1.0,
+            event_tx_id,
+            commit_oid: git2::Oid::from_str("abc")?,
+        };
+        let mut event_log = InMemoryEventLog::default();
+        event_log.append(vec![commit_event.clone()])?;
+        event_log.append(vec![hide_event.clone()])?;
+        assert_eq!(
+            event_log.iter()?.collect::<Vec<_>>(),
+            vec![commit_event, hide_event]
+        );
+
+        let replayer = EventReplayer::from_event_log_db(&event_log)?;
+        assert!(matches!(
+            replayer.get_cursor_commit_visibility(
+                replayer.make_default_cursor(),
+                git2::Oid::from_str("abc")?
+            ),
+            Some(CommitVisibility::Hidden)
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_different_event_transaction_ids() -> anyhow::Result<()> {
         with_git(|git| {
@@ -1410,4 +2097,244 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_make_cursor_at_time() -> anyhow::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(123);
+        let make_event = |timestamp: f64, commit_oid: &str| -> anyhow::Result<Event> {
+            Ok(Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: git2::Oid::from_str(commit_oid)?,
+            })
+        };
+        let mut replayer = EventReplayer::new();
+        replayer.process_event(&make_event(10.0, "abc")?);
+        replayer.process_event(&make_event(20.0, "def")?);
+        replayer.process_event(&make_event(20.0, "123")?);
+        replayer.process_event(&make_event(30.0, "456")?);
+
+        let at_time = |seconds: f64| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds);
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(5.0)),
+            replayer.make_cursor(0)
+        );
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(10.0)),
+            replayer.make_cursor(1)
+        );
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(15.0)),
+            replayer.make_cursor(1)
+        );
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(20.0)),
+            replayer.make_cursor(3)
+        );
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(100.0)),
+            replayer.make_default_cursor()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_clock_skew() -> anyhow::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(123);
+        let make_event = |timestamp: f64, commit_oid: &str| -> anyhow::Result<Event> {
+            Ok(Event::HideEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: git2::Oid::from_str(commit_oid)?,
+            })
+        };
+        let mut replayer = EventReplayer::new();
+        replayer.process_event(&make_event(10.0, "abc")?);
+        replayer.process_event(&make_event(30.0, "def")?);
+        // Recorded by a machine whose clock is behind.
+        replayer.process_event(&make_event(20.0, "123")?);
+        // Within the tolerance, so not reported.
+        replayer.process_event(&make_event(29.5, "456")?);
+        replayer.process_event(&make_event(40.0, "789")?);
+        // Commit events use the commit's own timestamp, so they're never
+        // reported.
+        replayer.process_event(&Event::CommitEvent {
+            timestamp: 1.0,
+            event_tx_id,
+            commit_oid: git2::Oid::from_str("abc")?,
+        });
+
+        let at_time = |seconds: f64| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds);
+        assert_eq!(
+            replayer.get_clock_skews(),
+            &[ClockSkew {
+                cursor: replayer.make_cursor(3),
+                timestamp: at_time(20.0),
+                previous_timestamp: at_time(30.0),
+            }]
+        );
+        assert_eq!(
+            replayer.get_clock_skew_before_cursor(replayer.make_cursor(3)),
+            Some(&replayer.get_clock_skews()[0])
+        );
+        assert_eq!(
+            replayer.get_clock_skew_before_cursor(replayer.make_cursor(4)),
+            None
+        );
+
+        assert_eq!(
+            replayer.get_cursor_logical_timestamp(replayer.make_cursor(0)),
+            None
+        );
+        assert_eq!(
+            replayer.get_cursor_logical_timestamp(replayer.make_cursor(3)),
+            Some(at_time(30.0))
+        );
+        assert_eq!(
+            replayer.get_cursor_logical_timestamp(replayer.make_cursor(4)),
+            Some(at_time(30.0))
+        );
+        assert_eq!(
+            replayer.get_cursor_logical_timestamp(replayer.make_cursor(5)),
+            Some(at_time(40.0))
+        );
+        assert_eq!(
+            replayer.get_cursor_logical_timestamp(replayer.make_cursor(6)),
+            Some(at_time(40.0))
+        );
+
+        // The skewed events are considered to have happened after the events
+        // recorded before them.
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(25.0)),
+            replayer.make_cursor(1)
+        );
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(30.0)),
+            replayer.make_cursor(4)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_event_logical_timestamp() -> anyhow::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(123);
+        let make_event = |timestamp: f64, commit_oid: &str| -> anyhow::Result<Event> {
+            Ok(Event::HideEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: git2::Oid::from_str(commit_oid)?,
+            })
+        };
+        let make_commit_event = |timestamp: f64, commit_oid: &str| -> anyhow::Result<Event> {
+            Ok(Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: git2::Oid::from_str(commit_oid)?,
+            })
+        };
+        let mut replayer = EventReplayer::new();
+        replayer.process_event(&make_event(10.0, "abc")?);
+        // A commit dated in the past, such as after fast-forwarding.
+        replayer.process_event(&make_commit_event(1.0, "def")?);
+        replayer.process_event(&make_event(20.0, "123")?);
+        // A commit dated in the future, followed by an event observed before
+        // that date.
+        replayer.process_event(&make_commit_event(100.0, "456")?);
+        replayer.process_event(&make_event(30.0, "789")?);
+
+        let at_time = |seconds: f64| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds);
+        let logical_timestamps: Vec<Option<SystemTime>> = (1..=5)
+            .map(|event_id| replayer.get_cursor_logical_timestamp(replayer.make_cursor(event_id)))
+            .collect();
+        assert_eq!(
+            logical_timestamps,
+            vec![
+                Some(at_time(10.0)),
+                Some(at_time(10.0)),
+                Some(at_time(20.0)),
+                Some(at_time(30.0)),
+                Some(at_time(30.0)),
+            ]
+        );
+        assert_eq!(
+            replayer.make_cursor_at_time(at_time(25.0)),
+            replayer.make_cursor(3)
+        );
+
+        // Neither commit is reported as clock skew.
+        assert!(replayer.get_clock_skews().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_events() -> anyhow::Result<()> {
+        let event_tx_id = make_dummy_transaction_id(123);
+        let oid1 = git2::Oid::from_str("1")?;
+        let oid2 = git2::Oid::from_str("2")?;
+        let oid3 = git2::Oid::from_str("3")?;
+        let gc_oid = git2::Oid::from_str("4")?;
+        let events = vec![
+            Event::CommitEvent {
+                timestamp: 1.0,
+                event_tx_id,
+                commit_oid: oid1,
+            },
+            Event::CommitEvent {
+                timestamp: 2.0,
+                event_tx_id,
+                commit_oid: gc_oid,
+            },
+            Event::RefUpdateEvent {
+                timestamp: 3.0,
+                event_tx_id,
+                ref_name: "HEAD".to_owned(),
+                old_ref: Some(oid1.to_string()),
+                new_ref: Some(oid2.to_string()),
+                message: None,
+            },
+            Event::RewriteEvent {
+                timestamp: 4.0,
+                event_tx_id,
+                old_commit_oid: oid1,
+                new_commit_oid: oid2,
+            },
+            Event::RefUpdateEvent {
+                timestamp: 5.0,
+                event_tx_id,
+                ref_name: "HEAD".to_owned(),
+                old_ref: Some(oid2.to_string()),
+                new_ref: Some(oid3.to_string()),
+                message: None,
+            },
+            Event::HideEvent {
+                timestamp: 6.0,
+                event_tx_id,
+                commit_oid: oid2,
+            },
+            Event::CommitEvent {
+                timestamp: 7.0,
+                event_tx_id,
+                commit_oid: oid3,
+            },
+        ];
+
+        let horizon = SystemTime::UNIX_EPOCH + Duration::from_secs(7);
+        let compacted_events = compact_events(events.clone(), horizon, |oid| oid != gc_oid);
+        assert_eq!(
+            compacted_events,
+            vec![
+                events[3].clone(),
+                events[4].clone(),
+                events[5].clone(),
+                events[6].clone(),
+            ]
+        );
+
+        let horizon = SystemTime::UNIX_EPOCH;
+        let compacted_events = compact_events(events.clone(), horizon, |oid| oid != gc_oid);
+        assert_eq!(compacted_events, events);
+
+        Ok(())
+    }
 }
@@ -4,7 +4,10 @@
 //! "TTY"). In the case of interactive output, we render with prettier non-ASCII
 //! characters and with colors, using shell-specific escape codes.
 
-use cursive::theme::{Effect, Style};
+use std::str::FromStr;
+
+use cursive::theme::{Color, ColorType, Effect, Style};
+use cursive::utils::lines::simple::simple_prefix;
 use cursive::utils::markup::StyledString;
 use cursive::utils::span::Span;
 
@@ -76,6 +79,10 @@ pub struct Glyphs {
     /// checked out.
     pub commit_main_head: &'static str,
 
+    /// Cursor for the commit belonging to the main branch which the currently
+    /// checked-out commit forked off from.
+    pub commit_main_fork_point: &'static str,
+
     /// Cursor for a hidden commit belonging to the main branch. (This is an
     /// unusual situation.)
     pub commit_main_hidden: &'static str,
@@ -84,17 +91,55 @@ pub struct Glyphs {
     /// currently checked out. (This is an unusual situation.)
     pub commit_main_hidden_head: &'static str,
 
+    /// Cursor for a stash entry, which is not currently checked out.
+    pub commit_stash: &'static str,
+
     /// Bullet-point character for a list of newline-separated items.
     pub bullet_point: &'static str,
+
+    /// Indicates that the end of a line was cut off to fit in the available
+    /// width.
+    pub ellipsis: &'static str,
+
+    /// Status of a commit whose tests or CI checks passed.
+    pub commit_status_success: &'static str,
+
+    /// Status of a commit whose tests or CI checks failed.
+    pub commit_status_failure: &'static str,
+
+    /// Status of a commit whose tests or CI checks haven't finished yet, or
+    /// whose status couldn't be interpreted.
+    pub commit_status_pending: &'static str,
 }
 
 impl Glyphs {
     /// Make the `Glyphs` object appropriate for `stdout`.
+    ///
+    /// Colors are written if they're enabled for `stdout`, which can be
+    /// overridden with `set_color_setting`.
     pub fn detect() -> Self {
-        if console::user_attended() {
+        let glyphs = if console::user_attended() {
             Glyphs::pretty()
         } else {
             Glyphs::text()
+        };
+        Glyphs {
+            should_write_ansi_escape_codes: console::colors_enabled(),
+            ..glyphs
+        }
+    }
+
+    /// Use `head_glyph` as the cursor for the visible commit which is currently
+    /// checked out, whether or not it's on the main branch.
+    ///
+    /// The glyphs are only ever created once per process, so the provided
+    /// string is leaked to give it the same lifetime as the built-in glyphs.
+    pub fn with_head_glyph(self, head_glyph: String) -> Self {
+        let head_glyph: &'static str = Box::leak(head_glyph.into_boxed_str());
+        Glyphs {
+            commit_visible_head: head_glyph,
+            commit_main_head: head_glyph,
+            ..self
         }
     }
 
@@ -112,10 +157,77 @@ impl Glyphs {
             commit_hidden_head: "%",
             commit_main: "O",
             commit_main_head: "@",
+            commit_main_fork_point: "O",
             commit_main_hidden: "X",
             commit_main_hidden_head: "%",
+            commit_stash: "s",
             bullet_point: "-",
+            ellipsis: "...",
+            commit_status_success: "(pass)",
+            commit_status_failure: "(fail)",
+            commit_status_pending: "(pending)",
+        }
+    }
+
+    /// Describe what each glyph used to draw the smartlog graph means, for
+    /// display in a legend. If several glyphs look the same in this mode (as in
+    /// text mode), only the first, most general, description is kept.
+    ///
+    /// Returns: Pairs of glyph and description, in display order.
+    pub fn legend(&self) -> Vec<(String, &'static str)> {
+        let entries = vec![
+            (
+                self.commit_visible.to_string(),
+                "a commit you're working on",
+            ),
+            (self.commit_main.to_string(), "a commit on the main branch"),
+            (
+                self.commit_visible_head.to_string(),
+                "the current commit (HEAD)",
+            ),
+            (
+                self.commit_main_head.to_string(),
+                "the current commit (HEAD), on the main branch",
+            ),
+            (
+                self.commit_main_fork_point.to_string(),
+                "the commit on the main branch which the current commit forked off from",
+            ),
+            (
+                self.commit_hidden.to_string(),
+                "a hidden commit, shown because it has visible descendants",
+            ),
+            (
+                self.commit_hidden_head.to_string(),
+                "the current commit (HEAD), which is hidden",
+            ),
+            (
+                self.commit_main_hidden.to_string(),
+                "a hidden commit on the main branch",
+            ),
+            (
+                self.commit_main_hidden_head.to_string(),
+                "the current commit (HEAD), which is hidden and on the main branch",
+            ),
+            (self.commit_stash.to_string(), "a stash entry"),
+            (
+                self.vertical_ellipsis.to_string(),
+                "some commits were omitted here",
+            ),
+            (self.line.to_string(), "connects a commit to its parent"),
+            (
+                format!("{}{}", self.line_with_offshoot, self.slash),
+                "a commit with more than one child",
+            ),
+        ];
+
+        let mut result: Vec<(String, &'static str)> = Vec::new();
+        for (glyph, description) in entries {
+            if !result.iter().any(|(other_glyph, _)| *other_glyph == glyph) {
+                result.push((glyph, description));
+            }
         }
+        result
     }
 
     /// Glyphs used for output to a TTY.
@@ -132,13 +244,67 @@ impl Glyphs {
             commit_hidden_head: "⦻",
             commit_main: "◇",
             commit_main_head: "◆",
+            commit_main_fork_point: "◈",
             commit_main_hidden: "✕",
             commit_main_hidden_head: "❖",
+            commit_stash: "▣",
             bullet_point: "•",
+            ellipsis: "…",
+            commit_status_success: "✓",
+            commit_status_failure: "✗",
+            commit_status_pending: "•",
+        }
+    }
+}
+
+/// Whether or not to write colors to the terminal, as set with the `--color`
+/// option or the `branchless.core.color` config key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSetting {
+    /// Always write colors, even if the output isn't a TTY.
+    Always,
+
+    /// Never write colors.
+    Never,
+
+    /// Write colors only if the output is a TTY.
+    #[default]
+    Auto,
+}
+
+impl FromStr for ColorSetting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" | "true" => Ok(ColorSetting::Always),
+            "never" | "false" => Ok(ColorSetting::Never),
+            "auto" => Ok(ColorSetting::Auto),
+            other => Err(format!(
+                "Unknown color setting {:?} (expected one of: always, never, auto)",
+                other
+            )),
         }
     }
 }
 
+/// Force colors on or off for all output of the current process, overriding
+/// the detection of whether the output is a TTY. This affects both `Glyphs`
+/// created with `Glyphs::detect` and strings styled with `console::style`. For
+/// `ColorSetting::Auto`, the default detection is left in place.
+///
+/// Args:
+/// * `color_setting`: The color setting to apply.
+pub fn set_color_setting(color_setting: ColorSetting) {
+    let colors_enabled = match color_setting {
+        ColorSetting::Always => true,
+        ColorSetting::Never => false,
+        ColorSetting::Auto => return,
+    };
+    console::set_colors_enabled(colors_enabled);
+    console::set_colors_enabled_stderr(colors_enabled);
+}
+
 /// Helper to build `StyledString`s by combining multiple strings (both regular
 /// `String`s and `StyledString`s).
 pub struct StyledStringBuilder {
@@ -219,6 +385,37 @@ impl StyledStringBuilder {
     }
 }
 
+/// Cut off the end of the styled string so that it fits into `max_width`
+/// columns, and append `glyphs.ellipsis` to indicate that it was truncated.
+/// Strings which already fit are returned unchanged.
+///
+/// Since the commit summary comes last on each line of the smartlog, this
+/// truncates the summary first, after accounting for the graph and any other
+/// commit metadata preceding it.
+pub fn truncate_styled_string(
+    glyphs: &Glyphs,
+    string: StyledString,
+    max_width: usize,
+) -> StyledString {
+    if string.width() <= max_width {
+        return string;
+    }
+
+    let ellipsis_width = simple_prefix(glyphs.ellipsis, usize::MAX).width;
+    let mut remaining_width = max_width.saturating_sub(ellipsis_width);
+    let mut result = StyledString::new();
+    for span in string.spans() {
+        let prefix = simple_prefix(span.content, remaining_width);
+        result.append_styled(&span.content[..prefix.length], *span.attr);
+        remaining_width -= prefix.width;
+        if prefix.length < span.content.len() {
+            break;
+        }
+    }
+    result.append_plain(glyphs.ellipsis);
+    result
+}
+
 /// Set the provided effect to all the internal spans of the styled string.
 pub fn set_effect(mut string: StyledString, effect: Effect) -> StyledString {
     string.spans_raw_attr_mut().for_each(|span| {
@@ -227,6 +424,17 @@ pub fn set_effect(mut string: StyledString, effect: Effect) -> StyledString {
     string
 }
 
+/// Change the foreground color of all the internal spans of the styled string
+/// which are rendered in color `from` to color `to` instead.
+pub fn replace_color(mut string: StyledString, from: Color, to: Color) -> StyledString {
+    string.spans_raw_attr_mut().for_each(|span| {
+        if span.attr.color.front == ColorType::Color(from) {
+            span.attr.color.front = ColorType::Color(to);
+        }
+    });
+    string
+}
+
 impl From<StyledStringBuilder> for StyledString {
     fn from(builder: StyledStringBuilder) -> Self {
         builder.build()
@@ -1,32 +1,96 @@
 //! Renders the smartlog commit graph based on user activity.
 //!
-//! This is the basic data structure that most of branchless operates on.
+//! This is the basic data structure that most of branchless operates on. It's
+//! also available to other tools which want to inspect the commits that the
+//! user is working on: use `make_smartlog_graph` to get the same graph that
+//! `git smartlog` displays, or `make_graph` for finer control over how it's
+//! constructed.
+//!
+//! The graph is a map from commit OID to `Node`. Each node records its parent
+//! and children *in the graph* (which may differ from its parents and children
+//! in the Git commit history, since uninteresting commits are omitted), and
+//! whether it's a main branch commit or a visible commit.
 
 use std::collections::{HashMap, HashSet, VecDeque};
 
 use fn_error_context::context;
 use log::warn;
+use rayon::prelude::*;
 
-use crate::core::eventlog::{CommitVisibility, Event, EventCursor, EventReplayer};
-use crate::core::mergebase::MergeBaseDb;
+use crate::core::commitgraph::{get_commit_generations, CommitGenerations};
+use crate::core::config::{get_core_max_path_commits, CORE_MAX_PATH_COMMITS_CONFIG_KEY};
+use crate::core::error::BranchlessError;
+use crate::core::eventlog::{CommitVisibility, Event, EventCursor, EventLogDb, EventReplayer};
+use crate::core::mergebase::{compute_merge_base_oid, should_cache_merge_base_oid, MergeBaseDb};
+use crate::core::timing::start_timer;
+use crate::util::{
+    get_additional_main_branch_oids, get_branch_oid_to_names, get_db_conn, get_head_oid,
+    get_main_branch_oid, get_shallow_boundary_oids, get_tag_oid_to_names, get_unhideable_ref_oids,
+};
 
 /// The OID of the repo's HEAD reference.
 #[derive(Debug)]
 pub struct HeadOid(pub Option<git2::Oid>);
 
-/// The OID that the repo's main branch points to.
+/// The OID that the repo's main branch points to. If multiple main branches
+/// are configured, this is the first of them.
 #[derive(Debug)]
 pub struct MainBranchOid(pub git2::Oid);
 
+/// The OIDs of any additional main branches (see `get_main_branch_names`),
+/// which are treated as main branches in the commit graph as well.
+#[derive(Debug)]
+pub struct AdditionalMainBranchOids(pub Vec<git2::Oid>);
+
 /// The OIDs of any branches whose pointed-to commits should be included in the
 /// commit graph.
 #[derive(Debug)]
 pub struct BranchOids(pub HashSet<git2::Oid>);
 
+/// The OIDs of any tags whose pointed-to commits should not be removed from the
+/// commit graph.
+#[derive(Debug)]
+pub struct TagOids(pub HashSet<git2::Oid>);
+
+/// The OIDs of any references configured with `branchless.core.unhideableRefs`
+/// (see `get_unhideable_ref_oids`), whose pointed-to commits should not be
+/// removed from the commit graph.
+#[derive(Debug)]
+pub struct UnhideableRefOids(pub HashSet<git2::Oid>);
+
 /// The OIDs of any visible commits that should be included in the commit graph.
 #[derive(Debug)]
 pub struct CommitOids(pub HashSet<git2::Oid>);
 
+/// A range of commit times, in seconds since the Unix epoch. Only visible
+/// commits whose committer timestamps fall into this range are used as the
+/// starting points for constructing the commit graph. Either bound may be
+/// omitted, in which case the range is unbounded in that direction.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CommitTimeRange {
+    /// Exclude commits committed before this time.
+    pub since: Option<i64>,
+
+    /// Exclude commits committed after this time.
+    pub until: Option<i64>,
+}
+
+impl CommitTimeRange {
+    /// Whether or not the given commit time falls into this range.
+    pub fn contains(&self, time: git2::Time) -> bool {
+        let seconds = time.seconds();
+        let is_after_since = match self.since {
+            Some(since) => since <= seconds,
+            None => true,
+        };
+        let is_before_until = match self.until {
+            Some(until) => seconds <= until,
+            None => true,
+        };
+        is_after_since && is_before_until
+    }
+}
+
 /// Node contained in the smartlog commit graph.
 #[derive(Debug)]
 pub struct Node<'repo> {
@@ -68,6 +132,15 @@ pub struct Node<'repo> {
     /// commit directly to the main branch and then later rewrite the commit.
     pub is_visible: bool,
 
+    /// Indicates that this commit is at the boundary of a shallow clone, so its
+    /// parents aren't available in the repository.
+    pub is_shallow_boundary: bool,
+
+    /// Indicates that this commit is a stash entry, rather than a commit that
+    /// the user is working on. Stashes are only included in the graph if
+    /// requested; see `SmartlogOptions::show_stashes`.
+    pub is_stash: bool,
+
     /// The latest event to affect this commit.
     ///
     /// It's possible that no event affected this commit, and it was simply
@@ -79,22 +152,117 @@ pub struct Node<'repo> {
 /// Graph of commits that the user is working on.
 pub type CommitGraph<'repo> = HashMap<git2::Oid, Node<'repo>>;
 
+/// Memoized results of walking the ancestry of nodes in a `CommitGraph`, so
+/// that the same relationships aren't recomputed when they're queried more
+/// than once during a single command.
+///
+/// The results are only valid for the graph that they were computed on, so a
+/// new cache should be created whenever the graph changes.
+#[derive(Debug, Default)]
+pub struct AncestryCache {
+    base_oids: HashMap<git2::Oid, git2::Oid>,
+    hidden_oids: HashMap<git2::Oid, bool>,
+    num_hits: usize,
+}
+
+impl AncestryCache {
+    /// Get the base of the stack containing `oid`, if it was previously
+    /// recorded with `insert_base_oid`.
+    pub fn get_base_oid(&mut self, oid: &git2::Oid) -> Option<git2::Oid> {
+        let result = self.base_oids.get(oid).copied();
+        if result.is_some() {
+            self.num_hits += 1;
+        }
+        result
+    }
+
+    /// The number of queries which were answered from the cache, rather than
+    /// by walking the graph.
+    pub fn num_hits(&self) -> usize {
+        self.num_hits
+    }
+
+    /// Record that `base_oid` is the base of the stack containing `oid`.
+    pub fn insert_base_oid(&mut self, oid: git2::Oid, base_oid: git2::Oid) {
+        self.base_oids.insert(oid, base_oid);
+    }
+}
+
 fn find_path_to_merge_base_internal<'repo>(
     repo: &'repo git2::Repository,
     merge_base_db: &MergeBaseDb,
     commit_oid: git2::Oid,
     target_oid: git2::Oid,
+    max_commits: usize,
+    visited_commit_callback: impl FnMut(git2::Oid),
+) -> anyhow::Result<Option<Vec<git2::Commit<'repo>>>> {
+    let merge_base_oid = merge_base_db.get_merge_base_oid(repo, commit_oid, target_oid)?;
+    let shallow_boundary_oids = get_shallow_boundary_oids(repo)?;
+    let commit_generations = get_commit_generations(repo)?;
+    find_path_with_merge_base(
+        repo,
+        commit_oid,
+        Some(target_oid),
+        merge_base_oid,
+        &shallow_boundary_oids,
+        commit_generations.as_ref(),
+        max_commits,
+        visited_commit_callback,
+    )
+}
+
+/// Find a shortest path between the given commits, given their already-known
+/// merge-base. Unlike `find_path_to_merge_base`, this doesn't need access to
+/// the `MergeBaseDb`, so it can be called from any thread.
+///
+/// Each commit is visited at most once. Rather than storing a full path for
+/// each commit in the queue, each visited commit stores the index of the
+/// commit it was reached from, and the path is reconstructed once the target
+/// is found.
+///
+/// The parents of commits in `shallow_boundary_oids` aren't in the repository,
+/// so they're not traversed. If `target_oid` is `None`, then the path to the
+/// nearest such commit is found instead.
+///
+/// If `commit_generations` is provided, then parents which can't be
+/// descendants of `target_oid` according to their generation numbers aren't
+/// traversed.
+///
+/// Returns an error if more than `max_commits` commits would be visited.
+fn find_path_with_merge_base<'repo>(
+    repo: &'repo git2::Repository,
+    commit_oid: git2::Oid,
+    target_oid: Option<git2::Oid>,
+    merge_base_oid: Option<git2::Oid>,
+    shallow_boundary_oids: &HashSet<git2::Oid>,
+    commit_generations: Option<&CommitGenerations>,
+    max_commits: usize,
     mut visited_commit_callback: impl FnMut(git2::Oid),
 ) -> anyhow::Result<Option<Vec<git2::Commit<'repo>>>> {
+    // Pairs of visited commit and the index of its child in this list, if any.
+    let mut visited: Vec<(git2::Commit<'repo>, Option<usize>)> = Vec::new();
+    let mut visited_oids = HashSet::new();
     let mut queue = VecDeque::new();
+
     visited_commit_callback(commit_oid);
-    queue.push_back(vec![repo.find_commit(commit_oid)?]);
-    let merge_base_oid = merge_base_db.get_merge_base_oid(repo, commit_oid, target_oid)?;
-    while let Some(path) = queue.pop_front() {
-        let last_commit = path
-            .last()
-            .expect("find_path_to_merge_base: empty path in queue");
-        if last_commit.id() == target_oid {
+    visited_oids.insert(commit_oid);
+    visited.push((repo.find_commit(commit_oid)?, None));
+    queue.push_back(0);
+    while let Some(index) = queue.pop_front() {
+        let last_commit = &visited[index].0;
+        let is_target = match target_oid {
+            Some(target_oid) => last_commit.id() == target_oid,
+            None => shallow_boundary_oids.contains(&last_commit.id()),
+        };
+        if is_target {
+            let mut path = Vec::new();
+            let mut current_index = Some(index);
+            while let Some(index) = current_index {
+                let (commit, child_index) = &visited[index];
+                path.push(commit.clone());
+                current_index = *child_index;
+            }
+            path.reverse();
             return Ok(Some(path));
         }
         if Some(last_commit.id()) == merge_base_oid {
@@ -105,12 +273,39 @@ fn find_path_to_merge_base_internal<'repo>(
             // actually a parent of `target_oid`.
             continue;
         }
+        if shallow_boundary_oids.contains(&last_commit.id()) {
+            // This commit's parents were never fetched.
+            continue;
+        }
 
-        for parent in last_commit.parents() {
+        let parents: Vec<git2::Commit> = last_commit.parents().collect();
+        for parent in parents {
+            if let (Some(target_oid), Some(commit_generations)) = (target_oid, commit_generations) {
+                if parent.id() != target_oid
+                    && commit_generations.cannot_be_ancestor(target_oid, parent.id())
+                {
+                    continue;
+                }
+            }
+            if !visited_oids.insert(parent.id()) {
+                continue;
+            }
+            if visited.len() >= max_commits {
+                anyhow::bail!(
+                    "Visited more than {} commits while looking for a path from {} to {}. \
+                    To raise this limit, set the `{}` config option.",
+                    max_commits,
+                    commit_oid,
+                    match target_oid {
+                        Some(target_oid) => target_oid.to_string(),
+                        None => "the shallow boundary".to_string(),
+                    },
+                    CORE_MAX_PATH_COMMITS_CONFIG_KEY,
+                );
+            }
             visited_commit_callback(parent.id());
-            let mut new_path = path.clone();
-            new_path.push(parent);
-            queue.push_back(new_path);
+            visited.push((parent, Some(index)));
+            queue.push_back(visited.len() - 1);
         }
     }
     Ok(None)
@@ -120,7 +315,9 @@ fn find_path_to_merge_base_internal<'repo>(
 ///
 /// This is particularly important for multi-parent commits (i.e. merge commits).
 /// If we don't happen to traverse the correct parent, we may end up traversing a
-/// huge amount of commit history, with a significant performance hit.
+/// huge amount of commit history, with a significant performance hit. To bound
+/// this, an error is returned if more commits would be traversed than allowed
+/// by `get_core_max_path_commits`.
 ///
 /// Args:
 /// * `repo`: The Git repository.
@@ -130,7 +327,8 @@ fn find_path_to_merge_base_internal<'repo>(
 ///
 /// Returns: A path of commits from `commit_oid` through parents to `target_oid`.
 /// The path includes `commit_oid` at the beginning and `target_oid` at the end.
-/// If there is no such path, returns `None`.
+/// If there is no such path, returns `None`. In a shallow clone, the search
+/// stops at the shallow boundary, since the commits beyond it aren't available.
 #[context("Finding path from {:?} to {:?}", commit_oid, target_oid)]
 pub fn find_path_to_merge_base<'repo>(
     repo: &'repo git2::Repository,
@@ -138,7 +336,138 @@ pub fn find_path_to_merge_base<'repo>(
     commit_oid: git2::Oid,
     target_oid: git2::Oid,
 ) -> anyhow::Result<Option<Vec<git2::Commit<'repo>>>> {
-    find_path_to_merge_base_internal(repo, merge_base_db, commit_oid, target_oid, |_commit| {})
+    let max_commits = get_core_max_path_commits(repo)?;
+    find_path_to_merge_base_internal(
+        repo,
+        merge_base_db,
+        commit_oid,
+        target_oid,
+        max_commits,
+        |_commit| {},
+    )
+}
+
+/// The result of walking from a single commit to its merge-base with the main
+/// branches. Only OIDs are stored, since the walk may have been carried out on
+/// another thread, using a different `git2::Repository` handle.
+struct CommitWalk {
+    commit_oid: git2::Oid,
+
+    /// The closest merge-base of `commit_oid` with any of the main branches.
+    merge_base_oid: Option<git2::Oid>,
+
+    /// The merge-bases which were computed during the walk, rather than looked
+    /// up from the `MergeBaseDb`, and so should be added to the cache, as pairs
+    /// of main branch OID and merge-base OID.
+    computed_merge_base_oids: Vec<(git2::Oid, Option<git2::Oid>)>,
+
+    /// The path from `commit_oid` to `merge_base_oid`, or `None` if no path
+    /// could be found. If there's no merge-base because the main branch is
+    /// only connected to the commit beyond the boundary of a shallow clone,
+    /// then this is the path to the boundary instead.
+    path_to_merge_base: Option<Vec<git2::Oid>>,
+}
+
+/// Pairs of main branch OID and the merge-base of some commit with that main
+/// branch, if it's already cached in the `MergeBaseDb`.
+type CachedMergeBaseOids = Vec<(git2::Oid, Option<Option<git2::Oid>>)>;
+
+/// Walk from the given commit to its merge-base with the main branches. If
+/// there are multiple main branches, the closest merge-base to the commit is
+/// used.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `main_branch_oids`: The main branches, along with the merge-base of the
+///   commit with each of them, if it's already cached.
+/// * `commit_oid`: The commit to walk from.
+/// * `shallow_boundary_oids`: The commits at the boundary of a shallow clone.
+///   See `get_shallow_boundary_oids`.
+/// * `commit_generations`: The generation numbers from the repository's
+///   commit-graph, if any. See `get_commit_generations`.
+/// * `max_path_commits`: The maximum number of commits to traverse while
+///   finding the path to the merge-base. See `get_core_max_path_commits`.
+///
+/// Returns: The result of the walk, or `None` if the commit no longer exists
+/// (for example, if it was garbage-collected).
+fn walk_from_commit(
+    repo: &git2::Repository,
+    main_branch_oids: &CachedMergeBaseOids,
+    commit_oid: git2::Oid,
+    shallow_boundary_oids: &HashSet<git2::Oid>,
+    commit_generations: Option<&CommitGenerations>,
+    max_path_commits: usize,
+) -> anyhow::Result<Option<CommitWalk>> {
+    if repo.find_commit(commit_oid).is_err() {
+        return Ok(None);
+    }
+
+    let mut merge_base_oid = None;
+    let mut computed_merge_base_oids = Vec::new();
+    for (main_branch_oid, cached_merge_base_oid) in main_branch_oids {
+        let candidate_oid = match cached_merge_base_oid {
+            Some(merge_base_oid) => *merge_base_oid,
+            None => {
+                let computed_oid = compute_merge_base_oid(repo, commit_oid, *main_branch_oid)?;
+                computed_merge_base_oids.push((*main_branch_oid, computed_oid));
+                computed_oid
+            }
+        };
+        merge_base_oid = match (merge_base_oid, candidate_oid) {
+            (None, candidate_oid) => candidate_oid,
+            (Some(best_oid), Some(candidate_oid))
+                if candidate_oid != best_oid
+                    && !matches!(commit_generations, Some(commit_generations)
+                        if commit_generations.cannot_be_ancestor(best_oid, candidate_oid))
+                    && repo.graph_descendant_of(candidate_oid, best_oid)? =>
+            {
+                Some(candidate_oid)
+            }
+            (Some(best_oid), _) => Some(best_oid),
+        };
+    }
+
+    let path_to_merge_base = match merge_base_oid {
+        // In a shallow clone, the merge-base may not have been fetched. Show
+        // the line of work down to the shallow boundary instead.
+        None if !shallow_boundary_oids.is_empty() => find_path_with_merge_base(
+            repo,
+            commit_oid,
+            None,
+            None,
+            shallow_boundary_oids,
+            commit_generations,
+            max_path_commits,
+            |_commit| {},
+        )?
+        .map(|path| path.iter().map(|commit| commit.id()).collect())
+        .or_else(|| Some(vec![commit_oid])),
+
+        // Occasionally we may find a commit that has no merge-base with the
+        // main branch. For example: a rewritten initial commit. This is
+        // somewhat pathological. We'll just add it to the graph as a
+        // standalone component and hope it works out.
+        None => Some(vec![commit_oid]),
+
+        Some(merge_base_oid) => find_path_with_merge_base(
+            repo,
+            commit_oid,
+            Some(merge_base_oid),
+            Some(merge_base_oid),
+            shallow_boundary_oids,
+            commit_generations,
+            max_path_commits,
+            |_commit| {},
+        )?
+        .map(|path| path.iter().map(|commit| commit.id()).collect()),
+    };
+
+    Ok(Some(CommitWalk {
+        commit_oid,
+        merge_base_oid,
+        computed_merge_base_oids,
+        path_to_merge_base,
+    }))
 }
 
 /// Find additional commits that should be displayed.
@@ -147,6 +476,11 @@ pub fn find_path_to_merge_base<'repo>(
 /// between it and the main branch, those intermediate commits should be shown
 /// (or else you won't get a good idea of the line of development that happened
 /// for this commit since the main branch).
+///
+/// The walks from each of the commits are independent, so they're carried out
+/// in parallel, with a separate `git2::Repository` handle per thread. The
+/// results are then merged into the graph sequentially, in order of commit OID,
+/// so that the resulting graph doesn't depend on thread scheduling.
 #[context("Walking from commits: {:?}", commit_oids)]
 fn walk_from_commits<'repo>(
     repo: &'repo git2::Repository,
@@ -154,74 +488,128 @@ fn walk_from_commits<'repo>(
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
     main_branch_oid: &MainBranchOid,
+    additional_main_branch_oids: &[git2::Oid],
     commit_oids: &CommitOids,
 ) -> anyhow::Result<CommitGraph<'repo>> {
+    let _timer = start_timer("walk_from_commits");
     let mut graph: CommitGraph = Default::default();
 
-    for commit_oid in &commit_oids.0 {
-        let commit = repo.find_commit(*commit_oid);
-        let current_commit = match commit {
-            Ok(commit) => commit,
+    let mut commit_oids: Vec<git2::Oid> = commit_oids.0.iter().copied().collect();
+    commit_oids.sort_unstable();
 
-            // Commit may have been garbage-collected.
-            Err(_) => continue,
-        };
+    let main_branch_oids = {
+        let mut main_branch_oids = vec![main_branch_oid.0];
+        for oid in additional_main_branch_oids {
+            if !main_branch_oids.contains(oid) {
+                main_branch_oids.push(*oid);
+            }
+        }
+        main_branch_oids
+    };
 
-        let merge_base_oid =
-            merge_base_db.get_merge_base_oid(repo, current_commit.id(), main_branch_oid.0)?;
-        let path_to_merge_base = match merge_base_oid {
-            // Occasionally we may find a commit that has no merge-base with the
-            // main branch. For example: a rewritten initial commit. This is
-            // somewhat pathological. We'll just add it to the graph as a
-            // standalone component and hope it works out.
-            None => vec![current_commit],
-            Some(merge_base_oid) => {
-                let path_to_merge_base = find_path_to_merge_base(
-                    repo,
-                    merge_base_db,
-                    current_commit.id(),
-                    merge_base_oid,
-                )?;
-                match path_to_merge_base {
-                    None => {
-                        warn!("No path to merge-base for commit {}", current_commit.id());
-                        continue;
+    // The `MergeBaseDb` can't be shared between threads, so look up any cached
+    // merge-bases ahead of time.
+    let commit_oids: Vec<(git2::Oid, CachedMergeBaseOids)> = commit_oids
+        .into_iter()
+        .map(|commit_oid| {
+            let cached_merge_base_oids = main_branch_oids
+                .iter()
+                .map(|main_branch_oid| {
+                    let cached_merge_base_oid =
+                        merge_base_db.get_cached_merge_base_oid(commit_oid, *main_branch_oid)?;
+                    Ok((*main_branch_oid, cached_merge_base_oid))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Ok((commit_oid, cached_merge_base_oids))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    let max_path_commits = get_core_max_path_commits(repo)?;
+    let shallow_boundary_oids = get_shallow_boundary_oids(repo)?;
+    let commit_generations = get_commit_generations(repo)?;
+    let repo_path = repo.path();
+    let commit_walks: Vec<Option<CommitWalk>> = commit_oids
+        .into_par_iter()
+        .map_init(
+            || git2::Repository::open(repo_path),
+            |thread_repo, (commit_oid, cached_merge_base_oids)| {
+                let thread_repo = match thread_repo {
+                    Ok(thread_repo) => thread_repo,
+                    Err(err) => {
+                        anyhow::bail!("Could not open repository at {:?}: {}", repo_path, err)
                     }
-                    Some(path_to_merge_base) => path_to_merge_base,
-                }
+                };
+                walk_from_commit(
+                    thread_repo,
+                    &cached_merge_base_oids,
+                    commit_oid,
+                    &shallow_boundary_oids,
+                    commit_generations.as_ref(),
+                    max_path_commits,
+                )
+            },
+        )
+        .collect::<anyhow::Result<_>>()?;
+
+    for commit_walk in commit_walks.into_iter().flatten() {
+        let CommitWalk {
+            commit_oid,
+            merge_base_oid,
+            computed_merge_base_oids,
+            path_to_merge_base,
+        } = commit_walk;
+
+        for (main_branch_oid, computed_merge_base_oid) in computed_merge_base_oids {
+            if !should_cache_merge_base_oid(repo) {
+                continue;
+            }
+            merge_base_db.cache_merge_base_oid(
+                commit_oid,
+                main_branch_oid,
+                computed_merge_base_oid,
+            )?;
+        }
+
+        let path_to_merge_base = match path_to_merge_base {
+            None => {
+                warn!("No path to merge-base for commit {}", commit_oid);
+                continue;
             }
+            Some(path_to_merge_base) => path_to_merge_base,
         };
 
-        for current_commit in path_to_merge_base.iter() {
-            if graph.contains_key(&current_commit.id()) {
+        for current_oid in path_to_merge_base {
+            if graph.contains_key(&current_oid) {
                 // This commit (and all of its parents!) should be in the graph
                 // already, so no need to continue this iteration.
                 break;
             }
 
-            let visibility =
-                event_replayer.get_cursor_commit_visibility(event_cursor, current_commit.id());
+            let current_commit = repo.find_commit(current_oid)?;
+            let visibility = event_replayer.get_cursor_commit_visibility(event_cursor, current_oid);
             let is_visible = match visibility {
                 Some(CommitVisibility::Visible) | None => true,
                 Some(CommitVisibility::Hidden) => false,
             };
 
             let is_main = match merge_base_oid {
-                Some(merge_base_oid) => (current_commit.id() == merge_base_oid),
+                Some(merge_base_oid) => (current_oid == merge_base_oid),
                 None => false,
             };
 
             let event = event_replayer
-                .get_cursor_commit_latest_event(event_cursor, current_commit.id())
+                .get_cursor_commit_latest_event(event_cursor, current_oid)
                 .cloned();
             graph.insert(
-                current_commit.id(),
+                current_oid,
                 Node {
-                    commit: current_commit.clone(),
+                    commit: current_commit,
                     parent: None,
                     children: HashSet::new(),
                     is_main,
                     is_visible,
+                    is_shallow_boundary: shallow_boundary_oids.contains(&current_oid),
+                    is_stash: false,
                     event,
                 },
             );
@@ -258,14 +646,17 @@ fn walk_from_commits<'repo>(
 }
 
 fn should_hide(
-    cache: &mut HashMap<git2::Oid, bool>,
+    ancestry_cache: &mut AncestryCache,
     graph: &CommitGraph,
     unhideable_oids: &HashSet<git2::Oid>,
     oid: &git2::Oid,
 ) -> bool {
     let result = {
-        match cache.get(oid) {
-            Some(result) => *result,
+        match ancestry_cache.hidden_oids.get(oid) {
+            Some(result) => {
+                ancestry_cache.num_hits += 1;
+                *result
+            }
             None => {
                 if unhideable_oids.contains(oid) {
                     false
@@ -284,35 +675,44 @@ fn should_hide(
                                 // for hiding purposes.
                                 .filter(|child_oid| !graph[child_oid].is_main)
                                 .all(|child_oid| {
-                                    should_hide(cache, graph, unhideable_oids, child_oid)
+                                    should_hide(ancestry_cache, graph, unhideable_oids, child_oid)
                                 })
                     } else {
                         !node.is_visible
                             && node.children.iter().all(|child_oid| {
-                                should_hide(cache, graph, unhideable_oids, child_oid)
+                                should_hide(ancestry_cache, graph, unhideable_oids, child_oid)
                             })
                     }
                 }
             }
         }
     };
-    cache.insert(*oid, result);
+    ancestry_cache.hidden_oids.insert(*oid, result);
     result
 }
 
 /// Remove commits from the graph according to their status.
-fn do_remove_commits(graph: &mut CommitGraph, head_oid: &HeadOid, branch_oids: &BranchOids) {
-    // OIDs which are pointed to by HEAD or a branch should not be hidden.
+fn do_remove_commits(
+    graph: &mut CommitGraph,
+    ancestry_cache: &mut AncestryCache,
+    head_oid: &HeadOid,
+    branch_oids: &BranchOids,
+    tag_oids: &TagOids,
+    unhideable_ref_oids: &UnhideableRefOids,
+) {
+    // OIDs which are pointed to by HEAD, a branch, a tag, or a reference
+    // configured with `branchless.core.unhideableRefs` should not be hidden.
     // Therefore, we can't hide them *or* their ancestors.
     let mut unhideable_oids = branch_oids.0.clone();
+    unhideable_oids.extend(tag_oids.0.iter().copied());
+    unhideable_oids.extend(unhideable_ref_oids.0.iter().copied());
     if let Some(head_oid) = head_oid.0 {
         unhideable_oids.insert(head_oid);
     }
 
-    let mut cache = HashMap::new();
     let all_oids_to_hide: HashSet<git2::Oid> = graph
         .keys()
-        .filter(|oid| should_hide(&mut cache, graph, &unhideable_oids, oid))
+        .filter(|oid| should_hide(ancestry_cache, graph, &unhideable_oids, oid))
         .cloned()
         .collect();
 
@@ -335,49 +735,492 @@ fn do_remove_commits(graph: &mut CommitGraph, head_oid: &HeadOid, branch_oids: &
 /// Args:
 /// * `repo`: The Git repository.
 /// * `merge_base_db`: The merge-base database.
+/// * `ancestry_cache`: The cache of ancestry results for this graph. Create one
+///   per command and pass it to any later queries on the same graph, such as
+///   `resolve_base_commit`.
 /// * `event_replayer`: The event replayer.
+/// * `event_cursor`: The point in the event log at which to construct the
+///   graph. Pass `event_replayer.make_default_cursor()` to use the most recent
+///   state.
 /// * `head_oid`: The OID of the repository's `HEAD` reference.
 /// * `main_branch_oid`: The OID of the main branch.
+/// * `additional_main_branch_oids`: The OIDs of any additional main branches
+///   (see `get_main_branch_names`), which are treated as main branches as well.
 /// * `branch_oids`: The set of OIDs pointed to by branches.
+/// * `tag_oids`: The set of OIDs pointed to by tags. Unlike branches, tags
+///   don't cause their commits to be added to the graph, but they do prevent
+///   commits already in the graph from being removed.
+/// * `unhideable_ref_oids`: The set of OIDs pointed to by references
+///   configured with `branchless.core.unhideableRefs`. Like tags, they prevent
+///   commits in the graph from being removed.
 /// * `hide_commits`: If set to `True`, then, after constructing the graph,
-/// remove nodes from it that appear to be hidden by user activity. This should
-/// be set to `True` for most display-related purposes.
+///   remove nodes from it that appear to be hidden by user activity. This should
+///   be set to `True` for most display-related purposes.
 ///
-/// Returns: A tuple of the head OID and the commit graph.
+/// Returns: The commit graph.
 #[context("Creating commit graph")]
 pub fn make_graph<'repo>(
     repo: &'repo git2::Repository,
     merge_base_db: &MergeBaseDb,
+    ancestry_cache: &mut AncestryCache,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
     head_oid: &HeadOid,
     main_branch_oid: &MainBranchOid,
+    additional_main_branch_oids: &AdditionalMainBranchOids,
     branch_oids: &BranchOids,
+    tag_oids: &TagOids,
+    unhideable_ref_oids: &UnhideableRefOids,
+    remove_commits: bool,
+) -> anyhow::Result<CommitGraph<'repo>> {
+    make_graph_in_time_range(
+        repo,
+        merge_base_db,
+        ancestry_cache,
+        event_replayer,
+        event_cursor,
+        head_oid,
+        main_branch_oid,
+        additional_main_branch_oids,
+        branch_oids,
+        tag_oids,
+        unhideable_ref_oids,
+        remove_commits,
+        &CommitTimeRange::default(),
+    )
+}
+
+/// Construct the commit graph for the repo, as in `make_graph`, using the
+/// repository's current `HEAD`, main branch, branches, tags, and unhideable
+/// references.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `merge_base_db`: The merge-base database.
+/// * `ancestry_cache`: The cache of ancestry results for this graph (see
+///   `make_graph`).
+/// * `event_replayer`: The event replayer.
+/// * `event_cursor`: The point in the event log at which to construct the
+///   graph.
+/// * `remove_commits`: Whether to remove hidden commits (see `make_graph`).
+///
+/// Returns: The commit graph.
+#[context("Creating commit graph for repository")]
+pub fn make_graph_from_repo<'repo>(
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    ancestry_cache: &mut AncestryCache,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
     remove_commits: bool,
 ) -> anyhow::Result<CommitGraph<'repo>> {
+    make_graph_from_repo_with_head(
+        repo,
+        merge_base_db,
+        ancestry_cache,
+        event_replayer,
+        event_cursor,
+        &HeadOid(get_head_oid(repo)?),
+        remove_commits,
+    )
+}
+
+/// Construct the commit graph for the repo, as in `make_graph_from_repo`, but
+/// treating `head_oid` as `HEAD` instead of the repository's `HEAD`. Since the
+/// `HEAD` commit is always included in the graph, this is useful for commands
+/// which operate on a commit that isn't checked out, such as `git move`.
+#[context("Creating commit graph for repository with HEAD {:?}", head_oid)]
+pub fn make_graph_from_repo_with_head<'repo>(
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    ancestry_cache: &mut AncestryCache,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    head_oid: &HeadOid,
+    remove_commits: bool,
+) -> anyhow::Result<CommitGraph<'repo>> {
+    let main_branch_oid = get_main_branch_oid(repo)?;
+    let branch_oid_to_names = get_branch_oid_to_names(repo)?;
+    let tag_oid_to_names = get_tag_oid_to_names(repo)?;
+    make_graph(
+        repo,
+        merge_base_db,
+        ancestry_cache,
+        event_replayer,
+        event_cursor,
+        head_oid,
+        &MainBranchOid(main_branch_oid),
+        &AdditionalMainBranchOids(get_additional_main_branch_oids(repo)?),
+        &BranchOids(branch_oid_to_names.keys().copied().collect()),
+        &TagOids(tag_oid_to_names.keys().copied().collect()),
+        &UnhideableRefOids(get_unhideable_ref_oids(repo)?),
+        remove_commits,
+    )
+}
+
+/// Construct the commit graph, as in `make_graph`, but only starting from the
+/// visible commits and branches whose commit times fall into
+/// `commit_time_range`. The commit for `HEAD` is always included.
+///
+/// Commits outside of the time range may still appear in the graph if they're
+/// needed to connect the included commits to the main branch.
+#[context("Creating commit graph for time range {:?}", commit_time_range)]
+pub fn make_graph_in_time_range<'repo>(
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    ancestry_cache: &mut AncestryCache,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+    additional_main_branch_oids: &AdditionalMainBranchOids,
+    branch_oids: &BranchOids,
+    tag_oids: &TagOids,
+    unhideable_ref_oids: &UnhideableRefOids,
+    remove_commits: bool,
+    commit_time_range: &CommitTimeRange,
+) -> anyhow::Result<CommitGraph<'repo>> {
+    let _timer = start_timer("make_graph");
+    let mut commit_oids = get_start_commit_oids(
+        repo,
+        event_replayer,
+        event_cursor,
+        head_oid,
+        branch_oids,
+        commit_time_range,
+    );
+    commit_oids.extend(additional_main_branch_oids.0.iter().copied());
+    make_graph_from_commit_oids(
+        repo,
+        merge_base_db,
+        ancestry_cache,
+        event_replayer,
+        event_cursor,
+        head_oid,
+        main_branch_oid,
+        additional_main_branch_oids,
+        branch_oids,
+        tag_oids,
+        unhideable_ref_oids,
+        remove_commits,
+        &CommitOids(commit_oids),
+    )
+}
+
+/// Get the commits to start constructing each component of the commit graph
+/// from. A component is made up of the visible commits and branches which
+/// forked off from the same main branch commit, so each component can be
+/// constructed with `make_graph_from_commit_oids` and rendered independently
+/// of the others. Otherwise, the commits are selected as in
+/// `make_graph_in_time_range`.
+///
+/// Returns: The start commits of each component. The component containing
+/// `head_oid` comes first, and it also includes the main branch commit, so
+/// that it's clear where the component is relative to it. The rest follow in
+/// order of the commit time of the main branch commit which they forked off
+/// from.
+#[context("Finding the commit graph components around HEAD {:?}", head_oid)]
+pub fn get_graph_components(
+    repo: &git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    head_oid: git2::Oid,
+    main_branch_oid: &MainBranchOid,
+    additional_main_branch_oids: &AdditionalMainBranchOids,
+    branch_oids: &BranchOids,
+    commit_time_range: &CommitTimeRange,
+) -> anyhow::Result<Vec<CommitOids>> {
+    let _timer = start_timer("get_graph_components");
+    let MainBranchOid(main_branch_oid) = main_branch_oid;
+    let mut start_commit_oids = get_start_commit_oids(
+        repo,
+        event_replayer,
+        event_cursor,
+        &HeadOid(Some(head_oid)),
+        branch_oids,
+        commit_time_range,
+    );
+    start_commit_oids.extend(additional_main_branch_oids.0.iter().copied());
+
+    let head_fork_point_oid = merge_base_db.get_merge_base_oid(repo, head_oid, *main_branch_oid)?;
+    let mut head_component = HashSet::new();
+    head_component.insert(*main_branch_oid);
+    let mut other_components: HashMap<Option<git2::Oid>, HashSet<git2::Oid>> = HashMap::new();
+    for commit_oid in start_commit_oids {
+        // Leave it to `walk_from_commits` to handle missing commits.
+        if repo.find_commit(commit_oid).is_err() {
+            head_component.insert(commit_oid);
+            continue;
+        }
+        let fork_point_oid =
+            merge_base_db.get_merge_base_oid(repo, commit_oid, *main_branch_oid)?;
+        if fork_point_oid == head_fork_point_oid {
+            head_component.insert(commit_oid);
+        } else {
+            other_components
+                .entry(fork_point_oid)
+                .or_default()
+                .insert(commit_oid);
+        }
+    }
+
+    let mut other_components = other_components
+        .into_iter()
+        .map(|(fork_point_oid, commit_oids)| {
+            let sort_key = match fork_point_oid {
+                Some(fork_point_oid) => {
+                    Some((repo.find_commit(fork_point_oid)?.time(), fork_point_oid))
+                }
+                None => None,
+            };
+            Ok((sort_key, commit_oids))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    other_components.sort_by_key(|(sort_key, _commit_oids)| *sort_key);
+
+    let mut components = vec![CommitOids(head_component)];
+    components.extend(
+        other_components
+            .into_iter()
+            .map(|(_sort_key, commit_oids)| CommitOids(commit_oids)),
+    );
+    Ok(components)
+}
+
+/// Get the commits to start constructing the commit graph from: the visible
+/// commits and branches whose commit times fall into `commit_time_range`, and
+/// the commit for `HEAD`.
+fn get_start_commit_oids(
+    repo: &git2::Repository,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    head_oid: &HeadOid,
+    branch_oids: &BranchOids,
+    commit_time_range: &CommitTimeRange,
+) -> HashSet<git2::Oid> {
     let mut commit_oids: HashSet<git2::Oid> = event_replayer
         .get_cursor_active_oids(event_cursor)
         .into_iter()
         .collect();
     commit_oids.extend(branch_oids.0.iter().cloned());
+    commit_oids.retain(|commit_oid| match repo.find_commit(*commit_oid) {
+        Ok(commit) => commit_time_range.contains(commit.time()),
+        // Leave it to `walk_from_commits` to handle missing commits.
+        Err(_) => true,
+    });
     if let HeadOid(Some(head_oid)) = head_oid {
         commit_oids.insert(*head_oid);
     }
-    let commit_oids = &CommitOids(commit_oids);
+    commit_oids
+}
+
+/// Construct the commit graph by walking from each of `commit_oids` to the
+/// main branch, and then remove the hidden commits if `remove_commits` is set.
+/// Use with `get_graph_components` to construct one component at a time.
+pub fn make_graph_from_commit_oids<'repo>(
+    repo: &'repo git2::Repository,
+    merge_base_db: &MergeBaseDb,
+    ancestry_cache: &mut AncestryCache,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    head_oid: &HeadOid,
+    main_branch_oid: &MainBranchOid,
+    additional_main_branch_oids: &AdditionalMainBranchOids,
+    branch_oids: &BranchOids,
+    tag_oids: &TagOids,
+    unhideable_ref_oids: &UnhideableRefOids,
+    remove_commits: bool,
+    commit_oids: &CommitOids,
+) -> anyhow::Result<CommitGraph<'repo>> {
     let mut graph = walk_from_commits(
         repo,
         merge_base_db,
         event_replayer,
         event_cursor,
         main_branch_oid,
+        &additional_main_branch_oids.0,
         commit_oids,
     )?;
     if remove_commits {
-        do_remove_commits(&mut graph, head_oid, branch_oids);
+        do_remove_commits(
+            &mut graph,
+            ancestry_cache,
+            head_oid,
+            branch_oids,
+            tag_oids,
+            unhideable_ref_oids,
+        );
     }
     Ok(graph)
 }
 
+/// Remove the nodes from the graph which don't match the given predicate.
+///
+/// The ancestors (in the graph) of matching nodes are kept as well, so that
+/// the matching nodes can still be rendered in the context of the main branch.
+pub fn filter_graph(graph: &mut CommitGraph, predicate: impl Fn(&Node) -> bool) {
+    let mut oids_to_keep = HashSet::new();
+    for (oid, node) in graph.iter() {
+        if !predicate(node) {
+            continue;
+        }
+
+        let mut current_oid = Some(*oid);
+        while let Some(oid) = current_oid {
+            if !oids_to_keep.insert(oid) {
+                // We've already kept this node's ancestors.
+                break;
+            }
+            current_oid = graph[&oid].parent;
+        }
+    }
+
+    graph.retain(|oid, _node| oids_to_keep.contains(oid));
+    for node in graph.values_mut() {
+        node.children
+            .retain(|child_oid| oids_to_keep.contains(child_oid));
+    }
+}
+
+/// Get the children of the given node in the graph, in a deterministic order.
+///
+/// Children are stored in a `HashSet`, so iterating over them directly yields
+/// an arbitrary order. Instead, children are sorted by their commit time, and
+/// then by their OID to break ties between commits made at the same time.
+///
+/// Args:
+/// * `graph`: The commit graph.
+/// * `oid`: The OID of the node whose children should be returned.
+///
+/// Returns: The OIDs of the children of the node which are present in the
+/// graph, oldest first.
+pub fn get_sorted_children(graph: &CommitGraph, oid: git2::Oid) -> Vec<git2::Oid> {
+    let mut children: Vec<git2::Oid> = graph[&oid]
+        .children
+        .iter()
+        .filter(|child_oid| graph.contains_key(child_oid))
+        .copied()
+        .collect();
+    children.sort_by_key(|child_oid| (graph[child_oid].commit.time(), *child_oid));
+    children
+}
+
+/// Get the commits in the subtree rooted at `root_oid`, in topological order.
+/// Commits on the main branch and hidden commits are skipped, although their
+/// descendants are still included.
+///
+/// Args:
+/// * `graph`: The commit graph.
+/// * `root_oid`: The OID of the root of the subtree.
+///
+/// Returns: The OIDs of `root_oid` and its descendants, parents before their
+/// children, and older children before newer ones.
+pub fn get_stack_commits(graph: &CommitGraph, root_oid: git2::Oid) -> Vec<git2::Oid> {
+    let mut result = Vec::new();
+    let mut stack = vec![root_oid];
+    while let Some(oid) = stack.pop() {
+        let node = match graph.get(&oid) {
+            Some(node) => node,
+            None => continue,
+        };
+        if node.is_visible && !node.is_main {
+            result.push(oid);
+        }
+        stack.extend(get_sorted_children(graph, oid).into_iter().rev());
+    }
+    result
+}
+
+/// Get the commits in the current stack: the visible commits from `head_oid`
+/// back to the main branch, following each commit's parent in the graph.
+/// Descendants of `head_oid` aren't included.
+///
+/// Args:
+/// * `graph`: The commit graph.
+/// * `head_oid`: The OID of the current commit, if any.
+///
+/// Returns: The OIDs of the commits in the current stack, parents before their
+/// children. This is empty if there's no current commit, or if it's on the
+/// main branch.
+pub fn resolve_current_stack(graph: &CommitGraph, head_oid: Option<git2::Oid>) -> Vec<git2::Oid> {
+    let mut result = Vec::new();
+    let mut current_oid = head_oid;
+    while let Some(oid) = current_oid {
+        let node = match graph.get(&oid) {
+            Some(node) => node,
+            None => break,
+        };
+        if node.is_main {
+            break;
+        }
+        if node.is_visible {
+            result.push(oid);
+        }
+        current_oid = node.parent;
+    }
+    result.reverse();
+    result
+}
+
+/// Find the commits in the graph whose summary lines contain `query`,
+/// case-insensitively.
+///
+/// Args:
+/// * `graph`: The commit graph.
+/// * `query`: The text to search for.
+///
+/// Returns: The OIDs of the matching commits, in order of commit time.
+pub fn find_commits_by_summary(graph: &CommitGraph, query: &str) -> Vec<git2::Oid> {
+    let query = query.to_lowercase();
+    let mut matching_nodes: Vec<&Node> = graph
+        .values()
+        .filter(|node| match node.commit.summary() {
+            Some(summary) => summary.to_lowercase().contains(&query),
+            None => false,
+        })
+        .collect();
+    matching_nodes.sort_by_key(|node| (node.commit.time(), node.commit.id().to_string()));
+    matching_nodes
+        .into_iter()
+        .map(|node| node.commit.id())
+        .collect()
+}
+
+/// Construct the smartlog graph for the repo, as displayed by `git smartlog`.
+///
+/// This is a convenience wrapper around `make_graph_from_repo`, which reads the
+/// branchless database and uses the most recent state of the event log.
+///
+/// Args:
+/// * `repo`: The Git repository.
+///
+/// Returns: The commit graph, with hidden commits removed.
+pub fn make_smartlog_graph<'repo>(
+    repo: &'repo git2::Repository,
+) -> Result<CommitGraph<'repo>, BranchlessError> {
+    let graph = make_smartlog_graph_internal(repo)?;
+    Ok(graph)
+}
+
+#[context("Creating smartlog graph for repository")]
+fn make_smartlog_graph_internal<'repo>(
+    repo: &'repo git2::Repository,
+) -> anyhow::Result<CommitGraph<'repo>> {
+    let conn = get_db_conn(repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+    make_graph_from_repo(
+        repo,
+        &merge_base_db,
+        &mut AncestryCache::default(),
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        true,
+    )
+}
+
 #[test]
 fn test_find_path_to_merge_base_stop_early() -> anyhow::Result<()> {
     crate::testing::with_git(|git| {
@@ -392,10 +1235,16 @@ fn test_find_path_to_merge_base_stop_early() -> anyhow::Result<()> {
         let merge_base_db = MergeBaseDb::new(&conn)?;
 
         let mut seen_oids = HashSet::new();
-        let path =
-            find_path_to_merge_base_internal(&repo, &merge_base_db, test2_oid, test3_oid, |oid| {
+        let path = find_path_to_merge_base_internal(
+            &repo,
+            &merge_base_db,
+            test2_oid,
+            test3_oid,
+            usize::MAX,
+            |oid| {
                 seen_oids.insert(oid);
-            })?;
+            },
+        )?;
         assert!(path.is_none());
 
         println!("Seen OIDs is {:?}", &seen_oids);
@@ -406,3 +1255,262 @@ fn test_find_path_to_merge_base_stop_early() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_find_path_to_merge_base_max_commits() -> anyhow::Result<()> {
+    crate::testing::with_git(|git| {
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+
+        let repo = git.get_repo()?;
+        let conn = crate::util::get_db_conn(&repo)?;
+        let merge_base_db = MergeBaseDb::new(&conn)?;
+
+        let path = find_path_to_merge_base_internal(
+            &repo,
+            &merge_base_db,
+            test3_oid,
+            test1_oid,
+            3,
+            |_oid| {},
+        )?;
+        let path: Option<Vec<git2::Oid>> =
+            path.map(|path| path.iter().map(|commit| commit.id()).collect());
+        assert_eq!(path, Some(vec![test3_oid, test2_oid, test1_oid]));
+
+        let result = find_path_to_merge_base_internal(
+            &repo,
+            &merge_base_db,
+            test3_oid,
+            test1_oid,
+            2,
+            |_oid| {},
+        );
+        assert!(result.is_err());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_find_path_to_merge_base_commit_graph() -> anyhow::Result<()> {
+    crate::testing::with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let side_oid = git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+        let target_oid = git.commit_file("test3", 3)?;
+        git.commit_file("test4", 4)?;
+        git.run(&["merge", "--no-ff", "-m", "merge", &side_oid.to_string()])?;
+        git.run(&["commit-graph", "write", "--reachable"])?;
+
+        let repo = git.get_repo()?;
+        let conn = crate::util::get_db_conn(&repo)?;
+        let merge_base_db = MergeBaseDb::new(&conn)?;
+        let merge_oid = repo.head()?.peel_to_commit()?.id();
+
+        let find_path = |seen_oids: &mut HashSet<git2::Oid>| -> anyhow::Result<usize> {
+            let path = find_path_to_merge_base_internal(
+                &repo,
+                &merge_base_db,
+                merge_oid,
+                target_oid,
+                usize::MAX,
+                |oid| {
+                    seen_oids.insert(oid);
+                },
+            )?;
+            Ok(path.map(|path| path.len()).unwrap_or_default())
+        };
+
+        // The side branch can't contain the target commit, since its
+        // generation number is lower.
+        let mut seen_oids = HashSet::new();
+        assert_eq!(find_path(&mut seen_oids)?, 3);
+        assert!(!seen_oids.contains(&side_oid));
+
+        git.run(&["config", "branchless.core.commitGraph", "false"])?;
+        let mut seen_oids = HashSet::new();
+        assert_eq!(find_path(&mut seen_oids)?, 3);
+        assert!(seen_oids.contains(&side_oid));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_walk_from_commits_in_parallel() -> anyhow::Result<()> {
+    use crate::core::eventlog::EventLogDb;
+
+    crate::testing::with_git(|git| {
+        git.init_repo()?;
+        let mut commit_oids = HashSet::new();
+        for i in 1..=5 {
+            git.run(&["checkout", "master"])?;
+            git.detach_head()?;
+            let parent_oid = git.commit_file(&format!("test{}", i), i)?;
+            let child_oid = git.commit_file(&format!("test{}-child", i), i)?;
+            commit_oids.insert(parent_oid);
+            commit_oids.insert(child_oid);
+        }
+
+        let repo = git.get_repo()?;
+        let conn = crate::util::get_db_conn(&repo)?;
+        let merge_base_db = MergeBaseDb::new(&conn)?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let main_branch_oid = crate::util::get_main_branch_oid(&repo)?;
+
+        let graph = walk_from_commits(
+            &repo,
+            &merge_base_db,
+            &event_replayer,
+            event_cursor,
+            &MainBranchOid(main_branch_oid),
+            &[],
+            &CommitOids(commit_oids.clone()),
+        )?;
+        assert_eq!(graph.len(), commit_oids.len() + 1);
+        assert_eq!(graph[&main_branch_oid].children.len(), 5);
+        for commit_oid in commit_oids.iter() {
+            let node = &graph[commit_oid];
+            assert_eq!(node.parent, Some(node.commit.parent_id(0)?));
+
+            // Merge-bases computed while walking should have been cached.
+            assert_eq!(
+                merge_base_db.get_cached_merge_base_oid(*commit_oid, main_branch_oid)?,
+                Some(Some(main_branch_oid)),
+            );
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_make_smartlog_graph() -> anyhow::Result<()> {
+    crate::testing::with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.run(&["hide", &test2_oid.to_string()])?;
+
+        let repo = git.get_repo()?;
+        let main_branch_oid = crate::util::get_main_branch_oid(&repo)?;
+        let graph = make_smartlog_graph(&repo)?;
+        assert_eq!(graph.len(), 2);
+
+        let main_node = &graph[&main_branch_oid];
+        assert!(main_node.is_main);
+        assert_eq!(main_node.children, vec![test1_oid].into_iter().collect());
+
+        let test1_node = &graph[&test1_oid];
+        assert!(!test1_node.is_main);
+        assert!(test1_node.is_visible);
+        assert_eq!(test1_node.parent, Some(main_branch_oid));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_make_graph_from_in_memory_event_log() -> anyhow::Result<()> {
+    use crate::core::eventlog::testing::make_dummy_transaction_id;
+    use crate::core::eventlog::InMemoryEventLog;
+
+    crate::testing::with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+
+        // Only the commits recorded in the in-memory event log are visible,
+        // regardless of what was recorded on disk.
+        let event_log = InMemoryEventLog::new(vec![Event::CommitEvent {
+            timestamp: 0.0,
+            event_tx_id: make_dummy_transaction_id(1),
+            commit_oid: test1_oid,
+        }]);
+        let event_replayer = EventReplayer::from_event_log_db(&event_log)?;
+
+        let repo = git.get_repo()?;
+        let conn = crate::util::get_db_conn(&repo)?;
+        let merge_base_db = MergeBaseDb::new(&conn)?;
+        let main_branch_oid = crate::util::get_main_branch_oid(&repo)?;
+        let graph = make_graph(
+            &repo,
+            &merge_base_db,
+            &mut AncestryCache::default(),
+            &event_replayer,
+            event_replayer.make_default_cursor(),
+            &HeadOid(Some(main_branch_oid)),
+            &MainBranchOid(main_branch_oid),
+            &AdditionalMainBranchOids(get_additional_main_branch_oids(&repo)?),
+            &BranchOids(vec![main_branch_oid].into_iter().collect()),
+            &TagOids(HashSet::new()),
+            &UnhideableRefOids(get_unhideable_ref_oids(&repo)?),
+            true,
+        )?;
+        let mut graph_oids: Vec<git2::Oid> = graph.keys().copied().collect();
+        graph_oids.sort();
+        let mut expected_oids = vec![main_branch_oid, test1_oid];
+        expected_oids.sort();
+        assert_eq!(graph_oids, expected_oids);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_make_smartlog_graph_main_branch_unresolved() -> anyhow::Result<()> {
+    crate::testing::with_git(|git| {
+        git.init_repo()?;
+        git.run(&["config", "branchless.core.mainBranch", "nonexistent"])?;
+
+        let repo = git.get_repo()?;
+        match make_smartlog_graph(&repo) {
+            Err(BranchlessError::MainBranchUnresolved {
+                main_branch_name, ..
+            }) => assert_eq!(main_branch_name, "nonexistent"),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Expected an error"),
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_get_sorted_children() -> anyhow::Result<()> {
+    crate::testing::with_git(|git| {
+        git.init_repo()?;
+        let mut child_oids = Vec::new();
+        for i in 1..=3 {
+            git.run(&["checkout", "master"])?;
+            git.detach_head()?;
+            child_oids.push(git.commit_file(&format!("test{}", i), 1)?);
+        }
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        let newest_child_oid = git.commit_file("test4", 2)?;
+
+        let repo = git.get_repo()?;
+        let main_branch_oid = crate::util::get_main_branch_oid(&repo)?;
+        let graph = make_smartlog_graph(&repo)?;
+
+        // Children made at the same time are ordered by OID, and come before
+        // newer children.
+        child_oids.sort();
+        child_oids.push(newest_child_oid);
+        assert_eq!(get_sorted_children(&graph, main_branch_oid), child_oids);
+
+        Ok(())
+    })
+}
@@ -14,10 +14,13 @@
 //! take a while. It can also happen when simply checking out an old commit to
 //! examine it.
 
+use std::collections::{HashSet, VecDeque};
+
 use anyhow::Context;
 use fn_error_context::context;
 use rusqlite::OptionalExtension;
 
+use crate::core::timing::{record_count, start_timer};
 use crate::util::wrap_git_error;
 
 /// On-disk cache for merge-base queries.
@@ -25,6 +28,13 @@ pub struct MergeBaseDb<'conn> {
     conn: &'conn rusqlite::Connection,
 }
 
+/// The version of the layout of the `merge_base_oids` table. Stored in the
+/// `merge_base_schema_version` table, so that migrations are only run once.
+///
+/// * 1: Rows are stored under a canonical ordering of their OIDs (see
+///   `normalize_oids`).
+const MERGE_BASE_SCHEMA_VERSION: i64 = 1;
+
 #[context("Initializing tables for `MergeBaseDb`")]
 fn init_tables(conn: &rusqlite::Connection) -> anyhow::Result<()> {
     conn.execute(
@@ -39,6 +49,61 @@ CREATE TABLE IF NOT EXISTS merge_base_oids (
         rusqlite::params![],
     )
     .context("Creating tables")?;
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS merge_base_schema_version (
+    version INTEGER NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .context("Creating schema version table")?;
+    Ok(())
+}
+
+#[context("Migrating tables for `MergeBaseDb`")]
+fn migrate_tables(conn: &rusqlite::Connection) -> anyhow::Result<()> {
+    let version: Option<i64> = conn
+        .query_row(
+            "SELECT MAX(version) FROM merge_base_schema_version",
+            rusqlite::params![],
+            |row| row.get(0),
+        )
+        .context("Querying schema version")?;
+    if version.unwrap_or(0) >= MERGE_BASE_SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    // Merge-base queries are cached under a canonical ordering of their OIDs
+    // (see `normalize_oids`). Move any rows which were stored under the other
+    // ordering, dropping them if they would duplicate an existing row.
+    tx.execute(
+        "
+INSERT OR IGNORE INTO merge_base_oids
+SELECT rhs_oid, lhs_oid, merge_base_oid
+FROM merge_base_oids
+WHERE lhs_oid > rhs_oid
+",
+        rusqlite::params![],
+    )
+    .context("Normalizing merge-base OIDs")?;
+    tx.execute(
+        "
+DELETE FROM merge_base_oids
+WHERE lhs_oid > rhs_oid
+",
+        rusqlite::params![],
+    )
+    .context("Deleting non-normalized merge-base OIDs")?;
+    tx.execute("DELETE FROM merge_base_schema_version", rusqlite::params![])
+        .context("Clearing schema version")?;
+    tx.execute(
+        "INSERT INTO merge_base_schema_version VALUES (?)",
+        rusqlite::params![MERGE_BASE_SCHEMA_VERSION],
+    )
+    .context("Recording schema version")?;
+    tx.commit()?;
     Ok(())
 }
 
@@ -47,33 +112,27 @@ impl<'conn> MergeBaseDb<'conn> {
     #[context("Constructing `MergeBaseDb`")]
     pub fn new(conn: &'conn rusqlite::Connection) -> anyhow::Result<Self> {
         init_tables(&conn).context("Initializing tables")?;
+        migrate_tables(conn).context("Migrating tables")?;
         Ok(MergeBaseDb { conn })
     }
 
-    /// Get the merge-base for two given commits.
-    ///
-    /// If the query is already in the cache, return the cached result. If
-    /// not, it is computed, cached, and returned.
-    ///
-    /// Args:
-    /// * `repo`: The Git repo.
-    /// * `lhs_oid`: The first OID (ordering is arbitrary).
-    /// * `rhs_oid`: The second OID (ordering is arbitrary).
+    /// Get the cached merge-base for two given commits, without querying the
+    /// Git repository.
     ///
-    /// Returns: The merge-base OID for these two commits. Returns `None` if no
-    /// merge-base could be found.
-    #[context("Querying for merge-base of OIDs {:?} and {:?}", lhs_oid, rhs_oid)]
-    pub fn get_merge_base_oid(
+    /// Returns: `None` if the query isn't in the cache. Otherwise, returns the
+    /// cached result, which may itself be `None` if there is no merge-base for
+    /// the given commits.
+    #[context(
+        "Querying for cached merge-base of OIDs {:?} and {:?}",
+        lhs_oid,
+        rhs_oid
+    )]
+    pub fn get_cached_merge_base_oid(
         &self,
-        repo: &git2::Repository,
         lhs_oid: git2::Oid,
         rhs_oid: git2::Oid,
-    ) -> anyhow::Result<Option<git2::Oid>> {
-        let (lhs_oid, rhs_oid) = if lhs_oid < rhs_oid {
-            (lhs_oid, rhs_oid)
-        } else {
-            (rhs_oid, lhs_oid)
-        };
+    ) -> anyhow::Result<Option<Option<git2::Oid>>> {
+        let (lhs_oid, rhs_oid) = normalize_oids(lhs_oid, rhs_oid);
 
         let merge_base_oid: Option<Option<String>> = self
             .conn
@@ -93,50 +152,312 @@ WHERE lhs_oid = :lhs_oid
             .optional()
             .context("Querying merge-base DB")?;
 
+        record_count(if merge_base_oid.is_some() {
+            "MergeBaseDb cache hits"
+        } else {
+            "MergeBaseDb cache misses"
+        });
         match merge_base_oid {
             // Cached and non-NULL.
             Some(Some(merge_base_oid)) => {
                 let merge_base_oid =
                     git2::Oid::from_str(&merge_base_oid).context("Parsing merge-base OID")?;
-                Ok(Some(merge_base_oid))
+                Ok(Some(Some(merge_base_oid)))
             }
 
             // Cached and NULL.
-            Some(None) => Ok(None),
+            Some(None) => Ok(Some(None)),
 
             // Not cached.
-            None => {
-                let merge_base_oid = match repo.merge_base(lhs_oid, rhs_oid) {
-                    Ok(merge_base_oid) => Ok(Some(merge_base_oid)),
-                    Err(err) => {
-                        if err.code() == git2::ErrorCode::NotFound {
-                            Ok(None)
-                        } else {
-                            Err(wrap_git_error(err))
-                        }
-                    }
-                }
-                .context("Querying Git repository for merge-base OID")?;
+            None => Ok(None),
+        }
+    }
 
-                // Cache computed merge-base OID.
-                self.conn
-                    .execute_named(
-                        "
-INSERT INTO merge_base_oids VALUES (
+    /// Store the merge-base for two given commits in the cache.
+    ///
+    /// This is useful if the merge-base was computed elsewhere (such as on
+    /// another thread, which can't use this `MergeBaseDb`).
+    #[context("Caching merge-base of OIDs {:?} and {:?}", lhs_oid, rhs_oid)]
+    pub fn cache_merge_base_oid(
+        &self,
+        lhs_oid: git2::Oid,
+        rhs_oid: git2::Oid,
+        merge_base_oid: Option<git2::Oid>,
+    ) -> anyhow::Result<()> {
+        let (lhs_oid, rhs_oid) = normalize_oids(lhs_oid, rhs_oid);
+        self.conn
+            .execute_named(
+                "
+INSERT OR REPLACE INTO merge_base_oids VALUES (
     :lhs_oid,
     :rhs_oid,
     :merge_base_oid
 )",
-                        rusqlite::named_params! {
-                            ":lhs_oid": &lhs_oid.to_string(),
-                            ":rhs_oid": &rhs_oid.to_string(),
-                            ":merge_base_oid": &merge_base_oid.map(|oid| oid.to_string()),
-                        },
-                    )
-                    .context("Caching merge-base OID")?;
-
-                Ok(merge_base_oid)
+                rusqlite::named_params! {
+                    ":lhs_oid": &lhs_oid.to_string(),
+                    ":rhs_oid": &rhs_oid.to_string(),
+                    ":merge_base_oid": &merge_base_oid.map(|oid| oid.to_string()),
+                },
+            )
+            .context("Caching merge-base OID")?;
+        Ok(())
+    }
+
+    /// Get the merge-base for two given commits.
+    ///
+    /// If the query is already in the cache, return the cached result. If
+    /// not, it is computed, cached, and returned.
+    ///
+    /// Args:
+    /// * `repo`: The Git repo.
+    /// * `lhs_oid`: The first OID (ordering is arbitrary).
+    /// * `rhs_oid`: The second OID (ordering is arbitrary).
+    ///
+    /// Returns: The merge-base OID for these two commits. Returns `None` if no
+    /// merge-base could be found.
+    #[context("Querying for merge-base of OIDs {:?} and {:?}", lhs_oid, rhs_oid)]
+    pub fn get_merge_base_oid(
+        &self,
+        repo: &git2::Repository,
+        lhs_oid: git2::Oid,
+        rhs_oid: git2::Oid,
+    ) -> anyhow::Result<Option<git2::Oid>> {
+        if let Some(merge_base_oid) = self.get_cached_merge_base_oid(lhs_oid, rhs_oid)? {
+            return Ok(merge_base_oid);
+        }
+
+        let merge_base_oid = compute_merge_base_oid(repo, lhs_oid, rhs_oid)?;
+        if should_cache_merge_base_oid(repo) {
+            self.cache_merge_base_oid(lhs_oid, rhs_oid, merge_base_oid)?;
+        }
+        Ok(merge_base_oid)
+    }
+}
+
+/// Whether computed merge-bases should be stored in the `MergeBaseDb`.
+///
+/// In a shallow clone, the merge-base may be beyond the shallow boundary, or
+/// only approximated (see `compute_shallow_merge_base_oid`), and the result
+/// could change once more history is fetched, so it's not cached.
+pub fn should_cache_merge_base_oid(repo: &git2::Repository) -> bool {
+    !repo.is_shallow()
+}
+
+/// Merge-base queries are symmetric, so store them under a canonical ordering.
+fn normalize_oids(lhs_oid: git2::Oid, rhs_oid: git2::Oid) -> (git2::Oid, git2::Oid) {
+    if lhs_oid < rhs_oid {
+        (lhs_oid, rhs_oid)
+    } else {
+        (rhs_oid, lhs_oid)
+    }
+}
+
+/// Query the Git repository for the merge-base of two commits, bypassing the
+/// cache.
+///
+/// Returns: The merge-base OID for these two commits. Returns `None` if no
+/// merge-base could be found, including if the repository is a shallow clone
+/// and the merge-base is beyond the shallow boundary.
+#[context(
+    "Querying Git repository for merge-base of OIDs {:?} and {:?}",
+    lhs_oid,
+    rhs_oid
+)]
+pub fn compute_merge_base_oid(
+    repo: &git2::Repository,
+    lhs_oid: git2::Oid,
+    rhs_oid: git2::Oid,
+) -> anyhow::Result<Option<git2::Oid>> {
+    let _timer = start_timer("compute_merge_base_oid");
+    match repo.merge_base(lhs_oid, rhs_oid) {
+        Ok(merge_base_oid) => Ok(Some(merge_base_oid)),
+        Err(err) => {
+            if err.code() == git2::ErrorCode::NotFound {
+                Ok(None)
+            } else if repo.is_shallow() {
+                // The merge-base computation fails with a generic error when
+                // it tries to look up a parent beyond the shallow boundary.
+                compute_shallow_merge_base_oid(repo, lhs_oid, rhs_oid)
+            } else {
+                Err(wrap_git_error(err))
+            }
+        }
+    }
+}
+
+/// Find a merge-base of two commits in a shallow clone, where libgit2 can't
+/// compute it because some parents are missing. Only the history on this side
+/// of the shallow boundary is considered.
+///
+/// Returns: The nearest ancestor of `lhs_oid` (by number of commits) which is
+/// also an ancestor of `rhs_oid`, or `None` if there's no such commit in the
+/// available history.
+fn compute_shallow_merge_base_oid(
+    repo: &git2::Repository,
+    lhs_oid: git2::Oid,
+    rhs_oid: git2::Oid,
+) -> anyhow::Result<Option<git2::Oid>> {
+    // Iterating over a commit's parents stops silently at the first parent
+    // which is missing, which is what we want here.
+    let walk_ancestors = |oid: git2::Oid| -> anyhow::Result<Vec<git2::Oid>> {
+        let mut result = vec![oid];
+        let mut seen_oids: HashSet<git2::Oid> = result.iter().copied().collect();
+        let mut queue: VecDeque<git2::Commit> = VecDeque::new();
+        queue.push_back(repo.find_commit(oid)?);
+        while let Some(commit) = queue.pop_front() {
+            for parent in commit.parents() {
+                if seen_oids.insert(parent.id()) {
+                    result.push(parent.id());
+                    queue.push_back(parent);
+                }
             }
         }
+        Ok(result)
+    };
+
+    let rhs_ancestor_oids: HashSet<git2::Oid> = walk_ancestors(rhs_oid)?.into_iter().collect();
+    let merge_base_oid = walk_ancestors(lhs_oid)?
+        .into_iter()
+        .find(|oid| rhs_ancestor_oids.contains(oid));
+    Ok(merge_base_oid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::with_git;
+    use crate::util::get_db_conn;
+
+    fn count_rows(conn: &rusqlite::Connection) -> anyhow::Result<isize> {
+        let count = conn.query_row(
+            "SELECT COUNT(*) FROM merge_base_oids",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    #[test]
+    fn test_merge_base_cache_is_symmetric() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            let test1_oid = git.commit_file("test1", 1)?;
+            git.run(&["checkout", "HEAD^"])?;
+            let test2_oid = git.commit_file("test2", 2)?;
+
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let merge_base_db = MergeBaseDb::new(&conn)?;
+            let initial_count = count_rows(&conn)?;
+
+            let merge_base_oid = merge_base_db.get_merge_base_oid(&repo, test1_oid, test2_oid)?;
+            assert_eq!(
+                merge_base_db.get_cached_merge_base_oid(test2_oid, test1_oid)?,
+                Some(merge_base_oid)
+            );
+            assert_eq!(
+                merge_base_db.get_merge_base_oid(&repo, test2_oid, test1_oid)?,
+                merge_base_oid
+            );
+            assert_eq!(count_rows(&conn)?, initial_count + 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_merge_base_cache_migration() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            let test1_oid = git.commit_file("test1", 1)?;
+            git.run(&["checkout", "HEAD^"])?;
+            let test2_oid = git.commit_file("test2", 2)?;
+            let (lhs_oid, rhs_oid) = normalize_oids(test1_oid, test2_oid);
+
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            init_tables(&conn)?;
+            conn.execute("DELETE FROM merge_base_oids", rusqlite::params![])?;
+            // Simulate a database from before the migration was run.
+            conn.execute("DELETE FROM merge_base_schema_version", rusqlite::params![])?;
+
+            // Simulate rows written under the non-canonical ordering, one of
+            // which duplicates a row written under the canonical ordering.
+            let insert = |lhs_oid: git2::Oid, rhs_oid: git2::Oid| {
+                conn.execute(
+                    "INSERT INTO merge_base_oids VALUES (?, ?, NULL)",
+                    rusqlite::params![lhs_oid.to_string(), rhs_oid.to_string()],
+                )
+            };
+            insert(rhs_oid, lhs_oid)?;
+            insert(lhs_oid, rhs_oid)?;
+            insert(rhs_oid, git2::Oid::zero())?;
+            assert_eq!(count_rows(&conn)?, 3);
+
+            let merge_base_db = MergeBaseDb::new(&conn)?;
+            assert_eq!(count_rows(&conn)?, 2);
+            assert_eq!(
+                merge_base_db.get_cached_merge_base_oid(rhs_oid, lhs_oid)?,
+                Some(None)
+            );
+            assert_eq!(
+                merge_base_db.get_cached_merge_base_oid(rhs_oid, git2::Oid::zero())?,
+                Some(None)
+            );
+
+            // The migration has been recorded, so it shouldn't run again.
+            insert(rhs_oid, lhs_oid)?;
+            MergeBaseDb::new(&conn)?;
+            assert_eq!(count_rows(&conn)?, 3);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_merge_base_is_cached() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            let test1_oid = git.commit_file("test1", 1)?;
+            git.run(&["checkout", "--orphan", "unrelated"])?;
+            let test2_oid = git.commit_file("test2", 2)?;
+
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let merge_base_db = MergeBaseDb::new(&conn)?;
+
+            // The commits have no merge-base, which should also be cached.
+            assert_eq!(
+                merge_base_db.get_cached_merge_base_oid(test1_oid, test2_oid)?,
+                None
+            );
+            assert_eq!(
+                merge_base_db.get_merge_base_oid(&repo, test1_oid, test2_oid)?,
+                None
+            );
+            assert_eq!(
+                merge_base_db.get_cached_merge_base_oid(test1_oid, test2_oid)?,
+                Some(None)
+            );
+
+            // Subsequent queries should be served from the cache, rather than
+            // the repository. Check this by tampering with the cached result.
+            let (lhs_oid, rhs_oid) = normalize_oids(test1_oid, test2_oid);
+            conn.execute(
+                "UPDATE merge_base_oids SET merge_base_oid = ? WHERE lhs_oid = ? AND rhs_oid = ?",
+                rusqlite::params![
+                    test1_oid.to_string(),
+                    lhs_oid.to_string(),
+                    rhs_oid.to_string()
+                ],
+            )?;
+            assert_eq!(
+                merge_base_db.get_merge_base_oid(&repo, test2_oid, test1_oid)?,
+                Some(test1_oid)
+            );
+
+            Ok(())
+        })
     }
 }
@@ -7,23 +7,29 @@
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::ops::Add;
+use std::path::Path;
 use std::time::{Duration, SystemTime};
 
-use cursive::theme::BaseColor;
+use cursive::theme::{BaseColor, Color, Effect};
 use cursive::utils::markup::StyledString;
 use fn_error_context::context;
 use lazy_static::lazy_static;
 use regex::Regex;
 
 use crate::core::config::{
-    get_commit_metadata_branches, get_commit_metadata_differential_revision,
-    get_commit_metadata_relative_time,
+    get_commit_metadata_ahead_behind, get_commit_metadata_branches,
+    get_commit_metadata_differential_revision, get_commit_metadata_max_branches,
+    get_commit_metadata_relative_time, get_commit_metadata_tags,
+    get_commit_metadata_working_copy_status, get_core_abbrev, get_hidden_explanation_restack_hint,
+    get_main_branch_names, get_smartlog_status_notes_ref,
 };
+use crate::util::{abbreviate_oid, get_additional_main_branch_oids, wrap_git_error};
 
+use super::diffstat::DiffStat;
 use super::eventlog::{Event, EventCursor, EventReplayer};
-use super::formatting::StyledStringBuilder;
-use super::graph::CommitGraph;
-use super::rewrite::find_rewrite_target;
+use super::formatting::{set_effect, Glyphs, Pluralize, StyledStringBuilder};
+use super::graph::{CommitGraph, MainBranchOid};
+use super::rewrite::{find_abandoned_children, find_rewrite_target};
 
 /// Interface to display information about a commit in the smartlog.
 pub trait CommitMetadataProvider {
@@ -32,6 +38,13 @@ pub trait CommitMetadataProvider {
     /// A return value of `None` indicates that this commit metadata provider was
     /// inapplicable for the provided commit.
     fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>>;
+
+    /// If `true`, this provider describes the commit's summary, which is
+    /// aligned to a column in the smartlog if `branchless.smartlog.summaryColumn`
+    /// is set. Only `CommitMessageProvider` returns `true`.
+    fn is_summary(&self) -> bool {
+        false
+    }
 }
 
 /// Get the complete description for a given commit.
@@ -50,23 +63,29 @@ pub fn render_commit_metadata(
     Ok(result)
 }
 
-/// Display an abbreviated commit hash.
-pub struct CommitOidProvider {
+/// Display an abbreviated commit hash (see `abbreviate_oid`).
+pub struct CommitOidProvider<'repo> {
+    repo: &'repo git2::Repository,
     use_color: bool,
+    abbrev_len: usize,
 }
 
-impl CommitOidProvider {
+impl<'repo> CommitOidProvider<'repo> {
     /// Constructor.
-    pub fn new(use_color: bool) -> anyhow::Result<Self> {
-        Ok(CommitOidProvider { use_color })
+    pub fn new(repo: &'repo git2::Repository, use_color: bool) -> anyhow::Result<Self> {
+        let abbrev_len = get_core_abbrev(repo)?;
+        Ok(CommitOidProvider {
+            repo,
+            use_color,
+            abbrev_len,
+        })
     }
 }
 
-impl CommitMetadataProvider for CommitOidProvider {
+impl<'repo> CommitMetadataProvider for CommitOidProvider<'repo> {
     #[context("Providing OID metadata for commit {:?}", commit.id())]
     fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
-        let oid = commit.id();
-        let oid = &oid.to_string()[..8];
+        let oid = abbreviate_oid(self.repo, commit.id(), self.abbrev_len)?;
         let oid = if self.use_color {
             StyledString::styled(oid, BaseColor::Yellow.dark())
         } else {
@@ -76,25 +95,71 @@ impl CommitMetadataProvider for CommitOidProvider {
     }
 }
 
+/// Determine whether the commit summary starts with one of `wip_prefixes`
+/// (see `get_smartlog_wip_prefixes`). The prefix must be followed by the end
+/// of the summary or a character which isn't alphanumeric, so that the `WIP`
+/// prefix matches `WIP: foo`, but not `WIPE foo`.
+pub fn is_wip_summary(summary: &str, wip_prefixes: &[String]) -> bool {
+    wip_prefixes.iter().any(|prefix| {
+        !prefix.is_empty()
+            && match summary.strip_prefix(prefix.as_str()) {
+                Some(rest) => !rest.starts_with(char::is_alphanumeric),
+                None => false,
+            }
+    })
+}
+
 /// Display the first line of the commit message.
-pub struct CommitMessageProvider;
+pub struct CommitMessageProvider {
+    wip_prefixes: Vec<String>,
+}
 
 impl CommitMessageProvider {
     /// Constructor.
     pub fn new() -> anyhow::Result<Self> {
-        Ok(CommitMessageProvider)
+        Ok(CommitMessageProvider {
+            wip_prefixes: Vec::new(),
+        })
+    }
+
+    /// Render the messages of work-in-progress commits, whose summaries start
+    /// with one of `wip_prefixes` (see `is_wip_summary`), dimmed and in
+    /// italics.
+    pub fn with_wip_prefixes(mut self, wip_prefixes: Vec<String>) -> Self {
+        self.wip_prefixes = wip_prefixes;
+        self
     }
 }
 
 impl CommitMetadataProvider for CommitMessageProvider {
+    fn is_summary(&self) -> bool {
+        true
+    }
+
     #[context("Providing message metadata for commit {:?}", commit.id())]
     fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
-        Ok(commit.summary().map(StyledString::plain))
+        let summary = match commit.summary() {
+            Some(summary) => summary,
+            None => return Ok(None),
+        };
+        let result = StyledString::plain(summary);
+        let result = if is_wip_summary(summary, &self.wip_prefixes) {
+            set_effect(set_effect(result, Effect::Dim), Effect::Italic)
+        } else {
+            result
+        };
+        Ok(Some(result))
     }
 }
 
+/// The color used to render the explanations for why commits are hidden.
+pub const HIDDEN_EXPLANATION_COLOR: Color = Color::Light(BaseColor::Black);
+
 /// For hidden commits, provide the reason that it's hidden.
 pub struct HiddenExplanationProvider<'a> {
+    repo: &'a git2::Repository,
+    abbrev_len: usize,
+    show_restack_hint: bool,
     graph: &'a CommitGraph<'a>,
     event_replayer: &'a EventReplayer,
     event_cursor: EventCursor,
@@ -103,11 +168,17 @@ pub struct HiddenExplanationProvider<'a> {
 impl<'a> HiddenExplanationProvider<'a> {
     /// Constructor.
     pub fn new(
+        repo: &'a git2::Repository,
         graph: &'a CommitGraph,
         event_replayer: &'a EventReplayer,
         event_cursor: EventCursor,
     ) -> anyhow::Result<Self> {
+        let abbrev_len = get_core_abbrev(repo)?;
+        let show_restack_hint = get_hidden_explanation_restack_hint(repo)?;
         Ok(HiddenExplanationProvider {
+            repo,
+            abbrev_len,
+            show_restack_hint,
             graph,
             event_replayer,
             event_cursor,
@@ -134,30 +205,66 @@ impl<'a> CommitMetadataProvider for HiddenExplanationProvider<'a> {
                     self.event_cursor,
                     commit.id(),
                 );
-                rewrite_target.map(|rewritten_oid| {
-                    StyledString::styled(
-                        format!("(rewritten as {})", &rewritten_oid.to_string()[..8]),
-                        BaseColor::Black.light(),
-                    )
-                })
+                let describe_rewrite = |rewritten_oid| -> anyhow::Result<StyledString> {
+                    // Only suggest restacking if there are visible descendants
+                    // which would actually be moved. Main branch commits
+                    // aren't moved by `git restack`.
+                    let needs_restack = self.show_restack_hint
+                        && self.graph.contains_key(&commit.id())
+                        && matches!(
+                            find_abandoned_children(
+                                self.graph,
+                                self.event_replayer,
+                                self.event_cursor,
+                                commit.id(),
+                            ),
+                            Some((_rewritten_oid, abandoned_children_oids))
+                                if abandoned_children_oids
+                                    .iter()
+                                    .any(|child_oid| !self.graph[child_oid].is_main)
+                        );
+
+                    let rewritten_oid = abbreviate_oid(self.repo, rewritten_oid, self.abbrev_len)?;
+                    let mut result = StyledStringBuilder::new()
+                        .append_styled("(rewritten as ", HIDDEN_EXPLANATION_COLOR)
+                        .append_styled(rewritten_oid, BaseColor::Yellow.dark());
+                    if needs_restack {
+                        result = result.append_styled(
+                            "; run `git restack` to move descendants",
+                            HIDDEN_EXPLANATION_COLOR,
+                        );
+                    }
+                    Ok(result.append_styled(")", HIDDEN_EXPLANATION_COLOR).build())
+                };
+                rewrite_target.map(describe_rewrite).transpose()?
             }
 
             Event::HideEvent { .. } => Some(StyledString::styled(
                 "(manually hidden)",
-                BaseColor::Black.light(),
+                HIDDEN_EXPLANATION_COLOR,
             )),
 
             Event::RefUpdateEvent { .. }
             | Event::CommitEvent { .. }
-            | Event::UnhideEvent { .. } => None,
+            | Event::UnhideEvent { .. }
+            | Event::MoveEvent { .. } => None,
         };
         Ok(result)
     }
 }
 
-/// Display branches that point to a given commit.
+/// Display branches pointing to a given commit.
+///
+/// The configured main branches are listed first, followed by the other
+/// branches in alphabetical order. Branch names which refer to a remote branch
+/// (such as a main branch of `origin/master`) are dimmed. If there are more
+/// than `branchless.commitMetadata.maxBranches` branches, the rest are
+/// summarized as `+N more`.
 pub struct BranchesProvider<'a> {
     is_enabled: bool,
+    main_branch_names: Vec<String>,
+    remote_names: Vec<String>,
+    max_branches: Option<usize>,
     branch_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
 }
 
@@ -168,11 +275,52 @@ impl<'a> BranchesProvider<'a> {
         branch_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
     ) -> anyhow::Result<Self> {
         let is_enabled = get_commit_metadata_branches(repo)?;
+        let main_branch_names = get_main_branch_names(repo)?;
+        let remote_names = repo
+            .remotes()
+            .map_err(wrap_git_error)?
+            .iter()
+            .flatten()
+            .map(String::from)
+            .collect();
+        let max_branches = get_commit_metadata_max_branches(repo)?;
         Ok(BranchesProvider {
             is_enabled,
+            main_branch_names,
+            remote_names,
+            max_branches,
             branch_oid_to_names,
         })
     }
+
+    /// Sort the branch names for display: the main branches first, in the
+    /// order that they were configured, and then the others alphabetically.
+    fn sort_branch_names<'b>(&self, branch_names: &'b HashSet<String>) -> Vec<&'b str> {
+        let mut branch_names: Vec<&str> = branch_names
+            .iter()
+            .map(|branch_name| branch_name.as_ref())
+            .collect();
+        branch_names.sort_unstable_by_key(|branch_name| {
+            let main_branch_index = self
+                .main_branch_names
+                .iter()
+                .position(|main_branch_name| main_branch_name == branch_name)
+                .unwrap_or(self.main_branch_names.len());
+            (main_branch_index, *branch_name)
+        });
+        branch_names
+    }
+
+    /// Determine whether the branch name refers to a remote branch, i.e. it's
+    /// prefixed with the name of a remote.
+    fn is_remote_branch_name(&self, branch_name: &str) -> bool {
+        self.remote_names.iter().any(|remote_name| {
+            branch_name
+                .strip_prefix(remote_name.as_str())
+                .map(|rest| rest.starts_with('/'))
+                .unwrap_or(false)
+        })
+    }
 }
 
 impl<'a> CommitMetadataProvider for BranchesProvider<'a> {
@@ -182,28 +330,200 @@ impl<'a> CommitMetadataProvider for BranchesProvider<'a> {
             return Ok(None);
         }
 
-        let branch_names: HashSet<&str> = match self.branch_oid_to_names.get(&commit.id()) {
-            Some(branch_names) => branch_names
-                .iter()
-                .map(|branch_name| branch_name.as_ref())
-                .collect(),
-            None => HashSet::new(),
+        let branch_names = match self.branch_oid_to_names.get(&commit.id()) {
+            Some(branch_names) if !branch_names.is_empty() => self.sort_branch_names(branch_names),
+            _ => return Ok(None),
+        };
+
+        let num_shown = match self.max_branches {
+            Some(max_branches) => max_branches.min(branch_names.len()),
+            None => branch_names.len(),
+        };
+        let local_color = BaseColor::Green.light();
+        let mut result = StyledStringBuilder::new().append_styled("(", local_color);
+        for (i, branch_name) in branch_names[..num_shown].iter().enumerate() {
+            if i > 0 {
+                result = result.append_styled(", ", local_color);
+            }
+            let color = if self.is_remote_branch_name(branch_name) {
+                BaseColor::Green.dark()
+            } else {
+                local_color
+            };
+            result = result.append_styled(*branch_name, color);
+        }
+        let num_hidden = branch_names.len() - num_shown;
+        if num_hidden > 0 {
+            result = result.append_styled(format!(", +{} more", num_hidden), local_color);
+        }
+        let result = result.append_styled(")", local_color).build();
+        Ok(Some(result))
+    }
+}
+
+/// Display how many commits a branch is ahead of and behind the main branch.
+///
+/// "Ahead" is the number of commits on the branch since its merge-base with the
+/// main branch, and "behind" is the number of main branch commits since that
+/// merge-base. A large "behind" count indicates that the branch is stale and
+/// may need to be restacked onto the main branch. Nothing is shown for the
+/// heads of the main branches themselves.
+pub struct AheadBehindProvider<'a> {
+    is_enabled: bool,
+    repo: &'a git2::Repository,
+    main_branch_oid: git2::Oid,
+    main_branch_head_oids: HashSet<git2::Oid>,
+    branch_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
+}
+
+impl<'a> AheadBehindProvider<'a> {
+    /// Constructor.
+    pub fn new(
+        repo: &'a git2::Repository,
+        main_branch_oid: &MainBranchOid,
+        branch_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
+    ) -> anyhow::Result<Self> {
+        let is_enabled = get_commit_metadata_ahead_behind(repo)?;
+        let MainBranchOid(main_branch_oid) = main_branch_oid;
+        let main_branch_head_oids = if is_enabled {
+            let mut main_branch_head_oids: HashSet<git2::Oid> =
+                get_additional_main_branch_oids(repo)?.into_iter().collect();
+            main_branch_head_oids.insert(*main_branch_oid);
+            main_branch_head_oids
+        } else {
+            HashSet::new()
         };
+        Ok(AheadBehindProvider {
+            is_enabled,
+            repo,
+            main_branch_oid: *main_branch_oid,
+            main_branch_head_oids,
+            branch_oid_to_names,
+        })
+    }
+}
 
-        if branch_names.is_empty() {
+impl<'a> CommitMetadataProvider for AheadBehindProvider<'a> {
+    #[context("Providing ahead/behind metadata for commit {:?}", commit.id())]
+    fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
+        if !self.is_enabled
+            || !self.branch_oid_to_names.contains_key(&commit.id())
+            || self.main_branch_head_oids.contains(&commit.id())
+        {
+            return Ok(None);
+        }
+
+        let (ahead, behind) = match self
+            .repo
+            .graph_ahead_behind(commit.id(), self.main_branch_oid)
+        {
+            Ok(ahead_behind) => ahead_behind,
+            // In a shallow clone, the counts can't be computed if the
+            // merge-base is beyond the shallow boundary.
+            Err(_) if self.repo.is_shallow() => return Ok(None),
+            Err(err) => return Err(wrap_git_error(err)),
+        };
+        let counts: Vec<String> = [("↑", ahead), ("↓", behind)]
+            .iter()
+            .filter(|(_arrow, count)| *count > 0)
+            .map(|(arrow, count)| format!("{}{}", arrow, count))
+            .collect();
+        if counts.is_empty() {
             Ok(None)
         } else {
-            let mut branch_names: Vec<&str> = branch_names.into_iter().collect();
-            branch_names.sort_unstable();
-            let result = StyledString::styled(
-                format!("({})", branch_names.join(", ")),
-                BaseColor::Green.light(),
-            );
+            let result =
+                StyledString::styled(format!("[{}]", counts.join(" ")), BaseColor::Cyan.light());
             Ok(Some(result))
         }
     }
 }
 
+/// Display tags that point to a given commit.
+pub struct TagsProvider<'a> {
+    is_enabled: bool,
+    tag_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
+}
+
+impl<'a> TagsProvider<'a> {
+    /// Constructor.
+    pub fn new(
+        repo: &git2::Repository,
+        tag_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
+    ) -> anyhow::Result<Self> {
+        let is_enabled = get_commit_metadata_tags(repo)?;
+        Ok(TagsProvider {
+            is_enabled,
+            tag_oid_to_names,
+        })
+    }
+}
+
+impl<'a> CommitMetadataProvider for TagsProvider<'a> {
+    #[context("Providing tag metadata for commit {:?}", commit.id())]
+    fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+
+        let mut tag_names: Vec<&str> = match self.tag_oid_to_names.get(&commit.id()) {
+            Some(tag_names) => tag_names.iter().map(|tag_name| tag_name.as_ref()).collect(),
+            None => return Ok(None),
+        };
+        if tag_names.is_empty() {
+            return Ok(None);
+        }
+
+        tag_names.sort_unstable();
+        let tag_names: Vec<String> = tag_names
+            .into_iter()
+            .map(|tag_name| format!("tag: {}", tag_name))
+            .collect();
+        let result = StyledString::styled(
+            format!("({})", tag_names.join(", ")),
+            BaseColor::Yellow.light(),
+        );
+        Ok(Some(result))
+    }
+}
+
+/// Display the names of stash entries, such as `stash@{0}`, for commits which
+/// are stashes.
+pub struct StashesProvider<'a> {
+    stash_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
+}
+
+impl<'a> StashesProvider<'a> {
+    /// Constructor.
+    pub fn new(
+        stash_oid_to_names: &'a HashMap<git2::Oid, HashSet<String>>,
+    ) -> anyhow::Result<Self> {
+        Ok(StashesProvider { stash_oid_to_names })
+    }
+}
+
+impl<'a> CommitMetadataProvider for StashesProvider<'a> {
+    #[context("Providing stash metadata for commit {:?}", commit.id())]
+    fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
+        let mut stash_names: Vec<&str> = match self.stash_oid_to_names.get(&commit.id()) {
+            Some(stash_names) => stash_names
+                .iter()
+                .map(|stash_name| stash_name.as_ref())
+                .collect(),
+            None => return Ok(None),
+        };
+        if stash_names.is_empty() {
+            return Ok(None);
+        }
+
+        stash_names.sort_unstable();
+        let result = StyledString::styled(
+            format!("({})", stash_names.join(", ")),
+            BaseColor::Magenta.light(),
+        );
+        Ok(Some(result))
+    }
+}
+
 /// Display the associated Phabricator revision for a given commit.
 pub struct DifferentialRevisionProvider {
     is_enabled: bool,
@@ -257,6 +577,49 @@ impl CommitMetadataProvider for DifferentialRevisionProvider {
     }
 }
 
+/// Display a summary of how many files and lines the commit changed, such as
+/// `(3 files, +40 -12)`. See `get_diff_stats`.
+pub struct DiffStatProvider {
+    diff_stats: HashMap<git2::Oid, DiffStat>,
+}
+
+impl DiffStatProvider {
+    /// Constructor. Diffstats are only shown for the commits in `diff_stats`.
+    pub fn new(diff_stats: HashMap<git2::Oid, DiffStat>) -> anyhow::Result<Self> {
+        Ok(DiffStatProvider { diff_stats })
+    }
+}
+
+impl CommitMetadataProvider for DiffStatProvider {
+    #[context("Providing diffstat metadata for commit {:?}", commit.id())]
+    fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
+        let DiffStat {
+            files_changed,
+            insertions,
+            deletions,
+        } = match self.diff_stats.get(&commit.id()) {
+            Some(diff_stat) => *diff_stat,
+            None => return Ok(None),
+        };
+        let result = StyledStringBuilder::new()
+            .append_plain(format!(
+                "({}, ",
+                Pluralize {
+                    amount: files_changed.try_into()?,
+                    singular: "file",
+                    plural: "files",
+                }
+                .to_string()
+            ))
+            .append_styled(format!("+{}", insertions), BaseColor::Green.dark())
+            .append_plain(" ")
+            .append_styled(format!("-{}", deletions), BaseColor::Red.dark())
+            .append_plain(")")
+            .build();
+        Ok(Some(result))
+    }
+}
+
 /// Display how long ago the given commit was committed.
 pub struct RelativeTimeProvider {
     is_enabled: bool,
@@ -329,12 +692,320 @@ impl CommitMetadataProvider for RelativeTimeProvider {
     }
 }
 
+/// Display whether the working copy has uncommitted changes, next to the
+/// current commit.
+pub struct WorkingCopyStatusProvider {
+    head_oid: Option<git2::Oid>,
+    is_dirty: bool,
+}
+
+impl WorkingCopyStatusProvider {
+    /// Constructor.
+    ///
+    /// Untracked files are not considered to be uncommitted changes. If the
+    /// status of the working copy can't be determined (such as in a bare
+    /// repository), then it's treated as clean.
+    pub fn new(repo: &git2::Repository, head_oid: Option<git2::Oid>) -> anyhow::Result<Self> {
+        let is_enabled = get_commit_metadata_working_copy_status(repo)?;
+        let is_dirty = is_enabled
+            && head_oid.is_some()
+            && !repo.is_bare()
+            && repo
+                .statuses(Some(
+                    git2::StatusOptions::new()
+                        .include_untracked(false)
+                        .include_ignored(false)
+                        .exclude_submodules(true),
+                ))
+                .map(|statuses| {
+                    statuses
+                        .iter()
+                        .any(|entry| entry.status() != git2::Status::CURRENT)
+                })
+                .unwrap_or(false);
+        Ok(WorkingCopyStatusProvider { head_oid, is_dirty })
+    }
+
+    /// Whether or not the working copy has uncommitted changes which will be
+    /// displayed.
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty
+    }
+}
+
+impl CommitMetadataProvider for WorkingCopyStatusProvider {
+    #[context("Providing working copy status metadata for commit {:?}", commit.id())]
+    fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
+        if !self.is_dirty || self.head_oid != Some(commit.id()) {
+            return Ok(None);
+        }
+
+        let result = StyledString::styled("(dirty)", BaseColor::Red.light());
+        Ok(Some(result))
+    }
+}
+
+/// The status of a commit's tests or CI checks, as recorded in a note
+/// attached to the commit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommitStatus {
+    /// The checks passed.
+    Success,
+
+    /// The checks failed.
+    Failure,
+
+    /// The checks haven't finished yet, or the note couldn't be interpreted.
+    Pending,
+}
+
+impl CommitStatus {
+    /// Interpret the contents of a status note. Only the first word of the
+    /// note is considered, case-insensitively and ignoring punctuation:
+    ///
+    /// * `success`, `pass`, `passed`, or `ok` indicate success.
+    /// * `failure`, `fail`, `failed`, or `error` indicate failure.
+    /// * Anything else is treated as pending.
+    pub fn from_note_message(message: &str) -> Self {
+        let status = message
+            .split(|c: char| !c.is_alphanumeric())
+            .find(|word| !word.is_empty())
+            .unwrap_or_default()
+            .to_lowercase();
+        match status.as_str() {
+            "success" | "pass" | "passed" | "ok" => CommitStatus::Success,
+            "failure" | "fail" | "failed" | "error" => CommitStatus::Failure,
+            _ => CommitStatus::Pending,
+        }
+    }
+}
+
+/// Display the test or CI status of each commit, as recorded in the notes
+/// reference configured with `branchless.smartlog.statusNotesRef`, such as the
+/// results of `git branchless test`. Commits without a note have no status
+/// displayed.
+pub struct CommitStatusProvider<'a> {
+    glyphs: &'a Glyphs,
+    repo: &'a git2::Repository,
+    notes_ref: Option<String>,
+}
+
+impl<'a> CommitStatusProvider<'a> {
+    /// Constructor.
+    pub fn new(glyphs: &'a Glyphs, repo: &'a git2::Repository) -> anyhow::Result<Self> {
+        let notes_ref = get_smartlog_status_notes_ref(repo)?;
+        Ok(CommitStatusProvider {
+            glyphs,
+            repo,
+            notes_ref,
+        })
+    }
+}
+
+impl<'a> CommitMetadataProvider for CommitStatusProvider<'a> {
+    #[context("Providing commit status metadata for commit {:?}", commit.id())]
+    fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
+        let notes_ref = match &self.notes_ref {
+            Some(notes_ref) => notes_ref,
+            None => return Ok(None),
+        };
+        let note = match self.repo.find_note(Some(notes_ref.as_str()), commit.id()) {
+            Ok(note) => note,
+            Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(wrap_git_error(err)),
+        };
+
+        let result = match CommitStatus::from_note_message(note.message().unwrap_or_default()) {
+            CommitStatus::Success => {
+                StyledString::styled(self.glyphs.commit_status_success, BaseColor::Green.light())
+            }
+            CommitStatus::Failure => {
+                StyledString::styled(self.glyphs.commit_status_failure, BaseColor::Red.light())
+            }
+            CommitStatus::Pending => {
+                StyledString::styled(self.glyphs.commit_status_pending, BaseColor::Yellow.light())
+            }
+        };
+        Ok(Some(result))
+    }
+}
+
+/// An operation which Git has started but not yet completed, such as a rebase
+/// which stopped due to a merge conflict.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperationState {
+    /// A description of the operation, such as `Rebase in progress (3/7)`.
+    pub description: String,
+
+    /// The commit which is currently being applied, if known.
+    pub current_oid: Option<git2::Oid>,
+
+    /// The commit which commits are being applied on top of, if known.
+    pub onto_oid: Option<git2::Oid>,
+}
+
+fn read_operation_state_file(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+        .filter(|contents| !contents.is_empty())
+}
+
+fn resolve_operation_state_oid(repo: &git2::Repository, spec: Option<String>) -> Option<git2::Oid> {
+    let spec = spec?;
+    let object = repo.revparse_single(&spec).ok()?;
+    Some(object.id())
+}
+
+/// Detect whether a rebase, `git am`, or cherry-pick is in progress in the
+/// repository. This only reads Git's state files, so it doesn't interfere with
+/// the operation.
+///
+/// Args:
+/// * `repo`: The Git repository.
+///
+/// Returns: The state of the in-progress operation, or `None` if there isn't
+/// one (or if its state couldn't be read).
+pub fn get_operation_state(repo: &git2::Repository) -> Option<OperationState> {
+    let describe =
+        |name: &str, step: Option<String>, num_steps: Option<String>| match (step, num_steps) {
+            (Some(step), Some(num_steps)) => {
+                format!("{} in progress ({}/{})", name, step, num_steps)
+            }
+            _ => format!("{} in progress", name),
+        };
+
+    let rebase_merge_dir = repo.path().join("rebase-merge");
+    if rebase_merge_dir.is_dir() {
+        let current_oid =
+            read_operation_state_file(&rebase_merge_dir.join("stopped-sha")).or_else(|| {
+                // The last picked commit is the one being applied.
+                let done = read_operation_state_file(&rebase_merge_dir.join("done"))?;
+                let last_line = done.lines().last()?;
+                last_line.split_whitespace().nth(1).map(String::from)
+            });
+        return Some(OperationState {
+            description: describe(
+                "Rebase",
+                read_operation_state_file(&rebase_merge_dir.join("msgnum")),
+                read_operation_state_file(&rebase_merge_dir.join("end")),
+            ),
+            current_oid: resolve_operation_state_oid(repo, current_oid),
+            onto_oid: resolve_operation_state_oid(
+                repo,
+                read_operation_state_file(&rebase_merge_dir.join("onto")),
+            ),
+        });
+    }
+
+    let rebase_apply_dir = repo.path().join("rebase-apply");
+    if rebase_apply_dir.is_dir() {
+        let name = if rebase_apply_dir.join("applying").exists() {
+            "Patch application"
+        } else {
+            "Rebase"
+        };
+        return Some(OperationState {
+            description: describe(
+                name,
+                read_operation_state_file(&rebase_apply_dir.join("next")),
+                read_operation_state_file(&rebase_apply_dir.join("last")),
+            ),
+            current_oid: resolve_operation_state_oid(
+                repo,
+                read_operation_state_file(&rebase_apply_dir.join("original-commit")),
+            ),
+            onto_oid: resolve_operation_state_oid(
+                repo,
+                read_operation_state_file(&rebase_apply_dir.join("onto")),
+            ),
+        });
+    }
+
+    let cherry_pick_head = read_operation_state_file(&repo.path().join("CHERRY_PICK_HEAD"));
+    if cherry_pick_head.is_some() {
+        return Some(OperationState {
+            description: describe("Cherry-pick", None, None),
+            current_oid: resolve_operation_state_oid(repo, cherry_pick_head),
+            onto_oid: None,
+        });
+    }
+
+    None
+}
+
+/// Display which commits are involved in an in-progress operation, such as a
+/// rebase. See `get_operation_state`.
+pub struct OperationStateProvider<'a> {
+    operation_state: &'a Option<OperationState>,
+}
+
+impl<'a> OperationStateProvider<'a> {
+    /// Constructor.
+    pub fn new(operation_state: &'a Option<OperationState>) -> anyhow::Result<Self> {
+        Ok(OperationStateProvider { operation_state })
+    }
+}
+
+impl<'a> CommitMetadataProvider for OperationStateProvider<'a> {
+    #[context("Providing operation state metadata for commit {:?}", commit.id())]
+    fn describe_commit(&mut self, commit: &git2::Commit) -> anyhow::Result<Option<StyledString>> {
+        let operation_state = match self.operation_state {
+            Some(operation_state) => operation_state,
+            None => return Ok(None),
+        };
+
+        let description = if operation_state.current_oid == Some(commit.id()) {
+            "(applying)"
+        } else if operation_state.onto_oid == Some(commit.id()) {
+            "(onto)"
+        } else {
+            return Ok(None);
+        };
+        let result = StyledString::styled(description, BaseColor::Yellow.light());
+        Ok(Some(result))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::ops::Sub;
 
     use super::*;
 
+    #[test]
+    fn test_is_wip_summary() {
+        let wip_prefixes: Vec<String> = vec!["fixup!".to_string(), "WIP".to_string()];
+        assert!(is_wip_summary("fixup! create test1.txt", &wip_prefixes));
+        assert!(is_wip_summary("WIP: create test1.txt", &wip_prefixes));
+        assert!(is_wip_summary("WIP", &wip_prefixes));
+        assert!(!is_wip_summary("WIPE create test1.txt", &wip_prefixes));
+        assert!(!is_wip_summary("create test1.txt (WIP)", &wip_prefixes));
+        assert!(!is_wip_summary("squash! create test1.txt", &wip_prefixes));
+        assert!(!is_wip_summary("fixup! create test1.txt", &[]));
+    }
+
+    #[test]
+    fn test_commit_status_from_note_message() {
+        assert_eq!(
+            CommitStatus::from_note_message("success"),
+            CommitStatus::Success
+        );
+        assert_eq!(
+            CommitStatus::from_note_message("  OK\nAll 12 checks passed"),
+            CommitStatus::Success
+        );
+        assert_eq!(
+            CommitStatus::from_note_message("FAILED: 3 tests"),
+            CommitStatus::Failure
+        );
+        assert_eq!(
+            CommitStatus::from_note_message("running"),
+            CommitStatus::Pending
+        );
+        assert_eq!(CommitStatus::from_note_message(""), CommitStatus::Pending);
+    }
+
     #[test]
     fn test_extract_diff_number() -> anyhow::Result<()> {
         let message = "\
@@ -0,0 +1,216 @@
+//! Persistent storage to cache patch IDs.
+//!
+//! A patch ID is a hash of the changes between two trees which doesn't depend
+//! on line numbers, in the same way as `git patch-id`. Patch IDs are used to
+//! find commits whose changes have already been applied elsewhere, which means
+//! computing one for every commit in a range of the main branch. Computing a
+//! patch ID requires diffing the two trees, so they're cached by the OIDs of
+//! the trees being compared.
+
+use anyhow::Context;
+use fn_error_context::context;
+use rusqlite::OptionalExtension;
+
+use crate::core::diffstat::EMPTY_TREE_OID;
+use crate::core::timing::record_count;
+
+/// On-disk cache for patch IDs, keyed by the OIDs of the trees being compared.
+pub struct PatchIdDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+#[context("Initializing tables for `PatchIdDb`")]
+fn init_tables(conn: &rusqlite::Connection) -> anyhow::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS patch_ids (
+    old_tree_oid TEXT NOT NULL,
+    new_tree_oid TEXT NOT NULL,
+    patch_id TEXT,
+    UNIQUE (old_tree_oid, new_tree_oid)
+)
+",
+        rusqlite::params![],
+    )
+    .context("Creating tables")?;
+    Ok(())
+}
+
+impl<'conn> PatchIdDb<'conn> {
+    /// Constructor.
+    #[context("Constructing `PatchIdDb`")]
+    pub fn new(conn: &'conn rusqlite::Connection) -> anyhow::Result<Self> {
+        init_tables(conn).context("Initializing tables")?;
+        Ok(PatchIdDb { conn })
+    }
+
+    /// Get the cached patch ID of the changes between the two given trees.
+    ///
+    /// Returns: `None` if the patch ID hasn't been computed before. Otherwise,
+    /// returns the cached result, which may itself be `None` if the trees are
+    /// the same.
+    #[context(
+        "Querying cached patch ID for trees {:?} and {:?}",
+        old_tree_oid,
+        new_tree_oid
+    )]
+    pub fn get_cached_patch_id(
+        &self,
+        old_tree_oid: &str,
+        new_tree_oid: &str,
+    ) -> anyhow::Result<Option<Option<git2::Oid>>> {
+        let patch_id: Option<Option<String>> = self
+            .conn
+            .query_row_named(
+                "
+SELECT patch_id
+FROM patch_ids
+WHERE old_tree_oid = :old_tree_oid
+  AND new_tree_oid = :new_tree_oid
+",
+                rusqlite::named_params! {
+                    ":old_tree_oid": old_tree_oid,
+                    ":new_tree_oid": new_tree_oid,
+                },
+                |row| row.get("patch_id"),
+            )
+            .optional()
+            .context("Querying patch ID DB")?;
+
+        record_count(if patch_id.is_some() {
+            "PatchIdDb cache hits"
+        } else {
+            "PatchIdDb cache misses"
+        });
+        match patch_id {
+            Some(Some(patch_id)) => {
+                let patch_id = git2::Oid::from_str(&patch_id).context("Parsing patch ID")?;
+                Ok(Some(Some(patch_id)))
+            }
+            Some(None) => Ok(Some(None)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the patch ID of the changes between the two given trees in the
+    /// cache.
+    #[context("Caching patch ID for trees {:?} and {:?}", old_tree_oid, new_tree_oid)]
+    pub fn cache_patch_id(
+        &self,
+        old_tree_oid: &str,
+        new_tree_oid: &str,
+        patch_id: Option<git2::Oid>,
+    ) -> anyhow::Result<()> {
+        self.conn
+            .execute_named(
+                "
+INSERT OR REPLACE INTO patch_ids
+VALUES (:old_tree_oid, :new_tree_oid, :patch_id)
+",
+                rusqlite::named_params! {
+                    ":old_tree_oid": old_tree_oid,
+                    ":new_tree_oid": new_tree_oid,
+                    ":patch_id": patch_id.map(|patch_id| patch_id.to_string()),
+                },
+            )
+            .context("Caching patch ID")?;
+        Ok(())
+    }
+}
+
+/// Get the patch ID of the changes between the two given trees, i.e. a hash of
+/// the diff which doesn't depend on line numbers. An `old_tree` of `None`
+/// stands for the empty tree.
+///
+/// Returns: The patch ID, or `None` if the trees are the same.
+pub fn get_diff_patch_id(
+    repo: &git2::Repository,
+    patch_id_db: &PatchIdDb,
+    old_tree: Option<&git2::Tree>,
+    new_tree: &git2::Tree,
+) -> anyhow::Result<Option<git2::Oid>> {
+    let old_tree_oid = match old_tree {
+        Some(old_tree) => old_tree.id().to_string(),
+        None => EMPTY_TREE_OID.to_string(),
+    };
+    let new_tree_oid = new_tree.id().to_string();
+    if let Some(patch_id) = patch_id_db.get_cached_patch_id(&old_tree_oid, &new_tree_oid)? {
+        return Ok(patch_id);
+    }
+
+    let diff = repo.diff_tree_to_tree(old_tree, Some(new_tree), None)?;
+    let patch_id = if diff.deltas().len() == 0 {
+        None
+    } else {
+        Some(diff.patchid(None)?)
+    };
+    patch_id_db.cache_patch_id(&old_tree_oid, &new_tree_oid, patch_id)?;
+    Ok(patch_id)
+}
+
+/// Get the patch ID of the given commit, i.e. a hash of the changes it
+/// introduces relative to its parent, which stays the same when the commit is
+/// cherry-picked or rebased without conflicts.
+///
+/// Returns: The patch ID, or `None` if the commit is a merge commit or doesn't
+/// introduce any changes.
+#[context("Computing patch ID for commit {:?}", commit.id())]
+pub fn get_patch_id(
+    repo: &git2::Repository,
+    patch_id_db: &PatchIdDb,
+    commit: &git2::Commit,
+) -> anyhow::Result<Option<git2::Oid>> {
+    let parent_tree = match commit.parent_count() {
+        0 => None,
+        1 => Some(commit.parent(0)?.tree()?),
+        _ => return Ok(None),
+    };
+    get_diff_patch_id(repo, patch_id_db, parent_tree.as_ref(), &commit.tree()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::with_git;
+    use crate::util::get_db_conn;
+
+    #[test]
+    fn test_patch_id_is_cached() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            let test1_oid = git.commit_file("test1", 1)?;
+            git.run(&["commit", "--allow-empty", "-m", "empty commit"])?;
+
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let patch_id_db = PatchIdDb::new(&conn)?;
+
+            let commit = repo.find_commit(test1_oid)?;
+            let old_tree_oid = commit.parent(0)?.tree_id().to_string();
+            let new_tree_oid = commit.tree_id().to_string();
+            assert_eq!(
+                patch_id_db.get_cached_patch_id(&old_tree_oid, &new_tree_oid)?,
+                None
+            );
+            let patch_id = get_patch_id(&repo, &patch_id_db, &commit)?;
+            assert!(patch_id.is_some());
+            assert_eq!(
+                patch_id_db.get_cached_patch_id(&old_tree_oid, &new_tree_oid)?,
+                Some(patch_id)
+            );
+
+            // Commits which don't change anything have no patch ID, which is
+            // cached as well.
+            let empty_commit = repo.head()?.peel_to_commit()?;
+            let tree_oid = empty_commit.tree_id().to_string();
+            assert_eq!(get_patch_id(&repo, &patch_id_db, &empty_commit)?, None);
+            assert_eq!(
+                patch_id_db.get_cached_patch_id(&tree_oid, &tree_oid)?,
+                Some(None)
+            );
+
+            Ok(())
+        })
+    }
+}
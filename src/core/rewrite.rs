@@ -2,6 +2,10 @@
 //! specifics on commit rewriting.
 
 use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
 
 use anyhow::Context;
 use cursive::utils::markup::StyledString;
@@ -10,14 +14,23 @@ use indicatif::{ProgressBar, ProgressStyle};
 
 use crate::core::formatting::printable_styled_string;
 use crate::util::{
-    get_branch_oid_to_names, get_repo_head, run_git, run_hook, wrap_git_error, GitExecutable,
+    checkout_commit_into_dir, get_branch_oid_to_names, get_committer_signature, get_repo_head,
+    get_sh, run_git, run_hook, wrap_git_error, GitExecutable, EXIT_CODE_BAD_ARGUMENTS,
+    EXIT_CODE_CONFLICT,
 };
 
-use super::eventlog::{Event, EventCursor, EventReplayer, EventTransactionId};
+use super::config::{
+    get_rewrite_empty_commits, get_rewrite_preserve_dates, get_rewrite_sign, EmptyCommitPolicy,
+};
+use super::eventlog::{
+    Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId, BACKUP_REF_PREFIX,
+};
 use super::formatting::Glyphs;
-use super::graph::{find_path_to_merge_base, CommitGraph, MainBranchOid};
+use super::graph::{find_path_to_merge_base, get_sorted_children, CommitGraph, MainBranchOid};
 use super::mergebase::MergeBaseDb;
 use super::metadata::{render_commit_metadata, CommitMessageProvider, CommitOidProvider};
+use super::patchid::{get_diff_patch_id, get_patch_id, PatchIdDb};
+use super::sign::{create_commit, CommitSigner};
 
 /// For a rewritten commit, find the newest version of the commit.
 ///
@@ -60,7 +73,8 @@ pub fn find_rewrite_target(
         Event::RefUpdateEvent { .. }
         | Event::CommitEvent { .. }
         | Event::HideEvent { .. }
-        | Event::UnhideEvent { .. } => None,
+        | Event::UnhideEvent { .. }
+        | Event::MoveEvent { .. } => None,
     }
 }
 
@@ -121,16 +135,62 @@ fn make_label_name(repo: &git2::Repository, mut preferred_name: String) -> anyho
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 enum RebaseCommand {
     Label { label_name: String },
     Reset { label_name: String },
     Pick { commit_oid: git2::Oid },
+    Fixup { commit_oid: git2::Oid },
+    Drop { commit_oid: git2::Oid },
+    // Like `Drop`, but for a commit whose changes have already been applied to
+    // the destination, so it's considered to have been rewritten into it.
+    SkipApplied { commit_oid: git2::Oid },
+}
+
+impl RebaseCommand {
+    /// If this command applies (or drops) a commit, get the commit and what's
+    /// done with it.
+    fn get_pick(&self) -> Option<(git2::Oid, PickAction)> {
+        match self {
+            RebaseCommand::Label { .. }
+            | RebaseCommand::Reset { .. }
+            | RebaseCommand::SkipApplied { .. } => None,
+            RebaseCommand::Pick { commit_oid } => Some((*commit_oid, PickAction::Pick)),
+            RebaseCommand::Fixup { commit_oid } => Some((*commit_oid, PickAction::Fixup)),
+            RebaseCommand::Drop { commit_oid } => Some((*commit_oid, PickAction::Drop)),
+        }
+    }
+}
+
+/// What to do with a commit in a rebase plan. See `RebasePlan::set_pick_action`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickAction {
+    /// Apply the commit on top of the previous one.
+    Pick,
+
+    /// Squash the commit into the previous one, keeping the previous commit's
+    /// message.
+    Fixup,
+
+    /// Don't apply the commit at all. Its descendants are applied on top of
+    /// its parent instead.
+    Drop,
+}
+
+impl std::fmt::Display for PickAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self {
+            PickAction::Pick => "pick",
+            PickAction::Fixup => "fixup",
+            PickAction::Drop => "drop",
+        };
+        f.pad(action)
+    }
 }
 
 /// Represents a sequence of commands that can be executed to carry out a rebase
 /// operation.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct RebasePlan {
     commands: Vec<RebaseCommand>,
 }
@@ -141,7 +201,117 @@ impl ToString for RebaseCommand {
             RebaseCommand::Label { label_name } => format!("label {}", label_name),
             RebaseCommand::Reset { label_name } => format!("reset {}", label_name),
             RebaseCommand::Pick { commit_oid } => format!("pick {}", commit_oid),
+            RebaseCommand::Fixup { commit_oid } => format!("fixup {}", commit_oid),
+            RebaseCommand::Drop { commit_oid } | RebaseCommand::SkipApplied { commit_oid } => {
+                format!("drop {}", commit_oid)
+            }
+        }
+    }
+}
+
+impl RebasePlan {
+    /// Get the commits which the plan applies, in the order that they're
+    /// applied, along with what's done with each of them. The indexes into
+    /// this list are used to edit the plan.
+    pub fn get_picks(&self) -> Vec<(git2::Oid, PickAction)> {
+        self.commands
+            .iter()
+            .filter_map(|command| command.get_pick())
+            .collect()
+    }
+
+    /// Get the commits which the plan drops (see `PickAction::Drop`).
+    pub fn get_dropped_oids(&self) -> Vec<git2::Oid> {
+        self.get_picks()
+            .into_iter()
+            .filter_map(|(commit_oid, action)| match action {
+                PickAction::Drop => Some(commit_oid),
+                PickAction::Pick | PickAction::Fixup => None,
+            })
+            .collect()
+    }
+
+    fn get_command_index(&self, pick_index: usize) -> Option<usize> {
+        self.commands
+            .iter()
+            .enumerate()
+            .filter(|(_, command)| command.get_pick().is_some())
+            .map(|(command_index, _)| command_index)
+            .nth(pick_index)
+    }
+
+    /// Swap the commit at `pick_index` (see `get_picks`) with the next one.
+    ///
+    /// Commits can only be swapped with their neighbors in the same stack, so
+    /// that the shape of the rebased commit graph is unchanged. Otherwise, the
+    /// plan is left as-is.
+    ///
+    /// Returns: Whether or not the commits were swapped.
+    pub fn swap_with_next_pick(&mut self, pick_index: usize) -> bool {
+        let command_index = match self.get_command_index(pick_index) {
+            Some(command_index) => command_index,
+            None => return false,
+        };
+        let is_swappable = match self.commands.get(command_index + 1) {
+            Some(next_command) => next_command.get_pick().is_some(),
+            None => false,
+        };
+        if !is_swappable {
+            return false;
+        }
+
+        let mut plan = self.clone();
+        plan.commands.swap(command_index, command_index + 1);
+        if !plan.are_fixups_valid() {
+            return false;
+        }
+        *self = plan;
+        true
+    }
+
+    /// Set what's done with the commit at `pick_index` (see `get_picks`).
+    ///
+    /// A commit can only be fixed up into the commit before it in the same
+    /// stack, and not into a dropped commit. Otherwise, the plan is left as-is.
+    ///
+    /// Returns: Whether or not the action was set.
+    pub fn set_pick_action(&mut self, pick_index: usize, action: PickAction) -> bool {
+        let command_index = match self.get_command_index(pick_index) {
+            Some(command_index) => command_index,
+            None => return false,
+        };
+        let commit_oid = match self.commands[command_index].get_pick() {
+            Some((commit_oid, _)) => commit_oid,
+            None => return false,
+        };
+
+        let mut plan = self.clone();
+        plan.commands[command_index] = match action {
+            PickAction::Pick => RebaseCommand::Pick { commit_oid },
+            PickAction::Fixup => RebaseCommand::Fixup { commit_oid },
+            PickAction::Drop => RebaseCommand::Drop { commit_oid },
+        };
+        if !plan.are_fixups_valid() {
+            return false;
+        }
+        *self = plan;
+        true
+    }
+
+    /// Each fixup has to directly follow a pick or another fixup, so that
+    /// there's a rebased commit to squash it into.
+    fn are_fixups_valid(&self) -> bool {
+        let mut previous_command: Option<&RebaseCommand> = None;
+        for command in self.commands.iter() {
+            if let RebaseCommand::Fixup { .. } = command {
+                match previous_command {
+                    Some(RebaseCommand::Pick { .. }) | Some(RebaseCommand::Fixup { .. }) => {}
+                    _ => return false,
+                }
+            }
+            previous_command = Some(command);
         }
+        true
     }
 }
 
@@ -158,22 +328,14 @@ fn make_rebase_plan_for_current_commit(
         });
         acc
     };
-    let current_node = match graph.get(&current_oid) {
-        Some(current_node) => current_node,
-        None => {
-            anyhow::bail!(format!(
-                "BUG: commit {} could not be found in the commit graph",
-                current_oid.to_string()
-            ))
-        }
-    };
+    if !graph.contains_key(&current_oid) {
+        anyhow::bail!(format!(
+            "BUG: commit {} could not be found in the commit graph",
+            current_oid.to_string()
+        ))
+    }
 
-    let children = {
-        // Sort for determinism.
-        let mut children = current_node.children.iter().copied().collect::<Vec<_>>();
-        children.sort_by_key(|child_oid| (graph[child_oid].commit.time(), child_oid.to_string()));
-        children
-    };
+    let children = get_sorted_children(graph, current_oid);
     match children.as_slice() {
         [] => Ok(acc),
         [only_child_oid] => {
@@ -205,14 +367,108 @@ fn make_rebase_plan_for_current_commit(
     }
 }
 
+/// Find the picked commits in the rebase plan whose changes have already been
+/// applied to `dest_oid`, such as when they were cherry-picked or squash-merged
+/// into the main branch. Picking them again would produce an empty commit or a
+/// merge conflict.
+///
+/// Only commits which are ancestors of `dest_oid`, but not of any of the picked
+/// commits, are considered as having applied the changes. A picked commit has
+/// been applied if one of them has the same patch ID. To detect squash-merges,
+/// the cumulative diff from the base of the picked commit's stack is also
+/// compared against their patch IDs, in which case the commit's ancestors in
+/// the stack have been applied as well. Trees aren't compared, since two
+/// commits can have the same tree without making the same changes.
+///
+/// Returns: The OIDs of the picked commits which have already been applied.
+#[context("Finding commits already applied to {:?}", dest_oid)]
+fn find_applied_commits(
+    repo: &git2::Repository,
+    patch_id_db: &PatchIdDb,
+    commands: &[RebaseCommand],
+    dest_oid: git2::Oid,
+) -> anyhow::Result<HashSet<git2::Oid>> {
+    let picked_oids: HashSet<git2::Oid> = commands
+        .iter()
+        .filter_map(|command| match command {
+            RebaseCommand::Pick { commit_oid } => Some(*commit_oid),
+            _ => None,
+        })
+        .collect();
+    if picked_oids.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let mut upstream_patch_ids = HashSet::new();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(dest_oid)?;
+    for picked_oid in picked_oids.iter() {
+        revwalk.hide(*picked_oid)?;
+    }
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if let Some(patch_id) = get_patch_id(repo, patch_id_db, &commit)? {
+            upstream_patch_ids.insert(patch_id);
+        }
+    }
+    if upstream_patch_ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let mut result = HashSet::new();
+    for picked_oid in picked_oids.iter() {
+        let commit = repo.find_commit(*picked_oid)?;
+        let patch_id = match get_patch_id(repo, patch_id_db, &commit)? {
+            Some(patch_id) => patch_id,
+            // Empty commits and merge commits are never considered applied.
+            None => continue,
+        };
+        if upstream_patch_ids.contains(&patch_id) {
+            result.insert(*picked_oid);
+            continue;
+        }
+
+        let mut stack_commits = vec![commit.clone()];
+        while let Some(parent) = stack_commits.last().unwrap().parents().next() {
+            if !picked_oids.contains(&parent.id()) {
+                break;
+            }
+            stack_commits.push(parent);
+        }
+        let base_parent_tree = match stack_commits.last().unwrap().parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let is_stack_applied = match get_diff_patch_id(
+            repo,
+            patch_id_db,
+            base_parent_tree.as_ref(),
+            &commit.tree()?,
+        )? {
+            Some(patch_id) => upstream_patch_ids.contains(&patch_id),
+            None => false,
+        };
+        if is_stack_applied {
+            result.extend(stack_commits.iter().map(|commit| commit.id()));
+        }
+    }
+    Ok(result)
+}
+
 /// Generate a sequence of rebase steps that cause the subtree at `source_oid`
-/// to be rebased on top of the commit at `main_branch_oid`.
+/// to be rebased on top of the commit at `dest_oid`.
+///
+/// Unless `branchless.rewrite.emptyCommits` is set to `keep` (see
+/// `get_rewrite_empty_commits`), commits whose changes have already been
+/// applied to `dest_oid` are skipped (see `find_applied_commits`).
 pub fn make_rebase_plan(
     repo: &git2::Repository,
     merge_base_db: &MergeBaseDb,
+    patch_id_db: &PatchIdDb,
     graph: &CommitGraph,
     main_branch_oid: &MainBranchOid,
     source_oid: git2::Oid,
+    dest_oid: git2::Oid,
 ) -> anyhow::Result<RebasePlan> {
     let label_name = make_label_name(&repo, "onto".to_string())?;
     let (source_oid, mut commands) = {
@@ -250,6 +506,22 @@ pub fn make_rebase_plan(
     });
     let commands =
         make_rebase_plan_for_current_commit(repo, graph, source_oid, &label_name, commands)?;
+
+    let commands = match get_rewrite_empty_commits(repo)? {
+        EmptyCommitPolicy::Keep => commands,
+        EmptyCommitPolicy::Drop => {
+            let applied_oids = find_applied_commits(repo, patch_id_db, &commands, dest_oid)?;
+            commands
+                .into_iter()
+                .map(|command| match command {
+                    RebaseCommand::Pick { commit_oid } if applied_oids.contains(&commit_oid) => {
+                        RebaseCommand::SkipApplied { commit_oid }
+                    }
+                    command => command,
+                })
+                .collect()
+        }
+    };
     Ok(RebasePlan { commands })
 }
 
@@ -265,12 +537,33 @@ enum RebaseInMemoryResult {
     },
 }
 
+/// Get the committer signature for a rebased commit. If
+/// `committer_date_is_author_date` is set, then the committer date is replaced
+/// with the author date, as with `git rebase --committer-date-is-author-date`.
+fn make_committer_signature(
+    committer: &git2::Signature,
+    author: &git2::Signature,
+    committer_date_is_author_date: bool,
+) -> anyhow::Result<git2::Signature<'static>> {
+    if !committer_date_is_author_date {
+        return Ok(committer.to_owned());
+    }
+    let name = String::from_utf8_lossy(committer.name_bytes());
+    let email = String::from_utf8_lossy(committer.email_bytes());
+    let signature = git2::Signature::new(&name, &email, &author.when()).map_err(wrap_git_error)?;
+    Ok(signature)
+}
+
 #[context("Rebasing in memory onto to {}", dest_oid.to_string())]
 fn rebase_in_memory(
     glyphs: &Glyphs,
     repo: &git2::Repository,
     rebase_plan: &RebasePlan,
     dest_oid: git2::Oid,
+    committer_signature: Option<&git2::Signature>,
+    committer_date_is_author_date: bool,
+    signer: Option<&CommitSigner>,
+    empty_commits: EmptyCommitPolicy,
 ) -> anyhow::Result<RebaseInMemoryResult> {
     let mut current_oid = dest_oid;
     let mut labels: HashMap<String, git2::Oid> = HashMap::new();
@@ -281,12 +574,15 @@ fn rebase_in_memory(
         .commands
         .iter()
         .filter(|command| match command {
-            RebaseCommand::Label { .. } | RebaseCommand::Reset { .. } => false,
-            RebaseCommand::Pick { .. } => true,
+            RebaseCommand::Label { .. }
+            | RebaseCommand::Reset { .. }
+            | RebaseCommand::Drop { .. }
+            | RebaseCommand::SkipApplied { .. } => false,
+            RebaseCommand::Pick { .. } | RebaseCommand::Fixup { .. } => true,
         })
         .count();
 
-    for command in rebase_plan.commands.iter() {
+    for (command_index, command) in rebase_plan.commands.iter().enumerate() {
         match command {
             RebaseCommand::Label { label_name } => {
                 labels.insert(label_name.clone(), current_oid);
@@ -297,7 +593,22 @@ fn rebase_in_memory(
                     None => anyhow::bail!("BUG: no associated OID for label: {}", label_name),
                 };
             }
-            RebaseCommand::Pick { commit_oid } => {
+            RebaseCommand::Drop { .. } => {}
+            RebaseCommand::SkipApplied { commit_oid } => {
+                // As with commits which become empty, record the skipped
+                // commit as having been rewritten into the commit it would
+                // have been applied onto.
+                rewritten_oids.push((*commit_oid, current_oid));
+                let commit_description =
+                    printable_styled_string(glyphs, friendly_describe_commit(repo, *commit_oid)?)?;
+                let progress = ProgressBar::new_spinner();
+                progress.finish_with_message(format!(
+                    "Skipped commit which was already applied: {}",
+                    commit_description
+                ));
+            }
+            RebaseCommand::Pick { commit_oid } | RebaseCommand::Fixup { commit_oid } => {
+                let is_fixup = matches!(command, RebaseCommand::Fixup { .. });
                 let current_commit = repo
                     .find_commit(current_oid)
                     .with_context(|| format!("Finding current commit by OID: {:?}", current_oid))?;
@@ -321,8 +632,21 @@ fn rebase_in_memory(
                 };
 
                 progress.set_message(format!("Applying patch for commit: {}", commit_description));
-                let mut rebased_index =
-                    repo.cherrypick_commit(&commit_to_apply, &current_commit, 0, None)?;
+                let mut rebased_index = if commit_to_apply.parent_count() == 0 {
+                    // A root commit, such as when grafting an unrelated
+                    // history. As with `git cherry-pick`, its changes are
+                    // taken relative to the empty tree.
+                    let empty_tree_oid = repo.treebuilder(None)?.write()?;
+                    let empty_tree = repo.find_tree(empty_tree_oid)?;
+                    repo.merge_trees(
+                        &empty_tree,
+                        &current_commit.tree()?,
+                        &commit_to_apply.tree()?,
+                        None,
+                    )?
+                } else {
+                    repo.cherrypick_commit(&commit_to_apply, &current_commit, 0, None)?
+                };
 
                 progress.set_message(format!(
                     "Checking for merge conflicts: {}",
@@ -344,25 +668,100 @@ fn rebase_in_memory(
                 let commit_tree = repo
                     .find_tree(commit_tree_oid)
                     .with_context(|| "Looking up freshly-written tree")?;
-                let commit_message = match commit_to_apply.message_raw() {
-                    Some(message) => message,
-                    None => anyhow::bail!(
-                        "Could not decode commit message for commit: {:?}",
-                        commit_oid
-                    ),
-                };
 
+                // Commits which were empty to begin with are kept regardless of
+                // the policy. Commits which are about to have another commit
+                // fixed up into them are also kept, since they won't be empty
+                // afterward.
+                let was_empty = match commit_to_apply.parents().next() {
+                    Some(parent) => parent.tree_id() == commit_to_apply.tree_id(),
+                    None => false,
+                };
+                let is_next_command_fixup = matches!(
+                    rebase_plan.commands.get(command_index + 1),
+                    Some(RebaseCommand::Fixup { .. })
+                );
+                if !is_fixup
+                    && empty_commits == EmptyCommitPolicy::Drop
+                    && commit_tree_oid == current_commit.tree_id()
+                    && !was_empty
+                    && !is_next_command_fixup
+                {
+                    // Record the dropped commit as having been rewritten into
+                    // the commit it would have been applied onto, so that any
+                    // branches pointing to it are moved there.
+                    rewritten_oids.push((*commit_oid, current_oid));
+                    progress.finish_with_message(format!(
+                        "Dropped commit which became empty: {}",
+                        commit_description
+                    ));
+                    continue;
+                }
                 progress.set_message(format!("Committing to repository: {}", commit_description));
-                let rebased_commit_oid = repo
-                    .commit(
-                        None,
-                        &commit_to_apply.author(),
-                        &commit_to_apply.committer(),
+                let rebased_commit_oid = if is_fixup {
+                    // Squash the changes into the commit which was just
+                    // rebased, which replaces it.
+                    if rewritten_oids.last().map(|(_, new_oid)| *new_oid) != Some(current_oid) {
+                        anyhow::bail!("BUG: no rebased commit to fix up into: {:?}", commit_oid);
+                    }
+                    let commit_message = match current_commit.message_raw() {
+                        Some(message) => message,
+                        None => anyhow::bail!(
+                            "Could not decode commit message for commit: {:?}",
+                            current_oid
+                        ),
+                    };
+                    let parents: Vec<git2::Commit> = current_commit.parents().collect();
+                    let parents: Vec<&git2::Commit> = parents.iter().collect();
+                    let author = current_commit.author();
+                    let original_committer = current_commit.committer();
+                    let committer = make_committer_signature(
+                        committer_signature.unwrap_or(&original_committer),
+                        &author,
+                        committer_date_is_author_date,
+                    )?;
+                    let fixed_up_commit_oid = create_commit(
+                        repo,
+                        signer,
+                        &author,
+                        &committer,
+                        commit_message,
+                        &commit_tree,
+                        &parents,
+                    )
+                    .with_context(|| "Applying fixed-up commit")?;
+                    for (_, new_oid) in rewritten_oids.iter_mut() {
+                        if *new_oid == current_oid {
+                            *new_oid = fixed_up_commit_oid;
+                        }
+                    }
+                    fixed_up_commit_oid
+                } else {
+                    let commit_message = match commit_to_apply.message_raw() {
+                        Some(message) => message,
+                        None => anyhow::bail!(
+                            "Could not decode commit message for commit: {:?}",
+                            commit_oid
+                        ),
+                    };
+                    let author = commit_to_apply.author();
+                    let original_committer = commit_to_apply.committer();
+                    let committer = make_committer_signature(
+                        committer_signature.unwrap_or(&original_committer),
+                        &author,
+                        committer_date_is_author_date,
+                    )?;
+                    create_commit(
+                        repo,
+                        signer,
+                        &author,
+                        &committer,
                         commit_message,
                         &commit_tree,
                         &[&current_commit],
                     )
-                    .with_context(|| "Applying rebased commit")?;
+                    .with_context(|| "Applying rebased commit")?
+                };
                 rewritten_oids.push((*commit_oid, rebased_commit_oid));
                 current_oid = rebased_commit_oid;
 
@@ -378,7 +777,85 @@ fn rebase_in_memory(
     Ok(RebaseInMemoryResult::Succeeded { rewritten_oids })
 }
 
-fn move_branches<'a>(
+/// Determine which commits in the rebase plan would cause merge conflicts if
+/// the plan were executed on top of `dest_oid`, without touching the working
+/// copy.
+///
+/// Each pick is applied in-memory on top of the result of the previous step. If
+/// a pick conflicts, then subsequent picks are applied on top of the original
+/// version of the conflicting commit, as if the conflict had been resolved in
+/// favor of that commit. Merge commits and root commits are not checked.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `rebase_plan`: The rebase plan to check.
+/// * `dest_oid`: The commit which the rebase plan would be executed on top of.
+///
+/// Returns: The OIDs of the commits which would conflict, in the order that they
+/// would be applied.
+#[context("Checking for merge conflicts when rebasing onto {}", dest_oid.to_string())]
+pub fn find_rebase_conflicts(
+    repo: &git2::Repository,
+    rebase_plan: &RebasePlan,
+    dest_oid: git2::Oid,
+) -> anyhow::Result<Vec<git2::Oid>> {
+    let mut current_tree_oid = repo
+        .find_commit(dest_oid)
+        .with_context(|| format!("Finding destination commit by OID: {:?}", dest_oid))?
+        .tree_id();
+    let mut labels: HashMap<String, git2::Oid> = HashMap::new();
+    let mut conflicting_oids = Vec::new();
+
+    for command in rebase_plan.commands.iter() {
+        match command {
+            RebaseCommand::Label { label_name } => {
+                labels.insert(label_name.clone(), current_tree_oid);
+            }
+            RebaseCommand::Reset { label_name } => {
+                current_tree_oid = match labels.get(label_name) {
+                    Some(oid) => *oid,
+                    None => anyhow::bail!("BUG: no associated tree for label: {}", label_name),
+                };
+            }
+            RebaseCommand::Drop { .. } | RebaseCommand::SkipApplied { .. } => {}
+            RebaseCommand::Pick { commit_oid } | RebaseCommand::Fixup { commit_oid } => {
+                let commit_to_apply = repo
+                    .find_commit(*commit_oid)
+                    .with_context(|| format!("Finding commit to apply by OID: {:?}", commit_oid))?;
+                if commit_to_apply.parent_count() != 1 {
+                    current_tree_oid = commit_to_apply.tree_id();
+                    continue;
+                }
+
+                let parent_tree = commit_to_apply.parent(0)?.tree()?;
+                let current_tree = repo.find_tree(current_tree_oid)?;
+                let mut rebased_index =
+                    repo.merge_trees(&parent_tree, &current_tree, &commit_to_apply.tree()?, None)?;
+                if rebased_index.has_conflicts() {
+                    conflicting_oids.push(*commit_oid);
+                    current_tree_oid = commit_to_apply.tree_id();
+                } else {
+                    current_tree_oid = rebased_index
+                        .write_tree_to(repo)
+                        .with_context(|| "Converting index to tree")?;
+                }
+            }
+        }
+    }
+
+    Ok(conflicting_oids)
+}
+
+/// Move the branches pointing to rewritten commits to point to the new versions
+/// of those commits, and record the updates under the provided transaction ID.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `event_tx_id`: The transaction ID to record the branch updates under.
+/// * `rewritten_oids_map`: A mapping from the old OIDs of rewritten commits to
+///   their new OIDs.
+#[context("Moving branches pointing to rewritten commits")]
+pub fn move_branches<'a>(
     repo: &'a git2::Repository,
     event_tx_id: EventTransactionId,
     rewritten_oids_map: &'a HashMap<git2::Oid, git2::Oid>,
@@ -420,7 +897,12 @@ fn move_branches<'a>(
     let branch_moves_stdin: String = branch_moves
         .into_iter()
         .map(|(old_oid, new_oid, name)| {
-            format!("{} {} {}\n", old_oid.to_string(), new_oid.to_string(), name)
+            format!(
+                "{} {} refs/heads/{}\n",
+                old_oid.to_string(),
+                new_oid.to_string(),
+                name
+            )
         })
         .collect();
     run_hook(
@@ -437,6 +919,7 @@ fn move_branches<'a>(
 }
 
 fn post_rebase_in_memory(
+    out: &mut impl Write,
     git_executable: &GitExecutable,
     repo: &git2::Repository,
     rewritten_oids: &[(git2::Oid, git2::Oid)],
@@ -453,8 +936,11 @@ fn post_rebase_in_memory(
         .and_then(|s| s.strip_prefix("refs/heads/"));
     let head_oid = head.peel_to_commit()?.id();
     // Avoid moving the branch which HEAD points to, or else the index will show
-    // a lot of changes in the working copy.
-    repo.set_head_detached(head_oid)?;
+    // a lot of changes in the working copy. If HEAD's commit wasn't rewritten,
+    // then its branch won't be moved, so leave HEAD as-is.
+    if rewritten_oids_map.contains_key(&head_oid) {
+        repo.set_head_detached(head_oid)?;
+    }
 
     move_branches(repo, event_tx_id, &rewritten_oids_map)?;
 
@@ -478,6 +964,7 @@ fn post_rebase_in_memory(
             None => new_head_oid.to_string(),
         };
         let result = run_git(
+            out,
             git_executable,
             Some(event_tx_id),
             &["checkout", &head_target],
@@ -490,15 +977,446 @@ fn post_rebase_in_memory(
     Ok(0)
 }
 
+/// Name of the file in the `rebase-merge` directory which indicates that the
+/// on-disk rebase was started by `execute_rebase_plan`, and so branches
+/// pointing to rewritten commits should be moved once the rebase completes.
+const MOVE_BRANCHES_FILE_NAME: &str = "branchless_move_branches";
+
+/// Name of the file in the `rebase-merge` directory which lists the commits
+/// which were skipped by `execute_rebase_plan` because their changes had
+/// already been applied (see `find_applied_commits`), one OID per line.
+const APPLIED_COMMITS_FILE_NAME: &str = "branchless_applied_commits";
+
+/// Name of the file in the `rebase-merge` directory which indicates that the
+/// commits rewritten by the on-disk rebase should have their original committer
+/// signatures restored once the rebase completes (see
+/// `get_rewrite_preserve_dates`).
+const PRESERVE_DATES_FILE_NAME: &str = "branchless_preserve_dates";
+
+/// Name of the file in the `rebase-merge` directory which holds the name of
+/// the reference made by `backup_before_rebase_on_disk`, so that it can be
+/// deleted once the rebase completes.
+const BACKUP_REF_FILE_NAME: &str = "branchless_backup_ref";
+
+/// The message of the commit made by `backup_before_rebase_on_disk` to hold
+/// the staged changes in the index.
+const BACKUP_INDEX_COMMIT_MESSAGE: &str = "branchless: backup of index before on-disk rebase\n";
+
+/// Find the commits which were dropped by an on-disk rebase started by
+/// `execute_rebase_plan` because they became empty (see
+/// `get_rewrite_empty_commits`) or had already been applied (see
+/// `find_applied_commits`). These are the commits which were picked, but which
+/// Git didn't report as rewritten, along with the skipped commits. This should
+/// be called from the `post-rewrite` hook, while the rebase state is still on
+/// disk.
+///
+/// Each dropped commit is paired with the commit it would have been applied
+/// onto: the rewritten version of its parent, or the `onto` commit of the
+/// rebase if its parent wasn't rewritten.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `rewritten_oids`: The pairs of old and new commit OIDs, as provided to the
+///   `post-rewrite` hook.
+///
+/// Returns: The pairs of dropped commit OIDs and the OIDs which they should be
+/// considered to have been rewritten as. Empty if the rebase was not started by
+/// `execute_rebase_plan`.
+#[context("Finding commits dropped by on-disk rebase")]
+pub fn find_dropped_commits_after_rebase_on_disk(
+    repo: &git2::Repository,
+    rewritten_oids: &[(git2::Oid, git2::Oid)],
+) -> anyhow::Result<Vec<(git2::Oid, git2::Oid)>> {
+    let rebase_dir = repo.path().join("rebase-merge");
+    if !rebase_dir.join(MOVE_BRANCHES_FILE_NAME).exists() {
+        return Ok(Vec::new());
+    }
+    let done = match std::fs::read_to_string(rebase_dir.join("done")) {
+        Ok(done) => done,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let onto_oid =
+        std::fs::read_to_string(rebase_dir.join("onto")).context("Reading `onto` for rebase")?;
+    let onto_oid = git2::Oid::from_str(onto_oid.trim()).context("Parsing `onto` for rebase")?;
+    let applied_oids: HashSet<git2::Oid> =
+        match std::fs::read_to_string(rebase_dir.join(APPLIED_COMMITS_FILE_NAME)) {
+            Ok(applied_oids) => applied_oids
+                .lines()
+                .map(git2::Oid::from_str)
+                .collect::<Result<_, _>>()
+                .context("Parsing applied commits for rebase")?,
+            Err(_) => HashSet::new(),
+        };
+
+    let mut rewritten_oids_map: HashMap<git2::Oid, git2::Oid> =
+        rewritten_oids.iter().copied().collect();
+    let mut result = Vec::new();
+    for line in done.lines() {
+        let commit_oid = match *line.split(' ').collect::<Vec<_>>().as_slice() {
+            ["pick", commit_oid, ..] => git2::Oid::from_str(commit_oid)
+                .with_context(|| format!("Parsing picked commit: {:?}", line))?,
+            ["drop", commit_oid, ..] => {
+                let commit_oid = git2::Oid::from_str(commit_oid)
+                    .with_context(|| format!("Parsing dropped commit: {:?}", line))?;
+                if !applied_oids.contains(&commit_oid) {
+                    continue;
+                }
+                commit_oid
+            }
+            _ => continue,
+        };
+        if rewritten_oids_map.contains_key(&commit_oid) {
+            continue;
+        }
+        let parent_oid = repo.find_commit(commit_oid)?.parent_id(0).ok();
+        let new_oid = parent_oid
+            .and_then(|parent_oid| rewritten_oids_map.get(&parent_oid))
+            .copied()
+            .unwrap_or(onto_oid);
+        // Later dropped commits may be based on this one.
+        rewritten_oids_map.insert(commit_oid, new_oid);
+        result.push((commit_oid, new_oid));
+    }
+    Ok(result)
+}
+
+/// Restore the original committer signatures, including the committer dates, of
+/// the commits rewritten by an on-disk rebase started by `execute_rebase_plan`.
+/// Git always sets the committer date of a picked commit to the current time,
+/// so each rewritten commit is recreated with the committer signature of the
+/// commit it was rewritten from, on top of the recreated versions of its
+/// parents. If `HEAD`, or the branch it's attached to, points to one of the
+/// rewritten commits, it's updated to point to the recreated commit.
+///
+/// This should be called from the `post-rewrite` hook, while the rebase state
+/// is still on disk. Does nothing if the rebase was not started by
+/// `execute_rebase_plan`, or if dates aren't being preserved.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `event_tx_id`: The transaction ID to record the update to `HEAD` under.
+/// * `rewritten_oids`: The pairs of old and new commit OIDs, as provided to the
+///   `post-rewrite` hook. These must be in the order in which the commits were
+///   applied.
+///
+/// Returns: The pairs of commit OIDs created by Git and the OIDs of the commits
+/// which they were recreated as.
+#[context("Restoring committer dates after on-disk rebase")]
+pub fn preserve_dates_after_rebase_on_disk(
+    repo: &git2::Repository,
+    event_tx_id: EventTransactionId,
+    rewritten_oids: &[(git2::Oid, git2::Oid)],
+) -> anyhow::Result<Vec<(git2::Oid, git2::Oid)>> {
+    let preserve_dates_file = repo
+        .path()
+        .join("rebase-merge")
+        .join(PRESERVE_DATES_FILE_NAME);
+    if !preserve_dates_file.exists() {
+        return Ok(Vec::new());
+    }
+
+    let signer = if get_rewrite_sign(repo)? {
+        Some(CommitSigner::from_config(repo)?)
+    } else {
+        None
+    };
+    let mut recreated_oids: HashMap<git2::Oid, git2::Oid> = HashMap::new();
+    let mut result = Vec::new();
+    for (old_commit_oid, new_commit_oid) in rewritten_oids.iter().copied() {
+        if recreated_oids.contains_key(&new_commit_oid) {
+            continue;
+        }
+        let old_commit = repo.find_commit(old_commit_oid)?;
+        let new_commit = repo.find_commit(new_commit_oid)?;
+        let parents = new_commit
+            .parent_ids()
+            .map(|parent_oid| {
+                let parent_oid = recreated_oids
+                    .get(&parent_oid)
+                    .copied()
+                    .unwrap_or(parent_oid);
+                repo.find_commit(parent_oid)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let message = match new_commit.message() {
+            Some(message) => message,
+            None => anyhow::bail!(
+                "Could not decode commit message for commit: {:?}",
+                new_commit_oid
+            ),
+        };
+        let recreated_oid = create_commit(
+            repo,
+            signer.as_ref(),
+            &new_commit.author(),
+            &old_commit.committer(),
+            message,
+            &new_commit.tree()?,
+            parents.iter().collect::<Vec<_>>().as_slice(),
+        )?;
+        recreated_oids.insert(new_commit_oid, recreated_oid);
+        if recreated_oid != new_commit_oid {
+            result.push((new_commit_oid, recreated_oid));
+        }
+    }
+
+    let mut head = repo.head()?;
+    let (head_oid, recreated_oid) = match head
+        .target()
+        .and_then(|head_oid| Some((head_oid, *recreated_oids.get(&head_oid)?)))
+    {
+        Some(oids) => oids,
+        None => return Ok(result),
+    };
+    if head.is_branch() {
+        // Git has already moved the checked-out branch to the commit it
+        // created, so move it again and record the update.
+        let head_name = match head.name() {
+            Some(head_name) => head_name.to_owned(),
+            None => anyhow::bail!("Could not decode name of HEAD reference"),
+        };
+        head.set_target(recreated_oid, "branchless: restoring committer dates")?;
+        run_hook(
+            repo,
+            "reference-transaction",
+            event_tx_id,
+            &["committed"],
+            Some(format!("{} {} {}\n", head_oid, recreated_oid, head_name)),
+        )?;
+    } else {
+        repo.set_head_detached(recreated_oid)?;
+    }
+    Ok(result)
+}
+
+/// Move branches pointing to commits rewritten by an on-disk rebase to the
+/// rewritten commits. This should be called from the `post-rewrite` hook, since
+/// Git doesn't move these branches itself. Does nothing if the rebase was not
+/// started by `execute_rebase_plan`.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `event_tx_id`: The transaction ID to record the branch updates under.
+/// * `rewritten_oids`: The pairs of old and new commit OIDs, as provided to the
+///   `post-rewrite` hook.
+#[context("Moving branches after on-disk rebase")]
+pub fn move_branches_after_rebase_on_disk(
+    repo: &git2::Repository,
+    event_tx_id: EventTransactionId,
+    rewritten_oids: &[(git2::Oid, git2::Oid)],
+) -> anyhow::Result<()> {
+    let move_branches_file = repo
+        .path()
+        .join("rebase-merge")
+        .join(MOVE_BRANCHES_FILE_NAME);
+    if !move_branches_file.exists() {
+        return Ok(());
+    }
+
+    let rewritten_oids_map: HashMap<git2::Oid, git2::Oid> =
+        rewritten_oids.iter().copied().collect();
+    move_branches(repo, event_tx_id, &rewritten_oids_map)?;
+    Ok(())
+}
+
+/// Delete the backup reference made by `backup_before_rebase_on_disk` once
+/// the on-disk rebase has completed. This should be called from the
+/// `post-rewrite` hook, while the rebase state is still on disk.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `event_tx_id`: The transaction ID to record the reference deletion under.
+#[context("Deleting backup reference after on-disk rebase")]
+pub fn delete_backup_after_rebase_on_disk(
+    repo: &git2::Repository,
+    event_tx_id: EventTransactionId,
+) -> anyhow::Result<()> {
+    let backup_ref_file = repo.path().join("rebase-merge").join(BACKUP_REF_FILE_NAME);
+    if !backup_ref_file.exists() {
+        return Ok(());
+    }
+
+    let ref_name = std::fs::read_to_string(backup_ref_file.as_path())
+        .with_context(|| format!("Reading `{}`", BACKUP_REF_FILE_NAME))?;
+    delete_backup_ref(repo, event_tx_id, ref_name.trim())
+}
+
+/// Delete the given backup reference, if it still exists, and record its
+/// deletion in the event log, so that `git undo` can restore it.
+#[context("Deleting backup reference: {}", ref_name)]
+fn delete_backup_ref(
+    repo: &git2::Repository,
+    event_tx_id: EventTransactionId,
+    ref_name: &str,
+) -> anyhow::Result<()> {
+    let mut reference = match repo.find_reference(ref_name) {
+        Ok(reference) => reference,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let backup_oid = match reference.target() {
+        Some(backup_oid) => backup_oid,
+        None => anyhow::bail!("Backup reference is not a direct reference: {}", ref_name),
+    };
+    reference.delete()?;
+    run_hook(
+        repo,
+        "reference-transaction",
+        event_tx_id,
+        &["committed"],
+        Some(format!(
+            "{} {} {}\n",
+            backup_oid,
+            git2::Oid::zero(),
+            ref_name
+        )),
+    )?;
+    Ok(())
+}
+
+/// Restore `HEAD` and the index from a backup made by
+/// `backup_before_rebase_on_disk`. `HEAD` is detached at the backed-up commit,
+/// and if the backup holds staged changes, the index is reset to them. The
+/// backup doesn't include the working copy, so the working copy is left as-is,
+/// rather than discarding any changes made since the backup was taken.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `repo`: The Git repository.
+/// * `event_tx_id`: The transaction ID to record the checkout under.
+/// * `backup_oid`: The OID which the backup reference pointed to.
+#[context("Restoring backup {:?}", backup_oid)]
+pub fn restore_backup(
+    out: &mut impl Write,
+    git_executable: &GitExecutable,
+    repo: &git2::Repository,
+    event_tx_id: EventTransactionId,
+    backup_oid: git2::Oid,
+) -> anyhow::Result<()> {
+    let backup_commit = repo.find_commit(backup_oid)?;
+    let head_oid = if backup_commit.message_raw() == Some(BACKUP_INDEX_COMMIT_MESSAGE) {
+        backup_commit.parent_id(0)?
+    } else {
+        backup_oid
+    };
+
+    if get_repo_head(repo)?.peel_to_commit()?.id() != head_oid {
+        let result = run_git(
+            out,
+            git_executable,
+            Some(event_tx_id),
+            &["checkout", "--detach", &head_oid.to_string()],
+        )?;
+        if result != 0 {
+            anyhow::bail!("Could not check out backed-up HEAD: {}", head_oid);
+        }
+    }
+    if head_oid != backup_oid {
+        let result = run_git(
+            out,
+            git_executable,
+            Some(event_tx_id),
+            &["read-tree", &backup_oid.to_string()],
+        )?;
+        if result != 0 {
+            anyhow::bail!("Could not restore backed-up index: {}", backup_oid);
+        }
+    }
+    Ok(())
+}
+
+/// Save `HEAD` and the contents of the index under a reference in
+/// `BACKUP_REF_PREFIX`, so that they can be recovered if the on-disk rebase
+/// goes wrong, such as when resolving merge conflicts. If the index has no
+/// staged changes, the reference points to `HEAD` itself; otherwise, it points
+/// to a new commit on top of `HEAD` containing the staged changes.
+///
+/// The reference is deleted once the rebase completes. Backups left behind by
+/// earlier on-disk rebases which were aborted are deleted here, so that at
+/// most one backup is kept. The creation and deletion of references is
+/// recorded in the event log, so `git undo` can restore the backup.
+///
+/// Args:
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `repo`: The Git repository.
+/// * `event_tx_id`: The transaction ID to record the reference creation under.
+///
+/// Returns: The name of the backup reference.
+#[context("Backing up HEAD and index before on-disk rebase")]
+fn backup_before_rebase_on_disk(
+    git_executable: &GitExecutable,
+    repo: &git2::Repository,
+    event_tx_id: EventTransactionId,
+) -> anyhow::Result<String> {
+    let stale_ref_names = repo
+        .references_glob(&format!("{}*", BACKUP_REF_PREFIX))?
+        .names()
+        .map(|ref_name| ref_name.map(|ref_name| ref_name.to_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    for ref_name in stale_ref_names {
+        delete_backup_ref(repo, event_tx_id, &ref_name)?;
+    }
+
+    let head_commit = get_repo_head(repo)?.peel_to_commit()?;
+    let index_tree_oid = repo
+        .index()?
+        .write_tree()
+        .with_context(|| "Writing index to tree")?;
+    let backup_oid = if index_tree_oid == head_commit.tree_id() {
+        head_commit.id()
+    } else {
+        let signature = get_committer_signature(repo, git_executable)?;
+        repo.commit(
+            None,
+            &signature,
+            &signature,
+            BACKUP_INDEX_COMMIT_MESSAGE,
+            &repo.find_tree(index_tree_oid)?,
+            &[&head_commit],
+        )
+        .with_context(|| "Committing backup of index")?
+    };
+
+    let ref_name = format!("{}{}", BACKUP_REF_PREFIX, event_tx_id.to_string());
+    repo.reference(
+        &ref_name,
+        backup_oid,
+        true,
+        "branchless: backup before on-disk rebase",
+    )
+    .with_context(|| format!("Creating backup reference: {}", ref_name))?;
+    run_hook(
+        repo,
+        "reference-transaction",
+        event_tx_id,
+        &["committed"],
+        Some(format!(
+            "{} {} {}\n",
+            git2::Oid::zero(),
+            backup_oid,
+            ref_name
+        )),
+    )?;
+    Ok(ref_name)
+}
+
 #[context("Rebasing on disk from {} to {}", source_oid.to_string(), dest_oid.to_string())]
 fn rebase_on_disk(
+    out: &mut impl Write,
     git_executable: &GitExecutable,
     repo: &git2::Repository,
     rebase_plan: &RebasePlan,
     source_oid: git2::Oid,
     dest_oid: git2::Oid,
     event_tx_id: EventTransactionId,
+    preserve_dates: bool,
+    committer_date_is_author_date: bool,
+    sign: bool,
+    empty_commits: EmptyCommitPolicy,
 ) -> anyhow::Result<isize> {
+    let backup_ref_name = backup_before_rebase_on_disk(git_executable, repo, event_tx_id)?;
+
     let progress = ProgressBar::new_spinner();
     progress.enable_steady_tick(100);
     progress.set_message("Initializing rebase");
@@ -534,18 +1452,99 @@ fn rebase_on_disk(
     )
     .with_context(|| format!("Writing `end` to: {:?}", end_file.as_path()))?;
 
+    let move_branches_file = repo
+        .path()
+        .join("rebase-merge")
+        .join(MOVE_BRANCHES_FILE_NAME);
+    std::fs::write(move_branches_file.as_path(), "")
+        .with_context(|| format!("Writing marker file: {:?}", move_branches_file.as_path()))?;
+
+    let backup_ref_file = repo.path().join("rebase-merge").join(BACKUP_REF_FILE_NAME);
+    std::fs::write(backup_ref_file.as_path(), &backup_ref_name)
+        .with_context(|| format!("Writing `{}`", BACKUP_REF_FILE_NAME))?;
+
+    let applied_commits_file = repo
+        .path()
+        .join("rebase-merge")
+        .join(APPLIED_COMMITS_FILE_NAME);
+    std::fs::write(
+        applied_commits_file.as_path(),
+        rebase_plan
+            .commands
+            .iter()
+            .filter_map(|command| match command {
+                RebaseCommand::SkipApplied { commit_oid } => Some(format!("{}\n", commit_oid)),
+                _ => None,
+            })
+            .collect::<String>(),
+    )
+    .with_context(|| format!("Writing `{}`", APPLIED_COMMITS_FILE_NAME))?;
+
+    if committer_date_is_author_date {
+        // This is the state file for `git rebase --committer-date-is-author-date`.
+        // It's ignored by versions of Git before v2.29.
+        let cdate_is_adate_file = repo.path().join("rebase-merge").join("cdate_is_adate");
+        std::fs::write(cdate_is_adate_file.as_path(), "")
+            .with_context(|| format!("Writing marker file: {:?}", cdate_is_adate_file.as_path()))?;
+    } else if preserve_dates {
+        // Git can't keep the original committer dates itself, so they're
+        // restored by the `post-rewrite` hook instead.
+        let preserve_dates_file = repo
+            .path()
+            .join("rebase-merge")
+            .join(PRESERVE_DATES_FILE_NAME);
+        std::fs::write(preserve_dates_file.as_path(), "")
+            .with_context(|| format!("Writing marker file: {:?}", preserve_dates_file.as_path()))?;
+    }
+
+    // These are the state files for `git rebase --empty=drop` and
+    // `--empty=keep`. They're ignored by versions of Git before v2.26.
+    let empty_commits_file_name = match empty_commits {
+        EmptyCommitPolicy::Keep => "keep_redundant_commits",
+        EmptyCommitPolicy::Drop => "drop_redundant_commits",
+    };
+    let empty_commits_file = repo
+        .path()
+        .join("rebase-merge")
+        .join(empty_commits_file_name);
+    std::fs::write(empty_commits_file.as_path(), "")
+        .with_context(|| format!("Writing state file: {:?}", empty_commits_file.as_path()))?;
+
+    if sign {
+        // This is the state file for `git rebase --gpg-sign`.
+        let gpg_sign_opt_file = repo.path().join("rebase-merge").join("gpg_sign_opt");
+        std::fs::write(gpg_sign_opt_file.as_path(), "-S")
+            .with_context(|| format!("Writing state file: {:?}", gpg_sign_opt_file.as_path()))?;
+    }
+
     progress.set_message("Calling Git for on-disk rebase");
     let result = run_git(
+        out,
         &git_executable,
         Some(event_tx_id),
         &["rebase", "--continue"],
     )?;
+    if result == 0 {
+        // The `post-rewrite` hook deletes the backup, but Git doesn't call it
+        // if no commits were rewritten.
+        delete_backup_ref(repo, event_tx_id, &backup_ref_name)?;
+    } else {
+        // Only mention the backup when the user may need it, such as when
+        // they have to resolve merge conflicts.
+        writeln!(
+            out,
+            "Backed up HEAD to {} before rebasing on disk. To return to it, run: git checkout {}",
+            backup_ref_name, backup_ref_name
+        )?;
+    }
 
     Ok(result)
 }
 
+/// Describe a commit with its abbreviated hash and summary line, for use in
+/// progress messages.
 #[context("Describing commit {}", commit_oid.to_string())]
-fn friendly_describe_commit(
+pub fn friendly_describe_commit(
     repo: &git2::Repository,
     commit_oid: git2::Oid,
 ) -> anyhow::Result<StyledString> {
@@ -555,7 +1554,7 @@ fn friendly_describe_commit(
     let description = render_commit_metadata(
         &commit,
         &mut [
-            &mut CommitOidProvider::new(true)?,
+            &mut CommitOidProvider::new(repo, true)?,
             &mut CommitMessageProvider::new()?,
         ],
     )?;
@@ -563,8 +1562,29 @@ fn friendly_describe_commit(
 }
 
 /// Execute the provided rebase plan. Returns the exit status (zero indicates
-/// success).
+/// success). If the rebase couldn't proceed because of a merge conflict, the
+/// exit status is `EXIT_CODE_CONFLICT`. If the plan can't be executed at all,
+/// such as because it contains a merge commit, the exit status is
+/// `EXIT_CODE_BAD_ARGUMENTS`.
+///
+/// If `abort_on_conflict` is set, then the rebase is not started at all if any
+/// of its commits would conflict. Otherwise, before an on-disk rebase, the
+/// commits which would conflict are reported, so that the user knows what to
+/// expect.
+///
+/// The rewritten commits keep their original dates, unless
+/// `branchless.rewrite.preserveDates` is disabled (see
+/// `get_rewrite_preserve_dates`). If `branchless.rewrite.sign` is enabled
+/// (see `get_rewrite_sign`), they're signed with the user's signing program.
+/// Commits which become empty are kept or dropped according to
+/// `branchless.rewrite.emptyCommits` (see `get_rewrite_empty_commits`). If
+/// `committer_date_is_author_date` is set, then the committer date of each
+/// rewritten commit is set to its author date instead.
+///
+/// Messages about the progress of the rebase, and the output of any `git`
+/// commands which are run, are written to `out`.
 pub fn execute_rebase_plan(
+    out: &mut impl Write,
     glyphs: &Glyphs,
     git_executable: &GitExecutable,
     repo: &git2::Repository,
@@ -573,49 +1593,249 @@ pub fn execute_rebase_plan(
     source_oid: git2::Oid,
     dest_oid: git2::Oid,
     force_on_disk: bool,
+    abort_on_conflict: bool,
+    committer_date_is_author_date: bool,
 ) -> anyhow::Result<isize> {
+    if force_on_disk || abort_on_conflict {
+        let conflicting_oids = find_rebase_conflicts(repo, rebase_plan, dest_oid)?;
+        if !conflicting_oids.is_empty() {
+            writeln!(out, "The following commits would cause merge conflicts:")?;
+            for commit_oid in conflicting_oids {
+                writeln!(
+                    out,
+                    "- {}",
+                    printable_styled_string(glyphs, friendly_describe_commit(repo, commit_oid)?)?
+                )?;
+            }
+            if abort_on_conflict {
+                writeln!(
+                    out,
+                    "Not moving any commits, since --abort-on-conflict was provided."
+                )?;
+                return Ok(EXIT_CODE_CONFLICT);
+            }
+        }
+    }
+
+    let preserve_dates = get_rewrite_preserve_dates(repo)?;
+    let sign = get_rewrite_sign(repo)?;
+    let empty_commits = get_rewrite_empty_commits(repo)?;
     if !force_on_disk {
-        println!("Attempting rebase in-memory...");
-        match rebase_in_memory(glyphs, &repo, &rebase_plan, dest_oid)? {
+        writeln!(out, "Attempting rebase in-memory...")?;
+        let committer_signature = if preserve_dates {
+            None
+        } else {
+            Some(get_committer_signature(repo, git_executable)?)
+        };
+        let signer = if sign {
+            Some(CommitSigner::from_config(repo)?)
+        } else {
+            None
+        };
+        match rebase_in_memory(
+            glyphs,
+            &repo,
+            &rebase_plan,
+            dest_oid,
+            committer_signature.as_ref(),
+            committer_date_is_author_date,
+            signer.as_ref(),
+            empty_commits,
+        )? {
             RebaseInMemoryResult::Succeeded { rewritten_oids } => {
-                post_rebase_in_memory(git_executable, repo, &rewritten_oids, event_tx_id)?;
-                println!("In-memory rebase succeeded.");
+                let result =
+                    post_rebase_in_memory(out, git_executable, repo, &rewritten_oids, event_tx_id)?;
+                if result != 0 {
+                    writeln!(
+                        out,
+                        "The commits were rebased in-memory, but the new HEAD could not be checked out."
+                    )?;
+                    return Ok(result);
+                }
+                writeln!(out, "In-memory rebase succeeded.")?;
                 return Ok(0);
             }
             RebaseInMemoryResult::CannotRebaseMergeCommit { commit_oid } => {
-                println!(
+                writeln!(
+                    out,
                     "Merge commits currently can't be rebased with `git move`. The merge commit was: {}",
                     printable_styled_string(glyphs, friendly_describe_commit(repo, commit_oid)?)?
-                );
-                return Ok(1);
+                )?;
+                return Ok(EXIT_CODE_BAD_ARGUMENTS);
             }
             RebaseInMemoryResult::MergeConflict { commit_oid } => {
-                println!(
+                writeln!(
+                    out,
                     "Merge conflict, falling back to rebase on-disk. The conflicting commit was: {}",
                     printable_styled_string(glyphs, friendly_describe_commit(repo, commit_oid)?)?,
-                );
+                )?;
             }
         }
     }
 
     let result = rebase_on_disk(
+        out,
         git_executable,
         repo,
         &rebase_plan,
         source_oid,
         dest_oid,
         event_tx_id,
+        preserve_dates,
+        committer_date_is_author_date,
+        sign,
+        empty_commits,
     )?;
     Ok(result)
 }
 
+/// The environment variable which is set to the OID of the rewritten commit
+/// when running an `--exec-each` command on it.
+pub const BRANCHLESS_EXEC_COMMIT_ENV_VAR: &str = "BRANCHLESS_EXEC_COMMIT";
+
+/// Shell commands to run after commits have been rewritten successfully, as
+/// with `git rebase --exec`.
+#[derive(Clone, Debug, Default)]
+pub struct ExecOptions {
+    /// A command to run once at the end, in the working copy, with the new
+    /// `HEAD` checked out.
+    pub exec: Option<String>,
+
+    /// Commands to run on each rewritten commit, in order.
+    pub exec_each: Vec<String>,
+}
+
+/// Get the commits which were rewritten under the given transaction, in the
+/// order that they were rewritten. Commits which were rewritten into the same
+/// commit (such as when a commit becomes empty and is dropped) are only
+/// included once.
+#[context("Getting commits rewritten in transaction {:?}", event_tx_id)]
+fn get_rewritten_commit_oids(
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+) -> anyhow::Result<Vec<git2::Oid>> {
+    let mut result = Vec::new();
+    let mut seen_oids = HashSet::new();
+    for event in event_log_db.get_events()? {
+        if let Event::RewriteEvent {
+            event_tx_id: event_event_tx_id,
+            new_commit_oid,
+            ..
+        } = event
+        {
+            if event_event_tx_id == event_tx_id && seen_oids.insert(new_commit_oid) {
+                result.push(new_commit_oid);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Run a shell command in `dir`, passing the terminal through to it.
+///
+/// Returns: The exit code of the command.
+#[context("Running command {:?}", command)]
+fn run_exec_command(
+    out: &mut impl Write,
+    command: &str,
+    dir: &Path,
+    commit_oid: Option<git2::Oid>,
+) -> anyhow::Result<isize> {
+    out.flush()?;
+    let mut process = Command::new(get_sh().context("shell needed to run exec command")?);
+    process.arg("-c").arg(command).current_dir(dir);
+    if let Some(commit_oid) = commit_oid {
+        process.env(BRANCHLESS_EXEC_COMMIT_ENV_VAR, commit_oid.to_string());
+    }
+    let status = process
+        .status()
+        .with_context(|| format!("Running exec command: {:?}", command))?;
+    // If the command was terminated by a signal, treat it as a failure.
+    let exit_code = status.code().unwrap_or(1).try_into()?;
+    Ok(exit_code)
+}
+
+/// Run the commands in `exec_options` after a successful rewrite.
+///
+/// Each of the `exec_each` commands is run on each of the commits rewritten
+/// under `event_tx_id`, in a temporary directory with that commit checked out,
+/// so that the working copy isn't touched. The `BRANCHLESS_EXEC_COMMIT`
+/// environment variable is set to the OID of the commit. Then the `exec`
+/// command is run in the working copy.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `glyphs`: The glyphs to use when describing commits.
+/// * `repo`: The Git repository.
+/// * `event_log_db`: The event log, which contains the rewrite events.
+/// * `event_tx_id`: The transaction which the commits were rewritten under.
+/// * `exec_options`: The commands to run.
+///
+/// Returns: exit code. If a command fails, then none of the remaining commands
+/// are run, and its exit code is returned.
+#[context("Running exec commands")]
+pub fn run_exec_commands(
+    out: &mut impl Write,
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    exec_options: &ExecOptions,
+) -> anyhow::Result<isize> {
+    let ExecOptions { exec, exec_each } = exec_options;
+    if !exec_each.is_empty() {
+        for commit_oid in get_rewritten_commit_oids(event_log_db, event_tx_id)? {
+            let commit = repo.find_commit(commit_oid)?;
+            let description =
+                printable_styled_string(glyphs, friendly_describe_commit(repo, commit_oid)?)?;
+            let temp_dir = tempfile::tempdir().context("Creating temporary directory")?;
+            checkout_commit_into_dir(repo, &commit, temp_dir.path())?;
+            for command in exec_each {
+                writeln!(out, "Executing: {} (on {})", command, description)?;
+                let exit_code = run_exec_command(out, command, temp_dir.path(), Some(commit_oid))?;
+                if exit_code != 0 {
+                    writeln!(
+                        out,
+                        "Command `{}` failed with exit code {} on commit: {}",
+                        command, exit_code, description
+                    )?;
+                    writeln!(out, "Not running any remaining commands.")?;
+                    return Ok(exit_code);
+                }
+            }
+        }
+    }
+
+    if let Some(command) = exec {
+        let workdir = match repo.workdir() {
+            Some(workdir) => workdir,
+            None => anyhow::bail!("Cannot run --exec command in a bare repository"),
+        };
+        writeln!(out, "Executing: {}", command)?;
+        let exit_code = run_exec_command(out, command, workdir, None)?;
+        if exit_code != 0 {
+            writeln!(
+                out,
+                "Command `{}` failed with exit code {}.",
+                command, exit_code
+            )?;
+            return Ok(exit_code);
+        }
+    }
+    Ok(0)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::core::eventlog::EventLogDb;
-    use crate::core::graph::{make_graph, BranchOids, HeadOid, MainBranchOid};
+    use crate::core::graph::{
+        make_graph_from_repo, make_graph_from_repo_with_head, AncestryCache, HeadOid, MainBranchOid,
+    };
     use crate::core::mergebase::MergeBaseDb;
+    use std::time::SystemTime;
+
     use crate::testing::{with_git, Git, GitRunOptions};
-    use crate::util::{get_branch_oid_to_names, get_db_conn, get_head_oid, get_main_branch_oid};
+    use crate::util::{get_db_conn, get_main_branch_oid};
 
     use super::*;
 
@@ -626,17 +1846,12 @@ mod tests {
         let event_log_db = EventLogDb::new(&conn)?;
         let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
         let event_cursor = event_replayer.make_default_cursor();
-        let head_oid = get_head_oid(&repo)?;
-        let main_branch_oid = get_main_branch_oid(&repo)?;
-        let branch_oid_to_names = get_branch_oid_to_names(&repo)?;
-        let graph = make_graph(
+        let graph = make_graph_from_repo(
             &repo,
             &merge_base_db,
+            &mut AncestryCache::default(),
             &event_replayer,
             event_cursor,
-            &HeadOid(head_oid),
-            &MainBranchOid(main_branch_oid),
-            &BranchOids(branch_oid_to_names.keys().copied().collect()),
             true,
         )?;
 
@@ -686,4 +1901,64 @@ mod tests {
             Ok(())
         })
     }
+
+    #[test]
+    fn test_execute_rebase_plan_output() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            git.detach_head()?;
+            let test1_oid = git.commit_file_with_contents("test1", 1, "foo\n")?;
+            git.run(&["checkout", "master"])?;
+            git.commit_file_with_contents("test1", 2, "bar\n")?;
+
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let merge_base_db = MergeBaseDb::new(&conn)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+            let main_branch_oid = get_main_branch_oid(&repo)?;
+            let graph = make_graph_from_repo_with_head(
+                &repo,
+                &merge_base_db,
+                &mut AncestryCache::default(),
+                &event_replayer,
+                event_replayer.make_default_cursor(),
+                &HeadOid(Some(test1_oid)),
+                true,
+            )?;
+            let rebase_plan = make_rebase_plan(
+                &repo,
+                &merge_base_db,
+                &PatchIdDb::new(&conn)?,
+                &graph,
+                &MainBranchOid(main_branch_oid),
+                test1_oid,
+                main_branch_oid,
+            )?;
+
+            let mut out = Vec::new();
+            let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+            let result = execute_rebase_plan(
+                &mut out,
+                &Glyphs::text(),
+                &GitExecutable(git.git_executable.clone()),
+                &repo,
+                event_tx_id,
+                &rebase_plan,
+                test1_oid,
+                main_branch_oid,
+                false,
+                true,
+                false,
+            )?;
+            assert_eq!(result, EXIT_CODE_CONFLICT);
+            insta::assert_snapshot!(String::from_utf8(out)?, @r###"
+            The following commits would cause merge conflicts:
+            - 2a0e648e create test1.txt
+            Not moving any commits, since --abort-on-conflict was provided.
+            "###);
+
+            Ok(())
+        })
+    }
 }
@@ -0,0 +1,217 @@
+//! Sign commits with the user's configured signing program.
+//!
+//! Git signs commits itself when they're made with `git commit -S` (or with
+//! `commit.gpgSign` set), but commits which we create directly, such as during
+//! an in-memory rebase, would otherwise come out unsigned. This reproduces
+//! Git's handling of the `gpg.*` configuration, so that such commits are
+//! signed in the same way as Git would sign them.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::Context;
+use fn_error_context::context;
+
+/// The kind of signature to produce, as configured by `gpg.format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigningFormat {
+    /// An OpenPGP signature, made with `gpg`.
+    OpenPgp,
+
+    /// An X.509 signature, made with `gpgsm`.
+    X509,
+
+    /// An SSH signature, made with `ssh-keygen`.
+    Ssh,
+}
+
+impl SigningFormat {
+    /// The name of the format in `gpg.format`.
+    fn config_name(&self) -> &'static str {
+        match self {
+            SigningFormat::OpenPgp => "openpgp",
+            SigningFormat::X509 => "x509",
+            SigningFormat::Ssh => "ssh",
+        }
+    }
+
+    /// The signing program to use if none is configured.
+    fn default_program(&self) -> &'static str {
+        match self {
+            SigningFormat::OpenPgp => "gpg",
+            SigningFormat::X509 => "gpgsm",
+            SigningFormat::Ssh => "ssh-keygen",
+        }
+    }
+}
+
+/// Signs commit contents by invoking the user's configured signing program.
+#[derive(Clone, Debug)]
+pub struct CommitSigner {
+    format: SigningFormat,
+    program: String,
+    key: Option<String>,
+}
+
+impl CommitSigner {
+    /// Construct a signer from the repository configuration, in the same way
+    /// as Git does:
+    ///
+    /// * `gpg.format` selects the kind of signature (`openpgp` by default).
+    /// * `gpg.<format>.program` selects the signing program. For `openpgp`,
+    ///   `gpg.program` is also consulted.
+    /// * `user.signingKey` selects the key to sign with. For `openpgp` and
+    ///   `x509`, it defaults to the committer's identity. For `ssh`, it's
+    ///   required, and may be either the path to a key or a literal public key
+    ///   (prefixed with `key::`) whose private key is in the SSH agent.
+    #[context("Reading commit signing configuration")]
+    pub fn from_config(repo: &git2::Repository) -> anyhow::Result<Self> {
+        let config = repo.config()?;
+        let format = match config.get_string("gpg.format") {
+            Err(_) => SigningFormat::OpenPgp,
+            Ok(format) => match format.as_str() {
+                "openpgp" => SigningFormat::OpenPgp,
+                "x509" => SigningFormat::X509,
+                "ssh" => SigningFormat::Ssh,
+                format => anyhow::bail!("Unsupported value for gpg.format: {:?}", format),
+            },
+        };
+        let program = config
+            .get_string(&format!("gpg.{}.program", format.config_name()))
+            .or_else(|err| match format {
+                SigningFormat::OpenPgp => config.get_string("gpg.program"),
+                SigningFormat::X509 | SigningFormat::Ssh => Err(err),
+            })
+            .unwrap_or_else(|_| format.default_program().to_string());
+        let key = config.get_string("user.signingKey").ok();
+        Ok(CommitSigner {
+            format,
+            program,
+            key,
+        })
+    }
+
+    /// Sign the given commit contents, as produced by
+    /// `git2::Repository::commit_create_buffer`.
+    ///
+    /// Args:
+    /// * `content`: The commit contents to sign.
+    /// * `committer`: The committer of the commit, whose identity is used as
+    ///   the signing key if `user.signingKey` isn't set.
+    ///
+    /// Returns: The ASCII-armored signature, suitable for the `gpgsig` header.
+    #[context("Signing commit contents with {:?}", self.program)]
+    pub fn sign(&self, content: &str, committer: &git2::Signature) -> anyhow::Result<String> {
+        let signature = match self.format {
+            SigningFormat::OpenPgp | SigningFormat::X509 => {
+                let key = match &self.key {
+                    Some(key) => key.clone(),
+                    None => committer.to_string(),
+                };
+                self.sign_gpg(content, &key)?
+            }
+            SigningFormat::Ssh => match &self.key {
+                Some(key) => self.sign_ssh(content, key)?,
+                None => anyhow::bail!("user.signingKey must be set to sign commits with SSH"),
+            },
+        };
+        if signature.trim().is_empty() {
+            anyhow::bail!("Signing program produced an empty signature");
+        }
+        Ok(signature)
+    }
+
+    fn sign_gpg(&self, content: &str, key: &str) -> anyhow::Result<String> {
+        let mut child = Command::new(&self.program)
+            .arg("--status-fd=2")
+            .arg("-bsau")
+            .arg(key)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Spawning signing program: {:?}", self.program))?;
+        child
+            .stdin
+            .take()
+            .context("Opening stdin of signing program")?
+            .write_all(content.as_bytes())
+            .context("Writing commit contents to signing program")?;
+        let output = child
+            .wait_with_output()
+            .context("Waiting for signing program")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Signing program failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        let signature =
+            String::from_utf8(output.stdout).context("Decoding signature from signing program")?;
+        Ok(signature)
+    }
+
+    fn sign_ssh(&self, content: &str, key: &str) -> anyhow::Result<String> {
+        let temp_dir = tempfile::tempdir().context("Creating temporary directory")?;
+        let content_path = temp_dir.path().join("commit");
+        std::fs::write(&content_path, content).context("Writing commit contents")?;
+
+        let mut command = Command::new(&self.program);
+        command.arg("-Y").arg("sign").arg("-n").arg("git");
+        match key.strip_prefix("key::") {
+            Some(literal_key) => {
+                // The private key is expected to be in the SSH agent.
+                let key_path = temp_dir.path().join("key.pub");
+                std::fs::write(&key_path, literal_key).context("Writing public key")?;
+                command.arg("-f").arg(&key_path).arg("-U");
+            }
+            None => {
+                command.arg("-f").arg(key);
+            }
+        }
+        let output = command
+            .arg(&content_path)
+            .output()
+            .with_context(|| format!("Running signing program: {:?}", self.program))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Signing program failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        let signature = std::fs::read_to_string(temp_dir.path().join("commit.sig"))
+            .context("Reading signature from signing program")?;
+        Ok(signature)
+    }
+}
+
+/// Create a commit without updating any references, as with
+/// `git2::Repository::commit`, but sign it with `signer` if provided.
+///
+/// Returns: The OID of the new commit.
+#[context("Creating commit")]
+pub fn create_commit(
+    repo: &git2::Repository,
+    signer: Option<&CommitSigner>,
+    author: &git2::Signature,
+    committer: &git2::Signature,
+    message: &str,
+    tree: &git2::Tree,
+    parents: &[&git2::Commit],
+) -> anyhow::Result<git2::Oid> {
+    let signer = match signer {
+        Some(signer) => signer,
+        None => {
+            let oid = repo.commit(None, author, committer, message, tree, parents)?;
+            return Ok(oid);
+        }
+    };
+    let content = repo.commit_create_buffer(author, committer, message, tree, parents)?;
+    let content = match content.as_str() {
+        Some(content) => content,
+        None => anyhow::bail!("Could not decode commit contents as UTF-8"),
+    };
+    let signature = signer.sign(content, committer)?;
+    let oid = repo.commit_signed(content, &signature, None)?;
+    Ok(oid)
+}
@@ -0,0 +1,188 @@
+//! Record how long each part of a command takes, and how often caches are hit,
+//! to help diagnose slow commands in large repositories.
+//!
+//! Recording is disabled by default, in which case it costs no more than
+//! checking a flag. It's enabled with `set_timings_enabled`, such as by passing
+//! `--timings`, after which the results can be displayed with `render_timings`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+static TIMINGS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref TIMINGS: Mutex<Vec<Timing>> = Mutex::new(Vec::new());
+}
+
+/// The accumulated measurements for one label.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timing {
+    /// What was measured, such as `make_graph`.
+    pub label: &'static str,
+
+    /// The number of times that the label was recorded.
+    pub count: usize,
+
+    /// The total time taken across all recordings, or `None` if the label is
+    /// only counted and not timed (see `record_count`).
+    pub total: Option<Duration>,
+}
+
+/// Enable or disable recording timings for the rest of the process.
+pub fn set_timings_enabled(enabled: bool) {
+    TIMINGS_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Whether or not timings are being recorded. See `set_timings_enabled`.
+pub fn timings_enabled() -> bool {
+    TIMINGS_ENABLED.load(Ordering::SeqCst)
+}
+
+fn record(label: &'static str, duration: Option<Duration>) {
+    if !timings_enabled() {
+        return;
+    }
+    // Timings are only diagnostic, so don't fail the operation if another
+    // thread panicked while recording them.
+    let mut timings = match TIMINGS.lock() {
+        Ok(timings) => timings,
+        Err(_) => return,
+    };
+    match timings.iter_mut().find(|timing| timing.label == label) {
+        Some(timing) => {
+            timing.count += 1;
+            timing.total = match (timing.total, duration) {
+                (Some(total), Some(duration)) => Some(total + duration),
+                (total, None) => total,
+                (None, duration) => duration,
+            };
+        }
+        None => timings.push(Timing {
+            label,
+            count: 1,
+            total: duration,
+        }),
+    }
+}
+
+/// Record that the event with the given label happened, such as a cache hit,
+/// without timing it.
+pub fn record_count(label: &'static str) {
+    record(label, None)
+}
+
+/// Times the scope that it's alive for, and records the elapsed time under its
+/// label when dropped. Created with `start_timer`.
+#[must_use = "the timer records the elapsed time when it's dropped"]
+pub struct Timer {
+    label: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            record(self.label, Some(start.elapsed()));
+        }
+    }
+}
+
+/// Start timing an operation. The elapsed time is recorded under `label` when
+/// the returned `Timer` is dropped.
+pub fn start_timer(label: &'static str) -> Timer {
+    let start = if timings_enabled() {
+        Some(Instant::now())
+    } else {
+        None
+    };
+    Timer { label, start }
+}
+
+/// Get the timings recorded so far, in the order that their labels were first
+/// recorded.
+pub fn get_timings() -> Vec<Timing> {
+    match TIMINGS.lock() {
+        Ok(timings) => timings.clone(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Render the timings recorded so far as a summary, one label per line.
+pub fn render_timings() -> Vec<String> {
+    let mut lines = vec!["Timings:".to_string()];
+    for Timing {
+        label,
+        count,
+        total,
+    } in get_timings()
+    {
+        lines.push(match total {
+            Some(total) => format!(
+                "  {}: {:.1}ms ({} {})",
+                label,
+                total.as_secs_f64() * 1000.0,
+                count,
+                if count == 1 { "call" } else { "calls" }
+            ),
+            None => format!("  {}: {}", label, count),
+        });
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Restores whether timings were enabled when dropped, so that enabling
+    /// them in one test doesn't affect the rest of the test process.
+    struct TimingsEnabledGuard {
+        previous: bool,
+    }
+
+    impl TimingsEnabledGuard {
+        fn new(enabled: bool) -> Self {
+            let previous = timings_enabled();
+            set_timings_enabled(enabled);
+            TimingsEnabledGuard { previous }
+        }
+    }
+
+    impl Drop for TimingsEnabledGuard {
+        fn drop(&mut self) {
+            set_timings_enabled(self.previous);
+        }
+    }
+
+    #[test]
+    fn test_record_timings() {
+        let _guard = TimingsEnabledGuard::new(true);
+        {
+            let _timer = start_timer("test_record_timings timer");
+        }
+        {
+            let _timer = start_timer("test_record_timings timer");
+        }
+        record_count("test_record_timings counter");
+
+        let timings: Vec<Timing> = get_timings()
+            .into_iter()
+            .filter(|timing| timing.label.starts_with("test_record_timings"))
+            .collect();
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].label, "test_record_timings timer");
+        assert_eq!(timings[0].count, 2);
+        assert!(timings[0].total.is_some());
+        assert_eq!(
+            timings[1],
+            Timing {
+                label: "test_record_timings counter",
+                count: 1,
+                total: None,
+            }
+        );
+    }
+}
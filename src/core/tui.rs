@@ -1,11 +1,131 @@
 //! Utilities to render an interactive text-based user interface.
-use cursive::theme::{Color, PaletteColor};
+use cursive::event::{Event, Key};
+use cursive::theme::{BaseColor, Color, PaletteColor};
 use cursive::{Cursive, CursiveRunnable, CursiveRunner};
+use fn_error_context::context;
+use log::warn;
+
+use crate::core::config::{
+    get_tui_keymap, get_tui_theme_color, get_tui_theme_preset, TUI_KEYMAP_CONFIG_SECTION,
+    TUI_THEME_CONFIG_SECTION,
+};
+use crate::core::metadata::HIDDEN_EXPLANATION_COLOR;
+
+/// Key in the Cursive palette holding the color used for commits on the main
+/// branch. See `TuiTheme::main_branch`.
+pub const MAIN_BRANCH_PALETTE_KEY: &str = "branchless_main_branch";
+
+/// Key in the Cursive palette holding the color used for hidden commits. See
+/// `TuiTheme::hidden`.
+pub const HIDDEN_PALETTE_KEY: &str = "branchless_hidden";
+
+/// The colors used to render interactive interfaces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TuiTheme {
+    /// The background color of the screen and of views.
+    pub background: Color,
+
+    /// The color of ordinary text.
+    pub foreground: Color,
+
+    /// The background color of the selected item.
+    pub highlight: Color,
+
+    /// The color of the glyphs for commits on the main branch.
+    pub main_branch: Color,
+
+    /// The color of the explanations for why commits are hidden.
+    pub hidden: Color,
+}
+
+impl Default for TuiTheme {
+    /// Use the terminal's own colors wherever possible.
+    fn default() -> Self {
+        TuiTheme {
+            background: Color::TerminalDefault,
+            foreground: Color::TerminalDefault,
+            highlight: BaseColor::Red.dark(),
+            main_branch: Color::TerminalDefault,
+            hidden: HIDDEN_EXPLANATION_COLOR,
+        }
+    }
+}
+
+impl TuiTheme {
+    /// Get the built-in theme with the given name, if any. The available
+    /// themes are `dark` and `light`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(TuiTheme {
+                background: BaseColor::Black.dark(),
+                foreground: BaseColor::White.light(),
+                highlight: BaseColor::Blue.dark(),
+                main_branch: BaseColor::Green.light(),
+                hidden: BaseColor::Black.light(),
+            }),
+            "light" => Some(TuiTheme {
+                background: BaseColor::White.light(),
+                foreground: BaseColor::Black.dark(),
+                highlight: BaseColor::Blue.dark(),
+                main_branch: BaseColor::Green.dark(),
+                hidden: BaseColor::Magenta.dark(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Get the theme to use for interactive interfaces, as configured by the user
+/// in the `branchless.tui.theme` config section.
+///
+/// The `preset` key selects one of the built-in themes (see
+/// `TuiTheme::preset`), and the `background`, `foreground`, `highlight`,
+/// `mainBranch`, and `hidden` keys override individual colors of that theme.
+/// Colors are written as e.g. `red`, `light blue`, `default`, or `#rrggbb`.
+/// Invalid values are ignored with a warning.
+#[context("Getting TUI theme")]
+pub fn get_tui_theme(repo: &git2::Repository) -> anyhow::Result<TuiTheme> {
+    let mut theme = match get_tui_theme_preset(repo)? {
+        None => TuiTheme::default(),
+        Some(preset) => TuiTheme::preset(&preset).unwrap_or_else(|| {
+            warn!(
+                "Unrecognized theme {:?} in config `{}.preset`, using default theme instead",
+                preset, TUI_THEME_CONFIG_SECTION
+            );
+            TuiTheme::default()
+        }),
+    };
+
+    for (element, color) in [
+        ("background", &mut theme.background),
+        ("foreground", &mut theme.foreground),
+        ("highlight", &mut theme.highlight),
+        ("mainBranch", &mut theme.main_branch),
+        ("hidden", &mut theme.hidden),
+    ] {
+        if let Some(value) = get_tui_theme_color(repo, element)? {
+            match Color::parse(value.trim()) {
+                Some(parsed_color) => *color = parsed_color,
+                None => warn!(
+                    "Unrecognized color {:?} in config `{}.{}`, ignoring",
+                    value, TUI_THEME_CONFIG_SECTION, element
+                ),
+            }
+        }
+    }
+    Ok(theme)
+}
 
 /// Create an instance of a `CursiveRunner`, and clean it up afterward.
+///
+/// The Cursive theme is set according to the user's `branchless.tui.theme`
+/// configuration (see `get_tui_theme`).
 pub(crate) fn with_siv<T, F: FnOnce(CursiveRunner<CursiveRunnable>) -> anyhow::Result<T>>(
+    repo: &git2::Repository,
     f: F,
 ) -> anyhow::Result<T> {
+    let tui_theme = get_tui_theme(repo)?;
+
     // I tried these back-ends:
     //
     // * `ncurses`/`pancurses`: Doesn't render ANSI escape codes. (NB: the fact
@@ -27,10 +147,17 @@ pub(crate) fn with_siv<T, F: FnOnce(CursiveRunner<CursiveRunnable>) -> anyhow::R
     siv.update_theme(|theme| {
         theme.shadow = false;
         theme.palette.extend(vec![
-            (PaletteColor::Background, Color::TerminalDefault),
-            (PaletteColor::View, Color::TerminalDefault),
-            (PaletteColor::Primary, Color::TerminalDefault),
+            (PaletteColor::Background, tui_theme.background),
+            (PaletteColor::View, tui_theme.background),
+            (PaletteColor::Primary, tui_theme.foreground),
+            (PaletteColor::Highlight, tui_theme.highlight),
         ]);
+        theme
+            .palette
+            .set_color(MAIN_BRANCH_PALETTE_KEY, tui_theme.main_branch);
+        theme
+            .palette
+            .set_color(HIDDEN_PALETTE_KEY, tui_theme.hidden);
     });
     let old_max_level = log::max_level();
     log::set_max_level(log::LevelFilter::Off);
@@ -40,6 +167,127 @@ pub(crate) fn with_siv<T, F: FnOnce(CursiveRunner<CursiveRunnable>) -> anyhow::R
     Ok(result)
 }
 
+/// The set of keys bound to an action in an interactive interface.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    names: Vec<String>,
+    events: Vec<Event>,
+}
+
+impl KeyBindings {
+    /// The Cursive events which should trigger the action.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Describe all of the bound keys to the user, such as `q/<esc>`.
+    pub fn describe(&self) -> String {
+        self.names.join("/")
+    }
+
+    /// Describe the first of the bound keys to the user. Useful for brief
+    /// hints.
+    pub fn describe_first(&self) -> &str {
+        &self.names[0]
+    }
+}
+
+/// Parse a key as written in the user's configuration.
+///
+/// A key is either a single character, such as `j` or `?`, or the name of a
+/// special key in angle brackets, such as `<down>`, `<enter>`, or `<c-n>` (for
+/// Ctrl-N).
+fn parse_key(key: &str) -> Option<Event> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Event::Char(c));
+    }
+
+    let name = key.strip_prefix('<')?.strip_suffix('>')?.to_lowercase();
+    let event = match name.as_str() {
+        "left" => Key::Left.into(),
+        "right" => Key::Right.into(),
+        "up" => Key::Up.into(),
+        "down" => Key::Down.into(),
+        "enter" | "cr" | "return" => Key::Enter.into(),
+        "esc" | "escape" => Key::Esc.into(),
+        "tab" => Key::Tab.into(),
+        "backspace" | "bs" => Key::Backspace.into(),
+        "del" | "delete" => Key::Del.into(),
+        "ins" | "insert" => Key::Ins.into(),
+        "home" => Key::Home.into(),
+        "end" => Key::End.into(),
+        "pageup" | "pgup" => Key::PageUp.into(),
+        "pagedown" | "pgdn" => Key::PageDown.into(),
+        "space" => Event::Char(' '),
+        "lt" => Event::Char('<'),
+        "gt" => Event::Char('>'),
+        name => {
+            let c = name.strip_prefix("c-")?;
+            let mut chars = c.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Event::CtrlChar(c),
+                _ => return None,
+            }
+        }
+    };
+    Some(event)
+}
+
+/// Get the keys bound to the given action, as configured by the user in the
+/// `branchless.tui.keymap` config section.
+///
+/// The configured value is a whitespace-separated list of keys (see
+/// `parse_key`). If it's not set, or if any of the keys can't be parsed, then
+/// a warning is issued and `default_keys` are used instead.
+#[context("Getting key bindings for TUI action: {}", action)]
+pub fn get_key_bindings(
+    repo: &git2::Repository,
+    action: &str,
+    default_keys: &[&str],
+) -> anyhow::Result<KeyBindings> {
+    let make_default_bindings = || KeyBindings {
+        names: default_keys.iter().map(|key| key.to_string()).collect(),
+        events: default_keys
+            .iter()
+            .map(|key| parse_key(key).expect("Invalid default key binding"))
+            .collect(),
+    };
+
+    let keys = match get_tui_keymap(repo, action)? {
+        Some(keys) => keys,
+        None => return Ok(make_default_bindings()),
+    };
+
+    let mut bindings = KeyBindings {
+        names: Vec::new(),
+        events: Vec::new(),
+    };
+    for key in keys.split_whitespace() {
+        match parse_key(key) {
+            Some(event) => {
+                bindings.names.push(key.to_owned());
+                bindings.events.push(event);
+            }
+            None => {
+                warn!(
+                    "Unrecognized key {:?} in config `{}.{}`, using default keys instead",
+                    key, TUI_KEYMAP_CONFIG_SECTION, action
+                );
+                return Ok(make_default_bindings());
+            }
+        }
+    }
+    if bindings.events.is_empty() {
+        warn!(
+            "No keys set in config `{}.{}`, using default keys instead",
+            TUI_KEYMAP_CONFIG_SECTION, action
+        );
+        return Ok(make_default_bindings());
+    }
+    Ok(bindings)
+}
+
 /// Type-safe "singleton" view: a kind of view which is addressed by name, for
 /// which exactly one copy exists in the Cursive application.
 pub trait SingletonView<V> {
@@ -97,6 +345,61 @@ macro_rules! declare_views {
     };
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::with_git;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("j"), Some(Event::Char('j')));
+        assert_eq!(parse_key("?"), Some(Event::Char('?')));
+        assert_eq!(parse_key("<"), Some(Event::Char('<')));
+        assert_eq!(parse_key("<Down>"), Some(Event::Key(Key::Down)));
+        assert_eq!(parse_key("<enter>"), Some(Event::Key(Key::Enter)));
+        assert_eq!(parse_key("<space>"), Some(Event::Char(' ')));
+        assert_eq!(parse_key("<c-n>"), Some(Event::CtrlChar('n')));
+        assert_eq!(parse_key("<c-nope>"), None);
+        assert_eq!(parse_key("<foo>"), None);
+        assert_eq!(parse_key("jk"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn test_get_tui_theme() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            let repo = git.get_repo()?;
+            assert_eq!(get_tui_theme(&repo)?, TuiTheme::default());
+
+            git.run(&["config", "branchless.tui.theme.preset", "light"])?;
+            assert_eq!(get_tui_theme(&repo)?, TuiTheme::preset("light").unwrap());
+
+            git.run(&["config", "branchless.tui.theme.hidden", "light blue"])?;
+            git.run(&["config", "branchless.tui.theme.mainBranch", "not a color"])?;
+            assert_eq!(
+                get_tui_theme(&repo)?,
+                TuiTheme {
+                    hidden: BaseColor::Blue.light(),
+                    ..TuiTheme::preset("light").unwrap()
+                }
+            );
+
+            git.run(&["config", "branchless.tui.theme.preset", "nonexistent"])?;
+            assert_eq!(
+                get_tui_theme(&repo)?,
+                TuiTheme {
+                    hidden: BaseColor::Blue.light(),
+                    ..TuiTheme::default()
+                }
+            );
+
+            Ok(())
+        })
+    }
+}
+
 /// Testing helpers for interactive interfaces.
 pub mod testing {
     use std::borrow::Borrow;
@@ -132,13 +435,24 @@ pub mod testing {
     }
 
     impl<'screenshot> CursiveTestingBackend {
-        /// Construct the testing backend with the provided set of events.
+        /// Construct the testing backend with the provided set of events, and
+        /// a virtual screen of 120x24 characters.
         pub fn init(events: Vec<CursiveTestingEvent>) -> Box<dyn Backend> {
+            Self::init_with_size(events, 120, 24)
+        }
+
+        /// Construct the testing backend with the provided set of events, and
+        /// a virtual screen of the given size in characters.
+        pub fn init_with_size(
+            events: Vec<CursiveTestingEvent>,
+            width: usize,
+            height: usize,
+        ) -> Box<dyn Backend> {
             Box::new(CursiveTestingBackend {
                 events,
                 event_index: 0,
                 just_emitted_event: false,
-                screen: RefCell::new(vec![vec![' '; 120]; 24]),
+                screen: RefCell::new(vec![vec![' '; width]; height]),
                 screenshots: Vec::new(),
             })
         }
@@ -180,7 +494,7 @@ pub mod testing {
         }
 
         fn print_at(&self, pos: cursive::Vec2, text: &str) {
-            for (i, c) in text.char_indices() {
+            for (i, c) in text.chars().enumerate() {
                 let mut screen = self.screen.borrow_mut();
                 let screen_width = screen[0].len();
                 if pos.x + i < screen_width {
@@ -35,6 +35,112 @@
 //!     old versions of rebased commits are considered hidden. You can also
 //!     manually hide commits that you no longer need. Not visible to you in the
 //!     branchless workflow.
+//!
+//! # Library usage
+//!
+//! The commit graph displayed by `git smartlog` can be constructed and
+//! inspected from other Rust programs. The main entry points are re-exported
+//! at the crate root:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! let repo = git2::Repository::open_from_env()?;
+//! let graph = branchless::make_smartlog_graph(&repo)?;
+//! for (oid, node) in graph.iter() {
+//!     if node.is_visible && !node.is_main {
+//!         println!("{} {}", oid, node.commit.summary().unwrap_or_default());
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! See the `core::graph` module for details, and `make_graph` for constructing
+//! the graph with non-default arguments (such as at an earlier point in the
+//! event log).
+//!
+//! The graph can be rendered into lines in the same way as `git smartlog`,
+//! without touching the terminal, with `render_smartlog_graph`. For example,
+//! to render it into a panel 40 columns wide:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use branchless::core::formatting::Glyphs;
+//!
+//! let repo = git2::Repository::open_from_env()?;
+//! let graph = branchless::make_smartlog_graph(&repo)?;
+//! let lines = branchless::render_smartlog_graph(&Glyphs::text(), &repo, &graph, Some(40))?;
+//! for line in lines {
+//!     println!("{}", line);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! See `commands::smartlog::render_graph` for rendering with other commit
+//! metadata or options.
+//!
+//! The sub-commands themselves are in the `commands` module. Some of them, such
+//! as `commands::move::move`, write their messages to a provided output stream
+//! rather than to `stdout`, so that the output can be captured:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use branchless::commands::r#move::{r#move, MoveOptions};
+//! use branchless::util::GitExecutable;
+//!
+//! let git_executable = GitExecutable("git".into());
+//! let mut output: Vec<u8> = Vec::new();
+//! let result = r#move(
+//!     &mut output,
+//!     &git_executable,
+//!     &MoveOptions {
+//!         source: Some("my-branch".to_string()),
+//!         dest: Some("master".to_string()),
+//!         ..Default::default()
+//!     },
+//! );
+//! print!("{}", String::from_utf8_lossy(&output));
+//! result?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The library entry points, such as `make_smartlog_graph` and
+//! `commands::move::move`, return a `BranchlessError` rather than an
+//! `anyhow::Error`, so that callers can match on the kind of failure:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use branchless::commands::r#move::{r#move, MoveOptions};
+//! use branchless::util::GitExecutable;
+//! use branchless::BranchlessError;
+//!
+//! let git_executable = GitExecutable("git".into());
+//! let mut output: Vec<u8> = Vec::new();
+//! let result = r#move(
+//!     &mut output,
+//!     &git_executable,
+//!     &MoveOptions {
+//!         source: Some("my-branch".to_string()),
+//!         dest: Some("master".to_string()),
+//!         abort_on_conflict: true,
+//!         ..Default::default()
+//!     },
+//! );
+//! match result {
+//!     Ok(()) => println!("Moved my-branch onto master"),
+//!     Err(BranchlessError::Conflict { commit_oids, .. }) => {
+//!         println!("Moving my-branch would conflict in {:?}", commit_oids)
+//!     }
+//!     Err(BranchlessError::MainBranchUnresolved { main_branch_name, .. }) => {
+//!         println!("Main branch not found: {}", main_branch_name)
+//!     }
+//!     Err(err) => return Err(err.into()),
+//! }
+//! # Ok(())
+//! # }
+//! ```
 
 #![warn(clippy::all, missing_docs)]
 #![allow(clippy::too_many_arguments)]
@@ -43,3 +149,10 @@ pub mod commands;
 pub mod core;
 pub mod testing;
 pub mod util;
+
+pub use crate::commands::smartlog::render_smartlog_graph;
+pub use crate::core::error::BranchlessError;
+pub use crate::core::graph::{
+    make_graph, make_smartlog_graph, AdditionalMainBranchOids, BranchOids, CommitGraph, CommitOids,
+    HeadOid, MainBranchOid, Node, TagOids, UnhideableRefOids,
+};
@@ -1,9 +1,15 @@
 use std::convert::TryInto;
+use std::io::stdout;
 use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 use branchless::commands::wrap;
-use branchless::util::GitExecutable;
+use branchless::core::config::get_core_color;
+use branchless::core::formatting::{set_color_setting, ColorSetting};
+use branchless::core::graph::CommitTimeRange;
+use branchless::core::rewrite::ExecOptions;
+use branchless::core::timing::{render_timings, set_timings_enabled};
+use branchless::util::{get_repo, parse_git_date, GitExecutable};
 use simple_logger::SimpleLogger;
 use structopt::StructOpt;
 
@@ -18,35 +24,203 @@ enum WrappedCommand {
 /// See the documentation at https://github.com/arxanas/git-branchless/wiki.
 #[derive(StructOpt)]
 #[structopt(version = env!("CARGO_PKG_VERSION"), author = "Waleed Khan <me@waleedkhan.name>")]
-enum Opts {
+struct Opts {
+    /// When to write colors to the terminal: `always`, `never`, or `auto` to
+    /// write them only if the output is a terminal. Defaults to the value of
+    /// `branchless.core.color`, or else `auto`.
+    #[structopt(long = "--color", global = true)]
+    color: Option<ColorSetting>,
+
+    /// Print a summary of how long each part of the command took to stderr,
+    /// such as building the commit graph and querying merge-bases.
+    #[structopt(long = "--timings", global = true)]
+    timings: bool,
+
+    /// Log more details, such as to include in a bug report: `-v` for
+    /// informational messages, `-vv` for debugging messages, and `-vvv` for
+    /// everything. Only warnings and errors are logged by default.
+    #[structopt(short = "-v", parse(from_occurrences), global = true)]
+    verbosity: u8,
+
+    /// The path to the repository's `.git` directory, as with `git --git-dir`.
+    /// Defaults to the value of `$GIT_DIR`, or else the repository containing
+    /// the current directory.
+    #[structopt(long = "--git-dir", global = true, parse(from_os_str))]
+    git_dir: Option<PathBuf>,
+
+    /// The path to the repository's working tree, as with `git --work-tree`.
+    /// Defaults to the value of `$GIT_WORK_TREE`.
+    #[structopt(long = "--work-tree", global = true, parse(from_os_str))]
+    work_tree: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
     /// Initialize the branchless workflow for this repository.
-    Init,
+    Init {
+        /// Install hooks even if the hooks directory configured with
+        /// `core.hooksPath` is outside of this repository, and overwrite
+        /// aliases which are already defined as something else.
+        #[structopt(long = "--force")]
+        force: bool,
+
+        /// Customize the installed aliases, such as `bsl=smartlog` to install
+        /// `git bsl` as an alias for `git branchless smartlog`, or `sl=` to not
+        /// install `git sl`. Can be given multiple times, and is applied after
+        /// `branchless.init.aliases`.
+        #[structopt(long = "--alias", number_of_values = 1)]
+        alias: Vec<String>,
+    },
 
     /// Display a nice graph of the commits you've recently worked on.
-    Smartlog,
+    Smartlog {
+        /// Don't pipe the output into a pager, even if it's too tall to fit
+        /// in the terminal.
+        #[structopt(long = "--no-pager")]
+        no_pager: bool,
+
+        /// Show the full commit message of each visible commit, not just its
+        /// summary line. (`-v` sets the log level instead; see `git
+        /// branchless --help`.)
+        #[structopt(long = "--verbose")]
+        verbose: bool,
+
+        /// The format to show the smartlog in: `text`, `mermaid` to render
+        /// it as a Mermaid `gitGraph` diagram, or a template to render each
+        /// commit as one line, such as `"%h %an %s"`. Templates support `%h`
+        /// and `%H` (abbreviated and full OID), `%s` (summary), `%an` and
+        /// `%ae` (author name and email), `%cr` (relative commit time), `%g`
+        /// (the commit's glyph in the graph), and `%%`.
+        #[structopt(long = "--format", default_value = "text")]
+        format: branchless::commands::smartlog::SmartlogFormat,
+
+        /// Only show commits and branches committed after this date, such as
+        /// `2 weeks ago`. Accepts the same date formats as Git.
+        #[structopt(long = "--since")]
+        since: Option<String>,
+
+        /// Only show commits and branches committed before this date, such as
+        /// `yesterday`. Accepts the same date formats as Git.
+        #[structopt(long = "--until")]
+        until: Option<String>,
+
+        /// Show the commits of each branch separately, under a header with the
+        /// branch name. Commits which are on multiple branches are shown under
+        /// the nearest one.
+        #[structopt(long = "--group-by-branch")]
+        group_by_branch: bool,
+
+        /// Render the smartlog from scratch, even if `branchless.smartlog.cache`
+        /// is set and a cached rendering is available.
+        #[structopt(long = "--no-cache")]
+        no_cache: bool,
+
+        /// Show a legend explaining what each glyph in the smartlog means.
+        #[structopt(long = "--legend")]
+        legend: bool,
+
+        /// Don't show main branch commits older than the one that the current
+        /// commit is based on, unless a branch points to them.
+        #[structopt(long = "--no-main")]
+        no_main: bool,
+
+        /// Only show commits whose author matches this pattern, such as
+        /// `jane` or `*@example.com`, along with the commits needed to connect
+        /// them to the main branch. The pattern is matched case-insensitively
+        /// as a substring of `Name <email>`, or as a glob if it contains `*` or
+        /// `?`.
+        #[structopt(long = "--author")]
+        author: Option<String>,
+
+        /// Show how many files and lines each commit changed relative to its
+        /// (first) parent, such as `(3 files, +40 -12)`.
+        #[structopt(long = "--stat")]
+        stat: bool,
+
+        /// Only show this page of stacks, starting from 1, with the most
+        /// recently updated stacks first. The number of stacks on each page is
+        /// set by `branchless.smartlog.pageSize` (default 5).
+        #[structopt(long = "--page")]
+        page: Option<usize>,
+
+        /// Show stash entries, such as `stash@{0}`, attached to the commits
+        /// they were based on. Can also be enabled with
+        /// `branchless.smartlog.showStashes`.
+        #[structopt(long = "--stashes")]
+        stashes: bool,
+
+        /// Only show the commits near `HEAD`: the main branch commit which it
+        /// forked off from, and the commits forked off from there. This is
+        /// faster in repositories with many other lines of work, since they
+        /// don't need to be found at all.
+        #[structopt(long = "--fast")]
+        fast: bool,
+
+        /// Print the commits near `HEAD` first, as with `--fast`, and then
+        /// print the other lines of work as they're found, without using the
+        /// pager.
+        #[structopt(
+            long = "--incremental",
+            conflicts_with_all(&["fast", "group-by-branch", "page"])
+        )]
+        incremental: bool,
+    },
 
     /// Hide the provided commits from the smartlog.
     Hide {
         /// Zero or more commits to hide.
         ///
-        /// Can either be hashes, like `abc123`, or ref-specs, like `HEAD^`.
+        /// Can either be hashes, like `abc123`, or ref-specs, like `HEAD^`. A
+        /// range, like `A..B`, hides the commits reachable from `B` but not
+        /// from `A`, except for those still reachable from a branch.
         commits: Vec<String>,
 
+        /// Also hide the commits in the current stack, from the current commit
+        /// back to the main branch.
+        #[structopt(long = "--stack")]
+        stack: bool,
+
         /// Also recursively hide all children commits of the provided commits.
         #[structopt(short = "-r", long = "--recursive")]
         recursive: bool,
+
+        /// Instead of hiding the provided commits, hide all visible commits
+        /// which have already been merged into the main branch, such as by
+        /// being cherry-picked or rebased onto it. The commits are listed for
+        /// confirmation before they're hidden.
+        #[structopt(long = "--merged", conflicts_with_all(&["commits", "stack", "recursive"]))]
+        merged: bool,
+
+        /// Only print the commits which would be hidden, without hiding them.
+        #[structopt(long = "--dry-run")]
+        dry_run: bool,
+
+        /// With `--merged`, don't ask for confirmation before hiding the
+        /// commits.
+        #[structopt(short = "-y", long = "--yes")]
+        yes: bool,
     },
 
     /// Unhide previously-hidden commits from the smartlog.
     Unhide {
         /// Zero or more commits to unhide.
         ///
-        /// Can either be hashes, like `abc123`, or ref-specs, like `HEAD^`.
+        /// Can either be hashes, like `abc123`, or ref-specs, like `HEAD^`. A
+        /// range, like `A..B`, unhides the commits reachable from `B` but not
+        /// from `A`.
         commits: Vec<String>,
 
         /// Also recursively unhide all children commits of the provided commits.
         #[structopt(short = "-r", long = "--recursive")]
         recursive: bool,
+
+        /// Only print the commits which would be unhidden, without unhiding
+        /// them.
+        #[structopt(long = "--dry-run")]
+        dry_run: bool,
     },
 
     /// Move to an earlier commit in the current stack.
@@ -69,6 +243,19 @@ enum Opts {
         /// When encountering multiple next commits, choose the newest.
         #[structopt(short = "-n", long = "--newest", conflicts_with("oldest"))]
         newest: bool,
+
+        /// Also traverse hidden commits, such as old versions of rewritten
+        /// commits. Checking out a hidden commit doesn't unhide it.
+        #[structopt(long = "--hidden")]
+        hidden: bool,
+    },
+
+    /// Check out a commit and display the smartlog.
+    Checkout {
+        /// The commit to check out. This can be a branch name, a commit hash
+        /// or other revision, or a substring of the summary line of a commit
+        /// in the smartlog, such as `"fix login"`.
+        target: String,
     },
 
     /// Move a subtree of commits from one location to another.
@@ -76,6 +263,9 @@ enum Opts {
     /// By default, `git move` attempts to rebase all commits in-memory. If you
     /// want to force an on-disk rebase, pass the `--on-disk` flag. Note that
     /// `post-commit` hooks are not called during in-memory rebases.
+    ///
+    /// Exits with code 1 if a merge conflict needs to be resolved manually, 2
+    /// if the arguments are invalid, or 3 if a commit couldn't be found.
     Move {
         /// The source commit to move. This commit, and all of its descendants,
         /// will be moved. If not provided, defaults to the current commit.
@@ -88,26 +278,210 @@ enum Opts {
         #[structopt(short = "-b", long = "--base", conflicts_with = "source")]
         base: Option<String>,
 
-        /// The destination commit to move all source commits onto. If not
-        /// provided, defaults to the current commit.
+        /// Move the current stack, i.e. the entire subtree containing the
+        /// current commit, starting from the main branch.
+        #[structopt(long = "--stack", conflicts_with_all(&["source", "base"]))]
+        stack: bool,
+
+        /// The destination commit to move all source commits onto. It becomes
+        /// the new parent of the root of the moved subtree. With `--base`, as
+        /// with `git rebase <upstream>`, commits which are already ancestors
+        /// of the destination aren't moved. If not provided, defaults to the
+        /// current commit. If `-`, then the source commit is moved back to
+        /// where it was before it was last moved.
         #[structopt(short = "-d", long = "--dest")]
         dest: Option<String>,
 
+        /// The commit to use as the new parent of the root of the moved
+        /// subtree, as with `git rebase --onto`. Unlike `--dest`, it never
+        /// affects which commits are moved: `--base` always moves the entire
+        /// subtree starting from the main branch. Can't be used with `--dest`.
+        #[structopt(long = "--onto")]
+        onto: Option<String>,
+
         /// Skip attempting to use an in-memory rebase, and try an
         /// on-disk rebase directly.
         #[structopt(long = "--on-disk")]
         force_on_disk: bool,
+
+        /// Check whether any of the commits would cause merge conflicts before
+        /// moving them, and don't start the move if so.
+        #[structopt(long = "--abort-on-conflict")]
+        abort_on_conflict: bool,
+
+        /// If the destination is a branch, check out that branch once the move
+        /// has completed, rather than leaving `HEAD` detached.
+        #[structopt(long = "--attach")]
+        attach: bool,
+
+        /// Before moving the commits, show the planned commits in a
+        /// text-based user interface, where they can be reordered within their
+        /// stacks, fixed up into the previous commit, or dropped.
+        #[structopt(short = "-i", long = "--interactive")]
+        interactive: bool,
+
+        /// Allow moving commits which have no history in common with the
+        /// destination, such as an imported repository. Its root commit is
+        /// grafted onto the destination.
+        #[structopt(long = "--allow-unrelated")]
+        allow_unrelated: bool,
+
+        /// Set the committer date of each moved commit to its author date, as
+        /// with `git rebase --committer-date-is-author-date`.
+        #[structopt(long = "--committer-date-is-author-date")]
+        committer_date_is_author_date: bool,
+
+        /// Run this shell command in the working copy once the commits have
+        /// been moved, with the new `HEAD` checked out.
+        #[structopt(long = "--exec")]
+        exec: Option<String>,
+
+        /// Run this shell command on each moved commit, in a temporary
+        /// directory with the commit checked out. Can be given multiple times.
+        #[structopt(long = "--exec-each", number_of_values = 1)]
+        exec_each: Vec<String>,
+    },
+
+    /// Amend the staged changes into a commit, and then restack its
+    /// descendants onto the amended commit.
+    ///
+    /// Exits with code 1 if a merge conflict needs to be resolved manually.
+    Amend {
+        /// The commit to amend. If not provided, defaults to the current
+        /// commit. The staged changes are applied on top of this commit.
+        commit: Option<String>,
+
+        /// Use the given message as the commit message, rather than keeping
+        /// the existing message.
+        #[structopt(short = "-m", long = "--message")]
+        message: Option<String>,
     },
 
     /// Fix up commits abandoned by a previous rewrite operation.
-    Restack,
+    Restack {
+        /// Set the committer date of each restacked commit to its author date,
+        /// as with `git rebase --committer-date-is-author-date`.
+        #[structopt(long = "--committer-date-is-author-date")]
+        committer_date_is_author_date: bool,
+
+        /// Run this shell command in the working copy once the commits have
+        /// been restacked, with the new `HEAD` checked out.
+        #[structopt(long = "--exec")]
+        exec: Option<String>,
+
+        /// Run this shell command on each restacked commit, in a temporary
+        /// directory with the commit checked out. Can be given multiple times.
+        #[structopt(long = "--exec-each", number_of_values = 1)]
+        exec_each: Vec<String>,
+    },
+
+    /// Point a branch at the current commit and check it out, creating the
+    /// branch if it doesn't exist, or moving it here if it does. The branch
+    /// update can be reverted with `git undo`.
+    Reattach {
+        /// The name of the branch. If not provided, you're prompted for it.
+        branch: Option<String>,
+    },
+
+    /// Move all stacks of commits onto the main branch, such as after pulling
+    /// new commits into it. Stacks which would cause merge conflicts are
+    /// skipped.
+    Sync,
+
+    /// Run a command on each commit in a stack, and report which commits it
+    /// passed or failed on. The results are shown in the smartlog.
+    ///
+    /// Each commit is checked out into a temporary directory to run the
+    /// command. Results are cached, so commits whose contents haven't changed
+    /// aren't tested again. Exits with code 1 if the command failed on any
+    /// commit.
+    Test {
+        /// The shell command to run on each commit, such as `"cargo test"`.
+        #[structopt(short = "-x", long = "--command")]
+        command: String,
+
+        /// The commit to test, along with all of its descendants. If not
+        /// provided, defaults to the current commit.
+        #[structopt(short = "-s", long = "--source")]
+        source: Option<String>,
+
+        /// A commit inside a stack to test. The entire stack, starting from
+        /// the main branch, will be tested.
+        #[structopt(short = "-b", long = "--base", conflicts_with = "source")]
+        base: Option<String>,
+
+        /// Test the commits in the current stack, from the main branch up to
+        /// the current commit.
+        #[structopt(long = "--stack", conflicts_with_all(&["source", "base"]))]
+        stack: bool,
+
+        /// Record the results as notes under this ref, such as
+        /// `refs/notes/branchless-test`. Defaults to
+        /// `branchless.smartlog.statusNotesRef`. If neither is set, the results
+        /// aren't recorded as notes.
+        #[structopt(long = "--notes-ref")]
+        notes_ref: Option<String>,
+    },
+
+    /// Show the diff of each commit in a stack against its parent, in the
+    /// order that they're shown in the smartlog.
+    StackDiff {
+        /// The commit to diff, along with all of its descendants. If neither
+        /// this nor `--base` is provided, the entire stack containing the
+        /// current commit is diffed.
+        #[structopt(short = "-s", long = "--source")]
+        source: Option<String>,
+
+        /// A commit inside a stack to diff. The entire stack, starting from
+        /// the main branch, will be diffed.
+        #[structopt(short = "-b", long = "--base", conflicts_with = "source")]
+        base: Option<String>,
+
+        /// Diff the commits in the current stack, from the main branch up to
+        /// the current commit.
+        #[structopt(long = "--stack", conflicts_with_all(&["source", "base"]))]
+        stack: bool,
+
+        /// Show the cumulative diff from the base of the stack to its tip,
+        /// rather than the diff of each commit.
+        #[structopt(long = "--combined")]
+        combined: bool,
+
+        /// Open each diff in the configured `git difftool`, rather than
+        /// printing it.
+        #[structopt(long = "--tool")]
+        use_difftool: bool,
+    },
 
     /// Browse or return to a previous state of the repository.
-    Undo,
+    Undo {
+        /// Apply the selected undo without asking for confirmation.
+        #[structopt(short = "-y", long = "--yes")]
+        yes: bool,
+    },
+
+    /// Show every event which refers to the provided commit, in
+    /// chronological order. Useful for understanding why a commit is visible
+    /// or hidden.
+    Events {
+        /// The commit to show events for.
+        ///
+        /// Can either be a hash, like `abc123`, or a ref-spec, like `HEAD^`.
+        commit: String,
+    },
+
+    /// Check that git-branchless is set up correctly in this repository, and
+    /// explain how to fix any problems.
+    Doctor,
 
     /// Run internal garbage collection.
     Gc,
 
+    /// Record any commits and reference updates which are missing from the
+    /// event log, such as because a hook failed to run, so that the smartlog
+    /// matches the repository again.
+    Repair,
+
     /// Wrap a Git command inside a branchless transaction.
     Wrap {
         #[structopt(long = "--git-executable")]
@@ -133,45 +507,153 @@ enum Opts {
     /// Internal use.
     HookPostCommit,
 
+    /// Internal use.
+    HookPostMerge { is_squash_merge: isize },
+
     /// Internal use.
     HookReferenceTransaction { transaction_state: String },
 }
 
+/// Get the maximum level of messages to log for the given number of `-v`
+/// flags.
+fn get_log_level_filter(verbosity: u8) -> log::LevelFilter {
+    match verbosity {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
 fn main() -> anyhow::Result<()> {
+    let Opts {
+        color,
+        timings,
+        verbosity,
+        git_dir,
+        work_tree,
+        command,
+    } = Opts::from_args();
+
+    // Like `git`, pass these options on through the environment, so that
+    // they're honored when opening the repository and by any `git` processes
+    // that are run.
+    if let Some(git_dir) = git_dir {
+        std::env::set_var("GIT_DIR", git_dir);
+    }
+    if let Some(work_tree) = work_tree {
+        std::env::set_var("GIT_WORK_TREE", work_tree);
+    }
+
+    // The level is temporarily overridden while a text-based user interface is
+    // shown (see `with_siv`), and restored afterward.
     SimpleLogger::new()
+        .with_level(get_log_level_filter(verbosity))
         .init()
         .with_context(|| "Initializing logging")?;
 
-    let opts = Opts::from_args();
+    set_timings_enabled(timings);
+    let color = match color {
+        Some(color) => color,
+        None => match get_repo() {
+            Ok(repo) => get_core_color(&repo)?.unwrap_or_default(),
+            Err(_) => ColorSetting::Auto,
+        },
+    };
+    set_color_setting(color);
+
     let git_executable = std::env::var("PATH_TO_GIT").unwrap_or_else(|_| "git".to_string());
     let git_executable = Path::new(&git_executable);
     let git_executable = GitExecutable(git_executable.to_path_buf());
 
-    let exit_code = match opts {
-        Opts::Init => {
-            branchless::commands::init::init(&git_executable)?;
-            0
+    let exit_code = match command {
+        Command::Init { force, alias } => {
+            branchless::commands::init::init(&git_executable, force, alias)?
         }
 
-        Opts::Smartlog => {
-            branchless::commands::smartlog::smartlog()?;
+        Command::Smartlog {
+            no_pager,
+            verbose,
+            format,
+            since,
+            until,
+            group_by_branch,
+            no_cache,
+            legend,
+            no_main,
+            author,
+            stat,
+            page,
+            stashes,
+            fast,
+            incremental,
+        } => {
+            let commit_time_range = {
+                let repo = get_repo()?;
+                let parse_date = |date: Option<String>| match date {
+                    Some(date) => parse_git_date(&repo, &git_executable, &date).map(Some),
+                    None => Ok(None),
+                };
+                CommitTimeRange {
+                    since: parse_date(since)?,
+                    until: parse_date(until)?,
+                }
+            };
+            branchless::commands::smartlog::smartlog(
+                &branchless::commands::smartlog::SmartlogOptions {
+                    use_pager: !no_pager,
+                    verbose,
+                    format,
+                    commit_time_range,
+                    group_by_branch,
+                    use_cache: !no_cache,
+                    show_legend: legend,
+                    hide_main_branch_tail: no_main,
+                    author,
+                    show_stat: stat,
+                    page,
+                    show_stashes: stashes,
+                    fast,
+                    incremental,
+                },
+            )?;
             0
         }
 
-        Opts::Hide { commits, recursive } => branchless::commands::hide::hide(commits, recursive)?,
+        Command::Hide {
+            commits,
+            stack,
+            recursive,
+            merged,
+            dry_run,
+            yes,
+        } => {
+            if merged {
+                branchless::commands::hide::hide_merged(dry_run, yes)?
+            } else {
+                branchless::commands::hide::hide(commits, stack, recursive, dry_run)?
+            }
+        }
+
+        Command::Unhide {
+            commits,
+            recursive,
+            dry_run,
+        } => branchless::commands::hide::unhide(commits, recursive, dry_run)?,
 
-        Opts::Unhide { commits, recursive } => {
-            branchless::commands::hide::unhide(commits, recursive)?
+        Command::Checkout { target } => {
+            branchless::commands::navigation::checkout(&git_executable, target)?
         }
 
-        Opts::Prev { num_commits } => {
+        Command::Prev { num_commits } => {
             branchless::commands::navigation::prev(&&git_executable, num_commits)?
         }
 
-        Opts::Next {
+        Command::Next {
             num_commits,
             oldest,
             newest,
+            hidden,
         } => {
             let towards = match (oldest, newest) {
                 (false, false) => None,
@@ -179,32 +661,120 @@ fn main() -> anyhow::Result<()> {
                 (false, true) => Some(branchless::commands::navigation::Towards::Newest),
                 (true, true) => anyhow::bail!("Both --oldest and --newest were set"),
             };
-            branchless::commands::navigation::next(&git_executable, num_commits, towards)?
+            branchless::commands::navigation::next(&git_executable, num_commits, towards, hidden)?
         }
 
-        Opts::Move {
+        Command::Move {
             source,
             dest,
+            onto,
             base,
+            stack,
             force_on_disk,
-        } => branchless::commands::r#move::r#move(
+            abort_on_conflict,
+            attach,
+            interactive,
+            allow_unrelated,
+            committer_date_is_author_date,
+            exec,
+            exec_each,
+        } => match branchless::commands::r#move::r#move(
+            &mut stdout(),
+            &git_executable,
+            &branchless::commands::r#move::MoveOptions {
+                source,
+                dest,
+                onto,
+                base,
+                stack,
+                force_on_disk,
+                abort_on_conflict,
+                attach,
+                interactive,
+                allow_unrelated,
+                committer_date_is_author_date,
+                exec_options: ExecOptions { exec, exec_each },
+            },
+        ) {
+            Ok(()) => 0,
+            // The reason for the failure has already been written to `stdout`.
+            Err(err) => match err.exit_code() {
+                Some(exit_code) => exit_code,
+                None => return Err(err.into()),
+            },
+        },
+
+        Command::Amend { commit, message } => {
+            branchless::commands::amend::amend(&mut stdout(), &git_executable, commit, message)?
+        }
+
+        Command::Restack {
+            committer_date_is_author_date,
+            exec,
+            exec_each,
+        } => branchless::commands::restack::restack(
             &git_executable,
+            committer_date_is_author_date,
+            &ExecOptions { exec, exec_each },
+        )?,
+
+        Command::Reattach { branch } => {
+            branchless::commands::reattach::reattach(&mut stdout(), &git_executable, branch)?
+        }
+
+        Command::Sync => branchless::commands::sync::sync(&git_executable)?,
+
+        Command::Test {
+            command,
             source,
-            dest,
             base,
-            force_on_disk,
+            stack,
+            notes_ref,
+        } => branchless::commands::test::test(
+            &mut stdout(),
+            &git_executable,
+            &command,
+            source,
+            base,
+            stack,
+            notes_ref,
+        )?,
+
+        Command::StackDiff {
+            source,
+            base,
+            stack,
+            combined,
+            use_difftool,
+        } => branchless::commands::stack_diff::stack_diff(
+            &mut stdout(),
+            &git_executable,
+            source,
+            base,
+            stack,
+            combined,
+            use_difftool,
         )?,
 
-        Opts::Restack => branchless::commands::restack::restack(&git_executable)?,
+        Command::Undo { yes } => branchless::commands::undo::undo(&git_executable, yes)?,
+
+        Command::Events { commit } => branchless::commands::events::events(&mut stdout(), commit)?,
+
+        Command::Doctor => branchless::commands::doctor::doctor(&mut stdout(), &git_executable)?,
 
-        Opts::Undo => branchless::commands::undo::undo(&git_executable)?,
+        Command::Repair => branchless::commands::repair::repair(&mut stdout())?,
+
+        Command::Gc => {
+            branchless::commands::gc::gc(true)?;
+            0
+        }
 
-        Opts::Gc | Opts::HookPreAutoGc => {
-            branchless::commands::gc::gc()?;
+        Command::HookPreAutoGc => {
+            branchless::commands::gc::gc(false)?;
             0
         }
 
-        Opts::Wrap {
+        Command::Wrap {
             git_executable: explicit_git_executable,
             command: WrappedCommand::WrappedCommand(args),
         } => {
@@ -216,12 +786,12 @@ fn main() -> anyhow::Result<()> {
             0
         }
 
-        Opts::HookPostRewrite { rewrite_type } => {
+        Command::HookPostRewrite { rewrite_type } => {
             branchless::commands::hooks::hook_post_rewrite(&rewrite_type)?;
             0
         }
 
-        Opts::HookPostCheckout {
+        Command::HookPostCheckout {
             previous_commit,
             current_commit,
             is_branch_checkout,
@@ -234,17 +804,28 @@ fn main() -> anyhow::Result<()> {
             0
         }
 
-        Opts::HookPostCommit => {
+        Command::HookPostCommit => {
             branchless::commands::hooks::hook_post_commit()?;
             0
         }
 
-        Opts::HookReferenceTransaction { transaction_state } => {
+        Command::HookPostMerge { is_squash_merge } => {
+            branchless::commands::hooks::hook_post_merge(is_squash_merge)?;
+            0
+        }
+
+        Command::HookReferenceTransaction { transaction_state } => {
             branchless::commands::hooks::hook_reference_transaction(&transaction_state)?;
             0
         }
     };
 
+    if timings {
+        for line in render_timings() {
+            eprintln!("{}", line);
+        }
+    }
+
     let exit_code: i32 = exit_code.try_into()?;
     std::process::exit(exit_code)
 }
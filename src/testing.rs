@@ -251,6 +251,7 @@ impl Git {
 
         if options.run_branchless_init {
             self.run(&["branchless", "init"])?;
+            self.run(&["config", "branchless.hint.smartlogLegend", "false"])?;
         }
 
         Ok(())
@@ -3,24 +3,63 @@
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::env;
-use std::io::{stderr, stdout, Write};
-use std::path::PathBuf;
+use std::io::{stderr, BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
 use std::str::FromStr;
 
 use anyhow::Context;
 use fn_error_context::context;
-use git2::ErrorCode;
 use log::warn;
 
-use crate::core::config::get_main_branch_name;
+use crate::core::config::{
+    get_core_hooks_path, get_core_pager, get_core_unhideable_refs, get_main_branch_name,
+    get_main_branch_names,
+};
+use crate::core::error::BranchlessError;
 use crate::core::eventlog::{EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
+use crate::core::formatting::{printable_styled_string, Glyphs};
+use crate::core::graph::{find_commits_by_summary, make_smartlog_graph, CommitGraph};
+use crate::core::metadata::{render_commit_metadata, CommitMessageProvider, CommitOidProvider};
 
 /// Convert a `git2::Error` into an `anyhow::Error` with an auto-generated message.
 pub fn wrap_git_error(error: git2::Error) -> anyhow::Error {
     anyhow::anyhow!("Git error {:?}: {}", error.code(), error.message())
 }
 
+/// Abbreviate an OID for display, using the fewest hexadecimal digits which
+/// are both at least `min_len` (see `get_core_abbrev`) and don't match any
+/// other object in the repository, in the same way as Git. This ensures that
+/// the abbreviated OID can be passed back to commands such as `git move`.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `oid`: The OID to abbreviate.
+/// * `min_len`: The minimum number of digits to use.
+///
+/// Returns: The abbreviated OID.
+#[context("Abbreviating OID {:?}", oid)]
+pub fn abbreviate_oid(
+    repo: &git2::Repository,
+    oid: git2::Oid,
+    min_len: usize,
+) -> anyhow::Result<String> {
+    let odb = repo.odb().context("Opening object database")?;
+    let oid = oid.to_string();
+    for len in min_len..oid.len() {
+        let prefix = &oid[..len];
+        match odb.exists_prefix(git2::Oid::from_str(prefix)?, len) {
+            Err(err) if err.code() == git2::ErrorCode::Ambiguous => continue,
+            // The object may not exist, such as if it's been garbage-collected,
+            // in which case it can't be ambiguous with anything else.
+            Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(prefix.to_string()),
+            Err(err) => return Err(wrap_git_error(err)),
+            Ok(_) => return Ok(prefix.to_string()),
+        }
+    }
+    Ok(oid)
+}
+
 /// Get the OID for the repository's `HEAD` reference.
 ///
 /// Args:
@@ -43,8 +82,91 @@ pub fn get_head_oid(repo: &git2::Repository) -> anyhow::Result<Option<git2::Oid>
     Ok(Some(head_commit.id()))
 }
 
+/// The names of branches which are commonly used as the main branch, in order
+/// of preference.
+const MAIN_BRANCH_NAME_CANDIDATES: &[&str] = &[
+    "master",
+    "main",
+    "mainline",
+    "devel",
+    "develop",
+    "development",
+    "trunk",
+];
+
+/// Find the local branches which look like they could be the main branch for
+/// the repository, based on their names.
+///
+/// Args:
+/// * `repo`: The Git repository.
+///
+/// Returns: The names of the candidate branches which exist in the
+/// repository, in order of preference.
+pub fn detect_main_branch_names(repo: &git2::Repository) -> Vec<String> {
+    MAIN_BRANCH_NAME_CANDIDATES
+        .iter()
+        .filter(|branch_name| {
+            repo.find_branch(branch_name, git2::BranchType::Local)
+                .is_ok()
+        })
+        .map(|branch_name| branch_name.to_string())
+        .collect()
+}
+
+/// Determine whether the main branch setting refers to a commit directly,
+/// rather than to a branch, such as when it's pinned to a specific commit in a
+/// CI environment.
+///
+/// A full 40-character OID is always treated as a commit, without looking up
+/// any references. An abbreviated OID is only treated as a commit if there is
+/// no branch with the same name.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `main_branch_name`: The main branch setting (see `get_main_branch_name`).
+///
+/// Returns: The OID which the main branch setting refers to, or `None` if it
+/// refers to a branch. The OID is not guaranteed to be a commit which exists in
+/// the repository.
+#[context("Resolving main branch {:?} as a commit OID", main_branch_name)]
+pub fn get_main_branch_commit_oid(
+    repo: &git2::Repository,
+    main_branch_name: &str,
+) -> anyhow::Result<Option<git2::Oid>> {
+    let looks_like_oid = (4..=40).contains(&main_branch_name.len())
+        && main_branch_name.chars().all(|c| c.is_ascii_hexdigit());
+    if !looks_like_oid {
+        return Ok(None);
+    }
+    if main_branch_name.len() == 40 {
+        return Ok(Some(git2::Oid::from_str(main_branch_name)?));
+    }
+
+    let is_branch = repo
+        .find_branch(main_branch_name, git2::BranchType::Local)
+        .or_else(|_| repo.find_branch(main_branch_name, git2::BranchType::Remote))
+        .is_ok();
+    if is_branch {
+        return Ok(None);
+    }
+    match repo
+        .revparse_single(main_branch_name)
+        .and_then(|object| object.peel_to_commit())
+    {
+        Ok(commit) => Ok(Some(commit.id())),
+        Err(_) => Ok(None),
+    }
+}
+
 /// Get the OID corresponding to the main branch.
 ///
+/// The main branch setting may also be a commit OID (see
+/// `get_main_branch_commit_oid`), in which case that commit is used directly.
+///
+/// If the main branch can't be found, such as because it was renamed, the
+/// error message suggests how to update the main branch setting, including the
+/// names of any branches which look like they could be the main branch.
+///
 /// Args:
 /// * `repo`: The Git repository.
 ///
@@ -52,26 +174,108 @@ pub fn get_head_oid(repo: &git2::Repository) -> anyhow::Result<Option<git2::Oid>
 #[context("Getting main branch OID for repository")]
 pub fn get_main_branch_oid(repo: &git2::Repository) -> anyhow::Result<git2::Oid> {
     let main_branch_name = get_main_branch_name(&repo)?;
+    if let Some(main_branch_oid) = get_main_branch_commit_oid(repo, &main_branch_name)? {
+        return match repo.find_commit(main_branch_oid) {
+            Ok(commit) => Ok(commit.id()),
+            Err(_) => Err(BranchlessError::MainBranchUnresolved {
+                message: format!(
+                    r"
+The main branch is set to commit {:?}, but that commit could not be found in your repository.
+Either fetch it, or update the main branch setting by running:
+
+    git config branchless.core.mainBranch <branch>
+",
+                    main_branch_name,
+                ),
+                main_branch_name,
+            }
+            .into()),
+        };
+    }
+
     let branch = repo
         .find_branch(&main_branch_name, git2::BranchType::Local)
         .or_else(|_| repo.find_branch(&main_branch_name, git2::BranchType::Remote));
     let branch = match branch {
         Ok(branch) => branch,
         // Drop the error trace here. It's confusing, and we don't want it to appear in the output.
-        Err(_) => anyhow::bail!(
-            r"
+        Err(_) => {
+            let candidate_names: Vec<String> = detect_main_branch_names(repo)
+                .into_iter()
+                .filter(|branch_name| branch_name != &main_branch_name)
+                .collect();
+            let (candidates_message, suggested_name) = match candidate_names.as_slice() {
+                [] => (String::new(), "<branch>"),
+                [first_name, ..] => (
+                    format!(
+                        "These branches look like they could be the main branch: {}\n",
+                        candidate_names.join(", ")
+                    ),
+                    first_name.as_str(),
+                ),
+            };
+            return Err(BranchlessError::MainBranchUnresolved {
+                message: format!(
+                    r"
 The main branch {:?} could not be found in your repository.
-Either create it, or update the main branch setting by running:
+{}Either create it, or update the main branch setting by running:
 
-    git config branchless.core.mainBranch <branch>
+    git config branchless.core.mainBranch {}
 ",
-            main_branch_name
-        ),
+                    main_branch_name, candidates_message, suggested_name,
+                ),
+                main_branch_name,
+            }
+            .into());
+        }
     };
-    let commit = branch.get().peel_to_commit()?;
+    let commit = branch
+        .get()
+        .peel_to_commit()
+        .with_context(|| format!("Resolving main branch {:?} to a commit", main_branch_name))?;
     Ok(commit.id())
 }
 
+/// Get the OIDs of the additional main branches for the repository, i.e. all
+/// the configured main branches except for the first one. See
+/// `get_main_branch_names`.
+///
+/// Unlike `get_main_branch_oid`, branches which can't be found are skipped with
+/// a warning, rather than producing an error.
+#[context("Getting additional main branch OIDs for repository")]
+pub fn get_additional_main_branch_oids(repo: &git2::Repository) -> anyhow::Result<Vec<git2::Oid>> {
+    let result = get_main_branch_names(repo)?
+        .into_iter()
+        .skip(1)
+        .filter_map(|main_branch_name| find_additional_main_branch_oid(repo, &main_branch_name))
+        .collect();
+    Ok(result)
+}
+
+/// Get the OID of the commit that the given additional main branch points to,
+/// as in `get_additional_main_branch_oids`.
+///
+/// Returns: The commit OID, or `None` (with a warning) if the branch can't be
+/// found.
+pub fn find_additional_main_branch_oid(
+    repo: &git2::Repository,
+    main_branch_name: &str,
+) -> Option<git2::Oid> {
+    let branch = repo
+        .find_branch(main_branch_name, git2::BranchType::Local)
+        .or_else(|_| repo.find_branch(main_branch_name, git2::BranchType::Remote));
+    match branch.map(|branch| branch.get().peel_to_commit()) {
+        Ok(Ok(commit)) => Some(commit.id()),
+        Ok(Err(_)) | Err(_) => {
+            warn!(
+                "Main branch {:?} could not be found, skipping",
+                main_branch_name
+            );
+            None
+        }
+    }
+}
+
 /// Get a mapping from OID to the names of branches which point to that OID.
 ///
 /// The returned branch names do not include the `refs/heads/` prefix.
@@ -118,29 +322,175 @@ pub fn get_branch_oid_to_names(
     }
 
     // The main branch may be a remote branch, in which case it won't be
-    // returned in the iteration above.
+    // returned in the iteration above. If it's a commit OID, then there's no
+    // branch name to show.
     let main_branch_name = get_main_branch_name(repo)?;
-    let main_branch_oid = get_main_branch_oid(repo)?;
-    result
-        .entry(main_branch_oid)
-        .or_insert_with(HashSet::new)
-        .insert(main_branch_name);
+    if get_main_branch_commit_oid(repo, &main_branch_name)?.is_none() {
+        let main_branch_oid = get_main_branch_oid(repo)?;
+        result
+            .entry(main_branch_oid)
+            .or_insert_with(HashSet::new)
+            .insert(main_branch_name);
+    }
 
     Ok(result)
 }
 
+/// Get a mapping from OID to the names of tags which point to that OID.
+///
+/// The returned tag names do not include the `refs/tags/` prefix. Annotated
+/// tags are peeled to the commit they point to. Tags which don't point to a
+/// commit are skipped.
+#[context("Getting tag-OID-to-names map for repository")]
+pub fn get_tag_oid_to_names(
+    repo: &git2::Repository,
+) -> anyhow::Result<HashMap<git2::Oid, HashSet<String>>> {
+    let references = repo
+        .references_glob("refs/tags/*")
+        .with_context(|| "Reading tags")?;
+
+    let mut result = HashMap::new();
+    for reference in references {
+        let reference = reference.with_context(|| "Iterating over tags")?;
+        let tag_name = match reference.shorthand() {
+            None => {
+                warn!(
+                    "Could not decode tag name, skipping: {:?}",
+                    reference.name_bytes()
+                );
+                continue;
+            }
+            Some(tag_name) => tag_name.to_owned(),
+        };
+
+        let tag_oid = match reference.peel_to_commit() {
+            Ok(commit) => commit.id(),
+            // The tag may point to a non-commit object, such as a tree or blob.
+            Err(_) => continue,
+        };
+        result
+            .entry(tag_oid)
+            .or_insert_with(HashSet::new)
+            .insert(tag_name);
+    }
+
+    Ok(result)
+}
+
+/// Get a mapping from OID to the names of stash entries which point to that
+/// OID, such as `stash@{0}`.
+///
+/// Stash entries are read from the reflog of `refs/stash`, where the most
+/// recent entry is `stash@{0}`. If there are no stash entries, returns an
+/// empty map.
+#[context("Getting stash-OID-to-names map for repository")]
+pub fn get_stash_oid_to_names(
+    repo: &git2::Repository,
+) -> anyhow::Result<HashMap<git2::Oid, HashSet<String>>> {
+    let mut result = HashMap::new();
+    if repo.find_reference("refs/stash").is_err() {
+        return Ok(result);
+    }
+
+    let reflog = repo
+        .reflog("refs/stash")
+        .with_context(|| "Reading stash reflog")?;
+    for (i, entry) in reflog.iter().enumerate() {
+        result
+            .entry(entry.id_new())
+            .or_insert_with(HashSet::new)
+            .insert(format!("stash@{{{}}}", i));
+    }
+    Ok(result)
+}
+
+/// Get the OIDs of the commits at the boundary of a shallow clone. These
+/// commits are present in the repository, but their parents aren't.
+///
+/// Returns: The boundary commit OIDs, as listed in `.git/shallow`. If the
+/// repository isn't a shallow clone, returns an empty set.
+#[context("Getting shallow boundary OIDs")]
+pub fn get_shallow_boundary_oids(repo: &git2::Repository) -> anyhow::Result<HashSet<git2::Oid>> {
+    if !repo.is_shallow() {
+        return Ok(HashSet::new());
+    }
+    let shallow_path = get_repo_common_dir(repo)?.join("shallow");
+    let contents = std::fs::read_to_string(&shallow_path)
+        .with_context(|| format!("Reading shallow file: {:?}", shallow_path))?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            git2::Oid::from_str(line)
+                .with_context(|| format!("Parsing shallow boundary OID: {:?}", line))
+        })
+        .collect()
+}
+
+/// Get the OIDs of the commits pointed to by the references which the user
+/// has configured to be unhideable with `branchless.core.unhideableRefs`.
+///
+/// References are peeled to the commit they point to. References which don't
+/// point to a commit are skipped.
+#[context("Getting OIDs for unhideable references")]
+pub fn get_unhideable_ref_oids(repo: &git2::Repository) -> anyhow::Result<HashSet<git2::Oid>> {
+    let mut result = HashSet::new();
+    for pattern in get_core_unhideable_refs(repo)? {
+        let references = repo
+            .references_glob(&pattern)
+            .with_context(|| format!("Reading references matching: {:?}", pattern))?;
+        for reference in references {
+            let reference = reference.with_context(|| "Iterating over references")?;
+            if let Ok(commit) = reference.peel_to_commit() {
+                result.insert(commit.id());
+            }
+        }
+    }
+    Ok(result)
+}
+
 /// Get the git repository associated with the current directory.
+///
+/// The repository is found in the same way as Git itself finds it, so the
+/// `GIT_DIR` and `GIT_WORK_TREE` environment variables (also set by the
+/// `--git-dir` and `--work-tree` options) are honored, as are
+/// `GIT_CEILING_DIRECTORIES` and `GIT_DISCOVERY_ACROSS_FILESYSTEM`. If
+/// `GIT_DIR` isn't set, the repository is searched for starting from the
+/// current directory.
 #[context("Getting `git2::Repository` for repo")]
 pub fn get_repo() -> anyhow::Result<git2::Repository> {
-    let path = std::env::current_dir().with_context(|| "Getting working directory")?;
-    let repository = git2::Repository::discover(path).map_err(wrap_git_error)?;
+    let repository = git2::Repository::open_from_env().map_err(wrap_git_error)?;
     Ok(repository)
 }
 
-/// Get the connection to the SQLite database for this repository.
+/// Get the directory which is shared between all of the repository's
+/// worktrees (`$GIT_COMMON_DIR`).
+///
+/// For a linked worktree (see `git-worktree(1)`), `repo.path()` is the
+/// worktree's own directory under `.git/worktrees`, which only holds its
+/// per-worktree state, such as `HEAD`. Everything else, such as the references,
+/// the hooks, and the branchless database, lives in the common directory.
+///
+/// Returns: The path to the common directory. For a repository without linked
+/// worktrees, this is the same as `repo.path()`.
+#[context("Getting common directory for repo")]
+pub fn get_repo_common_dir(repo: &git2::Repository) -> anyhow::Result<PathBuf> {
+    if !repo.is_worktree() {
+        return Ok(repo.path().to_path_buf());
+    }
+    let commondir_path = repo.path().join("commondir");
+    let common_dir = std::fs::read_to_string(&commondir_path)
+        .with_context(|| format!("Reading commondir file: {:?}", commondir_path))?;
+    // The path may be relative to the worktree's own directory.
+    Ok(repo.path().join(common_dir.trim()))
+}
+
+/// Get the connection to the SQLite database for this repository. The database
+/// is shared between all of the repository's worktrees.
 #[context("Getting connection to SQLite database for repo")]
 pub fn get_db_conn(repo: &git2::Repository) -> anyhow::Result<rusqlite::Connection> {
-    let dir = repo.path().join("branchless");
+    let dir = get_repo_common_dir(repo)?.join("branchless");
     std::fs::create_dir_all(&dir).with_context(|| "Creating .git/branchless dir")?;
     let path = dir.join("db.sqlite3");
     let conn = rusqlite::Connection::open(&path)
@@ -148,6 +498,19 @@ pub fn get_db_conn(repo: &git2::Repository) -> anyhow::Result<rusqlite::Connecti
     Ok(conn)
 }
 
+/// Exit code indicating that the command was interrupted by a merge conflict,
+/// which must be resolved manually (or the command aborted) before
+/// continuing.
+pub const EXIT_CODE_CONFLICT: isize = 1;
+
+/// Exit code indicating that the arguments passed to the command were invalid,
+/// such as a combination of mutually-exclusive options.
+pub const EXIT_CODE_BAD_ARGUMENTS: isize = 2;
+
+/// Exit code indicating that a commit passed to the command could not be
+/// resolved.
+pub const EXIT_CODE_COMMIT_NOT_FOUND: isize = 3;
+
 /// Path to the `git` executable on disk to be executed.
 #[derive(Clone, Debug)]
 pub struct GitExecutable(pub PathBuf);
@@ -158,8 +521,8 @@ pub struct GitExecutable(pub PathBuf);
 /// hooks. We don't want our process to be responsible for that.
 ///
 /// Args:
-/// * `out`: The output stream to write to.
-/// * `err`: The error stream to write to.
+/// * `out`: The output stream to write the command line and Git's output to.
+///   Git's error output is written to `stderr`.
 /// * `git_executable`: The path to the `git` executable on disk.
 /// * `event_tx_id`: The ID of the current event-log transaction, if any.
 /// * `args`: The list of arguments to pass to Git. Should not include the Git
@@ -169,20 +532,22 @@ pub struct GitExecutable(pub PathBuf);
 #[context("Running Git ({:?}) with args: {:?}", git_executable, args)]
 #[must_use = "The return code for `run_git` must be checked"]
 pub fn run_git<S: AsRef<str> + std::fmt::Debug>(
+    out: &mut impl Write,
     git_executable: &GitExecutable,
     event_tx_id: Option<EventTransactionId>,
     args: &[S],
 ) -> anyhow::Result<isize> {
     let GitExecutable(git_executable) = git_executable;
-    println!(
+    writeln!(
+        out,
         "branchless: {} {}",
         git_executable.to_string_lossy(),
         args.iter()
             .map(|arg| arg.as_ref())
             .collect::<Vec<_>>()
             .join(" ")
-    );
-    stdout().flush()?;
+    )?;
+    out.flush()?;
     stderr().flush()?;
 
     let mut command = Command::new(git_executable);
@@ -196,7 +561,7 @@ pub fn run_git<S: AsRef<str> + std::fmt::Debug>(
             git_executable, args
         )
     })?;
-    stdout().write_all(&result.stdout)?;
+    out.write_all(&result.stdout)?;
     stderr().write_all(&result.stderr)?;
 
     // On Unix, if the child process was terminated by a signal, we need to call
@@ -261,6 +626,78 @@ pub fn run_git_silent<S: AsRef<str> + std::fmt::Debug>(
     Ok(result)
 }
 
+/// Parse a date in any of the formats that Git accepts, such as `2 weeks ago`
+/// or `2021-06-01 12:00`. See the "DATE FORMATS" section of `git-commit(1)`.
+///
+/// Git's date parser is very lenient, so most inputs will produce some date,
+/// even if it's not the one the user intended.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `git_executable`: The path to the `git` executable on disk.
+/// * `date`: The date to parse.
+///
+/// Returns: The date, in seconds since the Unix epoch.
+#[context("Parsing date: {:?}", date)]
+pub fn parse_git_date(
+    repo: &git2::Repository,
+    git_executable: &GitExecutable,
+    date: &str,
+) -> anyhow::Result<i64> {
+    // `git rev-parse` converts `--since` into the equivalent `--max-age`
+    // option, whose argument is a timestamp.
+    let output = run_git_silent(
+        repo,
+        git_executable,
+        None,
+        &["rev-parse", &format!("--since={}", date)],
+    )?;
+    let timestamp = output
+        .trim()
+        .strip_prefix("--max-age=")
+        .ok_or_else(|| anyhow::anyhow!("Unexpected output from `git rev-parse`: {:?}", output))?;
+    let timestamp = timestamp
+        .parse()
+        .with_context(|| format!("Parsing timestamp: {:?}", timestamp))?;
+    Ok(timestamp)
+}
+
+/// Get the signature to use as the committer of new commits, as determined by
+/// Git. Unlike `git2::Repository::signature`, this respects the
+/// `GIT_COMMITTER_NAME`, `GIT_COMMITTER_EMAIL`, and `GIT_COMMITTER_DATE`
+/// environment variables.
+///
+/// Args:
+/// * `repo`: The Git repository.
+/// * `git_executable`: The path to the `git` executable on disk.
+///
+/// Returns: The committer signature.
+#[context("Getting committer signature")]
+pub fn get_committer_signature(
+    repo: &git2::Repository,
+    git_executable: &GitExecutable,
+) -> anyhow::Result<git2::Signature<'static>> {
+    let output = run_git_silent(repo, git_executable, None, &["var", "GIT_COMMITTER_IDENT"])?;
+    let ident = output.trim();
+    let parse = || -> Option<git2::Signature<'static>> {
+        let mut parts = ident.rsplitn(3, ' ');
+        let offset = parts.next()?;
+        let timestamp: i64 = parts.next()?.parse().ok()?;
+        let (name, email) = parts.next()?.strip_suffix('>')?.rsplit_once(" <")?;
+
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let offset = offset.trim_start_matches(['+', '-']);
+        if offset.len() != 4 {
+            return None;
+        }
+        let hours: i32 = offset[..2].parse().ok()?;
+        let minutes: i32 = offset[2..].parse().ok()?;
+        let time = git2::Time::new(timestamp, sign * (hours * 60 + minutes));
+        git2::Signature::new(name, email, &time).ok()
+    };
+    parse().ok_or_else(|| anyhow::anyhow!("Unexpected output from `git var`: {:?}", output))
+}
+
 /// Run a provided Git hook if it exists for the repository.
 ///
 /// See the man page for `githooks(5)` for more detail on Git hooks.
@@ -272,10 +709,7 @@ pub fn run_hook(
     args: &[impl AsRef<str>],
     stdin: Option<String>,
 ) -> anyhow::Result<()> {
-    let hook_path = repo
-        .config()?
-        .get_path("core.hooksPath")
-        .unwrap_or_else(|_| repo.path().join("hooks"));
+    let hook_path = get_core_hooks_path(repo)?;
     if hook_path.join(hook_name).exists() {
         let mut child = Command::new(get_sh().context("shell needed to run hook")?)
             .arg("-c")
@@ -302,6 +736,121 @@ pub fn run_hook(
     Ok(())
 }
 
+fn resolve_pager(
+    git_pager: Option<String>,
+    core_pager: Option<String>,
+    pager: Option<String>,
+) -> Option<String> {
+    let pager = git_pager
+        .or(core_pager)
+        .or(pager)
+        .unwrap_or_else(|| String::from("less"));
+    match pager.trim() {
+        "" | "cat" => None,
+        pager => Some(pager.to_owned()),
+    }
+}
+
+/// Get the pager command to use for displaying long output.
+///
+/// This follows the same order of precedence as Git: `$GIT_PAGER`, then
+/// `core.pager`, then `$PAGER`, and finally `less`.
+///
+/// Returns: The pager command, to be interpreted by the shell, or `None` if
+/// the user has disabled paging (by setting the pager to `cat` or the empty
+/// string).
+#[context("Getting pager command")]
+pub fn get_pager(repo: &git2::Repository) -> anyhow::Result<Option<String>> {
+    let git_pager = env::var("GIT_PAGER").ok();
+    let core_pager = get_core_pager(repo)?;
+    let pager = env::var("PAGER").ok();
+    Ok(resolve_pager(git_pager, core_pager, pager))
+}
+
+/// Write the provided output to the given pager, and wait for the user to
+/// exit it.
+///
+/// As with Git, `LESS` and `LV` are set to sensible defaults (such as passing
+/// ANSI color codes through) if the user hasn't already set them.
+#[context("Running pager: {}", pager)]
+pub fn run_pager(pager: &str, output: &str) -> anyhow::Result<()> {
+    let mut command = Command::new(get_sh().context("shell needed to run pager")?);
+    command.arg("-c").arg(pager).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Spawning pager: {}", pager))?;
+
+    {
+        let mut stdin = child.stdin.take().unwrap();
+        match stdin.write_all(output.as_bytes()) {
+            Ok(()) => {}
+            // The user may have quit the pager before reading all the output.
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+            Err(err) => return Err(err).context("Writing pager stdin"),
+        }
+    }
+
+    let _ignored: ExitStatus = child.wait()?;
+    Ok(())
+}
+
+/// Write `prompt` to `out`, and read a line of input from the user.
+///
+/// Returns: The line of input, with surrounding whitespace removed. If no
+/// input could be read (such as because standard input isn't a terminal and
+/// has already been closed), an error is returned instead of treating it as an
+/// empty response, since the user never had a chance to answer.
+#[context("Prompting for input: {:?}", prompt)]
+pub fn prompt_input(
+    in_: &mut impl BufRead,
+    out: &mut impl Write,
+    prompt: &str,
+) -> anyhow::Result<String> {
+    write!(out, "{}", prompt)?;
+    out.flush()?;
+    let mut input = String::new();
+    let num_bytes = in_.read_line(&mut input).context("Reading user input")?;
+    if num_bytes == 0 {
+        writeln!(out)?;
+        anyhow::bail!("Input is required, but none could be read from stdin (is it a terminal?)");
+    }
+    Ok(input.trim().to_string())
+}
+
+/// Ask the user to confirm an action, by writing `prompt` to `out` and
+/// reading their response.
+///
+/// Args:
+/// * `in_`: The input stream to read the response from.
+/// * `out`: The output stream to write the prompt to.
+/// * `prompt`: The question to ask, such as `Confirm? [yN] `.
+/// * `assume_yes`: If `true`, don't read any input and treat the action as
+///   confirmed, such as when the user passed `--yes`.
+///
+/// Returns: Whether the user confirmed the action. If confirmation is required
+/// but no input could be read, an error is returned which suggests passing
+/// `--yes`.
+pub fn prompt_confirmation(
+    in_: &mut impl BufRead,
+    out: &mut impl Write,
+    prompt: &str,
+    assume_yes: bool,
+) -> anyhow::Result<bool> {
+    if assume_yes {
+        writeln!(out, "{}y", prompt)?;
+        return Ok(true);
+    }
+    let input = prompt_input(in_, out, prompt)
+        .context("Confirmation is required; pass --yes to confirm non-interactively")?;
+    Ok(matches!(input.as_str(), "y" | "Y" | "yes"))
+}
+
 /// The parsed version of Git.
 #[derive(Debug, PartialEq, PartialOrd, Eq)]
 pub struct GitVersion(pub isize, pub isize, pub isize);
@@ -343,6 +892,28 @@ pub fn get_from_path(exe_name: &str) -> Option<PathBuf> {
     })
 }
 
+/// Check out the given commit into `dir`, without touching the working copy,
+/// index, or `HEAD` of the repository itself.
+#[context("Checking out commit {:?} into {:?}", commit.id(), dir)]
+pub fn checkout_commit_into_dir(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    dir: &Path,
+) -> anyhow::Result<()> {
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(
+            git2::build::CheckoutBuilder::new()
+                .target_dir(dir)
+                .update_index(false)
+                .recreate_missing(true)
+                .force(),
+        ),
+    )
+    .with_context(|| format!("Checking out commit into: {:?}", dir))?;
+    Ok(())
+}
+
 /// Returns the path to a shell suitable for running hooks.
 pub fn get_sh() -> Option<PathBuf> {
     let exe_name = if cfg!(target_os = "windows") {
@@ -365,9 +936,55 @@ pub fn get_sh() -> Option<PathBuf> {
     get_from_path(exe_name)
 }
 
+/// Determine whether `text` matches the glob `pattern` in its entirety. A `*`
+/// in the pattern matches any sequence of characters, and a `?` matches any
+/// single character; all other characters match themselves.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut pattern_index, mut text_index) = (0, 0);
+    // The position of the last `*` in the pattern, and the position in the text
+    // it was matched at, so that it can be made to match more characters if
+    // the rest of the pattern fails to match.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                backtrack = Some((pattern_index, text_index));
+                pattern_index += 1;
+            }
+            Some(c) if *c == '?' || *c == text[text_index] => {
+                pattern_index += 1;
+                text_index += 1;
+            }
+            _ => match backtrack {
+                Some((star_index, star_text_index)) => {
+                    pattern_index = star_index + 1;
+                    text_index = star_text_index + 1;
+                    backtrack = Some((star_index, star_text_index + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pattern_index..].iter().all(|c| *c == '*')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::with_git;
+
+    #[test]
+    fn test_resolve_pager() {
+        let s = |s: &str| Some(s.to_string());
+        assert_eq!(resolve_pager(None, None, None), s("less"));
+        assert_eq!(resolve_pager(s("more"), s("most"), s("pg")), s("more"));
+        assert_eq!(resolve_pager(None, s("most"), s("pg")), s("most"));
+        assert_eq!(resolve_pager(None, None, s("pg")), s("pg"));
+        assert_eq!(resolve_pager(s("cat"), s("most"), None), None);
+        assert_eq!(resolve_pager(None, s(""), s("pg")), None);
+    }
 
     #[test]
     fn test_parse_git_version_output() {
@@ -386,6 +1003,120 @@ mod tests {
             GitVersion(12, 34, 56)
         );
     }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*", "Jane Doe"));
+        assert!(glob_matches("jane*", "jane doe"));
+        assert!(glob_matches("*@example.com", "jane@example.com"));
+        assert!(glob_matches("j?ne *", "jane doe"));
+        assert!(glob_matches("*a*e*", "jane"));
+        assert!(!glob_matches("jane", "jane doe"));
+        assert!(!glob_matches("*@example.com", "jane@example.org"));
+        assert!(!glob_matches("j?ne", "jne"));
+    }
+
+    #[test]
+    fn test_prompt_confirmation() -> anyhow::Result<()> {
+        let mut out = Vec::new();
+        assert!(prompt_confirmation(
+            &mut "y\n".as_bytes(),
+            &mut out,
+            "Confirm? ",
+            false
+        )?);
+        assert!(!prompt_confirmation(
+            &mut "n\n".as_bytes(),
+            &mut out,
+            "Confirm? ",
+            false
+        )?);
+        assert!(!prompt_confirmation(
+            &mut "\n".as_bytes(),
+            &mut out,
+            "Confirm? ",
+            false
+        )?);
+        assert!(prompt_confirmation(
+            &mut "".as_bytes(),
+            &mut out,
+            "Confirm? ",
+            true
+        )?);
+        assert!(prompt_confirmation(&mut "".as_bytes(), &mut out, "Confirm? ", false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_commits_revisions() -> anyhow::Result<()> {
+        with_git(|git| {
+            git.init_repo()?;
+            let test1_oid = git.commit_file("test1", 1)?;
+            let test2_oid = git.commit_file("test2", 2)?;
+            let test3_oid = git.commit_file("test3", 3)?;
+            git.run(&["tag", "-a", "-m", "annotated tag", "my-tag", "HEAD^"])?;
+
+            let repo = git.get_repo()?;
+            let short_oid = test3_oid.to_string()[..8].to_string();
+            let revisions = vec![
+                "HEAD~2".to_string(),
+                "my-tag".to_string(),
+                short_oid,
+                "master".to_string(),
+            ];
+            match resolve_commits(&repo, revisions)? {
+                ResolveCommitsResult::Ok { commits } => {
+                    let oids: Vec<git2::Oid> = commits.iter().map(|commit| commit.id()).collect();
+                    assert_eq!(oids, vec![test1_oid, test2_oid, test3_oid, test3_oid]);
+                }
+                ResolveCommitsResult::CommitNotFound { commit } => {
+                    panic!("Commit not found: {}", commit)
+                }
+                ResolveCommitsResult::AmbiguousSummary { commit, .. } => {
+                    panic!("Ambiguous commit: {}", commit)
+                }
+                ResolveCommitsResult::NotACommit { commit, .. } => {
+                    panic!("Not a commit: {}", commit)
+                }
+            }
+
+            for revision in &["nonexistent", "HEAD~10", "HEAD~~~)", "master@{upstream}"] {
+                match resolve_commits(&repo, vec![revision.to_string()])? {
+                    ResolveCommitsResult::Ok { .. } => {
+                        panic!("Resolved invalid revision: {}", revision)
+                    }
+                    ResolveCommitsResult::CommitNotFound { commit } => {
+                        assert_eq!(&commit, revision)
+                    }
+                    ResolveCommitsResult::AmbiguousSummary { commit, .. } => {
+                        panic!("Ambiguous commit: {}", commit)
+                    }
+                    ResolveCommitsResult::NotACommit { commit, .. } => {
+                        panic!("Not a commit: {}", commit)
+                    }
+                }
+            }
+
+            for (revision, expected_object_type) in &[
+                ("HEAD^{tree}", git2::ObjectType::Tree),
+                ("HEAD:test3.txt", git2::ObjectType::Blob),
+            ] {
+                match resolve_commits(&repo, vec![revision.to_string()])? {
+                    ResolveCommitsResult::NotACommit {
+                        commit,
+                        object_type,
+                    } => {
+                        assert_eq!(&commit, revision);
+                        assert_eq!(object_type, *expected_object_type);
+                    }
+                    _ => panic!("Resolved non-commit revision: {}", revision),
+                }
+            }
+
+            Ok(())
+        })
+    }
 }
 
 /// The result of attempting to resolve commits.
@@ -401,6 +1132,71 @@ pub enum ResolveCommitsResult<'repo> {
         /// The identifier of the commit, as provided by the user.
         commit: String,
     },
+
+    /// The first commit which was searched for by its summary (see
+    /// `resolve_commits_by_summary`), but which matched multiple commits.
+    AmbiguousSummary {
+        /// The summary text, as provided by the user.
+        commit: String,
+
+        /// The commits whose summaries matched, in order of commit time.
+        commit_oids: Vec<git2::Oid>,
+    },
+
+    /// The first identifier which referred to an object other than a commit,
+    /// such as a tree or a blob. (Tags are peeled to the commit they point
+    /// to, so they're only reported here if they don't point to a commit.)
+    NotACommit {
+        /// The identifier of the object, as provided by the user.
+        commit: String,
+
+        /// The type of the object which the identifier referred to.
+        object_type: git2::ObjectType,
+    },
+}
+
+/// The result of looking up a single revision for `resolve_commits`.
+enum ResolvedRevision<'repo> {
+    Commit(git2::Commit<'repo>),
+    NotACommit(git2::ObjectType),
+    NotFound,
+}
+
+/// Look up a revision, peeling any tags to the object they point to.
+///
+/// Only a revision which doesn't exist or can't be parsed is reported as
+/// `ResolvedRevision::NotFound`. Any other error, such as an ambiguous short
+/// OID or a corrupt object, is returned as an error.
+#[context("Resolving revision {:?}", hash)]
+fn resolve_revision<'repo>(
+    repo: &'repo git2::Repository,
+    hash: &str,
+) -> anyhow::Result<ResolvedRevision<'repo>> {
+    let mut object = match repo.revparse_single(hash) {
+        Ok(object) => object,
+        Err(err)
+            if err.code() == git2::ErrorCode::NotFound
+                || err.code() == git2::ErrorCode::InvalidSpec =>
+        {
+            return Ok(ResolvedRevision::NotFound)
+        }
+        Err(err) => return Err(wrap_git_error(err)),
+    };
+    loop {
+        match object.kind() {
+            Some(git2::ObjectType::Tag) => {
+                object = object.peel(git2::ObjectType::Any).map_err(wrap_git_error)?;
+            }
+            Some(git2::ObjectType::Commit) => {
+                let commit = object
+                    .into_commit()
+                    .map_err(|object| anyhow::anyhow!("Object {} is not a commit", object.id()))?;
+                return Ok(ResolvedRevision::Commit(commit));
+            }
+            Some(object_type) => return Ok(ResolvedRevision::NotACommit(object_type)),
+            None => return Ok(ResolvedRevision::NotFound),
+        }
+    }
 }
 
 /// Parse strings which refer to commits, such as:
@@ -408,6 +1204,14 @@ pub enum ResolveCommitsResult<'repo> {
 /// - Full OIDs.
 /// - Short OIDs.
 /// - Reference names.
+/// - Any other revision accepted by `git rev-parse`, such as `HEAD~2` or
+///   `my-branch@{upstream}`. See `gitrevisions(7)`.
+///
+/// Annotated tags are peeled to the commit they point to. Revisions which
+/// don't exist or can't be parsed produce `ResolveCommitsResult::CommitNotFound`,
+/// and revisions which refer to some other kind of object, such as a tree or a
+/// blob, produce `ResolveCommitsResult::NotACommit`. Other lookup failures,
+/// such as an ambiguous short OID, are returned as errors.
 #[context("Resolving commits")]
 pub fn resolve_commits(
     repo: &git2::Repository,
@@ -415,21 +1219,135 @@ pub fn resolve_commits(
 ) -> anyhow::Result<ResolveCommitsResult> {
     let mut commits = Vec::new();
     for hash in hashes {
-        let commit = match repo.revparse_single(&hash) {
-            Ok(commit) => match commit.into_commit() {
-                Ok(commit) => commit,
-                Err(_) => return Ok(ResolveCommitsResult::CommitNotFound { commit: hash }),
-            },
-            Err(err) if err.code() == ErrorCode::NotFound => {
+        let commit = match resolve_revision(repo, &hash)? {
+            ResolvedRevision::Commit(commit) => commit,
+            ResolvedRevision::NotACommit(object_type) => {
+                return Ok(ResolveCommitsResult::NotACommit {
+                    commit: hash,
+                    object_type,
+                })
+            }
+            ResolvedRevision::NotFound => {
                 return Ok(ResolveCommitsResult::CommitNotFound { commit: hash })
             }
-            Err(err) => return Err(err.into()),
         };
         commits.push(commit)
     }
     Ok(ResolveCommitsResult::Ok { commits })
 }
 
+/// Parse strings which refer to commits, as in `resolve_commits`. If a string
+/// isn't a revision understood by Git, then fall back to searching the summary
+/// lines of the visible commits in the smartlog for the string,
+/// case-insensitively. For example, `"add auth"` resolves to the commit with
+/// the summary "Add authentication", as long as no other visible commit
+/// matches.
+///
+/// Returns: `ResolveCommitsResult::CommitNotFound` for the first string which
+/// matched no commits, `ResolveCommitsResult::AmbiguousSummary` for the first
+/// string which matched several commits, or `ResolveCommitsResult::NotACommit`
+/// for the first string which is a revision referring to something other than
+/// a commit. Such revisions aren't searched for as summaries.
+pub fn resolve_commits_by_summary<'repo>(
+    repo: &'repo git2::Repository,
+    hashes: Vec<String>,
+) -> anyhow::Result<ResolveCommitsResult<'repo>> {
+    // Only construct the graph if a string needs to be searched for, since
+    // it's comparatively expensive.
+    let mut graph: Option<CommitGraph> = None;
+    let mut commits = Vec::new();
+    for hash in hashes {
+        match resolve_revision(repo, &hash)? {
+            ResolvedRevision::Commit(commit) => {
+                commits.push(commit);
+                continue;
+            }
+            ResolvedRevision::NotACommit(object_type) => {
+                return Ok(ResolveCommitsResult::NotACommit {
+                    commit: hash,
+                    object_type,
+                })
+            }
+            ResolvedRevision::NotFound => {}
+        }
+
+        let graph = match &mut graph {
+            Some(graph) => graph,
+            None => graph.insert(make_smartlog_graph(repo)?),
+        };
+        match find_commits_by_summary(graph, &hash).as_slice() {
+            [] => return Ok(ResolveCommitsResult::CommitNotFound { commit: hash }),
+            [commit_oid] => commits.push(repo.find_commit(*commit_oid)?),
+            commit_oids => {
+                return Ok(ResolveCommitsResult::AmbiguousSummary {
+                    commit: hash,
+                    commit_oids: commit_oids.to_vec(),
+                })
+            }
+        }
+    }
+    Ok(ResolveCommitsResult::Ok { commits })
+}
+
+/// Tell the user which commits matched a summary search, for a
+/// `ResolveCommitsResult::AmbiguousSummary` result.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `glyphs`: The glyphs to render the commits with.
+/// * `repo`: The Git repository.
+/// * `commit`: The summary text, as provided by the user.
+/// * `commit_oids`: The commits whose summaries matched.
+pub fn write_ambiguous_summary_message(
+    out: &mut impl Write,
+    glyphs: &Glyphs,
+    repo: &git2::Repository,
+    commit: &str,
+    commit_oids: &[git2::Oid],
+) -> anyhow::Result<()> {
+    writeln!(out, "Found multiple commits matching {:?}:", commit)?;
+    for commit_oid in commit_oids {
+        let commit_text = render_commit_metadata(
+            &repo.find_commit(*commit_oid)?,
+            &mut [
+                &mut CommitOidProvider::new(repo, true)?,
+                &mut CommitMessageProvider::new()?,
+            ],
+        )?;
+        writeln!(
+            out,
+            "  {} {}",
+            glyphs.bullet_point,
+            printable_styled_string(glyphs, commit_text)?
+        )?;
+    }
+    writeln!(
+        out,
+        "(Pass a commit hash or a more specific search to disambiguate)"
+    )?;
+    Ok(())
+}
+
+/// Tell the user that an identifier didn't refer to a commit, for a
+/// `ResolveCommitsResult::NotACommit` result.
+///
+/// Args:
+/// * `out`: The output stream to write to.
+/// * `commit`: The identifier, as provided by the user.
+/// * `object_type`: The type of the object which the identifier referred to.
+pub fn write_not_a_commit_message(
+    out: &mut impl Write,
+    commit: &str,
+    object_type: git2::ObjectType,
+) -> anyhow::Result<()> {
+    writeln!(
+        out,
+        "{} is not a commit (it refers to a {})",
+        commit, object_type
+    )?;
+    Ok(())
+}
+
 /// Get the reference corresponding to `HEAD`. Don't use
 /// `git2::Repository::head` because that resolves the reference before
 /// returning it.
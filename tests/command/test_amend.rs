@@ -0,0 +1,130 @@
+use branchless::testing::{with_git, GitRunOptions};
+
+#[test]
+fn test_amend_restacks_descendants() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "HEAD^^"])?;
+
+        git.write_file("test1", "amended contents\n")?;
+        git.run(&["add", "test1.txt"])?;
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "amend"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Amended commit: 89b6eac7 create test1.txt
+            Attempting rebase in-memory...
+            branchless: processing 2 rewritten commits
+            In-memory rebase succeeded.
+            branchless: processing 1 rewritten commit
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 89b6eac7 create test1.txt
+            |
+            o 407b9249 create test2.txt
+            |
+            o ea784fd8 create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["status", "--short"])?;
+            insta::assert_snapshot!(stdout, @"");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_amend_earlier_commit() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&["checkout", "-b", "foo"])?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+
+        git.write_file("test1", "amended contents\n")?;
+        git.run(&["add", "test1.txt"])?;
+        {
+            let (stdout, _stderr) =
+                git.run(&["branchless", "amend", "HEAD^^", "-m", "amend test1.txt"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Amended commit: 457fdf74 amend test1.txt
+            Attempting rebase in-memory...
+            branchless: processing 1 update to a branch/ref
+            branchless: processing 2 rewritten commits
+            branchless: <git-executable> checkout foo
+            In-memory rebase succeeded.
+            branchless: processing 1 rewritten commit
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 457fdf74 amend test1.txt
+            |
+            o 1e53f1ce create test2.txt
+            |
+            @ 72ab18e6 (foo) create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["status", "--short"])?;
+            insta::assert_snapshot!(stdout, @"");
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["show", "--pretty=format:%s", "HEAD~2"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            amend test1.txt
+            diff --git a/test1.txt b/test1.txt
+            new file mode 100644
+            index 0000000..7df3c9f
+            --- /dev/null
+            +++ b/test1.txt
+            @@ -0,0 +1 @@
+            +amended contents
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_amend_ancestor_of_main_branch() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "amend", "HEAD^", "-m", "foo"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Cannot amend commit f777ecc9 create initial.txt, since it's an ancestor of the main branch.
+            "###);
+        }
+
+        Ok(())
+    })
+}
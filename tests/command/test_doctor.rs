@@ -0,0 +1,90 @@
+use branchless::testing::{with_git, GitRunOptions};
+
+/// Remove the Git version line from the output, since it depends on the
+/// version of Git being tested against.
+fn remove_git_version(stdout: &str) -> String {
+    stdout
+        .lines()
+        .filter(|line| !line.contains("Git version"))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[test]
+fn test_doctor() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "doctor"])?;
+            insta::assert_snapshot!(remove_git_version(&stdout), @r###"
+            OK   Hook is installed: post-commit
+            OK   Hook is installed: post-rewrite
+            OK   Hook is installed: post-checkout
+            OK   Hook is installed: post-merge
+            OK   Hook is installed: pre-auto-gc
+            OK   Hook is installed: reference-transaction
+            OK   Main branch resolves: master
+            OK   Event log database opens
+            No problems found.
+            "###);
+        }
+
+        let hooks_dir = git.repo_path.join(".git").join("hooks");
+        std::fs::remove_file(hooks_dir.join("post-commit"))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let hook_path = hooks_dir.join("post-checkout");
+            let mut permissions = std::fs::metadata(&hook_path)?.permissions();
+            permissions.set_mode(0o644);
+            std::fs::set_permissions(&hook_path, permissions)?;
+        }
+        git.run(&["config", "branchless.core.mainBranch", "nonexistent"])?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "doctor"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            let stdout = remove_git_version(&stdout)
+                .replace(&hooks_dir.to_string_lossy().to_string(), "<hooks>");
+            insta::assert_snapshot!(stdout, @r###"
+            FAIL Hook is not installed: post-commit
+                 To reinstall the hooks, run: git branchless init
+            OK   Hook is installed: post-rewrite
+            FAIL Hook is not executable: post-checkout
+                 To fix, run: chmod +x "<hooks>/post-checkout"
+            OK   Hook is installed: post-merge
+            OK   Hook is installed: pre-auto-gc
+            OK   Hook is installed: reference-transaction
+            FAIL Main branch could not be resolved: nonexistent
+                 To fix, run: git config branchless.core.mainBranch <branch>
+            OK   Event log database opens
+            Found 3 problems.
+            "###);
+        }
+
+        git.run(&["config", "branchless.core.mainBranch", "master"])?;
+        git.run(&["branchless", "init"])?;
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "doctor"])?;
+            insta::assert_snapshot!(remove_git_version(&stdout), @r###"
+            OK   Hook is installed: post-commit
+            OK   Hook is installed: post-rewrite
+            OK   Hook is installed: post-checkout
+            OK   Hook is installed: post-merge
+            OK   Hook is installed: pre-auto-gc
+            OK   Hook is installed: reference-transaction
+            OK   Main branch resolves: master
+            OK   Event log database opens
+            No problems found.
+            "###);
+        }
+
+        Ok(())
+    })
+}
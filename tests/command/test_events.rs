@@ -0,0 +1,116 @@
+use branchless::core::eventlog::testing::make_dummy_transaction_id;
+use branchless::core::eventlog::{Event, EventLogDb};
+use branchless::testing::{with_git, GitRunOptions};
+use branchless::util::get_db_conn;
+use regex::Regex;
+
+fn redact_timestamps(stdout: &str) -> String {
+    let re = Regex::new(r"timestamp \d+").unwrap();
+    re.replace_all(stdout, "timestamp <redacted>").into_owned()
+}
+
+#[test]
+fn test_events() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["commit", "--amend", "-m", "amended test1"])?;
+        git.run(&["hide", "HEAD"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "events", "62fc20d2"])?;
+            insta::assert_snapshot!(redact_timestamps(&stdout), @r###"
+            Event 2 (transaction 3, timestamp <redacted>):
+                Check out from f777ecc9 create initial.txt
+                            to 62fc20d2 create test1.txt
+            Event 3 (transaction 4, timestamp <redacted>):
+                Commit 62fc20d2 create test1.txt
+            Event 4 (transaction 5, timestamp <redacted>):
+                Check out from 62fc20d2 create test1.txt
+                            to ae94dc2a amended test1
+            Event 6 (transaction 7, timestamp <redacted>):
+                Rewrite commit 62fc20d2 create test1.txt
+                           as ae94dc2a amended test1
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "events", "HEAD"])?;
+            insta::assert_snapshot!(redact_timestamps(&stdout), @r###"
+            Event 4 (transaction 5, timestamp <redacted>):
+                Check out from 62fc20d2 create test1.txt
+                            to ae94dc2a amended test1
+            Event 5 (transaction 6, timestamp <redacted>):
+                Commit ae94dc2a amended test1
+            Event 6 (transaction 7, timestamp <redacted>):
+                Rewrite commit 62fc20d2 create test1.txt
+                           as ae94dc2a amended test1
+            Event 7 (transaction 8, timestamp <redacted>):
+                Hide commit ae94dc2a amended test1
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_events_commit_not_found() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "events", "nonexistent"],
+                &GitRunOptions {
+                    expected_exit_code: 3,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Commit not found: nonexistent
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_events_clock_skew() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+
+        {
+            // Simulate an event recorded by a machine whose clock is far behind.
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let mut event_log_db = EventLogDb::new(&conn)?;
+            event_log_db.add_events(vec![Event::HideEvent {
+                timestamp: 0.0,
+                event_tx_id: make_dummy_transaction_id(100),
+                commit_oid: test1_oid,
+            }])?;
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "events", "HEAD"])?;
+            let re = Regex::new(r"recorded \d+\w before")?;
+            let stdout = re.replace_all(&stdout, "recorded <redacted> before");
+            insta::assert_snapshot!(redact_timestamps(&stdout), @r###"
+            Event 2 (transaction 3, timestamp <redacted>):
+                Check out from f777ecc9 create initial.txt
+                            to 62fc20d2 create test1.txt
+            Event 3 (transaction 4, timestamp <redacted>):
+                Commit 62fc20d2 create test1.txt
+            Event 4 (transaction 100, timestamp <redacted>) (clock skew: recorded <redacted> before an earlier event):
+                Hide commit 62fc20d2 create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
@@ -62,6 +62,37 @@ fn test_hide_bad_commit() -> anyhow::Result<()> {
     })
 }
 
+#[test]
+fn test_hide_by_summary() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["hide", "Test2"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Hid commit: 96d1c37a create test2.txt
+            To unhide this commit, run: git unhide 96d1c37a
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["unhide", "test3"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @"Commit not found: test3");
+        }
+
+        Ok(())
+    })
+}
+
 #[test]
 fn test_hide_already_hidden_commit() -> anyhow::Result<()> {
     with_git(|git| {
@@ -309,3 +340,345 @@ fn test_hide_recursive() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_hide_merged() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.run_with_options(
+            &["cherry-pick", &test1_oid.to_string()],
+            &GitRunOptions {
+                time: 4,
+                ..Default::default()
+            },
+        )?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            | |
+            | o 96d1c37a create test2.txt
+            |\
+            | o 98b9119d create test3.txt
+            |
+            @ 84d21a02 (master) create test1.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["hide", "--merged", "--dry-run"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following 1 commit would be hidden:
+            - 62fc20d2 create test1.txt
+            (This was a dry run, so no commits were hidden.)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["hide", "--merged"],
+                &GitRunOptions {
+                    input: Some("n\n".to_string()),
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following 1 commit has been merged into the main branch:
+            - 62fc20d2 create test1.txt
+            Hide these commits? [y/N] Aborted. No commits were hidden.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["hide", "--merged"],
+                &GitRunOptions {
+                    input: Some("y\n".to_string()),
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following 1 commit has been merged into the main branch:
+            - 62fc20d2 create test1.txt
+            Hide these commits? [y/N] Hid commit: 62fc20d2 create test1.txt
+            To unhide this commit, run: git unhide 62fc20d2
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | x 62fc20d2 (manually hidden) create test1.txt
+            | |
+            | o 96d1c37a create test2.txt
+            |\
+            | o 98b9119d create test3.txt
+            |
+            @ 84d21a02 (master) create test1.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["hide", "--merged"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            There are no visible commits which have been merged into the main branch.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_hide_merged_yes() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.run_with_options(
+            &["cherry-pick", &test1_oid.to_string()],
+            &GitRunOptions {
+                time: 2,
+                ..Default::default()
+            },
+        )?;
+
+        {
+            let (_stdout, stderr) = git.run_with_options(
+                &["hide", "--merged"],
+                &GitRunOptions {
+                    input: Some("".to_string()),
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            assert!(stderr.contains("pass --yes to confirm non-interactively"));
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["hide", "--merged", "--yes"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following 1 commit has been merged into the main branch:
+            - 62fc20d2 create test1.txt
+            Hide these commits? [y/N] y
+            Hid commit: 62fc20d2 create test1.txt
+            To unhide this commit, run: git unhide 62fc20d2
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_hide_merged_same_tree() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.run(&["revert", "--no-edit", "HEAD"])?;
+        git.run(&["commit", "--allow-empty", "-m", "empty commit"])?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+        git.run(&["rm", "test1.txt", "test2.txt"])?;
+        git.run(&["commit", "-m", "remove test1.txt and test2.txt"])?;
+
+        {
+            // The revert and the empty commit have the same tree as the main
+            // branch, but neither of them has been merged into it.
+            let (stdout, _stderr) = git.run(&["hide", "--merged", "--dry-run"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            There are no visible commits which have been merged into the main branch.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_hide_dry_run() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.run(&["hide", &test2_oid.to_string()])?;
+
+        {
+            let (stdout, _stderr) =
+                git.run(&["hide", "--recursive", "--dry-run", &test1_oid.to_string()])?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following 2 commits would be hidden:
+            - 62fc20d2 create test1.txt
+            - 70deb1e2 create test3.txt
+            (This was a dry run, so no commits were hidden.)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["unhide", "--dry-run", &test1_oid.to_string()])?;
+            insta::assert_snapshot!(stdout, @r###"
+            No commits would be unhidden.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) =
+                git.run(&["unhide", "--recursive", "--dry-run", &test2_oid.to_string()])?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following 1 commit would be unhidden:
+            - 96d1c37a create test2.txt
+            (This was a dry run, so no commits were unhidden.)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            x 96d1c37a (manually hidden) create test2.txt
+            |
+            o 70deb1e2 create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_hide_stack() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "HEAD^"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["hide", "--stack"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Hid commit: 62fc20d2 create test1.txt
+            To unhide this commit, run: git unhide 62fc20d2
+            Hid commit: 96d1c37a create test2.txt
+            To unhide this commit, run: git unhide 96d1c37a
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            x 62fc20d2 (manually hidden) create test1.txt
+            |
+            % 96d1c37a (manually hidden) create test2.txt
+            |
+            o 70deb1e2 create test3.txt
+            "###);
+        }
+
+        git.run(&["checkout", "master"])?;
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["hide", "--stack"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The --stack option was provided, but the current commit is not in a stack.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_hide_range() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["branch", "feature"])?;
+        git.commit_file("test3", 3)?;
+        let test4_oid = git.commit_file("test4", 4)?;
+        git.run(&["checkout", "master"])?;
+
+        let range = format!("{}..{}", test1_oid, test4_oid);
+        {
+            let (stdout, _stderr) = git.run(&["hide", &range])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Not hiding 1 commit from the range which is still reachable from a branch:
+            - 96d1c37a create test2.txt
+            Hid commit: 70deb1e2 create test3.txt
+            To unhide this commit, run: git unhide 70deb1e2
+            Hid commit: 355e173b create test4.txt
+            To unhide this commit, run: git unhide 355e173b
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            o 96d1c37a (feature) create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["unhide", &range])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Unhid commit: 96d1c37a create test2.txt
+            (It was not hidden, so this operation had no effect.)
+            To hide this commit, run: git hide 96d1c37a
+            Unhid commit: 70deb1e2 create test3.txt
+            To hide this commit, run: git hide 70deb1e2
+            Unhid commit: 355e173b create test4.txt
+            To hide this commit, run: git hide 355e173b
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["hide", "nonexistent..master"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Commit not found: nonexistent..master
+            "###);
+        }
+
+        Ok(())
+    })
+}
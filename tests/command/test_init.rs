@@ -66,6 +66,7 @@ fn test_old_git_version_warning() -> anyhow::Result<()> {
             Installing hook: post-commit
             Installing hook: post-rewrite
             Installing hook: post-checkout
+            Installing hook: post-merge
             Installing hook: pre-auto-gc
             Installing hook: reference-transaction
             Installing alias (non-global): git smartlog -> git branchless smartlog
@@ -117,6 +118,7 @@ fn test_init_basic() -> anyhow::Result<()> {
             Installing hook: post-commit
             Installing hook: post-rewrite
             Installing hook: post-checkout
+            Installing hook: post-merge
             Installing hook: pre-auto-gc
             Installing hook: reference-transaction
             Installing alias (non-global): git smartlog -> git branchless smartlog
@@ -167,6 +169,7 @@ fn test_init_prompt_for_main_branch() -> anyhow::Result<()> {
             Installing hook: post-commit
             Installing hook: post-rewrite
             Installing hook: post-checkout
+            Installing hook: post-merge
             Installing hook: pre-auto-gc
             Installing hook: reference-transaction
             Installing alias (non-global): git smartlog -> git branchless smartlog
@@ -181,6 +184,7 @@ fn test_init_prompt_for_main_branch() -> anyhow::Result<()> {
             "###);
         }
 
+        git.run(&["config", "branchless.hint.smartlogLegend", "false"])?;
         {
             let (stdout, _stderr) = git.run(&["smartlog"])?;
             insta::assert_snapshot!(stdout, @"@ f777ecc9 (bespoke) create initial.txt
@@ -223,3 +227,290 @@ fn test_main_branch_not_found_error_message() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_main_branch_renamed_error_message() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&["branch", "-m", "master", "main"])?;
+
+        {
+            let (stdout, stderr) = git.run_with_options(
+                &["smartlog"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(trim_lines(stderr), @r###"
+            Error: Getting main branch OID for repository
+
+            Caused by:
+
+                The main branch "master" could not be found in your repository.
+                These branches look like they could be the main branch: main
+                Either create it, or update the main branch setting by running:
+
+                    git config branchless.core.mainBranch main
+
+            "###);
+            insta::assert_snapshot!(stdout, @"");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_init_preserves_existing_hook() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo_with_options(&GitInitOptions {
+            run_branchless_init: false,
+            ..Default::default()
+        })?;
+
+        let hook_path = git.repo_path.join(".git").join("hooks").join("post-commit");
+        std::fs::write(&hook_path, "#!/bin/sh\necho 'Running my hook'\n")?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "init"])?;
+            let stdout = stdout.replace(&git.repo_path.to_string_lossy().to_string(), "<repo>");
+            insta::assert_snapshot!(stdout, @r###"
+            Auto-detected your main branch as: master
+            If this is incorrect, run: git config branchless.core.mainBranch <branch>
+            Setting config (non-global): branchless.core.mainBranch = master
+            Setting config (non-global): advice.detachedHead = false
+            Installing hook: post-commit
+            Warning: the existing hook at "<repo>/.git/hooks/post-commit" contains other commands, which will be preserved:
+                echo 'Running my hook'
+            Installing hook: post-rewrite
+            Installing hook: post-checkout
+            Installing hook: post-merge
+            Installing hook: pre-auto-gc
+            Installing hook: reference-transaction
+            Installing alias (non-global): git smartlog -> git branchless smartlog
+            Installing alias (non-global): git sl -> git branchless smartlog
+            Installing alias (non-global): git hide -> git branchless hide
+            Installing alias (non-global): git unhide -> git branchless unhide
+            Installing alias (non-global): git prev -> git branchless prev
+            Installing alias (non-global): git next -> git branchless next
+            Installing alias (non-global): git restack -> git branchless restack
+            Installing alias (non-global): git undo -> git branchless undo
+            Installing alias (non-global): git move -> git branchless move
+            "###);
+        }
+
+        {
+            let hook_contents = std::fs::read_to_string(&hook_path)?;
+            insta::assert_snapshot!(hook_contents, @r###"
+            #!/bin/sh
+            echo 'Running my hook'
+            ## START BRANCHLESS CONFIG
+
+            git branchless hook-post-commit "$@"
+            ## END BRANCHLESS CONFIG
+            "###);
+        }
+
+        {
+            // Running `init` again should only replace the branchless section.
+            git.run(&["branchless", "init"])?;
+            let hook_contents = std::fs::read_to_string(&hook_path)?;
+            assert_eq!(hook_contents.matches("hook-post-commit").count(), 1);
+            assert!(hook_contents.contains("Running my hook"));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_init_hooks_path_outside_repo() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo_with_options(&GitInitOptions {
+            run_branchless_init: false,
+            ..Default::default()
+        })?;
+
+        let hooks_dir = tempfile::tempdir()?;
+        let hooks_path = hooks_dir.path().to_string_lossy().to_string();
+        git.run(&["config", "core.hooksPath", &hooks_path])?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "init"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            let stdout = stdout.replace(&hooks_path, "<hooks>");
+            insta::assert_snapshot!(stdout, @r###"
+            The hooks directory configured with `core.hooksPath` is outside of this
+            repository: "<hooks>"
+
+            It may be shared with other repositories or managed by another tool. To install
+            the branchless hooks there anyway, run: git branchless init --force
+            "###);
+        }
+        assert!(!hooks_dir.path().join("post-commit").exists());
+
+        git.run(&["branchless", "init", "--force"])?;
+        assert!(hooks_dir.path().join("post-commit").exists());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_init_shows_smartlog_legend_once() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo_with_options(&GitInitOptions {
+            run_branchless_init: false,
+            ..Default::default()
+        })?;
+        git.run(&["branchless", "init"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+
+            Legend:
+              o   a commit you're working on
+              O   a commit on the main branch
+              @   the current commit (HEAD)
+              x   a hidden commit, shown because it has visible descendants
+              %   the current commit (HEAD), which is hidden
+              X   a hidden commit on the main branch
+              s   a stash entry
+              :   some commits were omitted here
+              |   connects a commit to its parent
+              |\  a commit with more than one child
+            hint: this legend is only shown once; run `git smartlog --legend` to see it again
+            "###);
+        }
+
+        // Once the hint has been shown, the smartlog no longer writes to the
+        // config.
+        let (config_before, _stderr) = git.run(&["config", "--local", "--list"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            "###);
+        }
+        let (config_after, _stderr) = git.run(&["config", "--local", "--list"])?;
+        assert_eq!(config_before, config_after);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_init_custom_aliases() -> anyhow::Result<()> {
+    with_git(|git| {
+        if !git.supports_reference_transactions()? {
+            return Ok(());
+        }
+
+        git.init_repo_with_options(&GitInitOptions {
+            run_branchless_init: false,
+            ..Default::default()
+        })?;
+        git.run(&["config", "alias.hide", "log --oneline"])?;
+        git.run(&["config", "--add", "branchless.init.aliases", "sl="])?;
+        git.run(&["config", "--add", "branchless.init.aliases", "bsl=smartlog"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "branchless",
+                "init",
+                "--alias",
+                "prev=",
+                "--alias",
+                "top=next",
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Auto-detected your main branch as: master
+            If this is incorrect, run: git config branchless.core.mainBranch <branch>
+            Setting config (non-global): branchless.core.mainBranch = master
+            Setting config (non-global): advice.detachedHead = false
+            Installing hook: post-commit
+            Installing hook: post-rewrite
+            Installing hook: post-checkout
+            Installing hook: post-merge
+            Installing hook: pre-auto-gc
+            Installing hook: reference-transaction
+            Installing alias (non-global): git smartlog -> git branchless smartlog
+            Skipping alias: git sl (disabled by branchless.init.aliases)
+            Skipping alias: git hide (already defined as: log --oneline; use --force to overwrite)
+            Installing alias (non-global): git unhide -> git branchless unhide
+            Skipping alias: git prev (disabled by --alias)
+            Installing alias (non-global): git next -> git branchless next
+            Installing alias (non-global): git restack -> git branchless restack
+            Installing alias (non-global): git undo -> git branchless undo
+            Installing alias (non-global): git move -> git branchless move
+            Installing alias (non-global): git bsl -> git branchless smartlog
+            Installing alias (non-global): git top -> git branchless next
+            Installed 8 aliases and skipped 3 aliases.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["config", "alias.hide"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            log --oneline
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["config", "alias.bsl"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless smartlog
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "init", "--force"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Auto-detected your main branch as: master
+            If this is incorrect, run: git config branchless.core.mainBranch <branch>
+            Setting config (non-global): branchless.core.mainBranch = master
+            Setting config (non-global): advice.detachedHead = false
+            Installing hook: post-commit
+            Installing hook: post-rewrite
+            Installing hook: post-checkout
+            Installing hook: post-merge
+            Installing hook: pre-auto-gc
+            Installing hook: reference-transaction
+            Installing alias (non-global): git smartlog -> git branchless smartlog
+            Skipping alias: git sl (disabled by branchless.init.aliases)
+            Installing alias (non-global): git hide -> git branchless hide
+            Installing alias (non-global): git unhide -> git branchless unhide
+            Installing alias (non-global): git prev -> git branchless prev
+            Installing alias (non-global): git next -> git branchless next
+            Installing alias (non-global): git restack -> git branchless restack
+            Installing alias (non-global): git undo -> git branchless undo
+            Installing alias (non-global): git move -> git branchless move
+            Installing alias (non-global): git bsl -> git branchless smartlog
+            Installed 9 aliases and skipped 1 alias.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "init", "--alias", "sl"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Invalid alias from --alias: "sl" (expected `name=command`)
+            "###);
+        }
+
+        Ok(())
+    })
+}
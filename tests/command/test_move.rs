@@ -1,5 +1,24 @@
-use branchless::testing::{with_git, Git, GitRunOptions};
-use branchless::util::GitVersion;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use branchless::commands::r#move::testing::edit_rebase_plan;
+use branchless::core::eventlog::testing::get_event_replayer_events;
+use branchless::core::eventlog::{Event, EventLogDb, EventReplayer};
+use branchless::core::formatting::Glyphs;
+use branchless::core::graph::{make_graph_from_repo, AncestryCache, MainBranchOid};
+use branchless::core::mergebase::MergeBaseDb;
+use branchless::core::patchid::PatchIdDb;
+use branchless::core::rewrite::{execute_rebase_plan, make_rebase_plan, PickAction, RebasePlan};
+use branchless::core::tui::testing::{
+    screen_to_string, CursiveTestingBackend, CursiveTestingEvent,
+};
+use branchless::testing::{with_git, Git, GitInitOptions, GitRunOptions};
+use branchless::util::{get_db_conn, get_main_branch_oid, GitExecutable, GitVersion};
+
+use cursive::event::Key;
+use cursive::CursiveRunnable;
 
 /// Git v2.24 produces this message on `git move` tests:
 ///
@@ -43,9 +62,9 @@ fn test_move_stick_on_disk() -> anyhow::Result<()> {
             :
             O 62fc20d2 create test1.txt
             |\
-            | o cade1d30 create test3.txt
+            | o 4838e49b create test3.txt
             | |
-            | @ 5bb72580 create test4.txt
+            | @ a2482074 create test4.txt
             |
             O 96d1c37a (master) create test2.txt
             "###);
@@ -135,11 +154,11 @@ fn test_move_tree_on_disk() -> anyhow::Result<()> {
             :
             O 62fc20d2 create test1.txt
             |\
-            | @ cade1d30 create test3.txt
+            | @ 4838e49b create test3.txt
             | |\
-            | | o 5bb72580 create test4.txt
+            | | o a2482074 create test4.txt
             | |
-            | o df755ed1 create test5.txt
+            | o b1f9efa0 create test5.txt
             |
             O 96d1c37a (master) create test2.txt
             "###);
@@ -223,7 +242,7 @@ fn test_move_with_source_not_in_smartlog_on_disk() -> anyhow::Result<()> {
             |\
             : o 96d1c37a create test2.txt
             :
-            @ 5bb72580 (master) create test4.txt
+            @ a2482074 (master) create test4.txt
             "###);
         }
 
@@ -303,9 +322,11 @@ fn test_move_merge_conflict() -> anyhow::Result<()> {
             insta::assert_snapshot!(stdout, @r###"
             Attempting rebase in-memory...
             Merge conflict, falling back to rebase on-disk. The conflicting commit was: e85d25c7 create conflict.txt
+            branchless: processing 1 update to a branch/ref
             branchless: <git-executable> rebase --continue
-            CONFLICT (add/add): Merge conflict in conflict.txt
             Auto-merging conflict.txt
+            CONFLICT (add/add): Merge conflict in conflict.txt
+            Backed up HEAD to refs/branchless/backup/11 before rebasing on disk. To return to it, run: git checkout refs/branchless/backup/11
             "###);
         }
 
@@ -326,7 +347,112 @@ fn test_move_merge_conflict() -> anyhow::Result<()> {
             |
             o 202143f2 create conflict.txt
             |
-            @ 244e2bd1 create conflict.txt
+            @ 42951b5f create conflict.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_previous_dest() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "-s", &test2_oid.to_string(), "-d", "-"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The commit to move hasn't been moved before, so it has no previous location: 96d1c37a create test2.txt
+            "###);
+        }
+
+        git.run(&["move", "-s", &test2_oid.to_string(), "-d", "master"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ bf0d52a6 (master) create test4.txt
+            |
+            o 44352d00 create test2.txt
+            |
+            o cf5eb244 create test3.txt
+            "###);
+        }
+
+        // The parent of the moved commit is recorded along with the move.
+        {
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let old_parent_oids: Vec<git2::Oid> = event_log_db
+                .get_events()?
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::MoveEvent {
+                        commit_oid,
+                        old_parent_oid,
+                        ..
+                    } if commit_oid == test2_oid => Some(old_parent_oid),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(old_parent_oids, vec![test1_oid]);
+        }
+
+        // Amending the moved commit shouldn't prevent moving it back.
+        git.run(&["branchless", "checkout", "create test2"])?;
+        git.run(&["commit", "--amend", "-m", "amended test2"])?;
+        git.run(&["restack"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O bf0d52a6 (master) create test4.txt
+            |
+            @ 06cdf90a amended test2
+            |
+            o ab23d9b4 create test3.txt
+            "###);
+        }
+
+        git.run(&["move", "-d", "-"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |\
+            | @ cb8137ad amended test2
+            | |
+            | o 43a04ef7 create test3.txt
+            |
+            O bf0d52a6 (master) create test4.txt
+            "###);
+        }
+
+        git.run(&["move", "-d", "-"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O bf0d52a6 (master) create test4.txt
+            |
+            @ 06cdf90a amended test2
+            |
+            o ab23d9b4 create test3.txt
             "###);
         }
 
@@ -370,6 +496,44 @@ fn test_move_base() -> anyhow::Result<()> {
     })
 }
 
+#[test]
+fn test_move_stack() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+        git.run(&["checkout", &test2_oid.to_string()])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["move", "--stack", "-d", "master"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 2 rewritten commits
+            branchless: <git-executable> checkout 44352d000bc6b106acc6124f8b23294b572d84a6
+            In-memory rebase succeeded.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O bf0d52a6 (master) create test4.txt
+            |
+            @ 44352d00 create test2.txt
+            |
+            o cf5eb244 create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
 #[test]
 fn test_move_checkout_new_head() -> anyhow::Result<()> {
     with_git(|git| {
@@ -443,3 +607,1899 @@ fn test_move_branch() -> anyhow::Result<()> {
 
 // TODO: implement restack in terms of move
 // TODO: don't re-apply already-applied commits
+
+#[test]
+fn test_move_subtree_branches_in_memory() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        git.detach_head()?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.run(&["branch", "foo"])?;
+        git.commit_file("test4", 4)?;
+        git.run(&["branch", "bar"])?;
+        git.run(&["checkout", &test3_oid.to_string()])?;
+
+        git.run(&[
+            "move",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |\
+            | @ 4838e49b (foo) create test3.txt
+            | |
+            | o a2482074 (bar) create test4.txt
+            |
+            O 96d1c37a (master) create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["rev-parse", "foo", "bar"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            4838e49b08954becdd17c0900c1179c2c654c627
+            a248207402822b7396cabe0f1011d8a7ce7daf1b
+            "###);
+        }
+
+        {
+            // The branch moves are passed to the `reference-transaction` hook
+            // under their full reference names, as Git itself does.
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let mut branch_moves: Vec<(String, Option<String>, Option<String>)> = event_log_db
+                .get_events()?
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::RefUpdateEvent {
+                        ref_name,
+                        old_ref: old_ref @ Some(_),
+                        new_ref,
+                        ..
+                    } if ref_name.ends_with("foo") || ref_name.ends_with("bar") => {
+                        Some((ref_name, old_ref, new_ref))
+                    }
+                    _ => None,
+                })
+                .collect();
+            // The branches are updated in an unspecified order.
+            branch_moves.sort();
+            insta::assert_debug_snapshot!(branch_moves, @r###"
+            [
+                (
+                    "refs/heads/bar",
+                    Some(
+                        "355e173bf9c5d2efac2e451da0cdad3fb82b869a",
+                    ),
+                    Some(
+                        "a248207402822b7396cabe0f1011d8a7ce7daf1b",
+                    ),
+                ),
+                (
+                    "refs/heads/foo",
+                    Some(
+                        "70deb1e28791d8e7dd5a1f0c871a51b91282562f",
+                    ),
+                    Some(
+                        "4838e49b08954becdd17c0900c1179c2c654c627",
+                    ),
+                ),
+            ]
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_subtree_branches_on_disk() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        git.detach_head()?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.run(&["branch", "foo"])?;
+        git.commit_file("test4", 4)?;
+        git.run(&["branch", "bar"])?;
+        git.run(&["checkout", &test3_oid.to_string()])?;
+
+        git.run(&[
+            "move",
+            "--on-disk",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |\
+            | o 4838e49b (foo) create test3.txt
+            | |
+            | @ a2482074 (bar) create test4.txt
+            |
+            O 96d1c37a (master) create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["rev-parse", "foo", "bar"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            4838e49b08954becdd17c0900c1179c2c654c627
+            a248207402822b7396cabe0f1011d8a7ce7daf1b
+            "###);
+        }
+
+        {
+            // The branch should be moved in the same transaction as the
+            // rewrite of the commit it pointed to.
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+            let events = get_event_replayer_events(&event_replayer);
+            let rewrite_event_tx_id = events
+                .iter()
+                .find_map(|event| match event {
+                    Event::RewriteEvent {
+                        event_tx_id,
+                        old_commit_oid,
+                        ..
+                    } if *old_commit_oid == test3_oid => Some(*event_tx_id),
+                    _ => None,
+                })
+                .expect("rewrite event for test3 not found");
+            let branch_event_tx_id = events
+                .iter()
+                .rev()
+                .find_map(|event| match event {
+                    Event::RefUpdateEvent {
+                        event_tx_id,
+                        ref_name,
+                        ..
+                    } if ref_name == "refs/heads/foo" => Some(*event_tx_id),
+                    _ => None,
+                })
+                .expect("ref update event for foo not found");
+            assert_eq!(branch_event_tx_id, rewrite_event_tx_id);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_attach_dest_branch() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", &test1_oid.to_string()])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["move", "-s", "HEAD", "-d", "master", "--attach"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 1 rewritten commit
+            branchless: <git-executable> checkout 07709435a8f6d1566e0091896d130c78acd429dd
+            In-memory rebase succeeded.
+            branchless: <git-executable> checkout master
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branch", "--show-current"])?;
+            assert_eq!(stdout, "master\n");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_attach_dest_not_branch() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "-d", "HEAD^", "--attach"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The --attach option was provided, but the destination is not a branch: HEAD^
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_abort_on_conflict() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let base_oid = git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", &base_oid.to_string()])?;
+        git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "move",
+                    "--on-disk",
+                    "--abort-on-conflict",
+                    "-s",
+                    &other_oid.to_string(),
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following commits would cause merge conflicts:
+            - e85d25c7 create conflict.txt
+            Not moving any commits, since --abort-on-conflict was provided.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["status", "--short"])?;
+            assert_eq!(stdout, "");
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |\
+            | @ 202143f2 create conflict.txt
+            |
+            o e85d25c7 create conflict.txt
+            |
+            o a8cd78c4 create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_on_disk_reports_conflicts() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let base_oid = git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+        git.run(&["checkout", &base_oid.to_string()])?;
+        git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "--on-disk", "-s", &other_oid.to_string()],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The following commits would cause merge conflicts:
+            - e85d25c7 create conflict.txt
+            branchless: processing 1 update to a branch/ref
+            branchless: <git-executable> rebase --continue
+            Auto-merging conflict.txt
+            CONFLICT (add/add): Merge conflict in conflict.txt
+            Backed up HEAD to refs/branchless/backup/11 before rebasing on disk. To return to it, run: git checkout refs/branchless/backup/11
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["show-ref", "refs/branchless/backup/11"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            202143f2fdfc785285ab097422f6a695ff1d93cb refs/branchless/backup/11
+            "###);
+        }
+
+        // The backup is deleted once the rebase completes.
+        git.resolve_file("conflict", "conflict 1\nconflict 2\n")?;
+        git.run(&["rebase", "--continue"])?;
+        {
+            let (stdout, _stderr) = git.run(&["for-each-ref", "refs/branchless/backup/"])?;
+            assert_eq!(stdout, "");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_in_memory_preserves_working_copy() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.detach_head()?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        let test4_oid = git.commit_file("test4", 4)?;
+        git.run(&["checkout", "master"])?;
+        git.write_file("test1", "uncommitted changes\n")?;
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "move",
+                "-s",
+                &test4_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 1 rewritten commit
+            In-memory rebase succeeded.
+            "###);
+        }
+
+        {
+            // HEAD wasn't moved, so it should still be attached to its branch.
+            let (stdout, _stderr) = git.run(&["branch", "--show-current"])?;
+            assert_eq!(stdout, "master\n");
+        }
+
+        git.run(&["checkout", &test3_oid.to_string()])?;
+        {
+            let (stdout, _stderr) = git.run(&["move", "-d", &test1_oid.to_string()])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 1 rewritten commit
+            branchless: <git-executable> checkout 4838e49b08954becdd17c0900c1179c2c654c627
+            M	test1.txt
+            In-memory rebase succeeded.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["status", "--short"])?;
+            assert_eq!(stdout, " M test1.txt\n");
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |\
+            | @ 4838e49b (dirty) create test3.txt
+            |\
+            | o bf0d52a6 create test4.txt
+            |
+            O 96d1c37a (master) create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_exit_codes() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "-d", "nonexistent"],
+                &GitRunOptions {
+                    expected_exit_code: 3,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Commit not found: nonexistent
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_ambiguous_short_oid() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+
+        // Write blobs until two of them share a short OID prefix.
+        let repo = git.get_repo()?;
+        let mut prefixes = HashSet::new();
+        let ambiguous_prefix = (0..)
+            .find_map(|i| {
+                let blob_oid = repo.blob(format!("blob {}", i).as_bytes()).unwrap();
+                let prefix = blob_oid.to_string()[..4].to_string();
+                if prefixes.insert(prefix.clone()) {
+                    None
+                } else {
+                    Some(prefix)
+                }
+            })
+            .unwrap();
+
+        let (_stdout, stderr) = git.run_with_options(
+            &["move", "-s", &ambiguous_prefix, "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("Ambiguous"),
+            "ambiguous short OID not reported: {}",
+            stderr
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_non_commit_oids() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["tag", "-a", "-m", "annotated tag", "my-tag", "HEAD"])?;
+        git.run(&["tag", "-a", "-m", "tree tag", "my-tree-tag", "HEAD^{tree}"])?;
+        let tree_oid = git.get_repo()?.head()?.peel_to_tree()?.id();
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "-s", &tree_oid.to_string(), "-d", "master"],
+                &GitRunOptions {
+                    expected_exit_code: 3,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            aedff3aac8216d25422b52e8a198b802c0e94db3 is not a commit (it refers to a tree)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "-s", "my-tree-tag", "-d", "master"],
+                &GitRunOptions {
+                    expected_exit_code: 3,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            my-tree-tag is not a commit (it refers to a tree)
+            "###);
+        }
+
+        {
+            git.run(&["move", "-s", "my-tag", "-d", "master"])?;
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |\
+            | o 96d1c37a create test2.txt
+            | |
+            | x 70deb1e2 (rewritten as 4838e49b) (tag: my-tag) create test3.txt
+            |
+            @ 4838e49b create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_sign_commits() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+
+        // Stands in for `gpg`: it ignores the key and the commit contents, and
+        // prints a fixed signature, along with the status line which Git checks
+        // for.
+        let signer_path = git.repo_path.join(".git").join("fake-gpg");
+        std::fs::write(
+            &signer_path,
+            "#!/bin/sh
+cat >/dev/null
+echo '[GNUPG:] SIG_CREATED ' >&2
+echo '-----BEGIN PGP SIGNATURE-----'
+echo
+echo 'fake signature'
+echo '-----END PGP SIGNATURE-----'
+",
+        )?;
+        let mut permissions = std::fs::metadata(&signer_path)?.permissions();
+        permissions.set_mode(0o755);
+        std::fs::set_permissions(&signer_path, permissions)?;
+        git.run(&["config", "gpg.program", signer_path.to_str().unwrap()])?;
+
+        let get_head_contents = || -> anyhow::Result<String> {
+            let (stdout, _stderr) = git.run(&["cat-file", "-p", "HEAD"])?;
+            Ok(stdout)
+        };
+
+        git.run(&["move", "-s", "HEAD^", "-d", "master"])?;
+        assert!(!get_head_contents()?.contains("gpgsig"));
+
+        git.run(&["config", "branchless.rewrite.sign", "true"])?;
+        git.run(&["move", "-s", "HEAD^", "-d", "HEAD~3"])?;
+        let head_contents = get_head_contents()?;
+        assert!(
+            head_contents.contains("gpgsig -----BEGIN PGP SIGNATURE-----"),
+            "Commit was not signed in memory: {}",
+            head_contents
+        );
+
+        git.run(&["move", "--on-disk", "-s", "HEAD^", "-d", "master"])?;
+        let head_contents = get_head_contents()?;
+        assert!(
+            head_contents.contains("gpgsig -----BEGIN PGP SIGNATURE-----"),
+            "Commit was not signed on disk: {}",
+            head_contents
+        );
+
+        Ok(())
+    })
+}
+
+/// Create a stack on top of `test1` whose first commit makes the same changes
+/// as the commit on the main branch, so that it becomes empty when moved onto
+/// the main branch.
+///
+/// Returns: The OID of the first commit in the stack.
+fn make_stack_with_duplicate_commit(git: &Git, time: isize) -> anyhow::Result<git2::Oid> {
+    git.run(&["checkout", "master^"])?;
+    let duplicate_oid = git.commit_file("test2", time)?;
+    git.commit_file(&format!("test{}", time), time)?;
+    Ok(duplicate_oid)
+}
+
+#[test]
+fn test_move_drop_empty_commits() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        {
+            let duplicate_oid = make_stack_with_duplicate_commit(&git, 3)?;
+            git.run(&["move", "-s", &duplicate_oid.to_string(), "-d", "master"])?;
+            let (stdout, _stderr) = git.run(&["smartlog", "--no-main"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 96d1c37a (master) create test2.txt
+            |
+            @ 70deb1e2 create test3.txt
+            "###);
+        }
+
+        {
+            let duplicate_oid = make_stack_with_duplicate_commit(&git, 4)?;
+            git.run(&[
+                "move",
+                "--on-disk",
+                "-s",
+                &duplicate_oid.to_string(),
+                "-d",
+                "master",
+            ])?;
+            let (stdout, _stderr) = git.run(&["smartlog", "--no-main"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 96d1c37a (master) create test2.txt
+            |\
+            | o 70deb1e2 create test3.txt
+            |
+            @ f57e36f5 create test4.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_keep_empty_commits() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["config", "branchless.rewrite.emptyCommits", "keep"])?;
+
+        {
+            let duplicate_oid = make_stack_with_duplicate_commit(&git, 3)?;
+            git.run(&["move", "-s", &duplicate_oid.to_string(), "-d", "master"])?;
+            let (stdout, _stderr) = git.run(&["smartlog", "--no-main"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 96d1c37a (master) create test2.txt
+            |
+            o 2dccef60 create test2.txt
+            |
+            @ 10e1f325 create test3.txt
+            "###);
+        }
+
+        {
+            let duplicate_oid = make_stack_with_duplicate_commit(&git, 4)?;
+            git.run(&[
+                "move",
+                "--on-disk",
+                "-s",
+                &duplicate_oid.to_string(),
+                "-d",
+                "master",
+            ])?;
+            let (stdout, _stderr) = git.run(&["smartlog", "--no-main"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 96d1c37a (master) create test2.txt
+            |\
+            | o 2dccef60 create test2.txt
+            | |
+            | o 10e1f325 create test3.txt
+            |
+            o 779126d9 create test2.txt
+            |
+            @ 129d56e5 create test4.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_skip_applied_commits_on_disk() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file_with_contents("test1", 1, "first contents\n")?;
+        git.commit_file_with_contents("test1", 2, "second contents\n")?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+        // Simulate squash-merging the first two commits into the main branch.
+        git.write_file("test1", "second contents\n")?;
+        git.run(&["add", "."])?;
+        git.run(&["commit", "-m", "squash-merge test1.txt"])?;
+        git.detach_head()?;
+
+        git.run(&[
+            "move",
+            "--on-disk",
+            "-s",
+            &test1_oid.to_string(),
+            "-d",
+            "master",
+        ])?;
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 88069869 (master) squash-merge test1.txt
+        |
+        @ f722ff4a create test3.txt
+        "###);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_same_tree_not_applied() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["rm", "test1.txt"])?;
+        git.run(&["commit", "-m", "remove test1.txt"])?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+        git.run(&["rm", "test2.txt"])?;
+        git.run(&["commit", "-m", "remove test2.txt"])?;
+
+        // The main branch now has the same tree as the second commit in the
+        // stack, but neither commit in the stack has been applied to it.
+        git.run(&["move", "-s", &test1_oid.to_string(), "-d", "master"])?;
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ f95442cd (master) remove test2.txt
+        |
+        o 0ac1a6fb create test1.txt
+        |
+        o 2c72c199 remove test1.txt
+        "###);
+
+        Ok(())
+    })
+}
+
+/// Make the plan for moving `source_oid` and its descendants, and let the user
+/// edit it with the given events.
+fn run_edit_rebase_plan(
+    git: &Git,
+    source_oid: git2::Oid,
+    events: Vec<CursiveTestingEvent>,
+) -> anyhow::Result<Option<RebasePlan>> {
+    let repo = git.get_repo()?;
+    let main_branch_oid = get_main_branch_oid(&repo)?;
+    let conn = get_db_conn(&repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+    let graph = make_graph_from_repo(
+        &repo,
+        &merge_base_db,
+        &mut AncestryCache::default(),
+        &event_replayer,
+        event_replayer.make_default_cursor(),
+        true,
+    )?;
+    let rebase_plan = make_rebase_plan(
+        &repo,
+        &merge_base_db,
+        &PatchIdDb::new(&conn)?,
+        &graph,
+        &MainBranchOid(main_branch_oid),
+        source_oid,
+        main_branch_oid,
+    )?;
+    let siv = CursiveRunnable::new::<Infallible, _>(move || {
+        Ok(CursiveTestingBackend::init_with_size(
+            events.clone(),
+            80,
+            24,
+        ))
+    });
+    edit_rebase_plan(siv.into_runner(), &repo, &rebase_plan)
+}
+
+#[cfg(unix)]
+fn run_execute_rebase_plan(
+    git: &Git,
+    rebase_plan: &RebasePlan,
+    source_oid: git2::Oid,
+    dest_oid: git2::Oid,
+) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let glyphs = Glyphs::text();
+    let repo = git.get_repo()?;
+    let conn = get_db_conn(&repo)?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "move")?;
+
+    // Nested calls to `git` must run in the repository, and with
+    // `git-branchless` on the `PATH` for the hooks. Rather than changing the
+    // environment of the test process, which is shared with other tests, run
+    // them through a wrapper which sets up its own environment.
+    let git_wrapper_path = git.repo_path.join(".git").join("git-wrapper");
+    std::fs::write(
+        &git_wrapper_path,
+        format!(
+            "#!/bin/sh
+cd '{}' || exit 1
+PATH='{}'
+export PATH
+for var in $(env | sed -n 's/^\\(GIT_[A-Za-z_]*\\)=.*/\\1/p'); do
+    unset \"$var\"
+done
+exec '{}' \"$@\"
+",
+            repo.workdir().unwrap().display(),
+            git.get_path_for_env(),
+            git.git_executable.display(),
+        ),
+    )?;
+    let mut permissions = std::fs::metadata(&git_wrapper_path)?.permissions();
+    permissions.set_mode(0o755);
+    std::fs::set_permissions(&git_wrapper_path, permissions)?;
+
+    let mut out = Vec::new();
+    let result = execute_rebase_plan(
+        &mut out,
+        &glyphs,
+        &GitExecutable(git_wrapper_path),
+        &repo,
+        event_tx_id,
+        rebase_plan,
+        source_oid,
+        dest_oid,
+        false,
+        false,
+        false,
+    )?;
+    assert_eq!(result, 0);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_interactive_reorder_and_drop() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        let test4_oid = git.commit_file("test4", 4)?;
+
+        let screenshot1 = Default::default();
+        let rebase_plan = run_edit_rebase_plan(
+            &git,
+            test2_oid,
+            vec![
+                CursiveTestingEvent::Event('j'.into()),
+                CursiveTestingEvent::Event('j'.into()),
+                CursiveTestingEvent::Event('K'.into()),
+                CursiveTestingEvent::Event('j'.into()),
+                CursiveTestingEvent::Event('d'.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                CursiveTestingEvent::Event(Key::Enter.into()),
+            ],
+        )?;
+        insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+        pick  96d1c37a create test2.txt
+        pick  355e173b create test4.txt
+        > drop  70deb1e2 create test3.txt
+        Edit the commits to move. Press 'h' for help, '<enter>' to move the commits,
+        or 'q' to cancel.
+        "###);
+        let rebase_plan = rebase_plan.expect("the plan should have been confirmed");
+        assert_eq!(
+            rebase_plan.get_picks(),
+            vec![
+                (test2_oid, PickAction::Pick),
+                (test4_oid, PickAction::Pick),
+                (test3_oid, PickAction::Drop),
+            ]
+        );
+        assert_eq!(rebase_plan.get_dropped_oids(), vec![test3_oid]);
+
+        run_execute_rebase_plan(&git, &rebase_plan, test2_oid, test1_oid)?;
+        {
+            let (stdout, _stderr) = git.run(&["log", "--format=%s", "--name-only"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            create test4.txt
+
+            test4.txt
+            create test2.txt
+
+            test2.txt
+            create test1.txt
+
+            test1.txt
+            create initial.txt
+
+            initial.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(unix)]
+#[test]
+fn test_move_interactive_fixup() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+
+        let screenshot1 = Default::default();
+        let screenshot2 = Default::default();
+        let rebase_plan = run_edit_rebase_plan(
+            &git,
+            test2_oid,
+            vec![
+                CursiveTestingEvent::Event('f'.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                CursiveTestingEvent::Event('j'.into()),
+                CursiveTestingEvent::Event('f'.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot2)),
+                CursiveTestingEvent::Event(Key::Enter.into()),
+            ],
+        )?;
+        insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+        > pick  96d1c37a create test2.txt
+        pick  70deb1e2 create test3.txt
+        A commit can only be fixed up into the commit before it in its stack.
+        "###);
+        insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
+        pick  96d1c37a create test2.txt
+        > fixup 70deb1e2 create test3.txt
+        Edit the commits to move. Press 'h' for help, '<enter>' to move the commits,
+        or 'q' to cancel.
+        "###);
+        let rebase_plan = rebase_plan.expect("the plan should have been confirmed");
+        assert_eq!(
+            rebase_plan.get_picks(),
+            vec![
+                (test2_oid, PickAction::Pick),
+                (test3_oid, PickAction::Fixup)
+            ]
+        );
+
+        run_execute_rebase_plan(&git, &rebase_plan, test2_oid, test1_oid)?;
+        {
+            let (stdout, _stderr) = git.run(&["log", "--format=%s", "--name-only"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            create test2.txt
+
+            test2.txt
+            test3.txt
+            create test1.txt
+
+            test1.txt
+            create initial.txt
+
+            initial.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_interactive_cancel() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        let rebase_plan = run_edit_rebase_plan(
+            &git,
+            test1_oid,
+            vec![
+                CursiveTestingEvent::Event('d'.into()),
+                CursiveTestingEvent::Event('q'.into()),
+            ],
+        )?;
+        assert!(rebase_plan.is_none());
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_by_summary() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test3", 3)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "-s", "create test", "-d", "master"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Found multiple commits matching "create test":
+              - 62fc20d2 create test1.txt
+              - 96d1c37a create test2.txt
+              - 98b9119d create test3.txt
+            (Pass a commit hash or a more specific search to disambiguate)
+            "###);
+        }
+
+        git.run(&["move", "-s", "TEST2", "-d", "master"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            |
+            @ 98b9119d (master) create test3.txt
+            |
+            o 200e5477 create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+/// Make a stack of two commits on top of `test1`, and then advance the main
+/// branch past it.
+fn make_stack_behind_main(git: &Git) -> anyhow::Result<git2::Oid> {
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test4", 4)?;
+    Ok(test3_oid)
+}
+
+#[test]
+fn test_move_dest_source_vs_base() -> anyhow::Result<()> {
+    // `--dest` becomes the new parent of the root of the moved subtree.
+    // `--source` moves only the given commit and its descendants, while
+    // `--base` moves the entire stack.
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+        let test3_oid = make_stack_behind_main(&git)?;
+
+        git.run(&["move", "-s", &test3_oid.to_string(), "--dest", "master"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |\
+            | o 96d1c37a create test2.txt
+            |
+            @ bf0d52a6 (master) create test4.txt
+            |
+            o 0a4a701e create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })?;
+
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+        let test3_oid = make_stack_behind_main(&git)?;
+
+        git.run(&["move", "-b", &test3_oid.to_string(), "--dest", "master"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ bf0d52a6 (master) create test4.txt
+            |
+            o 44352d00 create test2.txt
+            |
+            o cf5eb244 create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_dest_main_branch_commit() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", &test1_oid.to_string()])?;
+        let test4_oid = git.commit_file("test4", 4)?;
+
+        // `--dest` moves the main branch commits descending from the source
+        // commit as well.
+        git.run(&[
+            "move",
+            "-s",
+            &test2_oid.to_string(),
+            "--dest",
+            &test4_oid.to_string(),
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ bf0d52a6 create test4.txt
+            :
+            O cf5eb244 (master) create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+/// Make a stack of three commits on top of `test1`, and a fourth commit which
+/// forks off from the first commit in the stack.
+fn make_forked_stack(git: &Git) -> anyhow::Result<(git2::Oid, git2::Oid)> {
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["checkout", &test2_oid.to_string()])?;
+    let test5_oid = git.commit_file("test5", 5)?;
+    Ok((test4_oid, test5_oid))
+}
+
+#[test]
+fn test_move_dest_vs_onto() -> anyhow::Result<()> {
+    // With `--base`, `--dest` only moves the commits which aren't already
+    // ancestors of the destination, as with `git rebase <upstream>`.
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+        let (test4_oid, test5_oid) = make_forked_stack(&git)?;
+
+        git.run(&[
+            "move",
+            "-b",
+            &test4_oid.to_string(),
+            "--dest",
+            &test5_oid.to_string(),
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            |
+            @ d2e18e38 create test5.txt
+            |
+            o 8e521a10 create test3.txt
+            |
+            o e96ba352 create test4.txt
+            "###);
+        }
+
+        Ok(())
+    })?;
+
+    // `--onto` doesn't affect which commits are moved, so `--base` selects the
+    // entire stack, which contains the destination.
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+        let (test4_oid, test5_oid) = make_forked_stack(&git)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "move",
+                    "-b",
+                    &test4_oid.to_string(),
+                    "--onto",
+                    &test5_oid.to_string(),
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Cannot move a commit onto its own descendant:
+            - Commit: 96d1c37a create test2.txt
+            - Destination: d2e18e38 create test5.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_onto_main_branch() -> anyhow::Result<()> {
+    // If none of the commits to move are ancestors of the destination, then
+    // `--onto` moves the same commits as `--dest` (see
+    // `test_move_dest_source_vs_base`).
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+        let test3_oid = make_stack_behind_main(&git)?;
+
+        git.run(&["move", "-b", &test3_oid.to_string(), "--onto", "master"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ bf0d52a6 (master) create test4.txt
+            |
+            o 44352d00 create test2.txt
+            |
+            o cf5eb244 create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_dest_and_onto() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "move",
+                    "-s",
+                    &test1_oid.to_string(),
+                    "--dest",
+                    "master",
+                    "--onto",
+                    "master",
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The --dest and --onto options cannot both be provided.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+/// Get the author and committer dates of the given commit, in seconds.
+fn get_commit_dates(git: &Git, commit_oid: git2::Oid) -> anyhow::Result<(i64, i64)> {
+    let repo = git.get_repo()?;
+    let commit = repo.find_commit(commit_oid)?;
+    let dates = (
+        commit.author().when().seconds(),
+        commit.committer().when().seconds(),
+    );
+    Ok(dates)
+}
+
+#[test]
+fn test_move_preserve_dates() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.detach_head()?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.commit_file("test4", 4)?;
+        // Give the commit a committer date different from its author date.
+        git.run_with_options(
+            &["commit", "--amend", "--no-edit"],
+            &GitRunOptions {
+                time: 10,
+                ..Default::default()
+            },
+        )?;
+        let test4_oid = git.get_repo()?.head()?.peel_to_commit()?.id();
+
+        let original_dates = get_commit_dates(&git, test4_oid)?;
+        assert_ne!(original_dates.0, original_dates.1);
+        let get_head_dates = || -> anyhow::Result<(i64, i64)> {
+            let head_oid = git.get_repo()?.head()?.peel_to_commit()?.id();
+            get_commit_dates(&git, head_oid)
+        };
+
+        git.run(&[
+            "move",
+            "-s",
+            &test3_oid.to_string(),
+            "-d",
+            &test1_oid.to_string(),
+        ])?;
+        assert_eq!(get_head_dates()?, original_dates);
+
+        git.run(&["move", "--on-disk", "-s", "test3", "-d", "master"])?;
+        assert_eq!(get_head_dates()?, original_dates);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_preserve_dates_disabled() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.detach_head()?;
+        git.commit_file("test3", 3)?;
+        git.run_with_options(
+            &["commit", "--amend", "--no-edit"],
+            &GitRunOptions {
+                time: 10,
+                ..Default::default()
+            },
+        )?;
+        let test3_oid = git.get_repo()?.head()?.peel_to_commit()?.id();
+        let original_dates = get_commit_dates(&git, test3_oid)?;
+
+        git.run(&["config", "branchless.rewrite.preserveDates", "false"])?;
+        git.run(&["move", "-s", "test3", "-d", &test1_oid.to_string()])?;
+        let head_oid = git.get_repo()?.head()?.peel_to_commit()?.id();
+        let (author_date, committer_date) = get_commit_dates(&git, head_oid)?;
+        assert_eq!(author_date, original_dates.0);
+        assert_ne!(committer_date, original_dates.1);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_exec() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        git.detach_head()?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.commit_file("test4", 4)?;
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "move",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+                "--exec-each",
+                "ls",
+                "--exec-each",
+                "echo $BRANCHLESS_EXEC_COMMIT",
+                "--exec",
+                "cat test4.txt",
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 2 rewritten commits
+            branchless: <git-executable> checkout a248207402822b7396cabe0f1011d8a7ce7daf1b
+            In-memory rebase succeeded.
+            Executing: ls (on 4838e49b create test3.txt)
+            initial.txt
+            test1.txt
+            test3.txt
+            Executing: echo $BRANCHLESS_EXEC_COMMIT (on 4838e49b create test3.txt)
+            4838e49b08954becdd17c0900c1179c2c654c627
+            Executing: ls (on a2482074 create test4.txt)
+            initial.txt
+            test1.txt
+            test3.txt
+            test4.txt
+            Executing: echo $BRANCHLESS_EXEC_COMMIT (on a2482074 create test4.txt)
+            a248207402822b7396cabe0f1011d8a7ce7daf1b
+            Executing: cat test4.txt
+            test4 contents
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_exec_failure() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        git.detach_head()?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.commit_file("test4", 4)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "move",
+                    "--on-disk",
+                    "-s",
+                    &test3_oid.to_string(),
+                    "-d",
+                    &test1_oid.to_string(),
+                    "--exec-each",
+                    "test -f test4.txt",
+                    "--exec",
+                    "echo should not run",
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: processing 1 update to a branch/ref
+            branchless: <git-executable> rebase --continue
+            Executing: test -f test4.txt (on 4838e49b create test3.txt)
+            Command `test -f test4.txt` failed with exit code 1 on commit: 4838e49b create test3.txt
+            Not running any remaining commands.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_allow_unrelated() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "--orphan", "imported"])?;
+        git.run(&["rm", "-rf", "."])?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move", "-s", &test2_oid.to_string(), "-d", "master"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            The commit to move has no history in common with the destination: 15256403 create test2.txt
+            (Pass --allow-unrelated to graft its history onto the destination anyway)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "move",
+                "-s",
+                &test2_oid.to_string(),
+                "-d",
+                "master",
+                "--allow-unrelated",
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 1 update to a branch/ref
+            branchless: processing 2 rewritten commits
+            branchless: <git-executable> checkout imported
+            In-memory rebase succeeded.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            |
+            @ 70deb1e2 (imported) create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["ls-files"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            initial.txt
+            test1.txt
+            test2.txt
+            test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_allow_unrelated_on_disk() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "--orphan", "imported"])?;
+        git.run(&["rm", "-rf", "."])?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "move",
+                "--on-disk",
+                "-s",
+                &test2_oid.to_string(),
+                "-d",
+                "master",
+                "--allow-unrelated",
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: processing 1 update to a branch/ref
+            branchless: <git-executable> rebase --continue
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            |
+            @ 70deb1e2 (imported) create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_committer_date_is_author_date() -> anyhow::Result<()> {
+    with_git(|git| {
+        if has_git_v2_24_bug(&git)? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        git.run(&[
+            "commit",
+            "--amend",
+            "--no-edit",
+            "--date",
+            "2001-02-03T04:05:06 +0000",
+        ])?;
+        git.commit_file("test3", 3)?;
+        git.run(&["branch", "foo"])?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["log", "-2", "--format=%s: %ad / %cd", "foo"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            create test3.txt: Thu Oct 29 12:34:56 2020 -0300 / Thu Oct 29 12:34:56 2020 -0300
+            create test2.txt: Sat Feb 3 04:05:06 2001 +0000 / Thu Oct 29 12:34:56 2020 +0000
+            "###);
+        }
+
+        git.run(&[
+            "move",
+            "-s",
+            "foo^",
+            "-d",
+            "master",
+            "--committer-date-is-author-date",
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["log", "-3", "--format=%s: %ad / %cd", "foo"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            create test3.txt: Thu Oct 29 12:34:56 2020 -0300 / Thu Oct 29 12:34:56 2020 -0300
+            create test2.txt: Sat Feb 3 04:05:06 2001 +0000 / Sat Feb 3 04:05:06 2001 +0000
+            create test4.txt: Thu Oct 29 12:34:56 2020 -0400 / Thu Oct 29 12:34:56 2020 -0400
+            "###);
+        }
+
+        git.run(&[
+            "move",
+            "--on-disk",
+            "-s",
+            "foo^",
+            "-d",
+            &test1_oid.to_string(),
+            "--committer-date-is-author-date",
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["log", "-3", "--format=%s: %ad / %cd", "foo"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            create test3.txt: Thu Oct 29 12:34:56 2020 -0300 / Thu Oct 29 12:34:56 2020 -0300
+            create test2.txt: Sat Feb 3 04:05:06 2001 +0000 / Sat Feb 3 04:05:06 2001 +0000
+            create test1.txt: Thu Oct 29 12:34:56 2020 -0100 / Thu Oct 29 12:34:56 2020 -0100
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_onto_own_descendant() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "move",
+                    "-s",
+                    &test1_oid.to_string(),
+                    "-d",
+                    &test2_oid.to_string(),
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Cannot move a commit onto its own descendant:
+            - Commit: 62fc20d2 create test1.txt
+            - Destination: 96d1c37a create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_onto_itself() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "move",
+                    "-s",
+                    &test1_oid.to_string(),
+                    "-d",
+                    &test1_oid.to_string(),
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Cannot move a commit onto itself: 62fc20d2 create test1.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_onto_current_parent() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "move",
+                "-s",
+                &test2_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Nothing to do: the destination is already the parent of the commit to move: 62fc20d2 create test1.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "move",
+                "-s",
+                &test2_oid.to_string(),
+                "-d",
+                &test1_oid.to_string(),
+                "--exec-each",
+                "echo $BRANCHLESS_EXEC_COMMIT",
+                "--exec",
+                "cat test2.txt",
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Nothing to do: the destination is already the parent of the commit to move: 62fc20d2 create test1.txt
+            Executing: cat test2.txt
+            test2 contents
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_move_unborn_head() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo_with_options(&GitInitOptions {
+            make_initial_commit: false,
+            run_branchless_init: false,
+        })?;
+        git.run_with_options(
+            &["branchless", "init"],
+            &GitRunOptions {
+                input: Some("master\n".to_string()),
+                ..Default::default()
+            },
+        )?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["move"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            No commit to move: HEAD is unborn. Pass --source or --base.
+            "###);
+        }
+
+        Ok(())
+    })
+}
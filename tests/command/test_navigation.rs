@@ -195,3 +195,145 @@ fn test_next_on_master2() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_next_hidden() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.run(&["checkout", "HEAD^"])?;
+        git.run(&["hide", &test3_oid.to_string()])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["next"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["next", "--hidden"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> checkout 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            |
+            % 70deb1e2 (manually hidden) create test3.txt
+            "###);
+        }
+
+        {
+            git.run(&["checkout", "HEAD^"])?;
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_checkout() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file_with_contents("test4", 4, "test4 contents\n")?;
+        git.run(&["commit", "--amend", "-m", "fix login page"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "checkout", "96d1c37"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> checkout 96d1c37a3d4363611c49f7e52186e189a04c531f
+            :
+            O 62fc20d2 (master) create test1.txt
+            |\
+            | o 933e4143 fix login page
+            |
+            @ 96d1c37a create test2.txt
+            |
+            o 70deb1e2 create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "checkout", "Login"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> checkout 933e414372610eb08713bb7aa5e5770accb8b774
+            :
+            O 62fc20d2 (master) create test1.txt
+            |\
+            | @ 933e4143 fix login page
+            |
+            o 96d1c37a create test2.txt
+            |
+            o 70deb1e2 create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "checkout", "master"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> checkout master
+            :
+            @ 62fc20d2 (master) create test1.txt
+            |\
+            | o 933e4143 fix login page
+            |
+            o 96d1c37a create test2.txt
+            |
+            o 70deb1e2 create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "checkout", "create test"],
+                &GitRunOptions {
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Found multiple commits matching "create test":
+              - 62fc20d2 create test1.txt
+              - 96d1c37a create test2.txt
+              - 70deb1e2 create test3.txt
+            (Pass a commit hash or a more specific search to disambiguate)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "checkout", "nonexistent"],
+                &GitRunOptions {
+                    expected_exit_code: 3,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Commit not found: nonexistent
+            "###);
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,129 @@
+use branchless::core::eventlog::{Event, EventLogDb};
+use branchless::testing::{with_git, GitRunOptions};
+use branchless::util::get_db_conn;
+
+#[test]
+fn test_reattach() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "reattach", "foo"])?;
+            let stdout = git.preprocess_stdout(stdout)?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> checkout -B foo
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a (foo) create test2.txt
+            "###);
+        }
+
+        // An existing branch is moved to `HEAD`.
+        git.run(&["prev"])?;
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "reattach", "foo"])?;
+            let stdout = git.preprocess_stdout(stdout)?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> checkout -B foo
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (foo) create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            "###);
+        }
+
+        if git.supports_reference_transactions()? {
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let branch_new_refs: Vec<Option<String>> = event_log_db
+                .get_events()?
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::RefUpdateEvent {
+                        ref_name, new_ref, ..
+                    } if ref_name == "refs/heads/foo" => Some(new_ref),
+                    _ => None,
+                })
+                .collect();
+            insta::assert_debug_snapshot!(branch_new_refs, @r###"
+            [
+                Some(
+                    "96d1c37a3d4363611c49f7e52186e189a04c531f",
+                ),
+                Some(
+                    "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+                ),
+            ]
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_reattach_prompt() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "reattach"],
+                &GitRunOptions {
+                    input: Some("bar\n".to_string()),
+                    ..Default::default()
+                },
+            )?;
+            let stdout = git.preprocess_stdout(stdout)?;
+            insta::assert_snapshot!(stdout, @r###"
+            Enter a name for the branch: branchless: <git-executable> checkout -B bar
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "reattach"],
+                &GitRunOptions {
+                    input: Some("\n".to_string()),
+                    expected_exit_code: 2,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Enter a name for the branch: No branch name provided.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (bar) create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
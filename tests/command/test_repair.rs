@@ -0,0 +1,69 @@
+use branchless::testing::with_git;
+
+#[test]
+fn test_repair_missing_events() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+
+        // Simulate the hooks failing to run by disabling them.
+        std::fs::write(git.repo_path.join("test2.txt"), "test2 contents\n")?;
+        git.run(&["add", "test2.txt"])?;
+        git.run(&[
+            "-c",
+            "core.hooksPath=/dev/null",
+            "commit",
+            "-m",
+            "create test2.txt",
+        ])?;
+        git.run(&[
+            "-c",
+            "core.hooksPath=/dev/null",
+            "branch",
+            "foo",
+            &test1_oid.to_string(),
+        ])?;
+        git.run(&["checkout", &test1_oid.to_string()])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (foo) create test1.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "repair"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Recorded commit: 5b61b5a0 create test2.txt
+            Recorded update to HEAD: 5b61b5a0 -> 62fc20d2
+            Recorded update to refs/heads/foo: (none) -> 62fc20d2
+            Recorded update to refs/heads/master: (none) -> f777ecc9
+            Repaired the event log by recording 4 events.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (foo) create test1.txt
+            |
+            o 5b61b5a0 create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "repair"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            The event log already matches the repository; there is nothing to repair.
+            "###);
+        }
+
+        Ok(())
+    })
+}
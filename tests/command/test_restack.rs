@@ -221,3 +221,70 @@ fn test_restack_aborts_during_rebase_conflict() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_restack_exec() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", &test1_oid.to_string()])?;
+        git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+        {
+            let (stdout, _stderr) =
+                git.run(&["restack", "--exec-each", "ls", "--exec", "echo done"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: <git-executable> rebase 62fc20d2a290daea0d52bdc2ed2ad4be6491010e 96d1c37a3d4363611c49f7e52186e189a04c531f --onto 024c35ce32dae6b12e981963465ee8a62b7eff9b
+            branchless: no more abandoned commits to restack
+            branchless: no more abandoned branches to restack
+            branchless: <git-executable> checkout 024c35ce32dae6b12e981963465ee8a62b7eff9b
+            Executing: ls (on 2e9a0858 create test2.txt)
+            initial.txt
+            test1.txt
+            test2.txt
+            Executing: echo done
+            done
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 024c35ce amend test1.txt
+            |
+            o 2e9a0858 create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_restack_committer_date_is_author_date() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["branch", "foo"])?;
+        git.run(&["checkout", "HEAD^"])?;
+        git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+        git.run_with_options(
+            &["restack", "--committer-date-is-author-date"],
+            &GitRunOptions {
+                time: 10,
+                ..Default::default()
+            },
+        )?;
+
+        {
+            let (stdout, _stderr) = git.run(&["log", "-2", "--format=%s: %ad / %cd", "foo"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            create test2.txt: Thu Oct 29 12:34:56 2020 -0200 / Thu Oct 29 12:34:56 2020 -0200
+            amend test1.txt: Thu Oct 29 12:34:56 2020 -0100 / Thu Oct 29 12:34:56 2020 +0000
+            "###);
+        }
+
+        Ok(())
+    })
+}
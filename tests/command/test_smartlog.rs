@@ -1,5 +1,14 @@
+use std::process::Command;
+
+use crate::util::trim_lines;
+
+use branchless::commands::smartlog::{render_graph, render_smartlog_graph, RenderOptions};
+use branchless::core::formatting::{printable_styled_string, Glyphs};
+use branchless::core::graph::{make_smartlog_graph, HeadOid, MainBranchOid};
+use branchless::core::mergebase::MergeBaseDb;
+use branchless::core::metadata::{CommitMessageProvider, CommitOidProvider};
 use branchless::testing::{get_git_executable, with_git, Git, GitInitOptions, GitRunOptions};
-use branchless::util::GitExecutable;
+use branchless::util::{get_db_conn, get_head_oid, get_main_branch_oid, GitExecutable};
 
 #[test]
 fn test_init_smartlog() -> anyhow::Result<()> {
@@ -363,6 +372,217 @@ fn test_main_remote_branch() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_smartlog_worktree() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let worktree_path = temp_dir.path().join("worktree");
+        git.run(&[
+            "worktree",
+            "add",
+            "--detach",
+            worktree_path.to_str().unwrap(),
+            &test1_oid.to_string(),
+        ])?;
+        let worktree = Git::new(worktree_path, GitExecutable(git.git_executable.clone()));
+        worktree.commit_file("test3", 3)?;
+
+        // Both worktrees share the same event log, but have their own `HEAD`.
+        {
+            let (stdout, _stderr) = worktree.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |\
+            | o 96d1c37a create test2.txt
+            |
+            @ 4838e49b create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |\
+            | @ 96d1c37a create test2.txt
+            |
+            o 4838e49b create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_no_main() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", &test1_oid.to_string()])?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test4", 4)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |\
+            | o 4838e49b create test3.txt
+            |
+            O 96d1c37a (master) create test2.txt
+            |
+            @ f57e36f5 create test4.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--no-main"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 96d1c37a (master) create test2.txt
+            |
+            @ f57e36f5 create test4.txt
+
+            :
+            o 4838e49b create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_shallow_clone() -> anyhow::Result<()> {
+    let git_executable = get_git_executable()?;
+    let git_executable = GitExecutable(git_executable);
+    let temp_dir = tempfile::tempdir()?;
+    let original_repo_path = temp_dir.path().join("original");
+    std::fs::create_dir(&original_repo_path)?;
+    let original_repo = Git::new(original_repo_path, git_executable.clone());
+    let cloned_repo_path = temp_dir.path().join("cloned");
+    let cloned_repo = Git::new(cloned_repo_path, git_executable);
+
+    {
+        std::env::set_current_dir(&original_repo.repo_path)?;
+        let git = original_repo.clone();
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "-b", "feature"])?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+        git.commit_file("test5", 5)?;
+        git.run(&[
+            "clone",
+            "--depth",
+            "2",
+            "--no-single-branch",
+            &format!("file://{}", original_repo.repo_path.to_str().unwrap()),
+            cloned_repo.repo_path.to_str().unwrap(),
+        ])?;
+    }
+
+    {
+        std::env::set_current_dir(&cloned_repo.repo_path)?;
+        let git = cloned_repo.clone();
+        git.init_repo_with_options(&GitInitOptions {
+            make_initial_commit: false,
+            ..Default::default()
+        })?;
+        git.run(&["branch", "feature", "origin/feature"])?;
+        let (stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 848121cb (master) create test5.txt
+
+        : (shallow boundary)
+        o 96d1c37a create test2.txt
+        |
+        o 70deb1e2 (feature) create test3.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_main_branch_commit_oid() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        git.run(&[
+            "config",
+            "branchless.core.mainBranch",
+            &test1_oid.to_string(),
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a (master) create test2.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.core.mainBranch", "62fc20d2"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a (master) create test2.txt
+            "###);
+        }
+
+        git.run(&[
+            "config",
+            "branchless.core.mainBranch",
+            "0123456789012345678901234567890123456789",
+        ])?;
+        {
+            let (stdout, stderr) = git.run_with_options(
+                &["smartlog"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(trim_lines(stderr), @r###"
+            Error: Getting main branch OID for repository
+
+            Caused by:
+
+                The main branch is set to commit "0123456789012345678901234567890123456789", but that commit could not be found in your repository.
+                Either fetch it, or update the main branch setting by running:
+
+                    git config branchless.core.mainBranch <branch>
+
+            "###);
+            insta::assert_snapshot!(stdout, @"");
+        }
+
+        Ok(())
+    })
+}
+
 #[test]
 fn test_show_rewritten_commit_hash() -> anyhow::Result<()> {
     with_git(|git| {
@@ -389,3 +609,1790 @@ fn test_show_rewritten_commit_hash() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_rewritten_commit_restack_hint() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&["config", "branchless.commitMetadata.restackHint", "true"])?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["commit", "--amend", "-m", "test1 amended"])?;
+        git.run(&["checkout", &test1_oid.to_string()])?;
+
+        {
+            // No descendants need to be restacked.
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |\
+            | o 14042005 test1 amended
+            |
+            % 62fc20d2 (rewritten as 14042005) create test1.txt
+            "###);
+        }
+
+        git.run(&["checkout", "HEAD~"])?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "HEAD~"])?;
+        git.run(&["commit", "--amend", "-m", "initial amended"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ 1524bc53 initial amended
+
+            X f777ecc9 (rewritten as 1524bc53; run `git restack` to move descendants) (master) create initial.txt
+            |\
+            | o 14042005 test1 amended
+            |
+            o fe65c1fe create test2.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.commitMetadata.restackHint", "false"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ 1524bc53 initial amended
+
+            X f777ecc9 (rewritten as 1524bc53) (master) create initial.txt
+            |\
+            | o 14042005 test1 amended
+            |
+            o fe65c1fe create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_rewritten_commit_restack_hint_main_branch() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&["config", "branchless.commitMetadata.restackHint", "true"])?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "HEAD~"])?;
+        git.run(&["commit", "--amend", "-m", "initial amended"])?;
+
+        {
+            // The descendant is on the main branch, so `git restack` wouldn't
+            // move it.
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ 1524bc53 initial amended
+
+            X f777ecc9 (rewritten as 1524bc53) create initial.txt
+            |
+            O 62fc20d2 (master) create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_show_tags() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["tag", "v1.0"])?;
+        git.run(&["tag", "-a", "-m", "annotated", "v1.0-annotated"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (tag: v1.0, tag: v1.0-annotated) create test1.txt
+            "###);
+        }
+
+        git.run(&["checkout", "master"])?;
+        git.run(&["hide", "62fc20d2"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |
+            x 62fc20d2 (manually hidden) (tag: v1.0, tag: v1.0-annotated) create test1.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.commitMetadata.tags", "false"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |
+            x 62fc20d2 (manually hidden) create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_verbose_shows_commit_body() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&[
+            "commit",
+            "--amend",
+            "-m",
+            "create test1.txt",
+            "-m",
+            "This is the body of the commit, which is long enough that it will need to be wrapped.",
+            "-m",
+            "It has a second paragraph.",
+        ])?;
+        git.commit_file("test2", 2)?;
+        git.run(&[
+            "commit",
+            "--amend",
+            "-m",
+            "create test2.txt",
+            "-m",
+            "This body is indented under the branch.",
+        ])?;
+        git.run(&["checkout", "HEAD^"])?;
+        git.commit_file("test3", 3)?;
+        git.run(&["config", "branchless.smartlog.bodyWrapWidth", "40"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--verbose"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 3428e611 create test1.txt
+            |   This is the body of the commit,
+            |   which is long enough that it will
+            |   need to be wrapped.
+            |
+            |   It has a second paragraph.
+            |\
+            | o faca5479 create test2.txt
+            |     This body is indented under the
+            |     branch.
+            |
+            @ c2abffe6 create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 3428e611 create test1.txt
+            |\
+            | o faca5479 create test2.txt
+            |
+            @ c2abffe6 create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_show_ahead_behind_counts() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&["checkout", "-b", "feature", "master"])?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test3", 3)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            | |
+            | o 96d1c37a (feature) create test2.txt
+            |
+            @ 98b9119d (master) create test3.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.commitMetadata.aheadBehind", "true"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            | |
+            | o 96d1c37a (feature) [↑2 ↓1] create test2.txt
+            |
+            @ 98b9119d (master) create test3.txt
+            "###);
+        }
+
+        // Main branches aren't compared against each other.
+        git.run(&["config", "branchless.core.mainBranch", "master, feature"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 96d1c37a (feature) create test2.txt
+            :
+            @ 98b9119d (master) create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_working_copy_status() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        git.write_file("untracked", "untracked\n")?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 create test1.txt
+            "###);
+        }
+
+        git.write_file("test1", "modified\n")?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (dirty) create test1.txt
+            "###);
+        }
+
+        git.run(&["add", "test1.txt"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (dirty) create test1.txt
+            "###);
+        }
+
+        git.run(&[
+            "config",
+            "branchless.commitMetadata.workingCopyStatus",
+            "false",
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_rebase_in_progress() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file_with_contents("conflict", 1, "conflict 1\n")?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file_with_contents("conflict", 3, "conflict 2\n")?;
+        git.run(&["checkout", "HEAD@{2}"])?;
+
+        git.run_with_options(
+            &["rebase", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Rebase in progress (1/2)
+            O f777ecc9 create initial.txt
+            |\
+            | o 7cf5e01c (applying) create conflict.txt
+            | |
+            | o 31269a5c create test2.txt
+            |
+            @ 4d4b633c (dirty) (onto) (master) create conflict.txt
+            "###);
+        }
+
+        git.run(&["rebase", "--abort"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 7cf5e01c create conflict.txt
+            | |
+            | @ 31269a5c create test2.txt
+            |
+            O 4d4b633c (master) create conflict.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_cherry_pick_in_progress() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let conflict_oid = git.commit_file_with_contents("conflict", 1, "conflict 1\n")?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+        git.run_with_options(
+            &["cherry-pick", &conflict_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Cherry-pick in progress
+            O f777ecc9 create initial.txt
+            |\
+            | o 7cf5e01c (applying) create conflict.txt
+            |
+            @ 1121617f (dirty) (master) create conflict.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_unhideable_refs() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["update-ref", "refs/pinned/test1", "HEAD"])?;
+        git.run(&["checkout", "master"])?;
+        git.run(&["hide", "refs/pinned/test1"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            "###);
+        }
+
+        git.run(&[
+            "config",
+            "--add",
+            "branchless.core.unhideableRefs",
+            "refs/notes/*",
+        ])?;
+        git.run(&[
+            "config",
+            "--add",
+            "branchless.core.unhideableRefs",
+            "refs/pinned/*",
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |
+            x 62fc20d2 (manually hidden) create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_multiple_main_branches() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "-b", "release/1.x", "HEAD^"])?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.detach_head()?;
+        git.commit_file("test4", 4)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o fe65c1fe create test2.txt
+            | |
+            | o 02067177 (release/1.x) create test3.txt
+            | |
+            | @ 8e62740b create test4.txt
+            |
+            O 62fc20d2 (master) create test1.txt
+            "###);
+        }
+
+        git.run(&[
+            "config",
+            "branchless.core.mainBranch",
+            "master, release/1.x",
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            :
+            O 02067177 (release/1.x) create test3.txt
+            |
+            @ 8e62740b create test4.txt
+            "###);
+        }
+
+        // The additional main branches are still treated as main branches
+        // when only the component containing `HEAD` is shown.
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--fast"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            :
+            O 02067177 (release/1.x) create test3.txt
+            |
+            @ 8e62740b create test4.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_format_mermaid() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", &test2_oid.to_string()])?;
+        git.run(&["branch", "feature"])?;
+        git.commit_file("test4", 4)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test5", 5)?;
+        git.run(&[
+            "commit",
+            "--amend",
+            "-m",
+            "amended \"test5\" \u{2014} with unicode",
+        ])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--format", "mermaid"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            %%{init: {'gitGraph': {'mainBranchName': 'master'}}}%%
+            gitGraph
+                commit id: "62fc20d2 create test1.txt" tag: "master"
+                branch stack-4fb2c2d2
+                commit id: "4fb2c2d2 amended 'test5' ? with unicode" type: HIGHLIGHT
+                checkout master
+                branch stack-96d1c37a
+                commit id: "96d1c37a create test2.txt" tag: "feature"
+                branch stack-f57e36f5
+                commit id: "f57e36f5 create test4.txt"
+                checkout stack-96d1c37a
+                commit id: "70deb1e2 create test3.txt"
+                checkout master
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_format_template() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", &test2_oid.to_string()])?;
+        git.commit_file("test4", 4)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--format", "%g %h %an: %s (100%%)"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O 62fc20d2 Testy McTestface: create test1.txt (100%)
+            o 96d1c37a Testy McTestface: create test2.txt (100%)
+            o 70deb1e2 Testy McTestface: create test3.txt (100%)
+            @ f57e36f5 Testy McTestface: create test4.txt (100%)
+            "###);
+        }
+
+        {
+            let (stdout, stderr) = git.run_with_options(
+                &["smartlog", "--format", "%h %x"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @"");
+            insta::assert_snapshot!(stderr, @r###"
+            error: Invalid value for '--format <format>': Unknown placeholder in format template: %x (expected one of: %h, %H, %s, %an, %ae, %cr, %g, %%)
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_since_until() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.detach_head()?;
+        git.commit_file("test4", 4)?;
+        git.commit_file("test5", 5)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test6", 6)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            : o 62fc20d2 create test1.txt
+            :
+            O 02067177 (master) create test3.txt
+            |\
+            | o 8e62740b create test4.txt
+            | |
+            | o 447139aa create test5.txt
+            |
+            @ bc44601b create test6.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) =
+                git.run(&["smartlog", "--since", "2020-10-29 15:00:00 +0000"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 02067177 (master) create test3.txt
+            |\
+            | o 8e62740b create test4.txt
+            | |
+            | o 447139aa create test5.txt
+            |
+            @ bc44601b create test6.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&[
+                "smartlog",
+                "--since",
+                "2020-10-29 15:00:00 +0000",
+                "--until",
+                "2020-10-29 17:00:00 +0000",
+            ])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 02067177 (master) create test3.txt
+            |\
+            | o 8e62740b create test4.txt
+            |
+            @ bc44601b create test6.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--since", "2 weeks ago"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 02067177 (master) create test3.txt
+            |
+            @ bc44601b create test6.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_commit_status_notes() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.commit_file("test4", 4)?;
+
+        git.run(&[
+            "notes",
+            "--ref",
+            "ci",
+            "add",
+            "-m",
+            "success",
+            &test1_oid.to_string(),
+        ])?;
+        git.run(&[
+            "notes",
+            "--ref",
+            "ci",
+            "add",
+            "-m",
+            "FAILED: 3 tests",
+            &test2_oid.to_string(),
+        ])?;
+        git.run(&[
+            "notes",
+            "--ref",
+            "ci",
+            "add",
+            "-m",
+            "running",
+            &test3_oid.to_string(),
+        ])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            |
+            o 70deb1e2 create test3.txt
+            |
+            @ 355e173b create test4.txt
+            "###);
+        }
+
+        git.run(&[
+            "config",
+            "branchless.smartlog.statusNotesRef",
+            "refs/notes/ci",
+        ])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 (pass) create test1.txt
+            |
+            o 96d1c37a (fail) create test2.txt
+            |
+            o 70deb1e2 (pending) create test3.txt
+            |
+            @ 355e173b create test4.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_color() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        let has_ansi_escape_codes = |stdout: &str| stdout.contains('\u{1b}');
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            assert!(!has_ansi_escape_codes(&stdout));
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--color", "always"])?;
+            assert!(has_ansi_escape_codes(&stdout));
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "--color", "always", "smartlog"])?;
+            assert!(has_ansi_escape_codes(&stdout));
+        }
+
+        git.run(&["config", "branchless.core.color", "always"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            assert!(has_ansi_escape_codes(&stdout));
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--color", "never"])?;
+            assert!(!has_ansi_escape_codes(&stdout));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_head_highlight() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["config", "branchless.smartlog.headGlyph", ">"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            > 96d1c37a create test2.txt
+            "###);
+        }
+
+        let reverse_video = "\u{1b}[7m";
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--color", "always"])?;
+            let highlighted_lines: Vec<&str> = stdout
+                .lines()
+                .filter(|line| line.contains(reverse_video))
+                .collect();
+            assert_eq!(highlighted_lines.len(), 1);
+            assert!(highlighted_lines[0].contains("create test2.txt"));
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--color", "never"])?;
+            assert!(!stdout.contains(reverse_video));
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_group_by_branch() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["branch", "feature-a"])?;
+        git.run(&["checkout", "HEAD^"])?;
+        git.commit_file("test3", 3)?;
+        git.run(&["branch", "feature-b"])?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+        git.detach_head()?;
+        git.commit_file("test5", 5)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            | |\
+            | | o 96d1c37a (feature-a) create test2.txt
+            | |
+            | o 4838e49b (feature-b) create test3.txt
+            |
+            O 8f7aef57 (master) create test4.txt
+            |
+            @ 47d30faa create test5.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--group-by-branch"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            master
+            O f777ecc9 create initial.txt
+            |
+            O 8f7aef57 (master) create test4.txt
+
+            feature-a (some commits are also on: feature-b)
+            O f777ecc9 create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            o 96d1c37a (feature-a) create test2.txt
+
+            feature-b (based on: feature-a)
+            :
+            o 4838e49b (feature-b) create test3.txt
+
+            (no branch)
+            :
+            O 8f7aef57 (master) create test4.txt
+            |
+            @ 47d30faa create test5.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_summary_column() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        git.run(&["branch", "feature-branch"])?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+
+        git.run(&["config", "branchless.smartlog.summaryColumn", "32"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2                      create test1.txt
+            |\
+            | o 96d1c37a (feature-branch)   create test2.txt
+            | |
+            | o 70deb1e2                    create test3.txt
+            |
+            @ bf0d52a6 (master)             create test4.txt
+            "###);
+        }
+
+        // Lines which are already past the column aren't truncated.
+        git.run(&["config", "branchless.smartlog.summaryColumn", "12"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2  create test1.txt
+            |\
+            | o 96d1c37a (feature-branch) create test2.txt
+            | |
+            | o 70deb1e2 create test3.txt
+            |
+            @ bf0d52a6 (master) create test4.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_cache() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+
+        git.run(&["config", "branchless.smartlog.cache", "true"])?;
+        let (first_stdout, _stderr) = git.run(&["smartlog"])?;
+        insta::assert_snapshot!(first_stdout, @r###"
+        :
+        O 62fc20d2 (master) create test1.txt
+        |
+        @ 96d1c37a create test2.txt
+        "###);
+        let (second_stdout, _stderr) = git.run(&["smartlog"])?;
+        assert_eq!(first_stdout, second_stdout);
+
+        // Moving a ref invalidates the cache.
+        git.run(&["branch", "foo"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            @ 96d1c37a (foo) create test2.txt
+            "###);
+        }
+
+        // Adding an event invalidates the cache.
+        git.commit_file("test3", 3)?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a (foo) create test2.txt
+            |
+            @ 70deb1e2 create test3.txt
+            "###);
+        }
+
+        // Dirtying the working copy invalidates the cache.
+        git.write_file("test3", "new contents")?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            let (no_cache_stdout, _stderr) = git.run(&["smartlog", "--no-cache"])?;
+            assert_eq!(stdout, no_cache_stdout);
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a (foo) create test2.txt
+            |
+            @ 70deb1e2 (dirty) create test3.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_legend() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--legend"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+
+            Legend:
+              o   a commit you're working on
+              O   a commit on the main branch
+              @   the current commit (HEAD)
+              x   a hidden commit, shown because it has visible descendants
+              %   the current commit (HEAD), which is hidden
+              X   a hidden commit on the main branch
+              s   a stash entry
+              :   some commits were omitted here
+              |   connects a commit to its parent
+              |\  a commit with more than one child
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+fn mask_durations(stderr: String) -> String {
+    regex::Regex::new(r"[0-9]+\.[0-9]ms")
+        .unwrap()
+        .replace_all(&stderr, "<duration>")
+        .into_owned()
+}
+
+#[test]
+fn test_smartlog_timings() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        {
+            let (_stdout, stderr) = git.run(&["smartlog", "--timings"])?;
+            insta::assert_snapshot!(mask_durations(stderr), @r###"
+            Timings:
+              EventReplayer::from_event_log_db: <duration> (1 call)
+              MergeBaseDb cache misses: 2
+              compute_merge_base_oid: <duration> (2 calls)
+              walk_from_commits: <duration> (1 call)
+              make_graph: <duration> (1 call)
+              MergeBaseDb cache hits: 1
+              render smartlog: <duration> (1 call)
+            "###);
+        }
+
+        // The merge-base was cached by the previous call.
+        {
+            let (_stdout, stderr) = git.run(&["smartlog", "--timings"])?;
+            insta::assert_snapshot!(mask_durations(stderr), @r###"
+            Timings:
+              EventReplayer::from_event_log_db: <duration> (1 call)
+              MergeBaseDb cache hits: 3
+              walk_from_commits: <duration> (1 call)
+              make_graph: <duration> (1 call)
+              render smartlog: <duration> (1 call)
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+fn render_with_fork_point(git: &Git) -> anyhow::Result<String> {
+    let glyphs = Glyphs {
+        commit_main_fork_point: "*",
+        ..Glyphs::text()
+    };
+    let repo = git.get_repo()?;
+    let conn = get_db_conn(&repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
+    let graph = make_smartlog_graph(&repo)?;
+    let lines = render_graph(
+        &glyphs,
+        &repo,
+        &merge_base_db,
+        &graph,
+        &HeadOid(get_head_oid(&repo)?),
+        &MainBranchOid(get_main_branch_oid(&repo)?),
+        &mut [
+            &mut CommitOidProvider::new(&repo, false)?,
+            &mut CommitMessageProvider::new()?,
+        ],
+        &RenderOptions::default(),
+    )?;
+    let lines = lines
+        .into_iter()
+        .map(|line| printable_styled_string(&glyphs, line))
+        .collect::<anyhow::Result<Vec<String>>>()?;
+    Ok(lines.join("\n"))
+}
+
+#[test]
+fn test_smartlog_fork_point() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+        git.run(&["checkout", &test3_oid.to_string()])?;
+
+        {
+            let stdout = render_with_fork_point(&git)?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            * 62fc20d2 create test1.txt
+            |\
+            | o 96d1c37a create test2.txt
+            | |
+            | @ 70deb1e2 create test3.txt
+            |
+            O bf0d52a6 create test4.txt
+            "###);
+        }
+
+        git.run(&["checkout", "master"])?;
+        {
+            let stdout = render_with_fork_point(&git)?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |\
+            | o 96d1c37a create test2.txt
+            | |
+            | o 70deb1e2 create test3.txt
+            |
+            @ bf0d52a6 create test4.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_render_smartlog_graph() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        git.run(&["branch", "a-branch-with-a-long-name"])?;
+
+        let repo = git.get_repo()?;
+        let graph = make_smartlog_graph(&repo)?;
+        {
+            let lines = render_smartlog_graph(&Glyphs::text(), &repo, &graph, None)?;
+            insta::assert_snapshot!(lines.join("\n"), @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            @ 96d1c37a (a-branch-with-a-long-name) create test2.txt
+            "###);
+        }
+
+        {
+            let lines = render_smartlog_graph(&Glyphs::text(), &repo, &graph, Some(30))?;
+            insta::assert_snapshot!(lines.join("\n"), @r###"
+            :
+            O 62fc20d2 (master) create ...
+            |
+            @ 96d1c37a (a-branch-with-a...
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_context_commits() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.detach_head()?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+        git.commit_file("test5", 5)?;
+        git.commit_file("test6", 6)?;
+        git.commit_file("test7", 7)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 96d1c37a create test2.txt
+            |\
+            : o 70deb1e2 create test3.txt
+            :
+            @ 8741e4d5 (master) create test7.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.smartlog.contextCommits", "1"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            O 62fc20d2 create test1.txt
+            |
+            O 96d1c37a create test2.txt
+            |\
+            | o 70deb1e2 create test3.txt
+            |
+            O f57e36f5 create test4.txt
+            :
+            O 2dac0f51 create test6.txt
+            |
+            @ 8741e4d5 (master) create test7.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.smartlog.contextCommits", "100"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |
+            O 62fc20d2 create test1.txt
+            |
+            O 96d1c37a create test2.txt
+            |\
+            | o 70deb1e2 create test3.txt
+            |
+            O f57e36f5 create test4.txt
+            |
+            O 20230db7 create test5.txt
+            |
+            O 2dac0f51 create test6.txt
+            |
+            @ 8741e4d5 (master) create test7.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_abbrev() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["config", "core.abbrev", "4"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777 (master) create initial.txt
+            |
+            @ 62fc create test1.txt
+            "###);
+        }
+
+        // Write a blob whose OID starts with the same 4 digits as the commit,
+        // so that the commit's abbreviated OID has to be lengthened.
+        let test1_oid = test1_oid.to_string();
+        let colliding_contents = (0..)
+            .map(|i| format!("collision {}\n", i))
+            .find(|contents| {
+                let oid = git2::Oid::hash_object(git2::ObjectType::Blob, contents.as_bytes())
+                    .unwrap()
+                    .to_string();
+                oid[..4] == test1_oid[..4] && oid[..5] != test1_oid[..5]
+            })
+            .unwrap();
+        git.get_repo()?.blob(colliding_contents.as_bytes())?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777 (master) create initial.txt
+            |
+            @ 62fc2 create test1.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["hide", &test1_oid[..5]])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Hid commit: 62fc2 create test1.txt
+            To unhide this commit, run: git unhide 62fc2
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_author() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.write_file("test2", "test2 contents\n")?;
+        git.run(&["add", "."])?;
+        git.run_with_options(
+            &[
+                "commit",
+                "-m",
+                "create test2.txt",
+                "--author",
+                "Jane Doe <jane@example.org>",
+            ],
+            &GitRunOptions {
+                time: 2,
+                ..Default::default()
+            },
+        )?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            |
+            o 8710b579 create test2.txt
+            |
+            o ef7bcea9 create test3.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--author", "JANE"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |
+            o 8710b579 create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--author", "*@example.org"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            |
+            o 8710b579 create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--author", "nobody"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_stat() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.write_file("test1", "updated test1 contents\nand another line\n")?;
+        git.write_file("test2", "test2 contents\n")?;
+        git.run(&["add", "."])?;
+        git.run(&["commit", "-m", "update test1.txt and create test2.txt"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--stat"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) (1 file, +1 -0) create initial.txt
+            |
+            o 62fc20d2 (1 file, +1 -0) create test1.txt
+            |
+            @ 0d022379 (2 files, +3 -1) update test1.txt and create test2.txt
+            "###);
+        }
+
+        {
+            // The diffstats should be read from the cache this time.
+            let (stdout, _stderr) = git.run(&["smartlog", "--stat"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) (1 file, +1 -0) create initial.txt
+            |
+            o 62fc20d2 (1 file, +1 -0) create test1.txt
+            |
+            @ 0d022379 (2 files, +3 -1) update test1.txt and create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_branch_names() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&["branch", "zzz"])?;
+        git.run(&["branch", "aaa"])?;
+        git.run(&["branch", "mmm"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master, aaa, mmm, zzz) create initial.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.commitMetadata.maxBranches", "2"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master, aaa, +2 more) create initial.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_page() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test4", 4)?;
+        git.run(&["config", "branchless.smartlog.pageSize", "2"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--page", "1"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |\
+            | o 98b9119d create test3.txt
+            |
+            @ 8f7aef57 create test4.txt
+            Stacks 1-2 of 3, use --page 2 for more
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--page", "2"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            Stack 3 of 3
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--page", "3"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            Page 3 is out of range: there are 3 stacks, in 2 pages of up to 2 stacks.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_log_verbosity() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+
+        {
+            // `-v` sets the log level, even after the subcommand, and is
+            // separate from `git smartlog --verbose`.
+            let (stdout, _stderr) = git.run(&["branchless", "smartlog", "-vv", "--verbose"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            @ f777ecc9 (master) create initial.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_stashes() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.write_file("test1", "stashed contents\n")?;
+        git.run(&["stash"])?;
+        git.commit_file("test2", 2)?;
+        git.write_file("test2", "stashed contents\n")?;
+        git.run(&["stash"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--stashes"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |\
+            | s ccd9307e (stash@{1}) WIP on (no branch): 62fc20d create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            |
+            s c83cd6df (stash@{0}) WIP on (no branch): 96d1c37 create test2.txt
+            "###);
+        }
+
+        {
+            git.run(&["stash", "drop", "stash@{1}"])?;
+            git.run(&["config", "branchless.smartlog.showStashes", "true"])?;
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            @ 96d1c37a create test2.txt
+            |
+            s c83cd6df (stash@{0}) WIP on (no branch): 96d1c37 create test2.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_git_dir() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        let unrelated_dir = tempfile::tempdir()?;
+        let git_dir = git.repo_path.join(".git");
+        let path_to_git = git.git_executable.to_str().unwrap();
+        let run_smartlog = |args: &[&str], env: &[(&str, &str)]| -> anyhow::Result<String> {
+            let output = Command::new(assert_cmd::cargo::cargo_bin("git-branchless"))
+                .args(args)
+                .current_dir(unrelated_dir.path())
+                .env_clear()
+                .env("PATH", git.get_path_for_env())
+                .env("PATH_TO_GIT", path_to_git)
+                .envs(env.iter().copied())
+                .output()?;
+            assert!(
+                output.status.success(),
+                "Smartlog failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            Ok(String::from_utf8(output.stdout)?)
+        };
+
+        {
+            let stdout = run_smartlog(&["smartlog"], &[("GIT_DIR", git_dir.to_str().unwrap())])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 create test1.txt
+            "###);
+        }
+
+        {
+            let stdout = run_smartlog(&["--git-dir", git_dir.to_str().unwrap(), "smartlog"], &[])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_fast() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+        git.detach_head()?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test4", 4)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test5", 5)?;
+        git.detach_head()?;
+        git.commit_file("test6", 6)?;
+        git.run(&["checkout", "HEAD^"])?;
+        git.run(&["checkout", "HEAD^"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            |
+            @ fe65c1fe create test2.txt
+            |\
+            | o 02067177 create test3.txt
+            |\
+            | o 4d4b1fe5 create test4.txt
+            |
+            O fb8baf97 (master) create test5.txt
+            |
+            o 2f43e6c0 create test6.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--fast"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ fe65c1fe create test2.txt
+            |\
+            | o 02067177 create test3.txt
+            |\
+            | o 4d4b1fe5 create test4.txt
+            |
+            O fb8baf97 (master) create test5.txt
+            "###);
+        }
+
+        git.run(&["checkout", &test1_oid.to_string()])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--fast"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            : @ 62fc20d2 create test1.txt
+            :
+            O fb8baf97 (master) create test5.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_incremental() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+        git.detach_head()?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test4", 4)?;
+        git.detach_head()?;
+        git.commit_file("test5", 5)?;
+        git.run(&["checkout", "HEAD^"])?;
+        git.run(&["checkout", "HEAD^"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            |
+            @ fe65c1fe create test2.txt
+            |\
+            | o 02067177 create test3.txt
+            |
+            O 4d4b1fe5 (master) create test4.txt
+            |
+            o 3130ddda create test5.txt
+            "###);
+        }
+
+        // The component containing `HEAD` is printed first, followed by the
+        // others in order.
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--incremental"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ fe65c1fe create test2.txt
+            |\
+            | o 02067177 create test3.txt
+            |
+            O 4d4b1fe5 (master) create test4.txt
+
+            O f777ecc9 create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+
+            :
+            O 4d4b1fe5 (master) create test4.txt
+            |
+            o 3130ddda create test5.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_smartlog_wip_commits() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["commit", "--allow-empty", "--fixup", "HEAD"])?;
+        git.run(&["commit", "--allow-empty", "-m", "WIP: try something"])?;
+        git.run(&["commit", "--allow-empty", "-m", "WIPE the cache"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            |
+            o 39a76b33 fixup! create test1.txt
+            |
+            o adf27814 WIP: try something
+            |
+            @ 067c71ba WIPE the cache
+            "###);
+        }
+
+        let italic = "\u{1b}[3m";
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--color", "always"])?;
+            let highlighted_lines: Vec<&str> = stdout
+                .lines()
+                .filter(|line| line.contains(italic))
+                .collect();
+            assert_eq!(highlighted_lines.len(), 2);
+            assert!(highlighted_lines[0].contains("fixup! create test1.txt"));
+            assert!(highlighted_lines[1].contains("WIP: try something"));
+        }
+
+        git.run(&["config", "branchless.smartlog.wipPrefixes", ""])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--color", "always"])?;
+            assert!(!stdout.contains(italic));
+        }
+
+        git.run(&["config", "branchless.smartlog.wipPrefixes", "WIPE"])?;
+        {
+            let (stdout, _stderr) = git.run(&["smartlog", "--color", "always"])?;
+            let highlighted_lines: Vec<&str> = stdout
+                .lines()
+                .filter(|line| line.contains(italic))
+                .collect();
+            assert_eq!(highlighted_lines.len(), 1);
+            assert!(highlighted_lines[0].contains("WIPE the cache"));
+        }
+
+        Ok(())
+    })
+}
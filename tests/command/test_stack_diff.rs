@@ -0,0 +1,90 @@
+use branchless::testing::with_git;
+
+#[test]
+fn test_stack_diff() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.write_file("test1", "updated contents\n")?;
+        git.run(&["commit", "-am", "update test1.txt"])?;
+        git.run(&["checkout", "HEAD^"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "stack-diff"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            62fc20d2 create test1.txt
+            diff --git a/test1.txt b/test1.txt
+            new file mode 100644
+            index 0000000..7432a8f
+            --- /dev/null
+            +++ b/test1.txt
+            @@ -0,0 +1 @@
+            +test1 contents
+            fc84bc3f update test1.txt
+            diff --git a/test1.txt b/test1.txt
+            index 7432a8f..27e2fc9 100644
+            --- a/test1.txt
+            +++ b/test1.txt
+            @@ -1 +1 @@
+            -test1 contents
+            +updated contents
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "stack-diff", "--combined"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Combined diff from f777ecc9 to fc84bc3f update test1.txt
+            diff --git a/test1.txt b/test1.txt
+            new file mode 100644
+            index 0000000..27e2fc9
+            --- /dev/null
+            +++ b/test1.txt
+            @@ -0,0 +1 @@
+            +updated contents
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "stack-diff", "-s", "update test1"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            fc84bc3f update test1.txt
+            diff --git a/test1.txt b/test1.txt
+            index 7432a8f..27e2fc9 100644
+            --- a/test1.txt
+            +++ b/test1.txt
+            @@ -1 +1 @@
+            -test1 contents
+            +updated contents
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_stack_diff_difftool() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["config", "diff.tool", "cat-new"])?;
+        git.run(&["config", "difftool.cat-new.cmd", "cat \"$REMOTE\""])?;
+        git.run(&["config", "difftool.prompt", "false"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "stack-diff", "--tool"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            62fc20d2 create test1.txt
+            test1 contents
+            96d1c37a create test2.txt
+            test2 contents
+            "###);
+        }
+
+        Ok(())
+    })
+}
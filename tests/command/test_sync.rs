@@ -0,0 +1,153 @@
+use branchless::testing::{with_git, GitRunOptions};
+
+#[test]
+fn test_sync_basic() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test3", 3)?;
+        git.detach_head()?;
+        git.commit_file("test4", 4)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test5", 5)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            | |
+            | o 96d1c37a create test2.txt
+            |
+            O 98b9119d create test3.txt
+            |\
+            | o 2b633ed7 create test4.txt
+            |
+            @ 117e0866 (master) create test5.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "sync"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 2 rewritten commits
+            In-memory rebase succeeded.
+            Attempting rebase in-memory...
+            branchless: processing 1 rewritten commit
+            In-memory rebase succeeded.
+            Synced 2 stacks.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ 117e0866 (master) create test5.txt
+            |\
+            | o 87c7a36c create test1.txt
+            | |
+            | o 8ee4f266 create test2.txt
+            |
+            o d7e7e6c3 create test4.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "sync"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            All stacks are already based on the main branch.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_sync_skips_conflicting_stacks() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file_with_contents("test2", 2, "conflicting contents\n")?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 3)?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &["branchless", "sync"],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 1 rewritten commit
+            In-memory rebase succeeded.
+            Synced 1 stack.
+            Skipped 1 stack, which could not be moved without conflicts:
+            - 19c46a97 create test2.txt
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 create initial.txt
+            |\
+            | o 19c46a97 create test2.txt
+            |
+            @ e32e9f7d (master) create test2.txt
+            |
+            o 8af9d441 create test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_sync_skips_squash_merged_commits() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file_with_contents("test1", 1, "first contents\n")?;
+        git.commit_file_with_contents("test1", 2, "second contents\n")?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test3", 3)?;
+        // Simulate squash-merging the stack into the main branch.
+        git.write_file("test1", "second contents\n")?;
+        git.run(&["add", "."])?;
+        git.run(&["commit", "-m", "squash-merge test1.txt"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "sync"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            Attempting rebase in-memory...
+            branchless: processing 2 rewritten commits
+            In-memory rebase succeeded.
+            Synced 1 stack.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            :
+            @ 41e70da9 (master) squash-merge test1.txt
+            "###);
+        }
+
+        Ok(())
+    })
+}
@@ -0,0 +1,232 @@
+use branchless::testing::{with_git, GitRunOptions};
+
+#[test]
+fn test_test_stack() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&[
+            "config",
+            "branchless.smartlog.statusNotesRef",
+            "refs/notes/branchless-test",
+        ])?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "HEAD^^"])?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "branchless",
+                    "test",
+                    "-x",
+                    "echo checking $BRANCHLESS_TEST_COMMIT; test ! -f test3.txt",
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            (pass) 62fc20d2 create test1.txt
+            (pass) 96d1c37a create test2.txt
+            checking 70deb1e28791d8e7dd5a1f0c871a51b91282562f
+            (fail) 70deb1e2 create test3.txt (exit code 1)
+            Ran `echo checking $BRANCHLESS_TEST_COMMIT; test ! -f test3.txt` on 3 commits: 2 passed, 1 failed.
+            "###);
+        }
+
+        // The results are cached, and shown in the smartlog.
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "branchless",
+                    "test",
+                    "-x",
+                    "echo checking $BRANCHLESS_TEST_COMMIT; test ! -f test3.txt",
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 1,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            (pass) 62fc20d2 create test1.txt (cached)
+            (pass) 96d1c37a create test2.txt (cached)
+            (fail) 70deb1e2 create test3.txt (exit code 1) (cached)
+            Ran `echo checking $BRANCHLESS_TEST_COMMIT; test ! -f test3.txt` on 3 commits: 2 passed, 1 failed.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 (pass) create test1.txt
+            |
+            o 96d1c37a (pass) create test2.txt
+            |
+            o 70deb1e2 (fail) create test3.txt
+            "###);
+        }
+
+        // The working copy is left untouched.
+        {
+            let (stdout, _stderr) = git.run(&["status", "--short"])?;
+            insta::assert_snapshot!(stdout, @"");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_test_base() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "test", "-x", "true", "-b", "HEAD"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            (pass) 62fc20d2 create test1.txt
+            (pass) 96d1c37a create test2.txt
+            Ran `true` on 2 commits: 2 passed, 0 failed.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "test", "-x", "true"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            (pass) 96d1c37a create test2.txt (cached)
+            Ran `true` on 1 commit: 1 passed, 0 failed.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_test_base_hidden() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.run(&["hide", &test1_oid.to_string()])?;
+
+        {
+            let (stdout, _stderr) = git.run_with_options(
+                &[
+                    "branchless",
+                    "test",
+                    "-x",
+                    "true",
+                    "-b",
+                    &test1_oid.to_string(),
+                ],
+                &GitRunOptions {
+                    expected_exit_code: 3,
+                    ..Default::default()
+                },
+            )?;
+            insta::assert_snapshot!(stdout, @r###"
+            Commit not found in the commit graph: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_test_cached_by_tree() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "test", "-x", "true"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            (pass) 62fc20d2 create test1.txt
+            Ran `true` on 1 commit: 1 passed, 0 failed.
+            "###);
+        }
+
+        // Rewording the commit leaves its tree unchanged, so the result is
+        // reused.
+        git.run(&["commit", "--amend", "-m", "amended test1"])?;
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "test", "-x", "true"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            (pass) ae94dc2a amended test1 (cached)
+            Ran `true` on 1 commit: 1 passed, 0 failed.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_test_current_stack() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.commit_file("test3", 3)?;
+        git.run(&["checkout", "HEAD^"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "test", "-x", "true", "--stack"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            (pass) 62fc20d2 create test1.txt
+            (pass) 96d1c37a create test2.txt
+            Ran `true` on 2 commits: 2 passed, 0 failed.
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_test_notes_ref() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+
+        // Without a configured notes ref, the results aren't recorded as notes.
+        git.run(&["branchless", "test", "-x", "true"])?;
+        {
+            let (stdout, _stderr) = git.run(&["for-each-ref", "refs/notes/"])?;
+            insta::assert_snapshot!(stdout, @"");
+        }
+
+        git.run(&[
+            "branchless",
+            "test",
+            "-x",
+            "true",
+            "--notes-ref",
+            "refs/notes/ci",
+        ])?;
+        {
+            let (stdout, _stderr) =
+                git.run(&["notes", "--ref", "refs/notes/ci", "show", "HEAD"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            passed `true`
+            "###);
+        }
+
+        Ok(())
+    })
+}
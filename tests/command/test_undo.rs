@@ -11,7 +11,7 @@ use branchless::core::mergebase::MergeBaseDb;
 use branchless::core::tui::testing::{
     screen_to_string, CursiveTestingBackend, CursiveTestingEvent,
 };
-use branchless::testing::{with_git, Git};
+use branchless::testing::{with_git, Git, GitRunOptions};
 use branchless::util::{get_db_conn, GitExecutable};
 
 use cursive::event::Key;
@@ -20,6 +20,15 @@ use cursive::CursiveRunnable;
 fn run_select_past_event(
     repo: &git2::Repository,
     events: Vec<CursiveTestingEvent>,
+) -> anyhow::Result<Option<EventCursor>> {
+    run_select_past_event_with_size(repo, events, 120, 24)
+}
+
+fn run_select_past_event_with_size(
+    repo: &git2::Repository,
+    events: Vec<CursiveTestingEvent>,
+    width: usize,
+    height: usize,
 ) -> anyhow::Result<Option<EventCursor>> {
     let glyphs = Glyphs::text();
     let conn = get_db_conn(&repo)?;
@@ -27,7 +36,11 @@ fn run_select_past_event(
     let event_log_db: EventLogDb = EventLogDb::new(&conn)?;
     let mut event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
     let siv = CursiveRunnable::new::<Infallible, _>(move || {
-        Ok(CursiveTestingBackend::init(events.clone()))
+        Ok(CursiveTestingBackend::init_with_size(
+            events.clone(),
+            width,
+            height,
+        ))
     });
     select_past_event(
         siv.into_runner(),
@@ -42,6 +55,7 @@ fn run_undo_events(git: &Git, event_cursor: EventCursor) -> anyhow::Result<Strin
     let glyphs = Glyphs::text();
     let repo = git.get_repo()?;
     let conn = get_db_conn(&repo)?;
+    let merge_base_db = MergeBaseDb::new(&conn)?;
     let mut event_log_db: EventLogDb = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
     let input = "y";
@@ -72,9 +86,11 @@ fn run_undo_events(git: &Git, event_cursor: EventCursor) -> anyhow::Result<Strin
         &glyphs,
         &repo,
         &GitExecutable(git.git_executable.clone()),
+        &merge_base_db,
         &mut event_log_db,
         &event_replayer,
         event_cursor,
+        false,
     )?;
     assert_eq!(result, 0);
 
@@ -100,16 +116,18 @@ fn test_undo_help() -> anyhow::Result<()> {
                 ],
             )?;
             insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
-            ┌───────────────────────────────────────────┤─How to use ├───────────────────────────────────────────┐
+            ┌───────────────────────────────────────────┤ How to use ├───────────────────────────────────────────┐
             │ Use `git undo` to view and revert to previous states of the repository.                            │
             │                                                                                                    │
-            │ h/?: Show this help.                                                                               │
-            │ q: Quit.                                                                                           │
-            │ p/n or <left>/<right>: View next/previous state.                                                   │
-            │ g: Go to a provided event ID.                                                                      │
+            │ h/H/?: Show this help.                                                                             │
+            │ q/Q: Quit.                                                                                         │
+            │ p/P/<left> or n/N/<right>: View previous/next state.                                               │
+            │ g/G: Go to a provided event ID.                                                                    │
+            │ /: Filter commits by hash prefix or message (<esc> to clear).                                      │
             │ <enter>: Revert the repository to the given state (requires confirmation).                         │
             │                                                                                                    │
             │ You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it. │
+            │ Key bindings can be changed in the `branchless.tui.keymap` config section.                         │
             │                                                                                                    │
             │                                                                                            <Close> │
             └────────────────────────────────────────────────────────────────────────────────────────────────────┘
@@ -120,6 +138,51 @@ fn test_undo_help() -> anyhow::Result<()> {
     })
 }
 
+#[test]
+fn test_undo_help_narrow_terminal() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+
+        {
+            let screenshot1 = Default::default();
+            run_select_past_event_with_size(
+                &git.get_repo()?,
+                vec![
+                    CursiveTestingEvent::Event('h'.into()),
+                    CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                    CursiveTestingEvent::Event('q'.into()),
+                ],
+                60,
+                24,
+            )?;
+            insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+            ┌─────────────────────┤ How to use ├─────────────────────┐
+            │ Use `git undo` to view and revert to previous states   │
+            │ of the repository.                                     │
+            │                                                        │
+            │ h/H/?: Show this help.                                 │
+            │ q/Q: Quit.                                             │
+            │ p/P/<left> or n/N/<right>: View previous/next state.   │
+            │ g/G: Go to a provided event ID.                        │
+            │ /: Filter commits by hash prefix or message (<esc> to  │
+            │ clear).                                                │
+            │ <enter>: Revert the repository to the given state      │
+            │ (requires confirmation).                               │
+            │                                                        │
+            │ You can also copy a commit hash from the past and      │
+            │ manually run `git unhide` or `git rebase` on it.       │
+            │ Key bindings can be changed in the                     │
+            │ `branchless.tui.keymap` config section.                │
+            │                                                        │
+            │                                                <Close> │
+            └────────────────────────────────────────────────────────┘
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
 #[test]
 fn test_undo_navigate() -> anyhow::Result<()> {
     with_git(|git| {
@@ -172,6 +235,138 @@ fn test_undo_navigate() -> anyhow::Result<()> {
     })
 }
 
+#[test]
+fn test_undo_custom_keymap() -> anyhow::Result<()> {
+    with_git(|git| {
+        if !git.supports_reference_transactions()? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["config", "branchless.tui.keymap.previous", "k <up>"])?;
+        git.run(&["config", "branchless.tui.keymap.confirm", "<space>"])?;
+        // Invalid key bindings fall back to the defaults.
+        git.run(&["config", "branchless.tui.keymap.help", "<nonexistent>"])?;
+
+        {
+            let screenshot1 = Default::default();
+            let screenshot2 = Default::default();
+            let event_cursor = run_select_past_event(
+                &git.get_repo()?,
+                vec![
+                    CursiveTestingEvent::Event('p'.into()),
+                    CursiveTestingEvent::Event('k'.into()),
+                    CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                    CursiveTestingEvent::Event('h'.into()),
+                    CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot2)),
+                    CursiveTestingEvent::Event(Key::Enter.into()),
+                    CursiveTestingEvent::Event(' '.into()),
+                ],
+            )?;
+            insta::assert_debug_snapshot!(event_cursor, @r###"
+            Some(
+                EventCursor {
+                    event_id: 5,
+                },
+            )
+            "###);
+            insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+            :
+            @ 96d1c37a (master) create test2.txt
+            Repo after transaction 3 (event 4). Press 'h' for help, 'q' to quit.
+            1. Check out from 62fc20d2 create test1.txt
+            to 96d1c37a create test2.txt
+            2. Move branch master from 62fc20d2 create test1.txt
+            to 96d1c37a create test2.txt
+            "###);
+            insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
+            ┌───────────────────────────────────────────┤ How to use ├───────────────────────────────────────────┐
+            │ Use `git undo` to view and revert to previous states of the repository.                            │
+            │                                                                                                    │
+            │ h/H/?: Show this help.                                                                             │
+            │ q/Q: Quit.                                                                                         │
+            │ k/<up> or n/N/<right>: View previous/next state.                                                   │
+            │ g/G: Go to a provided event ID.                                                                    │
+            │ /: Filter commits by hash prefix or message (<esc> to clear).                                      │
+            │ <space>: Revert the repository to the given state (requires confirmation).                         │
+            │                                                                                                    │
+            │ You can also copy a commit hash from the past and manually run `git unhide` or `git rebase` on it. │
+            │ Key bindings can be changed in the `branchless.tui.keymap` config section.                         │
+            │                                                                                                    │
+            │                                                                                            <Close> │
+            └────────────────────────────────────────────────────────────────────────────────────────────────────┘
+            "###);
+        };
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_undo_filter() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+
+        {
+            let screenshot1 = Default::default();
+            let screenshot2 = Default::default();
+            let screenshot3 = Default::default();
+            let mut events = vec![CursiveTestingEvent::Event('/'.into())];
+            events.extend(
+                "TEST1"
+                    .chars()
+                    .map(|c| CursiveTestingEvent::Event(c.into())),
+            );
+            events.extend(vec![
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                CursiveTestingEvent::Event(Key::Enter.into()),
+                CursiveTestingEvent::Event('p'.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot2)),
+                CursiveTestingEvent::Event(Key::Esc.into()),
+                CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot3)),
+                CursiveTestingEvent::Event('q'.into()),
+            ]);
+            run_select_past_event(&git.get_repo()?, events)?;
+            insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            TEST1_______________________________________________________________
+            Repo after transaction 9 (event 9). Press 'h' for help, 'q' to quit.
+            1. Commit fe65c1fe create test2.txt
+            "###);
+            insta::assert_snapshot!(screen_to_string(&screenshot2), @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            o 62fc20d2 create test1.txt
+            TEST1_______________________________________________________________
+            Repo after transaction 8 (event 8). Press 'h' for help, 'q' to quit.
+            1. Check out from f777ecc9 create initial.txt
+            to fe65c1fe create test2.txt
+            "###);
+            insta::assert_snapshot!(screen_to_string(&screenshot3), @r###"
+            O f777ecc9 (master) create initial.txt
+            |\
+            | o 62fc20d2 create test1.txt
+            |
+            @ fe65c1fe create test2.txt
+            Repo after transaction 8 (event 8). Press 'h' for help, 'q' to quit.
+            1. Check out from f777ecc9 create initial.txt
+            to fe65c1fe create test2.txt
+            "###);
+        };
+
+        Ok(())
+    })
+}
+
 #[test]
 fn test_go_to_event() -> anyhow::Result<()> {
     with_git(|git| {
@@ -268,7 +463,20 @@ fn test_undo_hide() -> anyhow::Result<()> {
 
             2. Unhide commit 62fc20d2 create test1.txt
 
-            Confirm? [yN] Applied 2 inverse events.
+            Smartlog before undo:
+            O f777ecc9 (master) create initial.txt
+            |
+            @ fe65c1fe create test2.txt
+            Smartlog after undo:
+            O f777ecc9 (master) create initial.txt
+            |\
+            | o 62fc20d2 (test1) create test1.txt
+            |
+            @ fe65c1fe create test2.txt
+            Changes:
+            + 62fc20d2 create test1.txt
+            ~ branch test1: (none) -> 62fc20d2
+            1 commit affected, 1 branch moved. Confirm? [yN] Applied 2 inverse events.
             "###);
         }
 
@@ -326,7 +534,15 @@ fn test_undo_move_refs() -> anyhow::Result<()> {
 
             3. Move branch master from 96d1c37a create test2.txt
                                     to 62fc20d2 create test1.txt
-            Confirm? [yN] Applied 3 inverse events.
+            Smartlog before undo:
+            :
+            @ 96d1c37a (master) create test2.txt
+            Smartlog after undo:
+            :
+            @ 62fc20d2 (master) create test1.txt
+            Changes:
+            ~ branch master: 96d1c37a -> 62fc20d2
+            0 commits affected, 1 branch moved. Confirm? [yN] Applied 3 inverse events.
             "###);
         }
 
@@ -448,7 +664,17 @@ fn test_undo_doesnt_make_working_dir_dirty() -> anyhow::Result<()> {
                                     to f777ecc9 create initial.txt
             5. Delete branch foo at f777ecc9 create initial.txt
 
-            Confirm? [yN] Applied 5 inverse events.
+            Smartlog before undo:
+            O f777ecc9 (foo) create initial.txt
+            |
+            @ 62fc20d2 (master, bar) create test1.txt
+            Smartlog after undo:
+            :
+            O 62fc20d2 (master) create test1.txt
+            Changes:
+            ~ branch bar: 62fc20d2 -> (none)
+            ~ branch foo: f777ecc9 -> (none)
+            0 commits affected, 2 branches moved. Confirm? [yN] Applied 5 inverse events.
             "###);
         }
         {
@@ -459,3 +685,195 @@ fn test_undo_doesnt_make_working_dir_dirty() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_undo_truncate_long_summaries() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        git.run(&[
+            "commit",
+            "--amend",
+            "-m",
+            "this commit has a very long summary line which does not fit on the screen at all",
+        ])?;
+        git.run(&["branch", "feature"])?;
+
+        {
+            let screenshot1 = Default::default();
+            run_select_past_event_with_size(
+                &git.get_repo()?,
+                vec![
+                    CursiveTestingEvent::TakeScreenshot(Rc::clone(&screenshot1)),
+                    CursiveTestingEvent::Event('q'.into()),
+                ],
+                80,
+                24,
+            )?;
+            insta::assert_snapshot!(screen_to_string(&screenshot1), @r###"
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            @ c42b9f77 (feature) this commit has a very long summary line which does no...
+            Repo after transaction 10 (event 11). Press 'h' for help, 'q' to quit.
+            1. Create branch feature at c42b9f77 this commit has a very long summary line
+            which does not fit on the screen at all
+            "###);
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_undo_restores_backup_before_rebase_on_disk() -> anyhow::Result<()> {
+    with_git(|git| {
+        if !git.supports_reference_transactions()? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.write_file("test3", "staged contents\n")?;
+        git.run(&["add", "test3.txt"])?;
+
+        let event_cursor = {
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+            event_replayer.make_default_cursor()
+        };
+
+        // Git refuses to start a rebase with staged changes, but the index
+        // has been backed up by then.
+        git.run_with_options(
+            &[
+                "move",
+                "--on-disk",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                "master",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        git.run(&["reset", "--hard"])?;
+        {
+            let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+            assert_eq!(stdout, "");
+        }
+
+        {
+            let stdout = run_undo_events(&git, event_cursor)?;
+            insta::assert_snapshot!(stdout, @r###"
+            Will apply these actions:
+            1. Check out from 70deb1e2 create test3.txt
+                           to 70deb1e2 create test3.txt
+            2. Delete ref refs/branchless/backup/9 at 6002c165 branchless: backup of index before on-disk rebase
+
+            Smartlog before undo:
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            |
+            @ 70deb1e2 create test3.txt
+            Smartlog after undo:
+            :
+            O 62fc20d2 (master) create test1.txt
+            |
+            o 96d1c37a create test2.txt
+            |
+            @ 70deb1e2 create test3.txt
+            0 commits affected, 0 branches moved. Confirm? [yN] Restored HEAD and the index from backup 6002c1658ceb40c9f66cf7a4836a54bf1369f15e.
+            Applied 2 inverse events.
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            MM test3.txt
+            "###);
+        }
+        {
+            let (stdout, _stderr) = git.run(&["for-each-ref", "refs/branchless/backup/"])?;
+            assert_eq!(stdout, "");
+        }
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_undo_restore_backup_keeps_unstaged_changes() -> anyhow::Result<()> {
+    with_git(|git| {
+        if !git.supports_reference_transactions()? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.commit_file("test1", 1)?;
+        git.detach_head()?;
+        git.commit_file("test2", 2)?;
+        let test3_oid = git.commit_file("test3", 3)?;
+        git.write_file("test3", "staged contents\n")?;
+        git.run(&["add", "test3.txt"])?;
+
+        let event_cursor = {
+            let repo = git.get_repo()?;
+            let conn = get_db_conn(&repo)?;
+            let event_log_db = EventLogDb::new(&conn)?;
+            let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+            event_replayer.make_default_cursor()
+        };
+
+        git.run_with_options(
+            &[
+                "move",
+                "--on-disk",
+                "-s",
+                &test3_oid.to_string(),
+                "-d",
+                "master",
+            ],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        git.run(&["reset", "--hard"])?;
+
+        // Changes made after the backup was taken aren't in the backup, so
+        // they shouldn't be discarded when restoring it.
+        git.write_file("test2", "unstaged contents\n")?;
+        run_undo_events(&git, event_cursor)?;
+
+        {
+            let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+            insta::assert_snapshot!(stdout, @r###"
+             M test2.txt
+            MM test3.txt
+            "###);
+        }
+        {
+            let (stdout, _stderr) = git.run(&["show", ":test3.txt"])?;
+            assert_eq!(stdout, "staged contents\n");
+        }
+        assert_eq!(
+            std::fs::read_to_string(git.repo_path.join("test2.txt"))?,
+            "unstaged contents\n"
+        );
+
+        Ok(())
+    })
+}
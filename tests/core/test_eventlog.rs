@@ -1,5 +1,5 @@
 use branchless::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
-use branchless::core::eventlog::{Event, EventLogDb, EventReplayer};
+use branchless::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
 use branchless::testing::with_git;
 use branchless::util::get_db_conn;
 
@@ -161,3 +161,144 @@ fn test_git_v2_31_events() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_iter_transactions() -> anyhow::Result<()> {
+    with_git(|git| {
+        if !git.supports_reference_transactions()? {
+            return Ok(());
+        }
+
+        git.init_repo()?;
+        git.detach_head()?;
+        let test1_oid = git.commit_file("test1", 1)?;
+        git.run(&["hide", &test1_oid.to_string()])?;
+
+        let conn = get_db_conn(&git.get_repo()?)?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let transactions: Vec<(EventTransactionId, Option<String>, Vec<Event>)> = event_log_db
+            .iter_transactions()?
+            .map(|transaction| {
+                (
+                    transaction.id,
+                    transaction.message,
+                    transaction
+                        .events
+                        .into_iter()
+                        .map(redact_event_timestamp)
+                        .collect(),
+                )
+            })
+            .collect();
+        insta::assert_debug_snapshot!(transactions, @r###"
+        [
+            (
+                EventTransactionId(
+                    1,
+                ),
+                Some(
+                    "reference-transaction",
+                ),
+                [
+                    RefUpdateEvent {
+                        timestamp: 0.0,
+                        event_tx_id: EventTransactionId(
+                            1,
+                        ),
+                        ref_name: "HEAD",
+                        old_ref: None,
+                        new_ref: Some(
+                            "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                        ),
+                        message: None,
+                    },
+                ],
+            ),
+            (
+                EventTransactionId(
+                    2,
+                ),
+                Some(
+                    "hook-post-checkout",
+                ),
+                [
+                    RefUpdateEvent {
+                        timestamp: 0.0,
+                        event_tx_id: EventTransactionId(
+                            2,
+                        ),
+                        ref_name: "HEAD",
+                        old_ref: Some(
+                            "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                        ),
+                        new_ref: Some(
+                            "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                        ),
+                        message: None,
+                    },
+                ],
+            ),
+            (
+                EventTransactionId(
+                    3,
+                ),
+                Some(
+                    "reference-transaction",
+                ),
+                [
+                    RefUpdateEvent {
+                        timestamp: 0.0,
+                        event_tx_id: EventTransactionId(
+                            3,
+                        ),
+                        ref_name: "HEAD",
+                        old_ref: Some(
+                            "f777ecc9b0db5ed372b2615695191a8a17f79f24",
+                        ),
+                        new_ref: Some(
+                            "62fc20d2a290daea0d52bdc2ed2ad4be6491010e",
+                        ),
+                        message: None,
+                    },
+                ],
+            ),
+            (
+                EventTransactionId(
+                    4,
+                ),
+                Some(
+                    "hook-post-commit",
+                ),
+                [
+                    CommitEvent {
+                        timestamp: 0.0,
+                        event_tx_id: EventTransactionId(
+                            4,
+                        ),
+                        commit_oid: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e,
+                    },
+                ],
+            ),
+            (
+                EventTransactionId(
+                    5,
+                ),
+                Some(
+                    "hide",
+                ),
+                [
+                    HideEvent {
+                        timestamp: 0.0,
+                        event_tx_id: EventTransactionId(
+                            5,
+                        ),
+                        commit_oid: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e,
+                    },
+                ],
+            ),
+        ]
+        "###);
+
+        Ok(())
+    })
+}
@@ -45,3 +45,71 @@ branchless: collecting garbage
         Ok(())
     })
 }
+
+#[test]
+fn test_gc_compact_event_log() -> anyhow::Result<()> {
+    branchless::testing::with_git(|git| {
+        git.init_repo()?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.commit_file("test2", 2)?;
+        git.run(&["hide", "HEAD"])?;
+        git.run(&["checkout", "HEAD^"])?;
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 create test1.txt
+            "###);
+        }
+
+        git.run(&["config", "branchless.gc.eventLogHorizonDays", "0"])?;
+
+        // Git's automatic garbage collection doesn't compact the event log.
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "hook-pre-auto-gc"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: collecting garbage
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: collecting garbage
+            branchless: compacting 6 events older than 0 days from the event log
+            branchless: warning: `git undo` will no longer be able to return to states from before then (configure this with `branchless.gc.eventLogHorizonDays`)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["smartlog"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            O f777ecc9 (master) create initial.txt
+            |
+            @ 62fc20d2 create test1.txt
+            "###);
+        }
+
+        git.run(&["gc", "--prune=now"])?;
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: collecting garbage
+            branchless: compacting 2 events older than 0 days from the event log
+            branchless: warning: `git undo` will no longer be able to return to states from before then (configure this with `branchless.gc.eventLogHorizonDays`)
+            "###);
+        }
+
+        {
+            let (stdout, _stderr) = git.run(&["branchless", "gc"])?;
+            insta::assert_snapshot!(stdout, @r###"
+            branchless: collecting garbage
+            "###);
+        }
+
+        Ok(())
+    })
+}
@@ -1,6 +1,9 @@
 use anyhow::Context;
+use branchless::core::eventlog::testing::get_event_replayer_events;
+use branchless::core::eventlog::{Event, EventLogDb, EventReplayer};
 use branchless::testing::with_git;
-use branchless::util::get_sh;
+use branchless::util::{get_db_conn, get_sh};
+use regex::Regex;
 use std::process::Command;
 
 fn preprocess_stderr(stderr: String) -> String {
@@ -195,3 +198,93 @@ fn test_pre_auto_gc() -> anyhow::Result<()> {
         Ok(())
     })
 }
+
+#[test]
+fn test_merge_commit_recorded() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        git.run(&["checkout", "-b", "feature"])?;
+        git.commit_file("test1", 1)?;
+        git.run(&["checkout", "master"])?;
+        git.commit_file("test2", 2)?;
+
+        git.run(&["merge", "--no-ff", "-m", "merge feature", "feature"])?;
+
+        let repo = git.get_repo()?;
+        let merge_commit_oid = repo.head()?.peel_to_commit()?.id();
+        let conn = get_db_conn(&repo)?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+        let commit_events: Vec<Event> = get_event_replayer_events(&event_replayer)
+            .iter()
+            .filter(|event| matches!(event, Event::CommitEvent { .. }))
+            .cloned()
+            .collect();
+        assert!(matches!(
+            commit_events.last(),
+            Some(Event::CommitEvent { commit_oid, .. }) if *commit_oid == merge_commit_oid
+        ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_hooks_event_sink() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        let sink_path = git.repo_path.join(".git").join("branchless-events.jsonl");
+        git.run(&[
+            "config",
+            "branchless.hooks.eventSink",
+            sink_path.to_str().unwrap(),
+        ])?;
+        git.detach_head()?;
+        git.commit_file("test1", 1)?;
+        git.run(&["commit", "--amend", "-m", "amended test1"])?;
+
+        let re = Regex::new(r#""timestamp":[0-9.]+"#)?;
+        let events: Vec<String> = std::fs::read_to_string(&sink_path)?
+            .lines()
+            .map(|line| {
+                re.replace_all(line, r#""timestamp":<redacted>"#)
+                    .into_owned()
+            })
+            .filter(|line| {
+                line.contains(r#""type":"commit""#) || line.contains(r#""type":"rewrite""#)
+            })
+            .collect();
+        insta::assert_snapshot!(events.join("\n"), @r###"
+        {"commit_oid":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e","event_tx_id":4,"timestamp":<redacted>,"type":"commit"}
+        {"commit_oid":"ae94dc2a748bc0965c88fcf3edac2e30074ff7e2","event_tx_id":6,"timestamp":<redacted>,"type":"commit"}
+        {"event_tx_id":7,"new_commit_oid":"ae94dc2a748bc0965c88fcf3edac2e30074ff7e2","old_commit_oid":"62fc20d2a290daea0d52bdc2ed2ad4be6491010e","timestamp":<redacted>,"type":"rewrite"}
+        "###);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn test_hooks_event_sink_failure() -> anyhow::Result<()> {
+    with_git(|git| {
+        git.init_repo()?;
+        // The sink is a directory, so it can't be written to, but the hooks
+        // should still succeed and record the event.
+        git.run(&[
+            "config",
+            "branchless.hooks.eventSink",
+            git.repo_path.to_str().unwrap(),
+        ])?;
+        let oid = git.commit_file("test1", 1)?;
+
+        let repo = git.get_repo()?;
+        let conn = get_db_conn(&repo)?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&event_log_db)?;
+        assert!(get_event_replayer_events(&event_replayer).iter().any(
+            |event| matches!(event, Event::CommitEvent { commit_oid, .. } if *commit_oid == oid)
+        ));
+
+        Ok(())
+    })
+}
@@ -7,11 +7,19 @@ mod core {
 }
 
 mod command {
+    mod test_amend;
+    mod test_doctor;
+    mod test_events;
     mod test_hide;
     mod test_init;
     mod test_move;
     mod test_navigation;
+    mod test_reattach;
+    mod test_repair;
     mod test_restack;
     mod test_smartlog;
+    mod test_stack_diff;
+    mod test_sync;
+    mod test_test;
     mod test_undo;
 }